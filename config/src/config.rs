@@ -24,8 +24,8 @@ use crate::{
     default_config_with_overrides_applied, default_one_point_oh, default_one_point_oh_f64,
     default_true, default_win32_acrylic_accent_color, GpuInfo, IntegratedTitleButtonColor,
     KeyMapPreference, LoadedConfig, MouseEventTriggerMods, RgbaColor, SerialDomain, SystemBackdrop,
-    WebGpuPowerPreference, CONFIG_DIRS, CONFIG_FILE_OVERRIDE, CONFIG_OVERRIDES, CONFIG_SKIP,
-    HOME_DIR,
+    WebGpuPowerPreference, WebGpuPresentMode, CONFIG_DIRS, CONFIG_FILE_OVERRIDE, CONFIG_OVERRIDES,
+    CONFIG_SKIP, HOME_DIR,
 };
 use anyhow::Context;
 use luahelper::impl_lua_conversion_dynamic;
@@ -337,6 +337,17 @@ pub struct Config {
     #[dynamic(default)]
     pub webgpu_preferred_adapter: Option<GpuInfo>,
 
+    /// Prefer the first compatible WebGpu adapter whose name contains
+    /// this string, compared case insensitively.
+    /// webgpu_preferred_adapter takes precedence, if set.
+    #[dynamic(default)]
+    pub webgpu_preferred_adapter_name: Option<String>,
+
+    /// How frames are presented to the display when using WebGpu.
+    /// Falls back to Fifo if the requested mode isn't supported.
+    #[dynamic(default)]
+    pub webgpu_present_mode: WebGpuPresentMode,
+
     #[dynamic(default)]
     pub wsl_domains: Option<Vec<WslDomain>>,
 
@@ -599,6 +610,13 @@ pub struct Config {
     #[dynamic(default = "default_one_point_oh")]
     pub text_background_opacity: f32,
 
+    /// The opacity of the whole window, including its decorations,
+    /// while it doesn't have the keyboard focus.
+    /// This relies on the window system supporting whole-window
+    /// opacity, which is currently only the case on X11.
+    #[dynamic(default = "default_one_point_oh")]
+    pub inactive_window_opacity: f32,
+
     /// Specifies how often a blinking cursor transitions between visible
     /// and invisible, expressed in milliseconds.
     /// Setting this to 0 disables blinking.
@@ -686,6 +704,30 @@ pub struct Config {
     #[dynamic(default = "default_true")]
     pub use_dead_keys: bool,
 
+    #[dynamic(default)]
+    pub compose_locale: Option<String>,
+
+    /// How many ms a clipboard transfer may go without making
+    /// progress before it is abandoned
+    #[dynamic(default = "default_clipboard_pipe_timeout_ms")]
+    pub clipboard_pipe_timeout_ms: u64,
+
+    /// The minimum interval, in ms, between resizes of the rendering
+    /// surface while a Wayland window is being interactively resized
+    #[dynamic(default = "default_wayland_resize_debounce_ms")]
+    pub wayland_resize_debounce_ms: u64,
+
+    /// Whether to clamp the sizes that a Wayland compositor configures
+    /// to the minimum and maximum size requested by the application,
+    /// for compositors that don't respect them
+    #[dynamic(default = "default_true")]
+    pub wayland_clamp_to_size_constraints: bool,
+
+    /// The dpi that corresponds to a Wayland output scale of 1.
+    /// When unset, this is the platform default dpi.
+    #[dynamic(default)]
+    pub wayland_base_dpi: Option<f64>,
+
     #[dynamic(default)]
     pub launch_menu: Vec<SpawnCommand>,
 
@@ -778,6 +820,12 @@ pub struct Config {
     #[dynamic(default = "default_swallow_mouse_click_on_window_focus")]
     pub swallow_mouse_click_on_window_focus: bool,
 
+    /// The longest time, in ms, between consecutive clicks for them
+    /// to be considered to be part of a double or triple click.
+    /// If not set, the double click time of the desktop is used.
+    #[dynamic(default)]
+    pub mouse_click_interval_ms: Option<u64>,
+
     #[dynamic(default)]
     pub pane_focus_follows_mouse: bool,
 
@@ -1611,6 +1659,14 @@ fn default_mux_output_parser_coalesce_delay_ms() -> u64 {
     3
 }
 
+fn default_clipboard_pipe_timeout_ms() -> u64 {
+    3000
+}
+
+fn default_wayland_resize_debounce_ms() -> u64 {
+    16
+}
+
 fn default_mux_output_parser_buffer_size() -> usize {
     128 * 1024
 }
@@ -55,3 +55,16 @@ impl Default for WebGpuPowerPreference {
         Self::LowPower
     }
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromDynamic, ToDynamic)]
+pub enum WebGpuPresentMode {
+    Fifo,
+    Mailbox,
+    Immediate,
+}
+
+impl Default for WebGpuPresentMode {
+    fn default() -> Self {
+        Self::Fifo
+    }
+}
@@ -54,6 +54,13 @@ impl DeferredKeyCode {
             return Ok(KeyCode::RawCode(num));
         }
 
+        if let Some(sym) = s.strip_prefix("unknown:") {
+            let num: u32 = sym.parse().map_err(|_| {
+                anyhow::anyhow!("expected unknown:<NUMBER> key symbol string, got: {}", s)
+            })?;
+            return Ok(KeyCode::Unknown(num));
+        }
+
         if let Some(mapped) = s.strip_prefix("mapped:") {
             return KeyCode::try_from(mapped).map_err(|err| anyhow::anyhow!("{}", err));
         }
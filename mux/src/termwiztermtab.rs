@@ -238,7 +238,7 @@ impl Pane for TermWizTerminalPane {
             MouseButton::WheelDown(_) => Buttons::VERT_WHEEL,
             MouseButton::WheelLeft(_) => Buttons::HORZ_WHEEL | Buttons::WHEEL_POSITIVE,
             MouseButton::WheelRight(_) => Buttons::HORZ_WHEEL,
-            MouseButton::None => Buttons::NONE,
+            MouseButton::Back | MouseButton::Forward | MouseButton::None => Buttons::NONE,
         };
 
         let event = InputEvent::Mouse(TermWizMouseEvent {
@@ -20,6 +20,10 @@ pub enum MouseButton {
     WheelLeft(usize),
     WheelRight(usize),
     None,
+    // These are last so that the encoding of the others on the
+    // mux protocol is unchanged
+    Back,
+    Forward,
 }
 
 #[cfg_attr(feature = "use_serde", derive(Deserialize, Serialize))]
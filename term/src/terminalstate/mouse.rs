@@ -30,7 +30,7 @@ impl TerminalState {
         }
     }
 
-    fn encode_x10_or_utf8(&mut self, event: MouseEvent, button: i8) -> anyhow::Result<()> {
+    fn encode_x10_or_utf8(&mut self, event: MouseEvent, button: i16) -> anyhow::Result<()> {
         let mut buf = vec![b'\x1b', b'[', b'M', (32 + button) as u8];
         self.encode_coord(event.x as i64, &mut buf);
         self.encode_coord(event.y, &mut buf);
@@ -40,7 +40,7 @@ impl TerminalState {
         Ok(())
     }
 
-    fn mouse_report_button_number(&self, event: &MouseEvent) -> (i8, MouseButton) {
+    fn mouse_report_button_number(&self, event: &MouseEvent) -> (i16, MouseButton) {
         let button = match event.button {
             MouseButton::None => self
                 .current_mouse_buttons
@@ -54,6 +54,8 @@ impl TerminalState {
             MouseButton::Left => 0,
             MouseButton::Middle => 1,
             MouseButton::Right => 2,
+            MouseButton::Back => 128,
+            MouseButton::Forward => 129,
             MouseButton::WheelUp(_) => 64,
             MouseButton::WheelDown(_) => 65,
             MouseButton::WheelLeft(_) => 66,
@@ -510,7 +510,8 @@ impl CommandDef {
                         | KeyCode::Meta
                         | KeyCode::Cancel
                         | KeyCode::Composed(_)
-                        | KeyCode::RawCode(_) => "".to_string(),
+                        | KeyCode::RawCode(_)
+                        | KeyCode::Unknown(_) => "".to_string(),
                         KeyCode::Char(c) => c.to_string(),
                         KeyCode::Physical(phys) => key_code_to_equivalent(&phys.to_key_code()),
                         _ => "".to_string(),
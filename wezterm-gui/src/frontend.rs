@@ -393,7 +393,7 @@ impl GuiFrontEnd {
             } else {
                 // We have more windows than are in the new workspace;
                 // we no longer need this one!
-                window.close();
+                window.force_close();
                 front_end().spawned_mux_window.borrow_mut().remove(&old_id);
             }
         }
@@ -215,6 +215,7 @@ pub fn confirm_close_window(
     mux_window_id: WindowId,
     window: ::window::Window,
     tab_id: TabId,
+    request: ::window::CloseRequest,
 ) -> anyhow::Result<()> {
     if run_confirmation_app(
         "🛑 Really kill this window and all contained tabs and panes?",
@@ -223,6 +224,7 @@ pub fn confirm_close_window(
         promise::spawn::spawn_into_main_thread(async move {
             let mux = Mux::get();
             mux.kill_window(mux_window_id);
+            request.allow();
         })
         .detach();
     }
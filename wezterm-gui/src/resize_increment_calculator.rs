@@ -12,18 +12,33 @@ pub struct ResizeIncrementCalculator {
     pub tab_bar_height: usize,
 }
 
+impl ResizeIncrementCalculator {
+    /// The size of everything around the cells
+    fn base_size(&self) -> (usize, usize) {
+        (
+            self.padding_left + self.padding_right + (self.border.left + self.border.right).get(),
+            self.padding_top
+                + self.padding_bottom
+                + (self.border.top + self.border.bottom).get()
+                + self.tab_bar_height,
+        )
+    }
+
+    /// The inner size of a window that has room for a single cell
+    pub fn min_inner_size(&self) -> (usize, usize) {
+        let (base_width, base_height) = self.base_size();
+        (base_width + self.x as usize, base_height + self.y as usize)
+    }
+}
+
 impl Into<ResizeIncrement> for ResizeIncrementCalculator {
     fn into(self) -> ResizeIncrement {
+        let (base_width, base_height) = self.base_size();
         ResizeIncrement {
             x: self.x,
             y: self.y,
-            base_width: (self.padding_left
-                + self.padding_right
-                + (self.border.left + self.border.right).get()) as u16,
-            base_height: (self.padding_top
-                + self.padding_bottom
-                + (self.border.top + self.border.bottom).get()
-                + self.tab_bar_height) as u16,
+            base_width: base_width as u16,
+            base_height: base_height as u16,
         }
     }
 }
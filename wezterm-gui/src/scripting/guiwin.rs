@@ -78,7 +78,7 @@ impl UserData for GuiWin {
             Ok(())
         });
         methods.add_method("focus", |_, this, _: ()| {
-            this.window.focus();
+            this.window.focus(None);
             Ok(())
         });
         methods.add_method(
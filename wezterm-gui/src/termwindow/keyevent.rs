@@ -766,7 +766,7 @@ impl super::TermWindow {
                 }
             }
             WK::Char('\u{1b}') => KC::Escape,
-            WK::RawCode(_) => return Key::None,
+            WK::RawCode(_) | WK::Unknown(_) => return Key::None,
             WK::Physical(phys) => {
                 return self.win_key_code_to_termwiz_key_code(&phys.to_key_code())
             }
@@ -854,6 +854,7 @@ impl super::TermWindow {
             WK::MediaPrevTrack => KC::MediaPrevTrack,
             WK::MediaStop => KC::MediaStop,
             WK::MediaPlayPause => KC::MediaPlayPause,
+            WK::BrightnessUp | WK::BrightnessDown | WK::Compose => return Key::None,
             WK::ApplicationLeftArrow => KC::ApplicationLeftArrow,
             WK::ApplicationRightArrow => KC::ApplicationRightArrow,
             WK::ApplicationUpArrow => KC::ApplicationUpArrow,
@@ -458,9 +458,14 @@ pub struct TermWindow {
 
     gl: Option<Rc<glium::backend::Context>>,
     webgpu: Option<Rc<WebGpuState>>,
+    /// Set while a replacement for a lost webgpu device is being
+    /// created; holds the outcome once it is ready
+    pending_webgpu: Option<PendingWebGpu>,
     config_subscription: Option<config::ConfigSubscription>,
 }
 
+type PendingWebGpu = Rc<RefCell<Option<anyhow::Result<WebGpuState>>>>;
+
 impl TermWindow {
     fn load_os_parameters(&mut self) {
         if let Some(ref window) = self.window {
@@ -474,13 +479,13 @@ impl TermWindow {
         }
     }
 
-    fn close_requested(&mut self, window: &Window) {
+    fn close_requested(&mut self, request: CloseRequest, window: &Window) {
         let mux = Mux::get();
         match self.config.window_close_confirmation {
             WindowCloseConfirmation::NeverPrompt => {
                 // Immediately kill the tabs and allow the window to close
                 mux.kill_window(self.mux_window_id);
-                window.close();
+                request.allow();
                 front_end().forget_known_window(window);
             }
             WindowCloseConfirmation::AlwaysPrompt => {
@@ -488,7 +493,7 @@ impl TermWindow {
                     Some(tab) => tab,
                     None => {
                         mux.kill_window(self.mux_window_id);
-                        window.close();
+                        request.allow();
                         front_end().forget_known_window(window);
                         return;
                     }
@@ -501,13 +506,13 @@ impl TermWindow {
                     .map_or(false, |w| w.can_close_without_prompting());
                 if can_close {
                     mux.kill_window(self.mux_window_id);
-                    window.close();
+                    request.allow();
                     front_end().forget_known_window(window);
                     return;
                 }
                 let window = self.window.clone().unwrap();
                 let (overlay, future) = start_overlay(self, &tab, move |tab_id, term| {
-                    confirm_close_window(term, mux_window_id, window, tab_id)
+                    confirm_close_window(term, mux_window_id, window, tab_id, request)
                 });
                 self.assign_overlay(tab.tab_id(), overlay);
                 promise::spawn::spawn(future).detach();
@@ -540,6 +545,7 @@ impl TermWindow {
 
         // force cursor to be repainted
         window.invalidate();
+        self.apply_window_opacity(window);
 
         if let Some(pane) = self.get_active_pane_or_overlay() {
             pane.focus_changed(focused);
@@ -549,6 +555,14 @@ impl TermWindow {
         self.emit_window_event("window-focus-changed", None);
     }
 
+    fn apply_window_opacity(&self, window: &Window) {
+        window.set_window_opacity(if self.focused.is_some() {
+            1.0
+        } else {
+            self.config.inactive_window_opacity
+        });
+    }
+
     fn created(&mut self, ctx: RenderContext) -> anyhow::Result<()> {
         self.render_state = None;
 
@@ -684,6 +698,7 @@ impl TermWindow {
             os_parameters: None,
             gl: None,
             webgpu: None,
+            pending_webgpu: None,
             window: None,
             window_background,
             config: config.clone(),
@@ -840,19 +855,32 @@ impl TermWindow {
             }
         });
 
-        let gl = match config.front_end {
-            FrontEndSelection::WebGpu => None,
-            _ => Some(window.enable_opengl().await?),
+        let webgpu = match config.front_end {
+            FrontEndSelection::WebGpu => {
+                match WebGpuState::new(&window, dimensions, &config).await {
+                    Ok(state) => Some(Rc::new(state)),
+                    Err(err) => {
+                        // Rather than failing to open the window, fall back
+                        // to OpenGL, which in turn resorts to software
+                        // rendering when there is no usable GPU, such as
+                        // in a VM without Vulkan support.
+                        log::error!(
+                            "Failed to initialize the WebGpu front end, \
+                             falling back to OpenGL: {err:#}"
+                        );
+                        None
+                    }
+                }
+            }
+            _ => None,
+        };
+        let gl = match webgpu {
+            Some(_) => None,
+            None => Some(window.enable_opengl().await?),
         };
 
         {
             let mut myself = tw.borrow_mut();
-            let webgpu = match config.front_end {
-                FrontEndSelection::WebGpu => Some(Rc::new(
-                    WebGpuState::new(&window, dimensions, &config).await?,
-                )),
-                _ => None,
-            };
             myself.config_subscription.replace(config_subscription);
             if config.use_resize_increments {
                 window.set_resize_increments(
@@ -907,8 +935,8 @@ impl TermWindow {
                 self.clear_all_overlays();
                 Ok(false)
             }
-            WindowEvent::CloseRequested => {
-                self.close_requested(window);
+            WindowEvent::CloseRequested(request) => {
+                self.close_requested(request, window);
                 Ok(true)
             }
             WindowEvent::AppearanceChanged(appearance) => {
@@ -934,7 +962,7 @@ impl TermWindow {
                 }
                 Ok(true)
             }
-            WindowEvent::FocusChanged(focused) => {
+            WindowEvent::FocusChanged(focused, _) => {
                 self.focus_changed(focused, window);
                 Ok(true)
             }
@@ -942,6 +970,35 @@ impl TermWindow {
                 self.mouse_event_impl(event, window);
                 Ok(true)
             }
+            WindowEvent::MouseEnter(_) => Ok(true),
+            WindowEvent::Touch(_) => Ok(true),
+            WindowEvent::Gesture(_) => Ok(true),
+            // We always repaint everything
+            WindowEvent::NeedRepaintRegions(_) => Ok(true),
+            WindowEvent::FramePresented(_) => Ok(true),
+            WindowEvent::VisibilityChanged(_) => Ok(true),
+            WindowEvent::ScreenChanged(screen) => {
+                log::debug!("Window is now on screen {}", screen.name);
+                Ok(true)
+            }
+            WindowEvent::Moved(_) => Ok(true),
+            WindowEvent::UiSettingsChanged(settings) => {
+                log::debug!("Desktop UI settings changed to {settings:?}");
+                Ok(true)
+            }
+            WindowEvent::KeyboardLayoutChanged(layout) => {
+                log::debug!("Keyboard layout changed to {layout:?}");
+                Ok(true)
+            }
+            WindowEvent::UntranslatedKeyEvent(_) => Ok(true),
+            WindowEvent::WmCapabilitiesChanged {
+                capabilities,
+                bounds,
+            } => {
+                log::debug!("Window manager capabilities {capabilities:?}, bounds {bounds:?}");
+                Ok(true)
+            }
+            WindowEvent::AnimationFrame(_) => Ok(true),
             WindowEvent::MouseLeave => {
                 self.mouse_leave_impl(window);
                 Ok(true)
@@ -1063,7 +1120,7 @@ impl TermWindow {
 
         if gl.is_context_lost() {
             log::error!("opengl context was lost; should reinit");
-            window.close();
+            window.force_close();
             front_end().forget_known_window(window);
             return false;
         }
@@ -1075,19 +1132,33 @@ impl TermWindow {
                 self.dimensions.pixel_height as u32,
             ),
         );
-        self.paint_impl(&mut RenderFrame::Glium(&mut frame));
+        self.paint_impl(&mut RenderFrame::Glium(&mut frame)).ok();
         window.finish_frame(frame).is_ok()
     }
 
     fn do_paint_webgpu(&mut self) -> anyhow::Result<bool> {
+        if self.pending_webgpu.is_some() {
+            // We'll paint once the replacement device is ready
+            return Ok(true);
+        }
+        if self.webgpu.as_ref().unwrap().is_device_lost() {
+            self.recreate_webgpu_state()?;
+            return Ok(true);
+        }
         self.webgpu.as_mut().unwrap().resize(self.dimensions);
         match self.do_paint_webgpu_impl() {
             Ok(ok) => Ok(ok),
             Err(err) => {
                 match err.downcast_ref::<wgpu::SurfaceError>() {
-                    Some(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
-                        self.webgpu.as_mut().unwrap().resize(self.dimensions);
-                        return self.do_paint_webgpu_impl();
+                    Some(wgpu::SurfaceError::Timeout) => {
+                        // Skip this frame; we'll get another chance
+                        // when we are next invalidated
+                        log::debug!("timed out acquiring surface texture");
+                        return Ok(true);
+                    }
+                    Some(wgpu::SurfaceError::OutOfMemory) => {
+                        self.recreate_webgpu_state()?;
+                        return Ok(true);
                     }
                     _ => {}
                 }
@@ -1097,10 +1168,75 @@ impl TermWindow {
     }
 
     fn do_paint_webgpu_impl(&mut self) -> anyhow::Result<bool> {
-        self.paint_impl(&mut RenderFrame::WebGpu);
+        self.paint_impl(&mut RenderFrame::WebGpu)?;
         Ok(true)
     }
 
+    /// Start to build a fresh WebGpu state after the device has been
+    /// lost (eg: due to a GPU reset), so that we can continue rendering
+    /// rather than terminating.
+    /// Creating the adapter and device can take a while, so it happens
+    /// in the background rather than blocking the event loop; painting
+    /// is suspended until finish_recreate_webgpu_state installs it.
+    fn recreate_webgpu_state(&mut self) -> anyhow::Result<()> {
+        if self.pending_webgpu.is_some() {
+            return Ok(());
+        }
+        let window = self
+            .window
+            .clone()
+            .ok_or_else(|| anyhow!("cannot recreate webgpu state without a window"))?;
+        log::warn!("Recreating webgpu state for window");
+
+        let pending: PendingWebGpu = Rc::new(RefCell::new(None));
+        self.pending_webgpu.replace(Rc::clone(&pending));
+        let dimensions = self.dimensions;
+        let config = self.config.clone();
+        promise::spawn::spawn(async move {
+            let result = WebGpuState::new(&window, dimensions, &config).await;
+            pending.borrow_mut().replace(result);
+            window.notify(TermWindowNotif::Apply(Box::new(|tw| {
+                tw.finish_recreate_webgpu_state();
+            })));
+        })
+        .detach();
+        Ok(())
+    }
+
+    fn finish_recreate_webgpu_state(&mut self) {
+        let Some(result) = self
+            .pending_webgpu
+            .take()
+            .and_then(|pending| pending.borrow_mut().take())
+        else {
+            return;
+        };
+        let Some(window) = self.window.clone() else {
+            return;
+        };
+
+        let result = result.and_then(|webgpu| {
+            let webgpu = Rc::new(webgpu);
+            self.render_state.take();
+            self.webgpu.replace(Rc::clone(&webgpu));
+            self.created(RenderContext::WebGpu(webgpu))
+        });
+        match result {
+            Ok(()) => {
+                self.invalidate_fancy_tab_bar();
+                self.invalidate_modal();
+                // Any resize that happened in the meantime is applied
+                // by do_paint_webgpu
+                window.invalidate();
+            }
+            Err(err) => {
+                log::error!("Failed to recreate webgpu state; closing window: {err:#}");
+                window.force_close();
+                front_end().forget_known_window(&window);
+            }
+        }
+    }
+
     fn dispatch_notif(&mut self, notif: TermWindowNotif, window: &Window) -> anyhow::Result<()> {
         fn chan_err<T>(e: smol::channel::TrySendError<T>) -> anyhow::Error {
             anyhow::anyhow!("{}", e)
@@ -1355,7 +1491,7 @@ impl TermWindow {
 
     fn set_inner_size(&mut self, window: &Window, width: usize, height: usize) {
         self.resizes_pending += 1;
-        window.set_inner_size(width, height);
+        window.set_inner_size(width, height, ConstrainedResize::Defer);
     }
 
     /// Take care to remove our panes from the mux, otherwise
@@ -1809,6 +1945,7 @@ impl TermWindow {
             self.apply_scale_change(&dimensions, self.fonts.get_font_scale());
             self.apply_dimensions(&dimensions, None, &window);
             window.config_did_change(&config);
+            self.apply_window_opacity(&window);
             window.invalidate();
         }
 
@@ -2122,7 +2259,7 @@ impl TermWindow {
     fn activate_window(&mut self, window_idx: usize) -> anyhow::Result<()> {
         let windows = front_end().gui_windows();
         if let Some(win) = windows.get(window_idx) {
-            win.window.focus();
+            win.window.focus(None);
         }
         Ok(())
     }
@@ -2154,7 +2291,7 @@ impl TermWindow {
         };
 
         if let Some(win) = windows.get(idx) {
-            win.window.focus();
+            win.window.focus(None);
         }
 
         Ok(())
@@ -2680,7 +2817,7 @@ impl TermWindow {
             }
             Hide => {
                 if let Some(w) = window.as_ref() {
-                    w.hide();
+                    w.minimize();
                 }
             }
             Show => {
@@ -60,6 +60,18 @@ impl super::TermWindow {
 
     pub fn mouse_event_impl(&mut self, event: MouseEvent, context: &dyn WindowOps) {
         log::trace!("{:?}", event);
+        if let WMEK::PixelScroll { .. }
+        | WMEK::ScrollStopped(_)
+        | WMEK::WheelValue120 { .. }
+        | WMEK::RelativeMove { .. } = event.kind
+        {
+            // We don't do smooth scrolling; the window layer also sends
+            // VertWheel/HorzWheel for each whole line or detent that
+            // accumulates.
+            // We never lock the pointer, so there is no relative motion
+            // for us to consume either.
+            return;
+        }
         let pane = match self.get_active_pane_or_overlay() {
             Some(pane) => pane,
             None => return,
@@ -159,23 +171,29 @@ impl super::TermWindow {
 
             WMEK::Move => {
                 if let Some(start) = self.window_drag_position.as_ref() {
-                    // Dragging the window
-                    // Compute the distance since the initial event
-                    let delta_x = start.screen_coords.x - event.screen_coords.x;
-                    let delta_y = start.screen_coords.y - event.screen_coords.y;
-
-                    // Now compute a new window position.
-                    // We don't have a direct way to get the position,
-                    // but we can infer it by comparing the mouse coords
-                    // with the screen coords in the initial event.
-                    // This computes the original top_left position,
-                    // and applies the total drag delta to it.
-                    let top_left = ::window::ScreenPoint::new(
-                        (start.screen_coords.x - start.coords.x) - delta_x,
-                        (start.screen_coords.y - start.coords.y) - delta_y,
-                    );
-                    // and now tell the window to go there
-                    context.set_window_position(top_left);
+                    // Dragging the window. Without screen coordinates
+                    // (Wayland), request_drag_move has left it to the
+                    // compositor.
+                    if let (Some(start_screen), Some(screen)) =
+                        (start.screen_coords, event.screen_coords)
+                    {
+                        // Compute the distance since the initial event
+                        let delta_x = start_screen.x - screen.x;
+                        let delta_y = start_screen.y - screen.y;
+
+                        // Now compute a new window position.
+                        // We don't have a direct way to get the position,
+                        // but we can infer it by comparing the mouse coords
+                        // with the screen coords in the initial event.
+                        // This computes the original top_left position,
+                        // and applies the total drag delta to it.
+                        let top_left = ::window::ScreenPoint::new(
+                            (start_screen.x - start.coords.x) - delta_x,
+                            (start_screen.y - start.coords.y) - delta_y,
+                        );
+                        // and now tell the window to go there
+                        context.set_window_position(top_left);
+                    }
                     return;
                 }
 
@@ -409,7 +427,9 @@ impl super::TermWindow {
         let action = match button {
             MousePress::Left => Some(KeyAssignment::SpawnTab(SpawnTabDomain::CurrentPaneDomain)),
             MousePress::Right => Some(KeyAssignment::ShowLauncher),
-            MousePress::Middle => None,
+            MousePress::Middle | MousePress::Back | MousePress::Forward | MousePress::Other(_) => {
+                None
+            }
         };
 
         async fn dispatch_new_tab_button(
@@ -494,7 +514,7 @@ impl super::TermWindow {
                     use window::IntegratedTitleButton as Button;
                     if let Some(ref window) = self.window {
                         match button {
-                            Button::Hide => window.hide(),
+                            Button::Hide => window.minimize(),
                             Button::Maximize => {
                                 let maximized = self
                                     .window_state
@@ -505,7 +525,7 @@ impl super::TermWindow {
                                     window.maximize();
                                 }
                             }
-                            Button::Close => self.close_requested(&window.clone()),
+                            Button::Close => window.close(),
                         }
                     }
                 }
@@ -536,17 +556,21 @@ impl super::TermWindow {
             },
             WMEK::Move => match item {
                 TabBarItem::None | TabBarItem::LeftStatus | TabBarItem::RightStatus => {
-                    context.set_window_drag_position(event.screen_coords);
+                    if let Some(screen_coords) = event.screen_coords {
+                        context.set_window_drag_position(screen_coords);
+                    }
                 }
                 TabBarItem::WindowButton(window::IntegratedTitleButton::Maximize) => {
-                    let item = self.last_ui_item.clone().unwrap();
-                    let bounds: ::window::ScreenRect = euclid::rect(
-                        item.x as isize - (event.coords.x as isize - event.screen_coords.x),
-                        item.y as isize - (event.coords.y as isize - event.screen_coords.y),
-                        item.width as isize,
-                        item.height as isize,
-                    );
-                    context.set_maximize_button_position(bounds);
+                    if let Some(screen_coords) = event.screen_coords {
+                        let item = self.last_ui_item.clone().unwrap();
+                        let bounds: ::window::ScreenRect = euclid::rect(
+                            item.x as isize - (event.coords.x as isize - screen_coords.x),
+                            item.y as isize - (event.coords.y as isize - screen_coords.y),
+                            item.width as isize,
+                            item.height as isize,
+                        );
+                        context.set_maximize_button_position(bounds);
+                    }
                 }
                 TabBarItem::WindowButton(_)
                 | TabBarItem::Tab { .. }
@@ -695,7 +719,12 @@ impl super::TermWindow {
                                 context.invalidate();
                             }
                         }
-                        WMEK::Release(_) | WMEK::HorzWheel(_) => {}
+                        WMEK::Release(_)
+                        | WMEK::HorzWheel(_)
+                        | WMEK::PixelScroll { .. }
+                        | WMEK::ScrollStopped(_)
+                        | WMEK::WheelValue120 { .. }
+                        | WMEK::RelativeMove { .. } => {}
                         WMEK::VertWheel(_) => {
                             // Let wheel events route to the hovered pane,
                             // even if it doesn't have focus
@@ -912,6 +941,10 @@ impl super::TermWindow {
                     button: MouseButton::WheelRight(-amount as usize),
                 },
             }),
+            WMEK::PixelScroll { .. }
+            | WMEK::ScrollStopped(_)
+            | WMEK::WheelValue120 { .. }
+            | WMEK::RelativeMove { .. } => return,
         };
 
         if allow_action {
@@ -990,8 +1023,13 @@ impl super::TermWindow {
 
         let mouse_event = wezterm_term::MouseEvent {
             kind: match event.kind {
-                WMEK::Move => TMEK::Move,
-                WMEK::VertWheel(_) | WMEK::HorzWheel(_) | WMEK::Press(_) => TMEK::Press,
+                WMEK::Move | WMEK::RelativeMove { .. } => TMEK::Move,
+                WMEK::VertWheel(_)
+                | WMEK::HorzWheel(_)
+                | WMEK::PixelScroll { .. }
+                | WMEK::ScrollStopped(_)
+                | WMEK::WheelValue120 { .. }
+                | WMEK::Press(_) => TMEK::Press,
                 WMEK::Release(_) => TMEK::Release,
             },
             button: match event.kind {
@@ -1021,6 +1059,10 @@ impl super::TermWindow {
                         TMB::WheelRight((-amount) as usize)
                     }
                 }
+                WMEK::PixelScroll { .. }
+                | WMEK::ScrollStopped(_)
+                | WMEK::WheelValue120 { .. }
+                | WMEK::RelativeMove { .. } => TMB::None,
             },
             x: column,
             y: row,
@@ -1049,5 +1091,8 @@ fn mouse_press_to_tmb(press: &MousePress) -> TMB {
         MousePress::Left => TMB::Left,
         MousePress::Right => TMB::Right,
         MousePress::Middle => TMB::Middle,
+        MousePress::Back => TMB::Back,
+        MousePress::Forward => TMB::Forward,
+        MousePress::Other(_) => TMB::None,
     }
 }
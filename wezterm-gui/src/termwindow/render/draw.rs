@@ -23,10 +23,11 @@ impl crate::TermWindow {
         let webgpu = self.webgpu.as_mut().unwrap();
         let render_state = self.render_state.as_ref().unwrap();
 
-        let output = webgpu.surface.get_current_texture()?;
-        let view = output
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
+        let output = webgpu.acquire_frame()?;
+        let view = output.texture.create_view(&wgpu::TextureViewDescriptor {
+            format: Some(webgpu.render_format),
+            ..Default::default()
+        });
         let mut encoder = webgpu
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor {
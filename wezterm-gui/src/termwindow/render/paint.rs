@@ -14,7 +14,7 @@ pub enum AllowImage {
 }
 
 impl crate::TermWindow {
-    pub fn paint_impl(&mut self, frame: &mut RenderFrame) {
+    pub fn paint_impl(&mut self, frame: &mut RenderFrame) -> anyhow::Result<()> {
         self.num_frames += 1;
         // If nothing on screen needs animating, then we can avoid
         // invalidating as frequently
@@ -105,7 +105,7 @@ impl crate::TermWindow {
         }
         log::debug!("paint_impl before call_draw elapsed={:?}", start.elapsed());
 
-        self.call_draw(frame).ok();
+        let draw_result = self.call_draw(frame);
         self.last_frame_duration = start.elapsed();
         log::debug!(
             "paint_impl elapsed={:?}, fps={}",
@@ -141,6 +141,8 @@ impl crate::TermWindow {
                 }
             }
         }
+
+        draw_result
     }
 
     pub fn paint_modal(&mut self) -> anyhow::Result<()> {
@@ -302,6 +302,9 @@ impl super::TermWindow {
         self.invalidate_fancy_tab_bar();
         self.update_title();
 
+        // Don't let the window get too small to show a single cell
+        let (min_width, min_height) = ri_calc.min_inner_size();
+        window.set_min_inner_size(min_width, min_height);
         window.set_resize_increments(if self.config.use_resize_increments {
             ri_calc.into()
         } else {
@@ -1,7 +1,9 @@
 use crate::quad::Vertex;
 use anyhow::anyhow;
-use config::{ConfigHandle, GpuInfo, WebGpuPowerPreference};
+use config::{ConfigHandle, GpuInfo, WebGpuPowerPreference, WebGpuPresentMode};
 use std::cell::RefCell;
+use std::rc::{Rc, Weak};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use wgpu::util::DeviceExt;
 use window::bitmaps::Texture2d;
@@ -21,13 +23,35 @@ pub struct ShaderUniform {
     // sampler2D atlas_linear_sampler;
 }
 
+thread_local! {
+    static SHARED_GPU: RefCell<Weak<SharedGpu>> = RefCell::new(Weak::new());
+}
+
+/// The instance, adapter, device and queue are shared by all windows,
+/// rather than each window holding its own device.
+/// Only a weak reference is retained here, so the device is released
+/// when the last window that uses it is closed, which guarantees that
+/// it is torn down before the underlying display connection.
+struct SharedGpu {
+    instance: wgpu::Instance,
+    adapter: wgpu::Adapter,
+    adapter_info: wgpu::AdapterInfo,
+    downlevel_caps: wgpu::DownlevelCapabilities,
+    device: Rc<wgpu::Device>,
+    queue: Arc<wgpu::Queue>,
+    device_lost: Arc<AtomicBool>,
+}
+
 pub struct WebGpuState {
     pub adapter_info: wgpu::AdapterInfo,
     pub downlevel_caps: wgpu::DownlevelCapabilities,
     pub surface: wgpu::Surface<'static>,
-    pub device: wgpu::Device,
+    pub device: Rc<wgpu::Device>,
     pub queue: Arc<wgpu::Queue>,
     pub config: RefCell<wgpu::SurfaceConfiguration>,
+    /// The format of the views of the surface textures that we render
+    /// through, which may differ from the format of the surface itself
+    pub render_format: wgpu::TextureFormat,
     pub dimensions: RefCell<Dimensions>,
     pub render_pipeline: wgpu::RenderPipeline,
     shader_uniform_bind_group_layout: wgpu::BindGroupLayout,
@@ -35,6 +59,7 @@ pub struct WebGpuState {
     pub texture_nearest_sampler: wgpu::Sampler,
     pub texture_linear_sampler: wgpu::Sampler,
     pub handle: RawHandlePair,
+    shared: Rc<SharedGpu>,
 }
 
 pub struct RawHandlePair {
@@ -208,30 +233,88 @@ fn compute_compatibility_list(
         .collect()
 }
 
-impl WebGpuState {
-    pub async fn new(
-        window: &Window,
-        dimensions: Dimensions,
+async fn request_device(
+    adapter: &wgpu::Adapter,
+) -> Result<(wgpu::Device, wgpu::Queue), wgpu::RequestDeviceError> {
+    adapter
+        .request_device(
+            &wgpu::DeviceDescriptor {
+                required_features: wgpu::Features::empty(),
+                // WebGL doesn't support all of wgpu's features, so if
+                // we're building for the web we'll have to disable some.
+                required_limits: if cfg!(target_arch = "wasm32") {
+                    wgpu::Limits::downlevel_webgl2_defaults()
+                } else {
+                    wgpu::Limits::downlevel_defaults()
+                }
+                .using_resolution(adapter.limits()),
+                label: None,
+                memory_hints: Default::default(),
+            },
+            None, // Trace path
+        )
+        .await
+}
+
+impl SharedGpu {
+    /// Returns the shared state along with a surface for the window
+    /// described by handle, creating the shared state if there are no
+    /// other live windows, or if its device has been lost.
+    async fn get_or_create(
+        handle: &RawHandlePair,
         config: &ConfigHandle,
-    ) -> anyhow::Result<Self> {
-        let handle = RawHandlePair::new(window);
-        Self::new_impl(handle, dimensions, config).await
+    ) -> anyhow::Result<(Rc<Self>, wgpu::Surface<'static>)> {
+        if let Some(shared) = SHARED_GPU.with_borrow(|s| s.upgrade()) {
+            if !shared.is_device_lost() {
+                let surface = unsafe {
+                    shared
+                        .instance
+                        .create_surface_unsafe(wgpu::SurfaceTargetUnsafe::from_window(handle)?)?
+                };
+                if shared.adapter.is_surface_supported(&surface) {
+                    return Ok((shared, surface));
+                }
+                // Use a device of our own for this window, but leave
+                // the shared device in place for the others
+                let info = adapter_info_to_gpu_info(shared.adapter_info.clone());
+                log::warn!(
+                    "shared adapter {} is not compatible with this window; \
+                     creating a separate device for it",
+                    info.to_string()
+                );
+                let (shared, surface) = Self::new(handle, config).await?;
+                return Ok((Rc::new(shared), surface));
+            }
+        }
+
+        let (shared, surface) = Self::new(handle, config).await?;
+        let shared = Rc::new(shared);
+        SHARED_GPU.with_borrow_mut(|s| *s = Rc::downgrade(&shared));
+        Ok((shared, surface))
     }
 
-    pub async fn new_impl(
-        handle: RawHandlePair,
-        dimensions: Dimensions,
+    fn is_device_lost(&self) -> bool {
+        self.device_lost.load(Ordering::Acquire)
+    }
+
+    async fn new(
+        handle: &RawHandlePair,
         config: &ConfigHandle,
-    ) -> anyhow::Result<Self> {
+    ) -> anyhow::Result<(Self, wgpu::Surface<'static>)> {
         let backends = wgpu::Backends::all();
         let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
             backends,
             ..Default::default()
         });
         let surface = unsafe {
-            instance.create_surface_unsafe(wgpu::SurfaceTargetUnsafe::from_window(&handle)?)?
+            instance.create_surface_unsafe(wgpu::SurfaceTargetUnsafe::from_window(handle)?)?
         };
 
+        log::debug!(
+            "webgpu adapters:\n{}",
+            compute_compatibility_list(&instance, backends, &surface).join("\n")
+        );
+
         let mut adapter: Option<wgpu::Adapter> = None;
 
         if let Some(preference) = &config.webgpu_preferred_adapter {
@@ -287,6 +370,22 @@ impl WebGpuState {
             }
         }
 
+        if adapter.is_none() {
+            if let Some(name) = &config.webgpu_preferred_adapter_name {
+                let name = name.to_lowercase();
+                adapter = instance.enumerate_adapters(backends).into_iter().find(|a| {
+                    a.is_surface_supported(&surface)
+                        && a.get_info().name.to_lowercase().contains(&name)
+                });
+                if adapter.is_none() {
+                    log::warn!(
+                        "No compatible webgpu adapter matches \
+                         webgpu_preferred_adapter_name '{name}'"
+                    );
+                }
+            }
+        }
+
         if adapter.is_none() {
             adapter = instance
                 .request_adapter(&wgpu::RequestAdapterOptions {
@@ -302,69 +401,177 @@ impl WebGpuState {
                 .await;
         }
 
-        let adapter = adapter.ok_or_else(|| {
+        // Should we be unable to create a device with the selected
+        // adapter, try the others in turn; a working but less
+        // preferable adapter is better than no window at all.
+        // When the fallback adapter was forced, don't fall back to
+        // the hardware adapters.
+        let mut candidates: Vec<wgpu::Adapter> = adapter.into_iter().collect();
+        if !config.webgpu_force_fallback_adapter {
+            for a in instance.enumerate_adapters(backends) {
+                let info = a.get_info();
+                if a.is_surface_supported(&surface)
+                    && !candidates.iter().any(|c| c.get_info() == info)
+                {
+                    candidates.push(a);
+                }
+            }
+        }
+
+        let mut errors = vec![];
+        let mut selected = None;
+        for adapter in candidates {
+            let info = adapter_info_to_gpu_info(adapter.get_info());
+            match request_device(&adapter).await {
+                Ok((device, queue)) => {
+                    selected.replace((adapter, device, queue));
+                    break;
+                }
+                Err(err) => {
+                    log::warn!(
+                        "Failed to create a device with {}: {err:#}",
+                        info.to_string()
+                    );
+                    errors.push(format!("{}: {err:#}", info.to_string()));
+                }
+            }
+        }
+
+        let (adapter, device, queue) = selected.ok_or_else(|| {
             let adapters = compute_compatibility_list(&instance, backends, &surface);
-            anyhow!(
-                "no compatible adapter found. Available:\n{}",
-                adapters.join("\n")
-            )
+            if errors.is_empty() {
+                anyhow!(
+                    "no compatible adapter found. Available:\n{}",
+                    adapters.join("\n")
+                )
+            } else {
+                anyhow!(
+                    "unable to create a device with any compatible adapter:\n{}\nAvailable:\n{}",
+                    errors.join("\n"),
+                    adapters.join("\n")
+                )
+            }
         })?;
 
         let adapter_info = adapter.get_info();
-        log::trace!("Using adapter: {adapter_info:?}");
-        let caps = surface.get_capabilities(&adapter);
-        log::trace!("caps: {caps:?}");
+        log::debug!("Using adapter: {adapter_info:?}");
         let downlevel_caps = adapter.get_downlevel_capabilities();
         log::trace!("downlevel_caps: {downlevel_caps:?}");
 
-        let (device, queue) = adapter
-            .request_device(
-                &wgpu::DeviceDescriptor {
-                    required_features: wgpu::Features::empty(),
-                    // WebGL doesn't support all of wgpu's features, so if
-                    // we're building for the web we'll have to disable some.
-                    required_limits: if cfg!(target_arch = "wasm32") {
-                        wgpu::Limits::downlevel_webgl2_defaults()
-                    } else {
-                        wgpu::Limits::downlevel_defaults()
-                    }
-                    .using_resolution(adapter.limits()),
-                    label: None,
-                    memory_hints: Default::default(),
-                },
-                None, // Trace path
-            )
-            .await?;
-
+        let device = Rc::new(device);
         let queue = Arc::new(queue);
 
-        // Explicitly request an SRGB format, if available
-        let pref_format_srgb = caps.formats[0].add_srgb_suffix();
-        let format = if caps.formats.contains(&pref_format_srgb) {
-            pref_format_srgb
-        } else {
-            caps.formats[0]
-        };
+        let device_lost = Arc::new(AtomicBool::new(false));
+        {
+            let device_lost = Arc::clone(&device_lost);
+            device.set_device_lost_callback(move |reason, message| {
+                log::error!("webgpu device lost: {reason:?} {message}");
+                device_lost.store(true, Ordering::Release);
+            });
+        }
+
+        Ok((
+            Self {
+                instance,
+                adapter,
+                adapter_info,
+                downlevel_caps,
+                device,
+                queue,
+                device_lost,
+            },
+            surface,
+        ))
+    }
+}
+
+/// Choose the format of the surface, and the format of the views of
+/// its textures that we render through.
+/// Our shader produces linear color, so we need to render through an
+/// sRGB view for the GPU to encode it for display. If the surface
+/// doesn't offer an sRGB format, that can still be achieved by viewing
+/// its textures as sRGB.
+fn select_formats(
+    available: &[wgpu::TextureFormat],
+    have_view_formats: bool,
+) -> (wgpu::TextureFormat, wgpu::TextureFormat) {
+    let preferred = available[0];
+    let srgb = preferred.add_srgb_suffix();
+    if available.contains(&srgb) {
+        (srgb, srgb)
+    } else if have_view_formats && srgb.is_srgb() {
+        (preferred, srgb)
+    } else {
+        log::warn!(
+            "No sRGB format is available for the surface (available: {available:?}); \
+             colors will appear darker than they should"
+        );
+        (preferred, preferred)
+    }
+}
+
+impl WebGpuState {
+    pub async fn new(
+        window: &Window,
+        dimensions: Dimensions,
+        config: &ConfigHandle,
+    ) -> anyhow::Result<Self> {
+        let handle = RawHandlePair::new(window);
+        Self::new_impl(handle, dimensions, config).await
+    }
+
+    pub async fn new_impl(
+        handle: RawHandlePair,
+        dimensions: Dimensions,
+        config: &ConfigHandle,
+    ) -> anyhow::Result<Self> {
+        let (shared, surface) = SharedGpu::get_or_create(&handle, config).await?;
+        let device = Rc::clone(&shared.device);
+        let queue = Arc::clone(&shared.queue);
+        let adapter_info = shared.adapter_info.clone();
+        let downlevel_caps = shared.downlevel_caps.clone();
+        let caps = surface.get_capabilities(&shared.adapter);
+        log::trace!("caps: {caps:?}");
 
         // Need to check that this is supported, as trying to set
         // view_formats without it will cause surface.configure
         // to panic
         // <https://github.com/wez/wezterm/issues/3565>
-        let view_formats = if downlevel_caps
+        let have_view_formats = downlevel_caps
             .flags
-            .contains(wgpu::DownlevelFlags::SURFACE_VIEW_FORMATS)
-        {
+            .contains(wgpu::DownlevelFlags::SURFACE_VIEW_FORMATS);
+        let (format, render_format) = select_formats(&caps.formats, have_view_formats);
+        log::debug!("Using surface format {format:?}, rendering as {render_format:?}");
+        let view_formats = if have_view_formats {
             vec![format.add_srgb_suffix(), format.remove_srgb_suffix()]
         } else {
             vec![]
         };
 
+        let requested_present_mode = match config.webgpu_present_mode {
+            WebGpuPresentMode::Fifo => wgpu::PresentMode::Fifo,
+            WebGpuPresentMode::Mailbox => wgpu::PresentMode::Mailbox,
+            WebGpuPresentMode::Immediate => wgpu::PresentMode::Immediate,
+        };
+        // Fifo is the only mode that is guaranteed to be supported
+        let present_mode = if caps.present_modes.contains(&requested_present_mode) {
+            requested_present_mode
+        } else {
+            log::warn!(
+                "webgpu_present_mode {requested_present_mode:?} is not supported \
+                 by this surface (supported: {:?}); using Fifo",
+                caps.present_modes
+            );
+            wgpu::PresentMode::Fifo
+        };
+        log::debug!("Using present mode {present_mode:?}");
+
         let config = wgpu::SurfaceConfiguration {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
             format,
             width: dimensions.pixel_width as u32,
             height: dimensions.pixel_height as u32,
-            present_mode: wgpu::PresentMode::Fifo,
+            present_mode,
             alpha_mode: if caps
                 .alpha_modes
                 .contains(&wgpu::CompositeAlphaMode::PostMultiplied)
@@ -466,7 +673,7 @@ impl WebGpuState {
                 module: &shader,
                 entry_point: "fs_main",
                 targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
+                    format: render_format,
                     blend: Some(wgpu::BlendState::ALPHA_BLENDING),
                     write_mask: wgpu::ColorWrites::ALL,
                 })],
@@ -499,6 +706,7 @@ impl WebGpuState {
             device,
             queue,
             config: RefCell::new(config),
+            render_format,
             dimensions: RefCell::new(dimensions),
             render_pipeline,
             handle,
@@ -506,9 +714,47 @@ impl WebGpuState {
             texture_bind_group_layout,
             texture_nearest_sampler,
             texture_linear_sampler,
+            shared,
         })
     }
 
+    /// Returns true if the driver has reported that the device was lost,
+    /// in which case this state must be discarded and created afresh
+    pub fn is_device_lost(&self) -> bool {
+        self.shared.is_device_lost()
+    }
+
+    /// Reconfigure the surface with the current configuration, which is
+    /// required after the surface reports that it is lost or outdated
+    fn reconfigure(&self) {
+        let config = self.config.borrow();
+        if config.width > 0 && config.height > 0 {
+            self.surface.configure(&self.device, &config);
+        }
+    }
+
+    /// Obtain the texture to render the next frame into.
+    /// If the surface is lost or outdated (eg: its size no longer
+    /// matches that of the window), it is reconfigured from our
+    /// configuration, which `resize` keeps up to date, and we try
+    /// once more.
+    pub fn acquire_frame(&self) -> Result<wgpu::SurfaceTexture, wgpu::SurfaceError> {
+        match self.surface.get_current_texture() {
+            Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                log::debug!("surface is lost or outdated; reconfiguring");
+                self.reconfigure();
+                self.surface.get_current_texture()
+            }
+            result => result,
+        }
+    }
+
+    /// Returns the present mode that is actually in use, which may
+    /// differ from the configured webgpu_present_mode
+    pub fn present_mode(&self) -> wgpu::PresentMode {
+        self.config.borrow().present_mode
+    }
+
     pub fn create_uniform(&self, uniform: ShaderUniform) -> wgpu::BindGroup {
         let buffer = self
             .device
@@ -559,3 +805,32 @@ impl WebGpuState {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use wgpu::TextureFormat;
+
+    #[test]
+    fn formats() {
+        assert_eq!(
+            select_formats(
+                &[TextureFormat::Bgra8Unorm, TextureFormat::Bgra8UnormSrgb],
+                false
+            ),
+            (TextureFormat::Bgra8UnormSrgb, TextureFormat::Bgra8UnormSrgb)
+        );
+        assert_eq!(
+            select_formats(&[TextureFormat::Bgra8Unorm], true),
+            (TextureFormat::Bgra8Unorm, TextureFormat::Bgra8UnormSrgb)
+        );
+        assert_eq!(
+            select_formats(&[TextureFormat::Bgra8Unorm], false),
+            (TextureFormat::Bgra8Unorm, TextureFormat::Bgra8Unorm)
+        );
+        assert_eq!(
+            select_formats(&[TextureFormat::Rgb10a2Unorm], true),
+            (TextureFormat::Rgb10a2Unorm, TextureFormat::Rgb10a2Unorm)
+        );
+    }
+}
@@ -25,6 +25,9 @@ pub enum KeyCode {
     Composed(String),
     RawCode(u32),
     Physical(PhysKeyCode),
+    /// A key that has no other representation here, holding the
+    /// windowing system's symbol for it; an X11/xkb keysym on Unix
+    Unknown(u32),
 
     Hyper,
     Super,
@@ -98,6 +101,10 @@ pub enum KeyCode {
     MediaPrevTrack,
     MediaStop,
     MediaPlayPause,
+    BrightnessUp,
+    BrightnessDown,
+    /// The Multi key used to start a compose sequence
+    Compose,
     ApplicationLeftArrow,
     ApplicationRightArrow,
     ApplicationUpArrow,
@@ -136,6 +143,28 @@ impl KeyCode {
         normalize_shift(self.clone(), modifiers)
     }
 
+    /// When this key is the text that a keypress produced, as opposed
+    /// to the unmodified key, returns the modifiers that the text already
+    /// reflects: SHIFT and ALT (eg: AltGr or Option composition) for
+    /// characters, and also CTRL for control characters.
+    /// Other keys, such as the arrow keys, reflect no modifiers, and
+    /// SUPER is never reflected.
+    pub fn modifiers_reflected_in_text(&self) -> Modifiers {
+        let shift_alt = Modifiers::SHIFT
+            | Modifiers::LEFT_SHIFT
+            | Modifiers::RIGHT_SHIFT
+            | Modifiers::ALT
+            | Modifiers::LEFT_ALT
+            | Modifiers::RIGHT_ALT;
+        match self {
+            Self::Char(c) if is_ascii_control(*c).is_some() => {
+                shift_alt | Modifiers::CTRL | Modifiers::LEFT_CTRL | Modifiers::RIGHT_CTRL
+            }
+            Self::Char(_) | Self::Composed(_) => shift_alt,
+            _ => Modifiers::NONE,
+        }
+    }
+
     pub fn composed(s: &str) -> Self {
         // Prefer to send along a single Char when the string
         // is just a single char, as the keymapping layer cannot
@@ -282,6 +311,9 @@ impl KeyCode {
             | Self::MediaPrevTrack
             | Self::MediaStop
             | Self::MediaPlayPause
+            | Self::BrightnessUp
+            | Self::BrightnessDown
+            | Self::Compose
             | Self::Copy
             | Self::Cut
             | Self::Paste
@@ -308,6 +340,7 @@ impl KeyCode {
             | Self::Meta
             | Self::Composed(_)
             | Self::RawCode(_)
+            | Self::Unknown(_)
             | Self::Char(_)
             | Self::Numpad(_)
             | Self::Function(_) => return None,
@@ -390,6 +423,9 @@ impl TryFrom<&str> for KeyCode {
             MediaPrevTrack,
             MediaStop,
             MediaPlayPause,
+            BrightnessUp,
+            BrightnessDown,
+            Compose,
             ApplicationLeftArrow,
             ApplicationRightArrow,
             ApplicationUpArrow,
@@ -442,6 +478,7 @@ impl ToString for KeyCode {
     fn to_string(&self) -> String {
         match self {
             Self::RawCode(n) => format!("raw:{}", n),
+            Self::Unknown(n) => format!("unknown:{}", n),
             Self::Char(c) => format!("mapped:{}", c),
             Self::Physical(phys) => phys.to_string(),
             Self::Composed(s) => s.to_string(),
@@ -1177,6 +1214,12 @@ bitflags! {
         const MIDDLE = 1<<2;
         const X1 = 1<<3;
         const X2 = 1<<4;
+        /// The "back" thumb button; the same as X1
+        const BACK = 1<<3;
+        /// The "forward" thumb button; the same as X2
+        const FORWARD = 1<<4;
+        /// Any button that is reported as MousePress::Other
+        const OTHER = 1<<5;
     }
 }
 
@@ -1185,26 +1228,131 @@ pub enum MousePress {
     Left,
     Right,
     Middle,
+    /// The "back" thumb button
+    Back,
+    /// The "forward" thumb button
+    Forward,
+    /// Some other button, identified by the button code
+    /// used by the windowing system
+    Other(u16),
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+impl MousePress {
+    /// Returns the MouseButtons bit that corresponds to this button
+    pub fn button_mask(self) -> MouseButtons {
+        match self {
+            Self::Left => MouseButtons::LEFT,
+            Self::Right => MouseButtons::RIGHT,
+            Self::Middle => MouseButtons::MIDDLE,
+            Self::Back => MouseButtons::BACK,
+            Self::Forward => MouseButtons::FORWARD,
+            Self::Other(_) => MouseButtons::OTHER,
+        }
+    }
+}
+
+/// The kind of input device that produced a scroll.
+/// Only reported on Wayland; X11 scrolls arrive as wheel button
+/// presses and only produce VertWheel and HorzWheel events.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ScrollSource {
+    /// A mouse wheel with discrete steps of motion
+    Wheel,
+    /// Fingers on a touchpad or touchscreen.  A ScrollStopped event
+    /// is produced when the fingers are lifted.
+    Finger,
+    /// Continuous motion that isn't produced by fingers, such as
+    /// scrolling with a trackball
+    Continuous,
+    /// Sideways tilting of a mouse wheel
+    WheelTilt,
+    /// The window system doesn't say
+    Unknown,
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum MouseEventKind {
     Move,
     Press(MousePress),
     Release(MousePress),
     VertWheel(i16),
     HorzWheel(i16),
+    /// A precise scroll, such as from a touchpad.  The deltas are
+    /// measured in pixels and use the same sign convention as
+    /// VertWheel and HorzWheel.  Backends that produce this also
+    /// produce VertWheel/HorzWheel events for each whole unit that
+    /// accumulates, so consumers can ignore this if they don't
+    /// support smooth scrolling.
+    PixelScroll {
+        horizontal: f64,
+        vertical: f64,
+        source: ScrollSource,
+    },
+    /// A scroll sequence has ended, for example because the fingers
+    /// were lifted from the touchpad.  Applications that implement
+    /// kinetic scrolling can use this to start their fling animation.
+    ScrollStopped(ScrollSource),
+    /// Relative pointer motion, measured in pixels, reported while the
+    /// pointer is locked or confined by the window.  The deltas are
+    /// reported even when the pointer itself cannot move.
+    /// On Wayland they are not subject to pointer acceleration.
+    /// On X11 they are the movement of the core pointer between
+    /// motion events, so they are accelerated.
+    RelativeMove {
+        dx: f64,
+        dy: f64,
+    },
+    /// Movement of a high resolution scroll wheel, measured in 1/120ths
+    /// of a detent, using the same sign convention as VertWheel and
+    /// HorzWheel.  Backends that produce this also produce
+    /// VertWheel/HorzWheel events once a whole detent accumulates.
+    WheelValue120 {
+        horizontal: i32,
+        vertical: i32,
+    },
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct MouseEvent {
     pub kind: MouseEventKind,
     /// Coordinates of the mouse relative to the top left of the window
     pub coords: Point,
-    /// The mouse position in screen coordinates
-    pub screen_coords: crate::ScreenPoint,
+    /// The mouse position in screen coordinates, or None if the backend
+    /// doesn't know where the window is on the screen.
+    /// X11, Windows and macOS report it. Wayland never does, because
+    /// compositors don't reveal the position of windows.
+    pub screen_coords: Option<crate::ScreenPoint>,
     pub mouse_buttons: MouseButtons,
     pub modifiers: Modifiers,
+    /// For Press events, the number of consecutive presses of the same
+    /// button in about the same place, each within the click interval
+    /// of the last: 1 for a single click, 2 for a double click and so on.
+    /// Zero for other kinds of event.
+    pub streak: u16,
+    /// When the event occurred, in milliseconds, as reported by the
+    /// window system.  Only the difference between the times of two
+    /// events is meaningful.  Zero if the backend doesn't report it.
+    pub time: u32,
+    /// Identifies a button press or release to the window system
+    pub serial: Option<InputSerial>,
+}
+
+/// Identifies an input event to the window system, so that requests
+/// that it only honors in response to user input, such as starting an
+/// interactive move, can show which event prompted them.
+/// The value is only meaningful to the backend that produced it; on
+/// Wayland it is the event serial and on X11 the server timestamp.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct InputSerial(u32);
+
+impl InputSerial {
+    pub fn new(value: u32) -> Self {
+        Self(value)
+    }
+
+    pub fn value(self) -> u32 {
+        self.0
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -1421,6 +1569,20 @@ pub struct KeyEvent {
     /// If true, this is a key down rather than a key up event
     pub key_is_down: bool,
 
+    /// The physical location of the key on an ANSI-Standard US layout,
+    /// if known. Unlike raw_code, this is the same for a given key
+    /// regardless of the backend or the keyboard layout.
+    pub phys: Option<PhysKeyCode>,
+
+    /// When the key was pressed or released, in milliseconds, as
+    /// reported by the window system.  Only the difference between the
+    /// times of two events is meaningful.  Zero if the backend doesn't
+    /// report it.
+    pub time: u32,
+
+    /// Identifies the event to the window system
+    pub serial: Option<InputSerial>,
+
     /// If triggered from a raw key event, here it is.
     pub raw: Option<RawKeyEvent>,
 
@@ -2217,6 +2379,31 @@ impl Default for UIKeyCapRendering {
 mod test {
     use super::*;
 
+    #[test]
+    fn modifiers_reflected_in_text() {
+        // CTRL-A produces \x01
+        assert_eq!(
+            KeyCode::Char('\x01').modifiers_reflected_in_text() & Modifiers::CTRL,
+            Modifiers::CTRL
+        );
+        // SHIFT-Tab produces \x19
+        assert!(KeyCode::Char('\x19')
+            .modifiers_reflected_in_text()
+            .contains(Modifiers::SHIFT | Modifiers::CTRL));
+        // AltGr or Option composition
+        let reflected = KeyCode::Char('é').modifiers_reflected_in_text();
+        assert!(reflected.contains(Modifiers::SHIFT | Modifiers::ALT | Modifiers::RIGHT_ALT));
+        assert!(!reflected.intersects(Modifiers::CTRL | Modifiers::SUPER));
+        assert_eq!(
+            KeyCode::composed("ü").modifiers_reflected_in_text(),
+            reflected
+        );
+        // CTRL-SHIFT-arrow, SUPER-PageUp
+        for key in [KeyCode::LeftArrow, KeyCode::PageUp, KeyCode::Function(5)] {
+            assert_eq!(key.modifiers_reflected_in_text(), Modifiers::NONE);
+        }
+    }
+
     #[test]
     fn encode_issue_3220() {
         let flags =
@@ -2229,6 +2416,9 @@ mod test {
                 leds: KeyboardLedStatus::empty(),
                 repeat_count: 1,
                 key_is_down: true,
+                phys: None,
+                time: 0,
+                serial: None,
                 raw: None,
                 #[cfg(windows)]
                 win32_uni_char: None,
@@ -2243,6 +2433,9 @@ mod test {
                 leds: KeyboardLedStatus::empty(),
                 repeat_count: 1,
                 key_is_down: false,
+                phys: None,
+                time: 0,
+                serial: None,
                 raw: None,
                 #[cfg(windows)]
                 win32_uni_char: None,
@@ -2252,6 +2445,28 @@ mod test {
         );
     }
 
+    #[test]
+    fn normalize_shift_keeps_phys() {
+        let event = KeyEvent {
+            key: KeyCode::Char('z'),
+            modifiers: Modifiers::SHIFT,
+            leds: KeyboardLedStatus::empty(),
+            repeat_count: 1,
+            key_is_down: true,
+            phys: Some(PhysKeyCode::Z),
+            time: 0,
+            serial: None,
+            raw: None,
+            #[cfg(windows)]
+            win32_uni_char: None,
+        }
+        .normalize_shift();
+
+        assert_eq!(event.key, KeyCode::Char('Z'));
+        assert_eq!(event.modifiers, Modifiers::NONE);
+        assert_eq!(event.phys, Some(PhysKeyCode::Z));
+    }
+
     #[test]
     fn encode_issue_3473() {
         let flags = KittyKeyboardFlags::DISAMBIGUATE_ESCAPE_CODES
@@ -2266,6 +2481,9 @@ mod test {
                 leds: KeyboardLedStatus::empty(),
                 repeat_count: 1,
                 key_is_down: true,
+                phys: None,
+                time: 0,
+                serial: None,
                 raw: None,
                 #[cfg(windows)]
                 win32_uni_char: None,
@@ -2280,6 +2498,9 @@ mod test {
                 leds: KeyboardLedStatus::empty(),
                 repeat_count: 1,
                 key_is_down: false,
+                phys: None,
+                time: 0,
+                serial: None,
                 raw: None,
                 #[cfg(windows)]
                 win32_uni_char: None,
@@ -2300,6 +2521,9 @@ mod test {
                 leds: KeyboardLedStatus::empty(),
                 repeat_count: 1,
                 key_is_down: true,
+                phys: None,
+                time: 0,
+                serial: None,
                 raw: None,
                 #[cfg(windows)]
                 win32_uni_char: None,
@@ -2314,6 +2538,9 @@ mod test {
                 leds: KeyboardLedStatus::empty(),
                 repeat_count: 1,
                 key_is_down: true,
+                phys: None,
+                time: 0,
+                serial: None,
                 raw: None,
                 #[cfg(windows)]
                 win32_uni_char: None,
@@ -2329,6 +2556,9 @@ mod test {
                 leds: KeyboardLedStatus::empty(),
                 repeat_count: 1,
                 key_is_down: true,
+                phys: None,
+                time: 0,
+                serial: None,
                 raw: None,
                 #[cfg(windows)]
                 win32_uni_char: None,
@@ -2345,6 +2575,9 @@ mod test {
                     leds: KeyboardLedStatus::empty(),
                     repeat_count: 1,
                     key_is_down: true,
+                    phys: None,
+                    time: 0,
+                    serial: None,
                     raw: None,
                     #[cfg(windows)]
                     win32_uni_char: None,
@@ -2362,6 +2595,9 @@ mod test {
                 leds: KeyboardLedStatus::empty(),
                 repeat_count: 1,
                 key_is_down: true,
+                phys: None,
+                time: 0,
+                serial: None,
                 raw: None,
                 #[cfg(windows)]
                 win32_uni_char: None,
@@ -2376,6 +2612,9 @@ mod test {
                 leds: KeyboardLedStatus::empty(),
                 repeat_count: 1,
                 key_is_down: true,
+                phys: None,
+                time: 0,
+                serial: None,
                 raw: None,
                 #[cfg(windows)]
                 win32_uni_char: None,
@@ -2391,6 +2630,9 @@ mod test {
                 leds: KeyboardLedStatus::empty(),
                 repeat_count: 1,
                 key_is_down: true,
+                phys: None,
+                time: 0,
+                serial: None,
                 raw: Some(RawKeyEvent {
                     key: KeyCode::Char('I'),
                     modifiers: Modifiers::SHIFT | Modifiers::CTRL,
@@ -2417,6 +2659,9 @@ mod test {
                 leds: KeyboardLedStatus::empty(),
                 repeat_count: 1,
                 key_is_down: true,
+                phys: None,
+                time: 0,
+                serial: None,
                 raw: None,
                 #[cfg(windows)]
                 win32_uni_char: None,
@@ -2431,6 +2676,9 @@ mod test {
                 leds: KeyboardLedStatus::empty(),
                 repeat_count: 1,
                 key_is_down: true,
+                phys: None,
+                time: 0,
+                serial: None,
                 raw: None,
                 #[cfg(windows)]
                 win32_uni_char: None,
@@ -2446,6 +2694,9 @@ mod test {
                 leds: KeyboardLedStatus::empty(),
                 repeat_count: 1,
                 key_is_down: true,
+                phys: None,
+                time: 0,
+                serial: None,
                 raw: None,
                 #[cfg(windows)]
                 win32_uni_char: None,
@@ -2461,6 +2712,9 @@ mod test {
                 leds: KeyboardLedStatus::empty(),
                 repeat_count: 1,
                 key_is_down: true,
+                phys: None,
+                time: 0,
+                serial: None,
                 raw: None,
                 #[cfg(windows)]
                 win32_uni_char: None,
@@ -2484,6 +2738,9 @@ mod test {
                 leds: KeyboardLedStatus::empty(),
                 repeat_count: 1,
                 key_is_down: true,
+                phys: None,
+                time: 0,
+                serial: None,
                 raw: None,
                 #[cfg(windows)]
                 win32_uni_char: None,
@@ -2498,6 +2755,9 @@ mod test {
                 leds: KeyboardLedStatus::empty(),
                 repeat_count: 1,
                 key_is_down: false,
+                phys: None,
+                time: 0,
+                serial: None,
                 raw: None,
                 #[cfg(windows)]
                 win32_uni_char: None,
@@ -2544,6 +2804,9 @@ mod test {
                     leds: KeyboardLedStatus::empty(),
                     repeat_count: 1,
                     key_is_down: true,
+                    phys: None,
+                    time: 0,
+                    serial: None,
                     raw: None,
                     #[cfg(windows)]
                     win32_uni_char: None,
@@ -2561,6 +2824,9 @@ mod test {
                     leds: KeyboardLedStatus::empty(),
                     repeat_count: 1,
                     key_is_down: false,
+                    phys: None,
+                    time: 0,
+                    serial: None,
                     raw: None,
                     #[cfg(windows)]
                     win32_uni_char: None,
@@ -2578,6 +2844,9 @@ mod test {
                     leds: KeyboardLedStatus::empty(),
                     repeat_count: 1,
                     key_is_down: true,
+                    phys: None,
+                    time: 0,
+                    serial: None,
                     raw: None,
                     #[cfg(windows)]
                     win32_uni_char: None,
@@ -2595,6 +2864,9 @@ mod test {
                     leds: KeyboardLedStatus::empty(),
                     repeat_count: 1,
                     key_is_down: false,
+                    phys: None,
+                    time: 0,
+                    serial: None,
                     raw: None,
                     #[cfg(windows)]
                     win32_uni_char: None,
@@ -2621,6 +2893,9 @@ mod test {
                     leds: KeyboardLedStatus::empty(),
                     repeat_count: 1,
                     key_is_down: true,
+                    phys: None,
+                    time: 0,
+                    serial: None,
                     raw: None,
                     #[cfg(windows)]
                     win32_uni_char: None,
@@ -2638,6 +2913,9 @@ mod test {
                     leds: KeyboardLedStatus::empty(),
                     repeat_count: 1,
                     key_is_down: true,
+                    phys: None,
+                    time: 0,
+                    serial: None,
                     raw: None,
                     #[cfg(windows)]
                     win32_uni_char: None,
@@ -2656,6 +2934,9 @@ mod test {
                     leds: KeyboardLedStatus::empty(),
                     repeat_count: 1,
                     key_is_down: true,
+                    phys: None,
+                    time: 0,
+                    serial: None,
                     raw: None,
                     #[cfg(windows)]
                     win32_uni_char: None,
@@ -2673,6 +2954,9 @@ mod test {
                     leds: KeyboardLedStatus::empty(),
                     repeat_count: 1,
                     key_is_down: true,
+                    phys: None,
+                    time: 0,
+                    serial: None,
                     raw: None,
                     #[cfg(windows)]
                     win32_uni_char: None,
@@ -2691,6 +2975,9 @@ mod test {
                     leds: KeyboardLedStatus::NUM_LOCK,
                     repeat_count: 1,
                     key_is_down: true,
+                    phys: None,
+                    time: 0,
+                    serial: None,
                     raw: None,
                     #[cfg(windows)]
                     win32_uni_char: None,
@@ -2708,6 +2995,9 @@ mod test {
                     leds: KeyboardLedStatus::NUM_LOCK,
                     repeat_count: 1,
                     key_is_down: true,
+                    phys: None,
+                    time: 0,
+                    serial: None,
                     raw: None,
                     #[cfg(windows)]
                     win32_uni_char: None,
@@ -2726,6 +3016,9 @@ mod test {
                     leds: KeyboardLedStatus::NUM_LOCK,
                     repeat_count: 1,
                     key_is_down: true,
+                    phys: None,
+                    time: 0,
+                    serial: None,
                     raw: None,
                     #[cfg(windows)]
                     win32_uni_char: None,
@@ -2744,6 +3037,9 @@ mod test {
                     leds: KeyboardLedStatus::empty(),
                     repeat_count: 1,
                     key_is_down: true,
+                    phys: None,
+                    time: 0,
+                    serial: None,
                     raw: None,
                     #[cfg(windows)]
                     win32_uni_char: None,
@@ -2771,6 +3067,9 @@ mod test {
                     leds: KeyboardLedStatus::NUM_LOCK,
                     repeat_count: 1,
                     key_is_down: true,
+                    phys: None,
+                    time: 0,
+                    serial: None,
                     raw: None,
                     #[cfg(windows)]
                     win32_uni_char: None,
@@ -2788,6 +3087,9 @@ mod test {
                     leds: KeyboardLedStatus::NUM_LOCK,
                     repeat_count: 1,
                     key_is_down: false,
+                    phys: None,
+                    time: 0,
+                    serial: None,
                     raw: None,
                     #[cfg(windows)]
                     win32_uni_char: None,
@@ -2806,6 +3108,9 @@ mod test {
                     leds: KeyboardLedStatus::empty(),
                     repeat_count: 1,
                     key_is_down: true,
+                    phys: None,
+                    time: 0,
+                    serial: None,
                     raw: None,
                     #[cfg(windows)]
                     win32_uni_char: None,
@@ -2824,6 +3129,9 @@ mod test {
                     leds: KeyboardLedStatus::empty(),
                     repeat_count: 1,
                     key_is_down: false,
+                    phys: None,
+                    time: 0,
+                    serial: None,
                     raw: None,
                     #[cfg(windows)]
                     win32_uni_char: None,
@@ -2846,6 +3154,9 @@ mod test {
                 leds: KeyboardLedStatus::empty(),
                 repeat_count: 1,
                 key_is_down: true,
+                phys: None,
+                time: 0,
+                serial: None,
                 raw: None,
                 #[cfg(windows)]
                 win32_uni_char: None,
@@ -2861,6 +3172,9 @@ mod test {
                 leds: KeyboardLedStatus::empty(),
                 repeat_count: 1,
                 key_is_down: true,
+                phys: None,
+                time: 0,
+                serial: None,
                 raw: None,
                 #[cfg(windows)]
                 win32_uni_char: None,
@@ -2876,6 +3190,9 @@ mod test {
                 leds: KeyboardLedStatus::empty(),
                 repeat_count: 1,
                 key_is_down: true,
+                phys: None,
+                time: 0,
+                serial: None,
                 raw: None,
                 #[cfg(windows)]
                 win32_uni_char: None,
@@ -2891,6 +3208,9 @@ mod test {
                 leds: KeyboardLedStatus::empty(),
                 repeat_count: 1,
                 key_is_down: true,
+                phys: None,
+                time: 0,
+                serial: None,
                 raw: None,
                 #[cfg(windows)]
                 win32_uni_char: None,
@@ -2915,6 +3235,9 @@ mod test {
                     leds: KeyboardLedStatus::empty(),
                     repeat_count: 1,
                     key_is_down: true,
+                    phys: None,
+                    time: 0,
+                    serial: None,
                     raw: None,
                     #[cfg(windows)]
                     win32_uni_char: None,
@@ -2933,6 +3256,9 @@ mod test {
                     leds: KeyboardLedStatus::empty(),
                     repeat_count: 1,
                     key_is_down: true,
+                    phys: None,
+                    time: 0,
+                    serial: None,
                     raw: None,
                     #[cfg(windows)]
                     win32_uni_char: None,
@@ -2960,6 +3286,9 @@ mod test {
                     leds: KeyboardLedStatus::empty(),
                     repeat_count: 1,
                     key_is_down: true,
+                    phys: None,
+                    time: 0,
+                    serial: None,
                     raw: None,
                     #[cfg(windows)]
                     win32_uni_char: None,
@@ -2978,6 +3307,9 @@ mod test {
                     leds: KeyboardLedStatus::empty(),
                     repeat_count: 1,
                     key_is_down: true,
+                    phys: None,
+                    time: 0,
+                    serial: None,
                     raw: None,
                     #[cfg(windows)]
                     win32_uni_char: None,
@@ -3000,6 +3332,9 @@ mod test {
                 leds: KeyboardLedStatus::NUM_LOCK,
                 repeat_count: 1,
                 key_is_down: true,
+                phys: None,
+                time: 0,
+                serial: None,
                 raw: None,
                 #[cfg(windows)]
                 win32_uni_char: None,
@@ -3015,6 +3350,9 @@ mod test {
                 leds: KeyboardLedStatus::CAPS_LOCK,
                 repeat_count: 1,
                 key_is_down: true,
+                phys: None,
+                time: 0,
+                serial: None,
                 raw: None,
                 #[cfg(windows)]
                 win32_uni_char: None,
@@ -3031,6 +3369,9 @@ mod test {
                     leds: KeyboardLedStatus::empty(),
                     repeat_count: 1,
                     key_is_down: true,
+                    phys: None,
+                    time: 0,
+                    serial: None,
                     raw: None,
                     #[cfg(windows)]
                     win32_uni_char: None,
@@ -3049,6 +3390,9 @@ mod test {
                     leds: KeyboardLedStatus::empty(),
                     repeat_count: 1,
                     key_is_down: true,
+                    phys: None,
+                    time: 0,
+                    serial: None,
                     raw: None,
                     #[cfg(windows)]
                     win32_uni_char: None,
@@ -3071,6 +3415,9 @@ mod test {
                 leds: KeyboardLedStatus::empty(),
                 repeat_count: 1,
                 key_is_down: true,
+                phys: None,
+                time: 0,
+                serial: None,
                 raw: None,
                 #[cfg(windows)]
                 win32_uni_char: None,
@@ -3086,6 +3433,9 @@ mod test {
                 leds: KeyboardLedStatus::empty(),
                 repeat_count: 1,
                 key_is_down: true,
+                phys: None,
+                time: 0,
+                serial: None,
                 raw: None,
                 #[cfg(windows)]
                 win32_uni_char: None,
@@ -3101,6 +3451,9 @@ mod test {
                 leds: KeyboardLedStatus::empty(),
                 repeat_count: 1,
                 key_is_down: true,
+                phys: None,
+                time: 0,
+                serial: None,
                 raw: None,
                 #[cfg(windows)]
                 win32_uni_char: None,
@@ -3116,6 +3469,9 @@ mod test {
                 leds: KeyboardLedStatus::empty(),
                 repeat_count: 1,
                 key_is_down: true,
+                phys: None,
+                time: 0,
+                serial: None,
                 raw: None,
                 #[cfg(windows)]
                 win32_uni_char: None,
@@ -10,6 +10,10 @@ struct MyWindow {
     cursor_pos: Point,
     dims: Dimensions,
     gl: Option<Rc<glium::backend::Context>>,
+    // Set when rendering with the CPU rather than OpenGL
+    software: bool,
+    // The other window, if any; pressing 'f' focuses it
+    other: Rc<RefCell<Option<Window>>>,
 }
 
 impl Drop for MyWindow {
@@ -21,12 +25,13 @@ impl Drop for MyWindow {
 impl MyWindow {
     fn dispatch(&mut self, event: WindowEvent, win: &Window) {
         match dbg!(event) {
-            WindowEvent::CloseRequested => {
+            WindowEvent::CloseRequested(request) => {
                 eprintln!("can I close?");
                 if self.allow_close {
-                    win.close();
+                    request.allow();
                 } else {
                     self.allow_close = true;
+                    request.deny();
                 }
             }
             WindowEvent::Destroyed => {
@@ -46,21 +51,70 @@ impl MyWindow {
             }
             WindowEvent::MouseEvent(event) => {
                 self.cursor_pos = event.coords;
-                // win.invalidate();
+                win.request_redraw();
                 win.set_cursor(Some(MouseCursor::Arrow));
 
-                if event.kind == MouseEventKind::Press(MousePress::Left) {
-                    eprintln!("{:?}", event);
+                match event.kind {
+                    MouseEventKind::Press(
+                        MousePress::Left | MousePress::Back | MousePress::Forward,
+                    ) => {
+                        eprintln!("{:?}", event);
+                    }
+                    _ => {}
                 }
             }
+            WindowEvent::MouseEnter(coords) => {
+                eprintln!("mouse entered at {:?}", coords);
+            }
+            WindowEvent::MouseLeave => {
+                eprintln!("mouse left");
+            }
+            WindowEvent::Touch(touch) => {
+                eprintln!("{:?}", touch);
+            }
+            WindowEvent::Gesture(gesture) => {
+                eprintln!("{:?}", gesture);
+            }
             WindowEvent::KeyEvent(key) => {
                 eprintln!("{:?}", key);
                 win.set_cursor(Some(MouseCursor::Text));
+
+                if key.key_is_down && key.key == KeyCode::Char('h') {
+                    // Demonstrate hiding the window and showing it again
+                    eprintln!("hiding for 2 seconds");
+                    win.hide();
+                    let win = win.clone();
+                    spawn(async move {
+                        async_io::Timer::after(std::time::Duration::from_secs(2)).await;
+                        eprintln!("showing again");
+                        win.show();
+                    })
+                    .detach();
+                }
+
+                if key.key_is_down && key.key == KeyCode::Char('f') {
+                    if let Some(other) = self.other.borrow().clone() {
+                        spawn(async move {
+                            match other.focus(None).await {
+                                Ok(outcome) => eprintln!("focus other window: {:?}", outcome),
+                                Err(err) => eprintln!("failed to focus other window: {:#}", err),
+                            }
+                        })
+                        .detach();
+                    }
+                }
             }
             WindowEvent::RawKeyEvent(key) => {
                 eprintln!("{:?}", key);
                 win.set_cursor(Some(MouseCursor::Text));
             }
+            WindowEvent::FocusChanged(focused, _) => {
+                // Demonstrate dimming the window while it is inactive
+                win.set_window_opacity(if focused { 1.0 } else { 0.7 });
+            }
+            WindowEvent::NeedRepaintRegions(_) => {}
+            WindowEvent::FramePresented(_) => {}
+            WindowEvent::AnimationFrame(_) => {}
             WindowEvent::NeedRepaint => {
                 if let Some(gl) = self.gl.as_mut() {
                     if gl.is_context_lost() {
@@ -76,25 +130,43 @@ impl MyWindow {
                     use glium::Surface;
                     frame.clear_color_srgb(0.25, 0.125, 0.375, 1.0);
                     win.finish_frame(frame).unwrap();
+                } else if self.software {
+                    let mut image = Image::new(self.dims.pixel_width, self.dims.pixel_height);
+                    image.clear(::window::color::SrgbaPixel::rgba(0x40, 0x20, 0x60, 0xff));
+                    let future = win.present_software(image);
+                    spawn(async move {
+                        if let Err(err) = future.await {
+                            eprintln!("failed to present: {:#}", err);
+                        }
+                    })
+                    .detach();
                 }
             }
             WindowEvent::AppearanceChanged(_)
             | WindowEvent::AdviseDeadKeyStatus(_)
             | WindowEvent::AdviseModifiersLedStatus(_, _)
             | WindowEvent::Notification(_)
-            | WindowEvent::FocusChanged(_)
             | WindowEvent::DraggedFile(_)
             | WindowEvent::DroppedFile(_)
             | WindowEvent::DroppedUrl(_)
             | WindowEvent::DroppedString(_)
             | WindowEvent::PerformKeyAssignment(_)
-            | WindowEvent::MouseLeave
+            | WindowEvent::ScreenChanged(_)
+            | WindowEvent::Moved(_)
+            | WindowEvent::WmCapabilitiesChanged { .. }
+            | WindowEvent::UiSettingsChanged(_)
+            | WindowEvent::KeyboardLayoutChanged(_)
+            | WindowEvent::UntranslatedKeyEvent(_)
+            | WindowEvent::VisibilityChanged(_)
             | WindowEvent::SetInnerSizeCompleted => {}
         }
     }
 }
 
-async fn spawn_window() -> Result<(), Box<dyn std::error::Error>> {
+async fn spawn_window(
+    title: &str,
+    other: Rc<RefCell<Option<Window>>>,
+) -> Result<Window, Box<dyn std::error::Error>> {
     let fontconfig = Rc::new(FontConfiguration::new(
         None,
         ::window::default_dpi() as usize,
@@ -109,12 +181,14 @@ async fn spawn_window() -> Result<(), Box<dyn std::error::Error>> {
             dpi: 0,
         },
         gl: None,
+        software: false,
+        other,
     }));
 
     let cb_state = Rc::clone(&state);
-    let win = Window::new_window(
+    let win = Window::new_window_with_options(
         "myclass",
-        "the title",
+        title,
         RequestedWindowGeometry {
             width: Dimension::Pixels(800.),
             height: Dimension::Pixels(600.),
@@ -122,6 +196,10 @@ async fn spawn_window() -> Result<(), Box<dyn std::error::Error>> {
         },
         None,
         fontconfig,
+        WindowOptions {
+            initial_state: InitialWindowState::Maximized,
+            ..Default::default()
+        },
         move |event, window| {
             let mut state = cb_state.borrow_mut();
             state.dispatch(event, window)
@@ -131,18 +209,100 @@ async fn spawn_window() -> Result<(), Box<dyn std::error::Error>> {
 
     eprintln!("before show");
     win.show();
-    let gl = win.enable_opengl().await?;
 
-    state.borrow_mut().gl.replace(gl);
+    #[cfg(all(unix, not(target_os = "macos")))]
+    if let Window::Headless(headless) = &win {
+        drive_headless(headless).await?;
+        return Ok(win);
+    }
+
+    // Pass --software to render with the CPU, as we also do
+    // if there is no usable OpenGL implementation
+    let gl = if std::env::args().any(|arg| arg == "--software") {
+        None
+    } else {
+        match win.enable_opengl().await {
+            Ok(gl) => Some(gl),
+            Err(err) => {
+                eprintln!(
+                    "OpenGL is unavailable, so rendering with the CPU: {:#}",
+                    err
+                );
+                None
+            }
+        }
+    };
+
+    let mut state = state.borrow_mut();
+    state.software = gl.is_none();
+    state.gl = gl;
+    drop(state);
     win.invalidate();
+    Ok(win)
+}
+
+/// There is no user to interact with the headless window, so synthesize
+/// some input and then close the window
+#[cfg(all(unix, not(target_os = "macos")))]
+async fn drive_headless(win: &::window::os::headless::HeadlessWindow) -> anyhow::Result<()> {
+    win.inject_resize(Dimensions {
+        pixel_width: 1024,
+        pixel_height: 768,
+        dpi: 96,
+    })
+    .await?;
+    win.inject_mouse_event(MouseEvent {
+        kind: MouseEventKind::Press(MousePress::Left),
+        coords: Point::new(10, 20),
+        screen_coords: Some(ScreenPoint::new(10, 20)),
+        mouse_buttons: MouseButtons::LEFT,
+        modifiers: Modifiers::NONE,
+        streak: 1,
+        time: 0,
+        serial: None,
+    })
+    .await?;
+    win.inject_key_event(KeyEvent {
+        key: KeyCode::Char('a'),
+        modifiers: Modifiers::NONE,
+        leds: KeyboardLedStatus::empty(),
+        repeat_count: 1,
+        key_is_down: true,
+        phys: None,
+        time: 0,
+        serial: None,
+        raw: None,
+    })
+    .await?;
+    eprintln!("headless ops: {:#?}", win.take_ops().await?);
+    // The first close request is refused, the second is allowed
+    win.inject_close_request().await?;
+    win.inject_close_request().await?;
     Ok(())
 }
 
 fn main() -> anyhow::Result<()> {
+    // Pass --headless to exercise the event plumbing without a
+    // display server or compositor
+    if std::env::args().any(|arg| arg == "--headless") {
+        std::env::set_var("WEZTERM_WINDOW_BACKEND", "headless");
+    }
     let conn = Connection::init()?;
     spawn(async {
         eprintln!("running this async block");
-        dbg!(spawn_window().await).ok();
+        let first = Rc::new(RefCell::new(None));
+        let second = Rc::new(RefCell::new(None));
+        if let Ok(win) = dbg!(spawn_window("the title", Rc::clone(&second)).await) {
+            #[cfg(all(unix, not(target_os = "macos")))]
+            if let Window::Headless(_) = &win {
+                return;
+            }
+            first.borrow_mut().replace(win);
+            // Press 'f' in either window to focus the other one
+            if let Ok(win) = dbg!(spawn_window("the other window", Rc::clone(&first)).await) {
+                second.borrow_mut().replace(win);
+            }
+        }
         eprintln!("end of async block");
     })
     .detach();
@@ -0,0 +1,232 @@
+//! Right-click in the window to open a context menu.
+//! Click on an item to choose it, or press Escape to dismiss the menu.
+use ::window::*;
+use config::Dimension;
+use promise::spawn::spawn;
+use std::cell::RefCell;
+use std::rc::Rc;
+use wezterm_font::FontConfiguration;
+
+const ITEMS: &[&str] = &["Copy", "Paste", "Select All"];
+const ITEM_HEIGHT: usize = 32;
+const MENU_WIDTH: usize = 160;
+
+/// Clears the frame to `background` and then fills in each of `bands`,
+/// given as the top y coordinate, height and color of a full width strip
+fn paint(
+    gl: &Rc<glium::backend::Context>,
+    win: &Window,
+    dims: Dimensions,
+    background: (f32, f32, f32, f32),
+    bands: &[(usize, usize, (f32, f32, f32, f32))],
+) {
+    if gl.is_context_lost() {
+        eprintln!("opengl context was lost; should reinit");
+        return;
+    }
+
+    let mut frame = glium::Frame::new(
+        Rc::clone(gl),
+        (dims.pixel_width as u32, dims.pixel_height as u32),
+    );
+
+    use glium::Surface;
+    frame.clear_color_srgb(background.0, background.1, background.2, background.3);
+    for &(top, height, color) in bands {
+        // glium rects have their origin at the bottom left
+        let rect = glium::Rect {
+            left: 0,
+            bottom: dims.pixel_height.saturating_sub(top + height) as u32,
+            width: dims.pixel_width as u32,
+            height: height as u32,
+        };
+        frame.clear(Some(&rect), Some(color), true, None, None);
+    }
+    win.finish_frame(frame).unwrap();
+}
+
+struct Menu {
+    dims: Dimensions,
+    gl: Option<Rc<glium::backend::Context>>,
+    hovered: Option<usize>,
+    /// Cleared when the menu is destroyed
+    slot: Rc<RefCell<Option<Window>>>,
+}
+
+impl Menu {
+    fn dispatch(&mut self, event: WindowEvent, win: &Window) {
+        match event {
+            WindowEvent::Destroyed => {
+                eprintln!("menu dismissed");
+                self.slot.borrow_mut().take();
+            }
+            WindowEvent::Resized { dimensions, .. } => {
+                self.dims = dimensions;
+            }
+            WindowEvent::MouseEvent(event) => {
+                let item = (event.coords.y >= 0)
+                    .then(|| event.coords.y as usize / ITEM_HEIGHT)
+                    .filter(|&item| item < ITEMS.len());
+                match event.kind {
+                    MouseEventKind::Press(MousePress::Left) => {
+                        if let Some(item) = item {
+                            eprintln!("chose {}", ITEMS[item]);
+                            win.force_close();
+                        }
+                    }
+                    _ => {
+                        if item != self.hovered {
+                            self.hovered = item;
+                            win.invalidate();
+                        }
+                    }
+                }
+            }
+            WindowEvent::KeyEvent(key) => {
+                if key.key_is_down && key.key == KeyCode::Char('\u{1b}') {
+                    win.force_close();
+                }
+            }
+            WindowEvent::NeedRepaint => {
+                if let Some(gl) = self.gl.as_ref() {
+                    let bands: Vec<_> = self
+                        .hovered
+                        .map(|item| (item * ITEM_HEIGHT, ITEM_HEIGHT, (0.3, 0.3, 0.6, 1.0)))
+                        .into_iter()
+                        .collect();
+                    paint(gl, win, self.dims, (0.9, 0.9, 0.9, 1.0), &bands);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+struct MyWindow {
+    dims: Dimensions,
+    gl: Option<Rc<glium::backend::Context>>,
+    menu: Rc<RefCell<Option<Window>>>,
+}
+
+impl MyWindow {
+    fn dispatch(&mut self, event: WindowEvent, win: &Window) {
+        match event {
+            WindowEvent::CloseRequested(request) => request.allow(),
+            WindowEvent::Destroyed => {
+                Connection::get().unwrap().terminate_message_loop();
+            }
+            WindowEvent::Resized { dimensions, .. } => {
+                self.dims = dimensions;
+            }
+            WindowEvent::MouseEvent(MouseEvent {
+                kind: MouseEventKind::Press(press),
+                coords,
+                ..
+            }) => {
+                // Not all window systems dismiss the menu for us
+                if let Some(menu) = self.menu.borrow_mut().take() {
+                    menu.force_close();
+                }
+                if press == MousePress::Right {
+                    let anchor = Rect::new(coords, Size::new(1, 1));
+                    let parent = win.clone();
+                    let slot = Rc::clone(&self.menu);
+                    spawn(async move {
+                        if let Err(err) = open_menu(&parent, anchor, slot).await {
+                            eprintln!("failed to open menu: {:#}", err);
+                        }
+                    })
+                    .detach();
+                }
+            }
+            WindowEvent::NeedRepaint => {
+                if let Some(gl) = self.gl.as_ref() {
+                    paint(gl, win, self.dims, (0.25, 0.125, 0.375, 1.0), &[]);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+async fn open_menu(
+    parent: &Window,
+    anchor: Rect,
+    slot: Rc<RefCell<Option<Window>>>,
+) -> anyhow::Result<()> {
+    let size = (MENU_WIDTH, ITEM_HEIGHT * ITEMS.len());
+    let menu = Rc::new(RefCell::new(Menu {
+        dims: Dimensions {
+            pixel_width: size.0,
+            pixel_height: size.1,
+            dpi: 0,
+        },
+        gl: None,
+        hovered: None,
+        slot: Rc::clone(&slot),
+    }));
+
+    let cb_menu = Rc::clone(&menu);
+    let popup = Window::new_popup(parent, anchor, size, move |event, window| {
+        cb_menu.borrow_mut().dispatch(event, window)
+    })
+    .await?;
+    slot.borrow_mut().replace(popup.clone());
+
+    // The popup has its own surface, and so its own gl context
+    let gl = popup.enable_opengl().await?;
+    menu.borrow_mut().gl.replace(gl);
+    popup.show();
+    popup.invalidate();
+    Ok(())
+}
+
+async fn spawn_window() -> anyhow::Result<Window> {
+    let fontconfig = Rc::new(FontConfiguration::new(
+        None,
+        ::window::default_dpi() as usize,
+    )?);
+
+    let state = Rc::new(RefCell::new(MyWindow {
+        dims: Dimensions {
+            pixel_width: 800,
+            pixel_height: 600,
+            dpi: 0,
+        },
+        gl: None,
+        menu: Rc::new(RefCell::new(None)),
+    }));
+
+    let cb_state = Rc::clone(&state);
+    let win = Window::new_window(
+        "myclass",
+        "right click for a menu",
+        RequestedWindowGeometry {
+            width: Dimension::Pixels(800.),
+            height: Dimension::Pixels(600.),
+            ..Default::default()
+        },
+        None,
+        fontconfig,
+        move |event, window| cb_state.borrow_mut().dispatch(event, window),
+    )
+    .await?;
+
+    win.show();
+    let gl = win.enable_opengl().await?;
+    state.borrow_mut().gl.replace(gl);
+    win.invalidate();
+    Ok(win)
+}
+
+fn main() -> anyhow::Result<()> {
+    let conn = Connection::init()?;
+    spawn(async {
+        if let Err(err) = spawn_window().await {
+            eprintln!("{:#}", err);
+            Connection::get().unwrap().terminate_message_loop();
+        }
+    })
+    .detach();
+    conn.run_message_loop()
+}
@@ -0,0 +1,167 @@
+//! Renders with a wgpu surface that the application creates from the
+//! raw handles of the window, rather than with `enable_opengl`.
+use ::window::*;
+use config::Dimension;
+use promise::spawn::spawn;
+use std::cell::RefCell;
+use std::rc::Rc;
+use wezterm_font::FontConfiguration;
+
+struct Gpu {
+    surface: wgpu::Surface<'static>,
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    config: wgpu::SurfaceConfiguration,
+}
+
+impl Gpu {
+    async fn new(win: &Window, dims: Dimensions) -> anyhow::Result<Self> {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+        // The surface is dropped before the window is allowed to close,
+        // so it doesn't outlive the handles that it was created from
+        let surface = unsafe {
+            instance.create_surface_unsafe(wgpu::SurfaceTargetUnsafe::from_window(win)?)?
+        };
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                compatible_surface: Some(&surface),
+                ..Default::default()
+            })
+            .await
+            .ok_or_else(|| anyhow::anyhow!("no adapter is compatible with the surface"))?;
+        let (device, queue) = adapter
+            .request_device(&wgpu::DeviceDescriptor::default(), None)
+            .await?;
+        let config = surface
+            .get_default_config(&adapter, dims.pixel_width as u32, dims.pixel_height as u32)
+            .ok_or_else(|| anyhow::anyhow!("the adapter doesn't support the surface"))?;
+        surface.configure(&device, &config);
+        Ok(Self {
+            surface,
+            device,
+            queue,
+            config,
+        })
+    }
+
+    fn resize(&mut self, dims: Dimensions) {
+        self.config.width = (dims.pixel_width as u32).max(1);
+        self.config.height = (dims.pixel_height as u32).max(1);
+        self.surface.configure(&self.device, &self.config);
+    }
+
+    fn paint(&mut self) -> anyhow::Result<()> {
+        let frame = match self.surface.get_current_texture() {
+            Ok(frame) => frame,
+            Err(wgpu::SurfaceError::Outdated | wgpu::SurfaceError::Lost) => {
+                self.surface.configure(&self.device, &self.config);
+                self.surface.get_current_texture()?
+            }
+            Err(err) => return Err(err.into()),
+        };
+        let view = frame
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: None,
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color {
+                        r: 0.25,
+                        g: 0.125,
+                        b: 0.375,
+                        a: 1.0,
+                    }),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        self.queue.submit(Some(encoder.finish()));
+        frame.present();
+        Ok(())
+    }
+}
+
+struct MyWindow {
+    gpu: Option<Gpu>,
+}
+
+impl MyWindow {
+    fn dispatch(&mut self, event: WindowEvent, _win: &Window) {
+        match event {
+            WindowEvent::CloseRequested(request) => {
+                self.gpu.take();
+                request.allow();
+            }
+            WindowEvent::Destroyed => {
+                Connection::get().unwrap().terminate_message_loop();
+            }
+            WindowEvent::Resized { dimensions, .. } => {
+                if let Some(gpu) = self.gpu.as_mut() {
+                    gpu.resize(dimensions);
+                }
+            }
+            WindowEvent::NeedRepaint => {
+                if let Some(gpu) = self.gpu.as_mut() {
+                    if let Err(err) = gpu.paint() {
+                        eprintln!("failed to paint: {:#}", err);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+async fn spawn_window() -> anyhow::Result<Window> {
+    let fontconfig = Rc::new(FontConfiguration::new(
+        None,
+        ::window::default_dpi() as usize,
+    )?);
+
+    let state = Rc::new(RefCell::new(MyWindow { gpu: None }));
+
+    let cb_state = Rc::clone(&state);
+    let win = Window::new_window(
+        "myclass",
+        "wgpu",
+        RequestedWindowGeometry {
+            width: Dimension::Pixels(800.),
+            height: Dimension::Pixels(600.),
+            ..Default::default()
+        },
+        None,
+        fontconfig,
+        move |event, window| cb_state.borrow_mut().dispatch(event, window),
+    )
+    .await?;
+
+    win.show();
+    // The window handle is unavailable from within the event handler,
+    // so the surface is created out here
+    let dims = win.get_dimensions().await?;
+    let gpu = Gpu::new(&win, dims).await?;
+    state.borrow_mut().gpu.replace(gpu);
+    win.invalidate();
+    Ok(win)
+}
+
+fn main() -> anyhow::Result<()> {
+    let conn = Connection::init()?;
+    spawn(async {
+        if let Err(err) = spawn_window().await {
+            eprintln!("{:#}", err);
+            Connection::get().unwrap().terminate_message_loop();
+        }
+    })
+    .detach();
+    conn.run_message_loop()
+}
@@ -0,0 +1,52 @@
+use crate::{Window, WindowOps};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Passed along with `WindowEvent::CloseRequested` so that the
+/// application can decide whether the window really closes.
+/// The decision doesn't need to be made while handling the event:
+/// the request can be held on to (and sent to another thread) and
+/// resolved later, for example once the user has answered a prompt.
+///
+/// While a request is outstanding, further close requests for the
+/// same window are not reported. Dropping the request without
+/// calling either method is the same as calling `deny`, so a
+/// request whose window is destroyed for some other reason in the
+/// meantime simply lapses.
+#[derive(Debug)]
+pub struct CloseRequest {
+    window: Window,
+    pending: Arc<AtomicBool>,
+}
+
+impl CloseRequest {
+    /// Returns a new request, or None if there is already one
+    /// outstanding for this window
+    pub(crate) fn new(window: Window, pending: &Arc<AtomicBool>) -> Option<Self> {
+        if pending.swap(true, Ordering::SeqCst) {
+            log::trace!("close already requested for {:?}", window);
+            return None;
+        }
+        Some(Self {
+            window,
+            pending: Arc::clone(pending),
+        })
+    }
+
+    /// Let the window close
+    pub fn allow(self) {
+        log::trace!("close allowed for {:?}", self.window);
+        self.window.force_close();
+    }
+
+    /// Keep the window open
+    pub fn deny(self) {
+        log::trace!("close denied for {:?}", self.window);
+    }
+}
+
+impl Drop for CloseRequest {
+    fn drop(&mut self) {
+        self.pending.store(false, Ordering::SeqCst);
+    }
+}
@@ -1,22 +1,140 @@
 use crate::screen::Screens;
-use crate::{Appearance, Connection, GeometryOrigin, RequestedWindowGeometry, ResolvedGeometry};
+use crate::{
+    Appearance, Connection, GeometryOrigin, KeyboardLayout, RequestedWindowGeometry,
+    ResolvedGeometry, UiSettings,
+};
 use anyhow::Result as Fallible;
 use config::keyassignment::KeyAssignment;
 use config::DimensionContext;
 use std::cell::RefCell;
 use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, SendError, Sender, TryRecvError};
 use std::sync::Mutex;
+use std::time::Duration;
 
 thread_local! {
     static CONN: RefCell<Option<Rc<Connection>>> = RefCell::new(None);
+    static CHANNELS: RefCell<Vec<Box<dyn FnMut() -> bool>>> = RefCell::new(vec![]);
 }
 
 fn nop_event_handler(_event: ApplicationEvent) {}
 
 static EVENT_HANDLER: Mutex<fn(ApplicationEvent)> = Mutex::new(nop_event_handler);
 
+/// Set by `ConnectionOps::request_terminate`, which may be called
+/// from any thread, and consumed by the message loop
+static TERMINATE_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Returns true, and clears the request, if the message loop has been
+/// asked to terminate since the last call
+pub(crate) fn take_terminate_request() -> bool {
+    TERMINATE_REQUESTED.swap(false, Ordering::SeqCst)
+}
+
 pub fn shutdown() {
-    CONN.with(|m| drop(m.borrow_mut().take()));
+    // Release the borrow before dropping the connection, as windows
+    // that are destroyed along with it may call Connection::get
+    let conn = CONN.with(|m| m.borrow_mut().take());
+    drop(conn);
+}
+
+/// Wake up the event loop on the main thread.
+/// Unlike `ConnectionOps::wake`, this can be called from any thread.
+pub fn wake_main_thread() {
+    crate::spawn::SPAWN_QUEUE.wake();
+}
+
+/// The sending half of a channel created by `main_thread_channel`
+pub struct MainThreadSender<T> {
+    tx: Sender<T>,
+}
+
+impl<T> Clone for MainThreadSender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            tx: self.tx.clone(),
+        }
+    }
+}
+
+impl<T> MainThreadSender<T> {
+    /// Send a message and wake up the main thread to process it
+    pub fn send(&self, msg: T) -> Result<(), SendError<T>> {
+        self.tx.send(msg)?;
+        wake_main_thread();
+        Ok(())
+    }
+}
+
+/// Create a channel for delivering messages from other threads to
+/// the main thread, where `handler` is called with each of them
+/// as the event loop wakes up.
+/// This must be called on the main thread.
+/// The handler is dropped once all of the senders have been dropped.
+pub fn main_thread_channel<T: 'static>(
+    mut handler: impl FnMut(T) + 'static,
+) -> MainThreadSender<T> {
+    let (tx, rx) = channel();
+    CHANNELS.with(|channels| {
+        channels.borrow_mut().push(Box::new(move || loop {
+            match rx.try_recv() {
+                Ok(msg) => handler(msg),
+                Err(TryRecvError::Empty) => return true,
+                Err(TryRecvError::Disconnected) => return false,
+            }
+        }))
+    });
+    MainThreadSender { tx }
+}
+
+/// Deliver the messages that are waiting in the channels that were
+/// created by `main_thread_channel`
+pub(crate) fn service_main_thread_channels() {
+    // Take the list while calling the handlers, so that they
+    // are free to create more channels
+    let mut channels = CHANNELS.with(|channels| std::mem::take(&mut *channels.borrow_mut()));
+    if channels.is_empty() {
+        return;
+    }
+    channels.retain_mut(|service| service());
+    CHANNELS.with(|c| {
+        let mut c = c.borrow_mut();
+        channels.append(&mut c);
+        *c = channels;
+    });
+}
+
+/// Limits how much spawned work the event loop runs in one go before
+/// it goes back to dispatching events from the display server.
+/// At least one task is run each time around the loop.
+/// This is currently only applied on X11, Wayland and the headless
+/// backend.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpawnBudget {
+    pub max_tasks: usize,
+    pub max_duration: Duration,
+}
+
+impl Default for SpawnBudget {
+    fn default() -> Self {
+        Self {
+            max_tasks: 32,
+            max_duration: Duration::from_millis(4),
+        }
+    }
+}
+
+/// Counters that describe the work done by the event loop,
+/// to help with diagnosing jank
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EventLoopStats {
+    /// The number of spawned tasks waiting to run
+    pub queue_depth: usize,
+    /// The number of spawned tasks that have run so far
+    pub tasks_run: u64,
+    /// The longest that any single spawned task took to run
+    pub max_task_duration: Duration,
 }
 
 #[derive(Debug)]
@@ -61,13 +179,59 @@ pub trait ConnectionOps {
     }
 
     fn terminate_message_loop(&self);
+
+    /// Ask the message loop to terminate.
+    /// Unlike `terminate_message_loop`, this can be called from any
+    /// thread, and doesn't rely on the main thread getting around to
+    /// running spawned work.
+    fn request_terminate() {
+        TERMINATE_REQUESTED.store(true, Ordering::SeqCst);
+        wake_main_thread();
+    }
+
     fn run_message_loop(&self) -> Fallible<()>;
 
+    /// When enabled, run_message_loop returns once the last window
+    /// has been destroyed and the work that was spawned before that
+    /// has run. Windows count from the moment that they are created,
+    /// even if they haven't been shown yet.
+    /// This is off by default, and is currently only implemented
+    /// for X11, Wayland and the headless backend.
+    fn set_terminate_when_last_window_closed(&self, _terminate: bool) {}
+
+    /// Change how much spawned work is run before the event loop
+    /// dispatches events from the display server again
+    fn set_spawn_budget(&self, budget: SpawnBudget) {
+        crate::spawn::SPAWN_QUEUE.set_budget(budget);
+    }
+
+    /// Returns counters that describe the work done by the event loop
+    fn event_loop_stats(&self) -> EventLoopStats {
+        crate::spawn::SPAWN_QUEUE.stats()
+    }
+
+    /// Wake up the event loop so that it promptly runs another iteration.
+    /// Use `wake_main_thread` or `main_thread_channel` from other threads.
+    fn wake(&self) {
+        wake_main_thread();
+    }
+
     /// Retrieve the current appearance for the application.
     fn get_appearance(&self) -> Appearance {
         Appearance::Light
     }
 
+    /// Retrieve the desktop interaction settings, such as the double
+    /// click time. Settings that can't be determined are None.
+    fn get_ui_settings(&self) -> UiSettings {
+        UiSettings::default()
+    }
+
+    /// Retrieve the active keyboard layout, if it is known
+    fn keyboard_layout(&self) -> Option<KeyboardLayout> {
+        None
+    }
+
     /// Hide the application.
     /// This actions hides all of the windows of the application and switches
     /// focus away from it.
@@ -135,3 +299,32 @@ pub trait ConnectionOps {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn channel_delivers_on_main_thread() {
+        let received = Rc::new(RefCell::new(vec![]));
+        let tx = {
+            let received = Rc::clone(&received);
+            main_thread_channel(move |msg: u32| received.borrow_mut().push(msg))
+        };
+
+        let sender = tx.clone();
+        std::thread::spawn(move || {
+            sender.send(1).unwrap();
+            sender.send(2).unwrap();
+        })
+        .join()
+        .unwrap();
+        service_main_thread_channels();
+        assert_eq!(*received.borrow(), vec![1, 2]);
+
+        // Once the senders are gone, the handler is dropped
+        drop(tx);
+        service_main_thread_channels();
+        assert_eq!(Rc::strong_count(&received), 1);
+    }
+}
@@ -1,4 +1,6 @@
+use crate::Rect;
 use anyhow::{anyhow, bail, ensure, Error};
+use std::cell::RefCell;
 use std::ffi::c_void;
 use std::rc::Rc;
 
@@ -59,7 +61,6 @@ pub struct GlConnection {
 }
 
 impl GlConnection {
-    #[allow(dead_code)]
     pub fn has_extension(&self, wanted: &str) -> bool {
         self.extensions.split(' ').any(|ext| ext == wanted)
     }
@@ -81,11 +82,17 @@ impl Drop for GlConnection {
     }
 }
 
+/// The regions of the surface, in pixels with the origin at the top
+/// left, that changed in the frame that is about to be swapped.
+/// None means that the whole surface changed.
+pub(crate) type SwapDamage = Rc<RefCell<Option<Vec<Rect>>>>;
+
 #[derive(Debug)]
 pub struct GlState {
     connection: Rc<GlConnection>,
     surface: ffi::types::EGLSurface,
     context: ffi::types::EGLContext,
+    damage: SwapDamage,
 }
 
 impl Drop for GlState {
@@ -400,6 +407,14 @@ impl GlState {
         &self.connection
     }
 
+    /// Returns the damage that will be reported to the compositor by
+    /// the next swap_buffers. It is reset to the whole surface after
+    /// each swap.
+    #[cfg_attr(any(windows, target_os = "macos"), allow(unused))]
+    pub(crate) fn swap_damage(&self) -> SwapDamage {
+        Rc::clone(&self.damage)
+    }
+
     fn with_egl_lib<F: FnMut(EglWrapper) -> anyhow::Result<Self>>(
         mut func: F,
     ) -> anyhow::Result<Self> {
@@ -650,6 +665,7 @@ impl GlState {
                 connection: Rc::clone(connection),
                 context,
                 surface,
+                damage: SwapDamage::default(),
             });
         }
 
@@ -663,9 +679,40 @@ unsafe impl glium::backend::Backend for GlState {
     }
 
     fn swap_buffers(&self) -> Result<(), glium::SwapBuffersError> {
-        let res = unsafe {
-            self.connection
-                .SwapBuffers(self.connection.display, self.surface)
+        let damage = self.damage.borrow_mut().take();
+        let res = match damage {
+            Some(rects)
+                if !rects.is_empty()
+                    && self
+                        .connection
+                        .has_extension("EGL_KHR_swap_buffers_with_damage") =>
+            {
+                // EGL wants the rects with the origin at the bottom left
+                let (_width, height) = self.get_framebuffer_dimensions();
+                let mut rects: Vec<ffi::EGLint> = rects
+                    .iter()
+                    .flat_map(|r| {
+                        [
+                            r.min_x() as ffi::EGLint,
+                            height as ffi::EGLint - r.max_y() as ffi::EGLint,
+                            r.width() as ffi::EGLint,
+                            r.height() as ffi::EGLint,
+                        ]
+                    })
+                    .collect();
+                unsafe {
+                    self.connection.SwapBuffersWithDamageKHR(
+                        self.connection.display,
+                        self.surface,
+                        rects.as_mut_ptr(),
+                        (rects.len() / 4) as ffi::EGLint,
+                    )
+                }
+            }
+            _ => unsafe {
+                self.connection
+                    .SwapBuffers(self.connection.display, self.surface)
+            },
         };
         if res != 1 {
             Err(match unsafe { self.connection.GetError() } as u32 {
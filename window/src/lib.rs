@@ -1,3 +1,4 @@
+use crate::screen::ScreenInfo;
 use async_trait::async_trait;
 use bitflags::bitflags;
 use config::window::WindowLevel;
@@ -6,9 +7,13 @@ use promise::Future;
 use std::any::Any;
 use std::path::PathBuf;
 use std::rc::Rc;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use thiserror::Error;
 use url::Url;
 pub mod bitmaps;
+mod close_request;
 pub use wezterm_color_types as color;
 mod configuration;
 pub mod connection;
@@ -33,6 +38,7 @@ pub fn default_dpi() -> f64 {
 mod egl;
 
 pub use bitmaps::{BitmapImage, Image};
+pub use close_request::CloseRequest;
 pub use connection::*;
 pub use glium;
 pub use os::*;
@@ -50,6 +56,19 @@ impl Default for Clipboard {
     }
 }
 
+/// The edge or corner of a window that is being interactively resized
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowResizeEdge {
+    Top,
+    Bottom,
+    Left,
+    Right,
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Dimensions {
     pub pixel_width: usize,
@@ -65,12 +84,24 @@ pub type SizeF = euclid::Size2D<f32, PixelUnit>;
 pub type ScreenRect = euclid::Rect<isize, ScreenPixelUnit>;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
 pub enum MouseCursor {
     Arrow,
     Hand,
     Text,
     SizeUpDown,
     SizeLeftRight,
+    /// Resize along the top-left to bottom-right diagonal
+    SizeNWSE,
+    /// Resize along the top-right to bottom-left diagonal
+    SizeNESW,
+    Crosshair,
+    NotAllowed,
+    /// Something can be grabbed and dragged
+    Grab,
+    /// Something is being dragged
+    Grabbing,
+    Wait,
 }
 
 /// Represents the preferred appearance of the windowing
@@ -89,6 +120,53 @@ pub enum Appearance {
     DarkHighContrast,
 }
 
+/// Interaction settings that the user has chosen for their desktop.
+/// Fields are None where the desktop doesn't provide a value, in which
+/// case the application should use its own default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UiSettings {
+    /// The longest time between consecutive presses of a mouse button
+    /// for them to count as a double or triple click
+    pub double_click_interval: Option<Duration>,
+    /// Whether the text cursor should blink
+    pub cursor_blink: Option<bool>,
+    /// The time taken by one on-off cycle of a blinking text cursor
+    pub cursor_blink_interval: Option<Duration>,
+    /// Whether scrolling moves the content, rather than the view, in
+    /// the direction of the gesture
+    pub natural_scrolling: Option<bool>,
+}
+
+/// Identifies one of the layouts of a keyboard with several,
+/// eg: for switching between latin and cyrillic input
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyboardLayout {
+    /// The human readable description of the layout, eg: "English (US)"
+    pub name: String,
+    /// The position of the layout in the keymap
+    pub index: u32,
+}
+
+/// A key press or release as reported by the keyboard, alongside
+/// what the keymap makes of it, but without the translation and
+/// normalization that is applied to KeyEvent.
+/// These are only delivered when enabled via WindowOps::set_raw_key_mode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UntranslatedKeyEvent {
+    /// The evdev code of the key
+    pub evdev_code: u32,
+    /// The keysym that the keymap yields for the key in the current state
+    pub keysym: u32,
+    /// The text that the key yields in the current state, if any
+    pub utf8: Option<String>,
+    /// If true, this is a key down rather than a key up event
+    pub key_is_down: bool,
+    /// All of the modifiers that are active, including those that
+    /// would be consumed in producing the keysym
+    pub modifiers: Modifiers,
+    pub leds: KeyboardLedStatus,
+}
+
 impl std::string::ToString for Appearance {
     fn to_string(&self) -> String {
         match self {
@@ -103,7 +181,7 @@ impl std::string::ToString for Appearance {
 
 bitflags! {
     #[derive(Default)]
-    pub struct WindowState: u8 {
+    pub struct WindowState: u16 {
         /// Occupies the whole screen; cannot be resized while in this state.
         const FULL_SCREEN = 1<<1;
         /// Maximized along either or both of horizontal or vertical dimensions;
@@ -116,6 +194,49 @@ bitflags! {
         const ALWAYS_ON_TOP = 1<<4;
         /// Always on bottom (docked) window
         const ALWAYS_ON_BOTTOM = 1<<5;
+        /// Has the keyboard focus.  This is only reported by
+        /// WindowOps::get_window_state and is not included in the
+        /// state passed with WindowEvent::Resized.
+        const FOCUSED = 1<<6;
+        /// The left edge is constrained by a tiling arrangement
+        const TILED_LEFT = 1<<7;
+        /// The right edge is constrained by a tiling arrangement
+        const TILED_RIGHT = 1<<8;
+        /// The top edge is constrained by a tiling arrangement
+        const TILED_TOP = 1<<9;
+        /// The bottom edge is constrained by a tiling arrangement
+        const TILED_BOTTOM = 1<<10;
+        /// The screen is being kept from blanking or locking at the
+        /// request of WindowOps::set_idle_inhibited. Like FOCUSED, this
+        /// is only reported by WindowOps::get_window_state.
+        const IDLE_INHIBITED = 1<<11;
+        /// Keyboard shortcuts are delivered to the window rather than
+        /// being acted upon by the window system, at the request of
+        /// WindowOps::set_shortcut_inhibition.  Like FOCUSED, this is
+        /// only reported by WindowOps::get_window_state.
+        const SHORTCUTS_INHIBITED = 1<<12;
+        /// Tiled on any edge
+        const TILED = Self::TILED_LEFT.bits | Self::TILED_RIGHT.bits | Self::TILED_TOP.bits | Self::TILED_BOTTOM.bits;
+    }
+}
+
+bitflags! {
+    /// The window management actions that the window system is able to
+    /// carry out for a window. Decorations and menus should only offer
+    /// actions that are present here.
+    pub struct WmCapabilities: u8 {
+        const MAXIMIZE = 1<<0;
+        const MINIMIZE = 1<<1;
+        const FULL_SCREEN = 1<<2;
+        /// WindowOps::show_window_menu
+        const WINDOW_MENU = 1<<3;
+    }
+}
+
+impl Default for WmCapabilities {
+    /// Most window systems don't say, so assume that everything works
+    fn default() -> Self {
+        Self::all()
     }
 }
 
@@ -154,13 +275,155 @@ pub enum DeadKeyStatus {
     Composing(String),
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TouchPhase {
+    /// A new touch point made contact with the window
+    Down,
+    /// A touch point moved
+    Motion,
+    /// A touch point was lifted
+    Up,
+    /// The window system took over the touch sequence, for example
+    /// because it recognized a gesture; the touch point is gone
+    Cancel,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TouchEvent {
+    /// Identifies the touch point for the duration of its contact.
+    /// Ids may be reused once a touch point has gone.
+    pub id: i32,
+    pub phase: TouchPhase,
+    /// Coordinates of the touch point relative to the top left of the window
+    pub coords: Point,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GestureKind {
+    /// Fingers moving towards or away from each other, and/or rotating
+    Pinch,
+    /// Fingers moving together in the same direction
+    Swipe,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GesturePhase {
+    Begin,
+    Update,
+    /// The fingers were lifted, completing the gesture
+    End,
+    /// The gesture was cancelled, for example because the window
+    /// system recognized it as one of its own
+    Cancel,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GestureEvent {
+    pub kind: GestureKind,
+    pub phase: GesturePhase,
+    /// The number of fingers taking part in the gesture
+    pub fingers: u32,
+    /// The factor by which the distance between the fingers has changed
+    /// since the previous event for this gesture.  Always 1.0 for swipes.
+    pub scale_delta: f64,
+    /// The angle, in degrees clockwise, by which the fingers have rotated
+    /// since the previous event for this gesture.  Always 0.0 for swipes.
+    pub rotation: f64,
+    /// The distance, in pixels, that the center of the fingers has moved
+    /// since the previous event for this gesture
+    pub dx: f64,
+    pub dy: f64,
+}
+
+bitflags! {
+    /// How the presentation of a frame was carried out, as reported
+    /// by the window system
+    #[derive(Default)]
+    pub struct PresentationFlags: u8 {
+        /// The frame was shown in step with the vertical retrace
+        /// of the display, so it didn't tear
+        const VSYNC = 1<<0;
+        /// The presentation timestamp came from a hardware clock
+        /// rather than being sampled in software
+        const HW_CLOCK = 1<<1;
+        /// The display hardware signalled completion of the
+        /// presentation, rather than it being assumed
+        const HW_COMPLETION = 1<<2;
+        /// The frame was scanned out directly from our buffer
+        /// without being composited
+        const ZERO_COPY = 1<<3;
+    }
+}
+
+/// The result of WindowOps::focus
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FocusOutcome {
+    /// The window has the keyboard focus
+    Focused,
+    /// The window system declined to move the focus, typically because
+    /// the user hasn't recently interacted with the application.
+    /// The window was flagged as requesting attention instead.
+    Deferred,
+}
+
+/// Why the window gained or lost the keyboard focus, as far as the
+/// window system lets us know
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FocusReason {
+    /// The window system didn't say, or this is the initial state
+    /// of a newly shown window
+    #[default]
+    Unknown,
+    /// The window was activated in response to WindowOps::focus
+    Activation,
+    /// Another client, or the window manager, started or ended a
+    /// keyboard grab, such as when a menu or screen locker is shown
+    Grab,
+}
+
+/// Passed to WindowOps::set_animation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct AnimationHint {
+    /// The highest rate at which the application needs to animate.
+    /// None means that every frame that the display can show should
+    /// be painted.
+    pub max_fps: Option<u32>,
+}
+
+/// When a frame that we painted made it to the screen
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameTiming {
+    /// When the window asked the application to paint the frame
+    pub requested: Instant,
+    /// When the frame started to be shown on the display, or None
+    /// if the window system discarded it without ever showing it
+    pub presented: Option<Instant>,
+    /// The refresh interval of the display that showed the frame,
+    /// if it is known and constant
+    pub refresh: Option<Duration>,
+    pub flags: PresentationFlags,
+    /// The window system cannot report when frames are presented;
+    /// `presented` is the time that the application finished
+    /// submitting the frame, which is the best available estimate
+    pub estimated: bool,
+}
+
+impl FrameTiming {
+    /// The time between asking for the frame and it being shown
+    pub fn latency(&self) -> Option<Duration> {
+        self.presented
+            .map(|presented| presented.saturating_duration_since(self.requested))
+    }
+}
+
 #[derive(Debug)]
 pub enum WindowEvent {
     /// Called when the window close button is clicked.
     /// The window closure is deferred and this event is
     /// sent to your application to decide whether it will
-    /// really close the window.
-    CloseRequested,
+    /// really close the window; the decision is made by
+    /// resolving the request, which may happen later on.
+    CloseRequested(CloseRequest),
 
     /// Called when the window is being destroyed by the window system
     Destroyed,
@@ -179,8 +442,33 @@ pub enum WindowEvent {
     /// be repainted
     NeedRepaint,
 
-    /// Called when the window gains/loses focus
-    FocusChanged(bool),
+    /// Sent immediately before NeedRepaint while animation has been
+    /// enabled via `WindowOps::set_animation`, with the time that has
+    /// elapsed since the previous animation frame
+    AnimationFrame(Duration),
+
+    /// Sent immediately before NeedRepaint when only these regions,
+    /// in pixels, have been invalidated via `invalidate_rect` since
+    /// the previous repaint.
+    /// The regions are reported to the compositor so that it can
+    /// avoid recompositing the rest of the window. An application
+    /// that knows that its back buffer holds the previous frame may
+    /// also limit its drawing to them.
+    NeedRepaintRegions(Vec<Rect>),
+
+    /// Called when the window gains/loses focus.
+    /// The initial focus state is reported once the window is shown,
+    /// so that it is never left unknown.
+    FocusChanged(bool, FocusReason),
+
+    /// Called when the window becomes invisible, because it has been
+    /// minimized, is on another workspace or is fully covered by other
+    /// windows, and again when it becomes visible.
+    /// NeedRepaint is held back while the window is invisible; a single
+    /// one follows becoming visible if the window was invalidated
+    /// in the meantime.
+    /// Not every window system reports this.
+    VisibilityChanged(bool),
 
     AdviseDeadKeyStatus(DeadKeyStatus),
 
@@ -194,10 +482,56 @@ pub enum WindowEvent {
     KeyEvent(KeyEvent),
 
     MouseEvent(MouseEvent),
+    /// The mouse pointer entered the window at the specified
+    /// window-relative coordinates
+    MouseEnter(Point),
     MouseLeave,
 
+    /// Raw multi-touch input, for applications that want to handle
+    /// gestures.  Touches made with a single finger are also reported
+    /// as left button MouseEvents, so handling this is optional.
+    Touch(TouchEvent),
+
+    /// A touchpad gesture.  This is only reported by window systems
+    /// that recognize gestures on behalf of the application.
+    Gesture(GestureEvent),
+
+    /// A frame that was painted in response to NeedRepaint has been
+    /// presented (or discarded) by the window system
+    FramePresented(FrameTiming),
+
     AppearanceChanged(Appearance),
 
+    /// The window moved to a different screen
+    ScreenChanged(ScreenInfo),
+
+    /// The window moved on the screen. The position is that of the
+    /// top left of the client area, as for `get_window_position`.
+    /// Only backends that know the position of the window report this.
+    Moved(ScreenPoint),
+
+    /// The user changed their desktop interaction settings.
+    /// The new values are also available from ConnectionOps::get_ui_settings.
+    UiSettingsChanged(UiSettings),
+
+    /// The active keyboard layout changed, either because the user
+    /// switched layouts or because the keymap was replaced.
+    /// Any dead key composition in progress has been abandoned.
+    KeyboardLayoutChanged(KeyboardLayout),
+
+    /// A key was pressed or released while raw key mode is enabled.
+    /// The usual KeyEvent, if any, follows.
+    UntranslatedKeyEvent(UntranslatedKeyEvent),
+
+    /// The window system changed the actions that it supports for the
+    /// window, or the largest size that it recommends for the window,
+    /// such as the work area of the screen. `bounds` is the inner size
+    /// in pixels, and is None when there is no recommendation.
+    WmCapabilitiesChanged {
+        capabilities: WmCapabilities,
+        bounds: Option<(usize, usize)>,
+    },
+
     Notification(Box<dyn Any + Send + Sync>),
 
     // Called when the files are being dragged into the window
@@ -221,6 +555,7 @@ pub enum WindowEvent {
 pub struct WindowEventSender {
     handler: Box<dyn FnMut(WindowEvent, &Window)>,
     window: Option<Window>,
+    close_pending: Arc<AtomicBool>,
 }
 
 impl WindowEventSender {
@@ -228,6 +563,7 @@ impl WindowEventSender {
         Self {
             handler: Box::new(handler),
             window: None,
+            close_pending: Arc::new(AtomicBool::new(false)),
         }
     }
 
@@ -241,12 +577,65 @@ impl WindowEventSender {
             (self.handler)(event, window);
         }
     }
+
+    /// Ask the application whether the window may close.
+    /// Nothing is dispatched while an earlier request is still
+    /// waiting to be resolved.
+    pub(crate) fn request_close(&mut self) {
+        let request = match self.window.as_ref() {
+            Some(window) => CloseRequest::new(window.clone(), &self.close_pending),
+            None => None,
+        };
+        if let Some(request) = request {
+            self.dispatch(WindowEvent::CloseRequested(request));
+        }
+    }
 }
 
 #[derive(Debug, Error)]
 #[error("Graphics drivers lost context")]
 pub struct GraphicsDriversLostContext {}
 
+/// Returned when an operation depends on something that the display
+/// server doesn't provide, for example the clipboard on a minimal
+/// wayland compositor
+#[derive(Debug, Error)]
+#[error("{what} is not supported by the display server")]
+pub struct Unsupported {
+    pub what: &'static str,
+}
+
+/// Returned by `set_inner_size` when the window is full screen or
+/// maximized and `ConstrainedResize::Reject` was requested
+#[derive(Debug, Error)]
+#[error("the window can't be resized while its state is {state:?}")]
+pub struct ResizeRejected {
+    pub state: WindowState,
+}
+
+/// What `set_inner_size` does while the window is full screen or
+/// maximized, when the window system is dictating its size
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ConstrainedResize {
+    /// Remember the size and apply it once the window returns to its
+    /// normal state. It is forgotten if the window is sized by some
+    /// other means first.
+    #[default]
+    Defer,
+    /// Fail with `ResizeRejected`
+    Reject,
+}
+
+/// Returned by `run_message_loop` when the connection to the display
+/// server fails, for example because the compositor crashed.
+/// The windows have been destroyed by the time that this is returned;
+/// the application may try to establish a fresh connection.
+#[derive(Debug, Error)]
+#[error("Lost connection to the display server: {reason}")]
+pub struct ConnectionLost {
+    pub reason: String,
+}
+
 #[async_trait(?Send)]
 pub trait WindowOps {
     /// Show a hidden window
@@ -264,24 +653,146 @@ pub trait WindowOps {
         Ok(())
     }
 
-    /// Hide a visible window
+    /// Show an image that the application rendered with the CPU,
+    /// rather than via OpenGL or WebGpu; for use when there is no
+    /// usable GPU. The image should be the size of the window in
+    /// pixels, and is typically presented in response to
+    /// `WindowEvent::NeedRepaint`.
+    /// This can't be mixed with `enable_opengl` on the same window.
+    fn present_software(&self, _image: Image) -> Future<()> {
+        Future::err(anyhow::anyhow!(
+            "present_software is not supported on this platform"
+        ))
+    }
+
+    /// Hide a visible window.
+    /// On X11 and Wayland this unmaps the window entirely; on other
+    /// systems it is equivalent to minimize.
     fn hide(&self);
 
-    /// Schedule the window to be closed
+    /// Minimize (iconify) the window.
+    /// Use restore or show to bring it back.
+    fn minimize(&self) {
+        self.hide();
+    }
+
+    /// Ask for the window to be closed, as though the user had clicked
+    /// its close button: a `WindowEvent::CloseRequested` is dispatched
+    /// and the window only closes if the application allows it
     fn close(&self);
 
+    /// Schedule the window to be closed without consulting the
+    /// application
+    fn force_close(&self);
+
     /// Change the cursor
     fn set_cursor(&self, cursor: Option<MouseCursor>);
 
+    /// Change the mouse cursor to the supplied image, which should have
+    /// premultiplied alpha.  `hotspot` is the position within the image,
+    /// in pixels, that corresponds to the pointer location.
+    /// The custom cursor remains in effect until the next call to
+    /// `set_cursor` or `set_custom_cursor`.
+    fn set_custom_cursor(&self, _image: Image, _hotspot: Point) -> Future<()> {
+        Future::err(anyhow::anyhow!(
+            "set_custom_cursor is not supported on this platform"
+        ))
+    }
+
     /// Invalidate the window so that the entire client area will
     /// be repainted shortly
     fn invalidate(&self);
 
+    /// Request that the window be repainted.
+    /// Where the platform allows it, the repaint happens in step with
+    /// the display refresh, and multiple requests made before then are
+    /// coalesced into a single repaint.
+    /// This is the same as `invalidate`.
+    fn request_redraw(&self) {
+        self.invalidate();
+    }
+
+    /// Enable or disable continuous animation.
+    /// While enabled, the window repaints itself in step with the
+    /// display, or as close to that as the platform permits, sending
+    /// AnimationFrame before each NeedRepaint, so that the application
+    /// doesn't need to drive its animation with timers.
+    /// None returns to painting only when the window is invalidated.
+    /// Platforms that don't support this ignore it.
+    fn set_animation(&self, _hint: Option<AnimationHint>) {}
+
+    /// Invalidate just the specified region, in pixels, of the window.
+    /// Where the platform doesn't support partial repaints, this is
+    /// the same as `invalidate`.
+    fn invalidate_rect(&self, _rect: Rect) {
+        self.invalidate();
+    }
+
+    /// Set the opacity of the whole window, including any decorations
+    /// provided by the window system, from 0.0 (transparent) to 1.0
+    /// (opaque). This is independent of the alpha of the pixels that
+    /// the application renders, and persists until changed again.
+    /// This is currently only implemented on X11, where it requires a
+    /// compositing window manager; elsewhere it is ignored.
+    fn set_window_opacity(&self, _opacity: f32) {}
+
+    /// Ask the system not to blank or lock the screen due to inactivity,
+    /// for example, while a long running task is in progress.
+    /// The inhibition is lifted while the window is hidden and when it
+    /// is closed. Whether it is in effect is reported as
+    /// WindowState::IDLE_INHIBITED by get_window_state.
+    /// This is implemented on X11 and Wayland; elsewhere it is ignored.
+    fn set_idle_inhibited(&self, _inhibit: bool) {}
+
+    /// Ask the window system to deliver keys that it would otherwise
+    /// act upon itself, such as Super or Alt-Tab, to the window; for
+    /// example, while it is showing a remote desktop or a virtual machine.
+    /// The inhibition only applies while the window has the keyboard
+    /// focus; it is suspended when the focus is lost and resumes when
+    /// it is regained, until it is turned off again.
+    /// Resolves with an error if the window system declines or is
+    /// unable to do so; that is only known once the window has the
+    /// focus, so this doesn't resolve until then.
+    /// Whether it is in effect is reported as
+    /// WindowState::SHORTCUTS_INHIBITED by get_window_state.
+    /// On X11 this is approximated by grabbing the keyboard.
+    fn set_shortcut_inhibition(&self, _inhibit: bool) -> Future<()> {
+        Future::err(anyhow::anyhow!(
+            "set_shortcut_inhibition is not supported on this platform"
+        ))
+    }
+
+    /// Tell the window system which region of the window, in pixels,
+    /// is fully opaque, so that it needn't draw whatever is behind it.
+    /// None means that the window may be translucent anywhere; that is
+    /// also the initial state.
+    /// This is only a hint, which platforms may ignore.
+    fn set_opaque_region(&self, _region: Option<Rect>) {}
+
     /// Change the titlebar text for the window
     fn set_title(&self, title: &str);
 
-    /// Resize the inner or client area of the window
-    fn set_inner_size(&self, width: usize, height: usize);
+    /// Change the class that the window was created with: the app_id
+    /// on Wayland, or the WM_CLASS property on X11, where the instance
+    /// name defaults to the class name as for `WindowOptions::instance_name`.
+    /// Some window managers only evaluate their rules when a window
+    /// is mapped, so this may not be picked up until then.
+    /// This is currently only implemented on X11 and Wayland.
+    fn set_window_class(&self, _class_name: &str, _instance_name: Option<&str>) {}
+
+    /// Resize the inner or client area of the window.
+    /// Resolves with the dimensions that the window has once the
+    /// resize has been applied, which can differ from those requested
+    /// due to scale rounding, size constraints, or the window system
+    /// choosing a different size.
+    /// `constrained` decides what happens if the window is full screen
+    /// or maximized.
+    fn set_inner_size(
+        &self,
+        width: usize,
+        height: usize,
+        constrained: ConstrainedResize,
+    ) -> Future<Dimensions>;
 
     /// Use for windows snap layouts
     fn set_maximize_button_position(&self, _rect: ScreenRect) {}
@@ -292,6 +803,80 @@ pub trait WindowOps {
     /// window movement on the server side (Wayland).
     fn request_drag_move(&self) {}
 
+    /// Ask the window system to start an interactive, pointer driven,
+    /// move of the window, as though its titlebar had been dragged.
+    /// This must be called while a mouse button is held, typically in
+    /// response to a press in a custom titlebar area; the future
+    /// resolves to an error if no button is held.
+    /// `serial` should be that of the triggering button press; when
+    /// it is None, the most recently seen serial is used instead.
+    fn start_interactive_move(&self, _serial: Option<InputSerial>) -> Future<()> {
+        Future::err(anyhow::anyhow!(
+            "start_interactive_move is not supported on this platform"
+        ))
+    }
+
+    /// Ask the window system to start an interactive, pointer driven,
+    /// resize of the window from the specified edge.
+    /// As with start_interactive_move, a mouse button must be held.
+    fn start_interactive_resize(
+        &self,
+        _edge: WindowResizeEdge,
+        _serial: Option<InputSerial>,
+    ) -> Future<()> {
+        Future::err(anyhow::anyhow!(
+            "start_interactive_resize is not supported on this platform"
+        ))
+    }
+
+    /// Lock the pointer in place while it is over the window, so that
+    /// pointer motion is reported via `MouseEventKind::RelativeMove`
+    /// rather than moving the pointer.  If `position_hint` is specified,
+    /// the pointer will be placed at those window relative coordinates
+    /// when the lock is released.
+    /// The lock is released when the window loses focus.
+    /// Locking an already locked pointer has no effect.
+    fn lock_pointer(&self, _position_hint: Option<Point>) -> Future<()> {
+        Future::err(anyhow::anyhow!(
+            "lock_pointer is not supported on this platform"
+        ))
+    }
+
+    /// Confine the pointer to the specified region of the window.
+    /// Relative motion is reported via `MouseEventKind::RelativeMove`
+    /// in addition to the usual `MouseEventKind::Move` events.
+    /// The confinement is released when the window loses focus.
+    fn confine_pointer(&self, _region: Rect) -> Future<()> {
+        Future::err(anyhow::anyhow!(
+            "confine_pointer is not supported on this platform"
+        ))
+    }
+
+    /// Release a lock or confinement established by `lock_pointer`
+    /// or `confine_pointer`
+    fn unlock_pointer(&self) {}
+
+    /// Ask the window system to show its window menu (minimize, maximize,
+    /// move to another workspace and so on) at the specified window
+    /// relative coordinates.  This should be called in response to
+    /// a mouse button press, otherwise the request may be ignored;
+    /// pass the serial of that press, if known.
+    fn show_window_menu(&self, _coords: Point, _serial: Option<InputSerial>) -> Future<()> {
+        Future::err(anyhow::anyhow!(
+            "show_window_menu is not supported on this platform"
+        ))
+    }
+
+    /// Retrieve the timings of the most recently presented frames,
+    /// oldest first.
+    /// Only a limited number of frames are remembered; applications
+    /// that want all of them should handle WindowEvent::FramePresented.
+    fn frame_timings(&self) -> Future<Vec<FrameTiming>> {
+        Future::err(anyhow::anyhow!(
+            "frame_timings is not supported on this platform"
+        ))
+    }
+
     /// Signal to the windowing system that the mouse is over
     /// a window dragging area.
     ///
@@ -308,6 +893,33 @@ pub trait WindowOps {
     /// windows to move themselves (not Wayland).
     fn set_window_position(&self, _coords: ScreenPoint) {}
 
+    /// Returns the location of the top left pixel of the client area
+    /// on the screen, in the same terms as set_window_position, so
+    /// that a position that was set can be read back.
+    ///
+    /// Every backend reports the client area rather than the outer
+    /// frame: the title bar and borders drawn by the system or window
+    /// manager (the area in _NET_FRAME_EXTENTS on X11) are not included,
+    /// so the frame origin is above and to the left of this point.
+    ///
+    /// None if the backend doesn't know where the window is, which is
+    /// always the case on Wayland.
+    fn get_window_position(&self) -> Future<Option<ScreenPoint>> {
+        Future::ok(None)
+    }
+
+    /// Returns the screen that the window is on. Where it spans several,
+    /// this is the one that WindowEvent::ScreenChanged last reported.
+    /// Backends that can't tell report the active screen.
+    fn get_current_screen(&self) -> Future<ScreenInfo> {
+        Future::result(
+            Connection::get()
+                .ok_or_else(|| anyhow::anyhow!("no connection"))
+                .and_then(|conn| conn.screens())
+                .map(|screens| screens.active),
+        )
+    }
+
     /// inform the windowing system of the current textual
     /// cursor input location.  This is used primarily for
     /// the platform specific input method editor
@@ -319,9 +931,56 @@ pub trait WindowOps {
     /// Set some text in the clipboard
     fn set_clipboard(&self, clipboard: Clipboard, text: String);
 
+    /// Initiate transfer of the clipboard contents in the specified
+    /// mime type, such as `image/png`.  The future resolves to an
+    /// error if the clipboard doesn't offer that type.
+    fn get_clipboard_data(&self, _clipboard: Clipboard, _mime: &str) -> Future<Vec<u8>> {
+        Future::err(anyhow::anyhow!(
+            "get_clipboard_data is not supported on this platform"
+        ))
+    }
+
+    /// Place data of the specified mime type into the clipboard
+    fn set_clipboard_data(&self, _clipboard: Clipboard, _mime: &str, _data: Vec<u8>) {}
+
+    /// Returns the formats advertised by the current owner of the
+    /// clipboard.  These are mime types on Wayland and target atom
+    /// names on X11.  The list is empty if nothing owns the clipboard.
+    fn list_clipboard_formats(&self, _clipboard: Clipboard) -> Future<Vec<String>> {
+        Future::err(anyhow::anyhow!(
+            "list_clipboard_formats is not supported on this platform"
+        ))
+    }
+
     /// Set window level. Depending on the environment and user preferences
     fn set_window_level(&self, _level: WindowLevel) {}
 
+    /// Ask the window manager to keep the window above other windows.
+    /// The request persists while the window is hidden and shown again.
+    /// Fails with `Unsupported` where applications can't ask for this,
+    /// such as on Wayland, where the compositor decides.
+    fn set_always_on_top(&self, _enable: bool) -> Future<()> {
+        Future::err(
+            Unsupported {
+                what: "set_always_on_top",
+            }
+            .into(),
+        )
+    }
+
+    /// Ask the window manager to leave the window out of the task bar.
+    /// The request persists while the window is hidden and shown again.
+    /// Fails with `Unsupported` where applications can't ask for this,
+    /// such as on Wayland, where the compositor decides.
+    fn set_skip_taskbar(&self, _enable: bool) -> Future<()> {
+        Future::err(
+            Unsupported {
+                what: "set_skip_taskbar",
+            }
+            .into(),
+        )
+    }
+
     /// Set the icon for the window.
     /// Depending on the system this may be shown in its titlebar
     /// and/or in the task manager/task switcher
@@ -329,7 +988,22 @@ pub trait WindowOps {
 
     fn maximize(&self) {}
     fn restore(&self) {}
-    fn focus(&self) {}
+
+    /// Bring the window to the front and give it the keyboard focus.
+    /// The window system may decline to do so when the user hasn't
+    /// interacted with the application recently, in which case the
+    /// window requests attention instead and the future resolves to
+    /// FocusOutcome::Deferred.
+    /// Passing the serial of the input event that prompted the request,
+    /// if any, improves the odds of it being honored.
+    fn focus(&self, _serial: Option<InputSerial>) -> Future<FocusOutcome> {
+        Future::err(anyhow::anyhow!("focus is not supported on this platform"))
+    }
+
+    /// Ask the user to look at the window, without taking the focus.
+    /// Typically, its entry in the task bar is highlighted until it
+    /// is focused.
+    fn request_attention(&self) {}
 
     fn toggle_fullscreen(&self) {}
 
@@ -342,6 +1016,20 @@ pub trait WindowOps {
     /// environment.
     fn set_resize_increments(&self, _incr: ResizeIncrement) {}
 
+    /// Ask the window system not to let the window be resized below
+    /// the specified inner size, in pixels. Passing 0 for both removes
+    /// the constraint.
+    fn set_min_inner_size(&self, _width: usize, _height: usize) {}
+
+    /// Ask the window system not to let the window be resized above
+    /// the specified inner size, in pixels, or remove that constraint.
+    fn set_max_inner_size(&self, _size: Option<(usize, usize)>) {}
+
+    /// Enable or disable the delivery of WindowEvent::UntranslatedKeyEvent
+    /// for each key press and release.  When disabling it, the releases
+    /// of keys whose presses were already delivered are still delivered.
+    fn set_raw_key_mode(&self, _enable: bool) {}
+
     fn get_os_parameters(
         &self,
         _config: &ConfigHandle,
@@ -349,6 +1037,29 @@ pub trait WindowOps {
     ) -> anyhow::Result<Option<os::parameters::Parameters>> {
         Ok(None)
     }
+
+    /// Returns the current dimensions of the window.
+    /// If set_inner_size was called, the result reflects the requested
+    /// size even if the window system has yet to confirm it.
+    fn get_dimensions(&self) -> Future<Dimensions> {
+        Future::err(anyhow::anyhow!(
+            "get_dimensions is not supported on this platform"
+        ))
+    }
+
+    /// Returns the window management actions that the window system
+    /// supports for the window
+    fn get_wm_capabilities(&self) -> Future<WmCapabilities> {
+        Future::ok(WmCapabilities::default())
+    }
+
+    /// Returns the current state of the window; this includes
+    /// WindowState::FOCUSED if the window has the keyboard focus
+    fn get_window_state(&self) -> Future<WindowState> {
+        Future::err(anyhow::anyhow!(
+            "get_window_state is not supported on this platform"
+        ))
+    }
 }
 
 #[derive(Debug, Clone, Default)]
@@ -362,6 +1073,58 @@ pub struct RequestedWindowGeometry {
     pub origin: GeometryOrigin,
 }
 
+/// Less commonly needed parameters for `Window::new_window_with_options`.
+/// These are currently ignored on macOS and Windows.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WindowOptions<'a> {
+    /// Make the window a child of `parent`: the window system keeps it
+    /// above its parent and leaves it out of the task bar.
+    /// Children are closed along with their parent.
+    /// This is currently only implemented on X11 and Wayland.
+    pub parent: Option<&'a Window>,
+    /// Treat the window as a dialog that prevents interaction with its
+    /// parent until it is closed. Has no effect without a `parent`.
+    pub modal: bool,
+    /// The state that the window is created in
+    pub initial_state: InitialWindowState,
+    /// Use these decorations for the window rather than the
+    /// `window_decorations` from the config
+    pub decorations: Option<WindowDecorations>,
+    /// Ask the window system not to let the user resize the window.
+    /// This sets both the minimum and maximum size to the initial size;
+    /// `set_min_inner_size` and `set_max_inner_size` can relax it later.
+    pub fixed_size: bool,
+    /// The initial opacity of the whole window; see `set_window_opacity`
+    pub opacity: Option<f32>,
+    /// The instance name in the X11 WM_CLASS property, which is
+    /// otherwise the same as the class name.
+    /// Wayland has no equivalent, so this is ignored there.
+    pub instance_name: Option<&'a str>,
+}
+
+/// The state of a window when it is first mapped.
+/// These are requests; the window system may not honor them.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum InitialWindowState {
+    #[default]
+    Normal,
+    Maximized,
+    FullScreen,
+    /// Don't map the window until `show` is called
+    Hidden,
+}
+
+impl InitialWindowState {
+    pub(crate) fn window_state(self) -> WindowState {
+        match self {
+            Self::Normal => WindowState::default(),
+            Self::Maximized => WindowState::MAXIMIZED,
+            Self::FullScreen => WindowState::FULL_SCREEN,
+            Self::Hidden => WindowState::HIDDEN,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct ResolvedGeometry {
     pub x: Option<i32>,
@@ -370,7 +1133,7 @@ pub struct ResolvedGeometry {
     pub height: usize,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct ResizeIncrement {
     pub x: u16,
     pub y: u16,
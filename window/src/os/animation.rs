@@ -0,0 +1,117 @@
+use crate::AnimationHint;
+use std::time::{Duration, Instant};
+
+/// Tracks the animation mode requested via WindowOps::set_animation,
+/// so that the backends can decide when the next animation frame is
+/// due and report the time elapsed since the previous one.
+#[derive(Debug, Default)]
+pub(crate) struct Animation {
+    hint: Option<AnimationHint>,
+    last_frame: Option<Instant>,
+}
+
+impl Animation {
+    pub(crate) fn set(&mut self, hint: Option<AnimationHint>) {
+        if hint.is_none() {
+            // Don't report the time spent idle as part of the
+            // interval when animation is later resumed
+            self.last_frame.take();
+        }
+        self.hint = hint;
+    }
+
+    pub(crate) fn is_active(&self) -> bool {
+        self.hint.is_some()
+    }
+
+    /// Returns how long to wait before painting the next animation
+    /// frame, or None if we're not animating
+    pub(crate) fn delay_until_next_frame(&self, now: Instant) -> Option<Duration> {
+        let hint = self.hint?;
+        let interval = match hint.max_fps {
+            Some(fps) if fps > 0 => Duration::from_secs(1) / fps,
+            _ => return Some(Duration::ZERO),
+        };
+        match self.last_frame {
+            Some(last) => Some(interval.saturating_sub(now.saturating_duration_since(last))),
+            None => Some(Duration::ZERO),
+        }
+    }
+
+    /// Called as a frame is painted; returns the time elapsed since
+    /// the previous animation frame, or None if we're not animating
+    pub(crate) fn tick(&mut self, now: Instant) -> Option<Duration> {
+        if !self.is_active() {
+            return None;
+        }
+        let elapsed = self
+            .last_frame
+            .map(|last| now.saturating_duration_since(last))
+            .unwrap_or(Duration::ZERO);
+        self.last_frame.replace(now);
+        Some(elapsed)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn inactive() {
+        let mut animation = Animation::default();
+        let now = Instant::now();
+        assert_eq!(animation.delay_until_next_frame(now), None);
+        assert_eq!(animation.tick(now), None);
+    }
+
+    #[test]
+    fn every_frame() {
+        let mut animation = Animation::default();
+        animation.set(Some(AnimationHint::default()));
+        let start = Instant::now();
+        assert_eq!(animation.tick(start), Some(Duration::ZERO));
+        assert_eq!(
+            animation.delay_until_next_frame(start),
+            Some(Duration::ZERO)
+        );
+        let later = start + Duration::from_millis(16);
+        assert_eq!(animation.tick(later), Some(Duration::from_millis(16)));
+    }
+
+    #[test]
+    fn limited_rate() {
+        let mut animation = Animation::default();
+        animation.set(Some(AnimationHint { max_fps: Some(10) }));
+        let start = Instant::now();
+        assert_eq!(
+            animation.delay_until_next_frame(start),
+            Some(Duration::ZERO)
+        );
+        animation.tick(start);
+        assert_eq!(
+            animation.delay_until_next_frame(start + Duration::from_millis(40)),
+            Some(Duration::from_millis(60))
+        );
+        assert_eq!(
+            animation.delay_until_next_frame(start + Duration::from_millis(150)),
+            Some(Duration::ZERO)
+        );
+    }
+
+    #[test]
+    fn stopping_resets_interval() {
+        let mut animation = Animation::default();
+        animation.set(Some(AnimationHint::default()));
+        let start = Instant::now();
+        animation.tick(start);
+        animation.set(None);
+        assert_eq!(animation.tick(start + Duration::from_secs(5)), None);
+
+        animation.set(Some(AnimationHint::default()));
+        assert_eq!(
+            animation.tick(start + Duration::from_secs(10)),
+            Some(Duration::ZERO)
+        );
+    }
+}
@@ -0,0 +1,161 @@
+use crate::{MousePress, Point, UiSettings};
+use config::ConfigHandle;
+use std::sync::Mutex;
+
+/// The longest time between presses that continue a streak when neither
+/// the config nor the desktop specify it
+const DEFAULT_INTERVAL_MS: u64 = 500;
+
+/// The desktop's double click time in milliseconds, if it has one.
+/// Asking the desktop can mean a round trip over DBus, which is
+/// too slow to do for every press.
+static DESKTOP_INTERVAL_MS: Mutex<Option<u64>> = Mutex::new(None);
+
+/// Remembers the double click time from `settings`.  The backends
+/// call this when the connection is established and whenever the
+/// desktop tells them that the UI settings have changed.
+pub(crate) fn update_desktop_interval(settings: &UiSettings) {
+    *DESKTOP_INTERVAL_MS.lock().unwrap() = settings
+        .double_click_interval
+        .map(|interval| interval.as_millis() as u64);
+}
+
+/// Returns the longest time between presses that continue a streak.
+/// An explicitly configured interval takes precedence over the desktop's
+/// double click time.
+pub(crate) fn click_interval_ms(config: &ConfigHandle) -> u64 {
+    config
+        .mouse_click_interval_ms
+        .or_else(|| *DESKTOP_INTERVAL_MS.lock().unwrap())
+        .unwrap_or(DEFAULT_INTERVAL_MS)
+}
+
+/// How far, in pixels, the pointer may stray from the position of
+/// the previous press and still continue the streak
+const STREAK_SLOP: isize = 4;
+
+#[derive(Debug)]
+struct LastPress {
+    button: MousePress,
+    coords: Point,
+    time: u32,
+    streak: u16,
+}
+
+/// Counts consecutive presses of the same mouse button so that the
+/// backends can report double and triple clicks.
+/// Times are the millisecond timestamps supplied by the window system
+/// with the input events, rather than the time that we got around to
+/// processing them, so that the counting isn't thrown off by a
+/// busy main loop.
+#[derive(Debug, Default)]
+pub(crate) struct ClickStreak {
+    last: Option<LastPress>,
+}
+
+impl ClickStreak {
+    /// Records a press and returns the streak that it belongs to
+    pub(crate) fn press(
+        &mut self,
+        button: MousePress,
+        coords: Point,
+        time: u32,
+        interval_ms: u64,
+    ) -> u16 {
+        let streak = match &self.last {
+            Some(last)
+                if last.button == button
+                    && u64::from(time.wrapping_sub(last.time)) <= interval_ms
+                    && is_near(last.coords, coords) =>
+            {
+                last.streak.saturating_add(1)
+            }
+            _ => 1,
+        };
+        self.last.replace(LastPress {
+            button,
+            coords,
+            time,
+            streak,
+        });
+        streak
+    }
+
+    /// Moving away from the position of the last press ends the streak
+    pub(crate) fn moved(&mut self, coords: Point) {
+        if let Some(last) = &self.last {
+            if !is_near(last.coords, coords) {
+                self.last.take();
+            }
+        }
+    }
+}
+
+fn is_near(a: Point, b: Point) -> bool {
+    (a.x - b.x).abs() <= STREAK_SLOP && (a.y - b.y).abs() <= STREAK_SLOP
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn counts_clicks_within_interval() {
+        let mut streak = ClickStreak::default();
+        let p = Point::new(10, 10);
+        assert_eq!(streak.press(MousePress::Left, p, 1000, 500), 1);
+        assert_eq!(streak.press(MousePress::Left, p, 1200, 500), 2);
+        assert_eq!(streak.press(MousePress::Left, p, 1600, 500), 3);
+        assert_eq!(streak.press(MousePress::Left, p, 2200, 500), 1);
+    }
+
+    #[test]
+    fn other_button_resets() {
+        let mut streak = ClickStreak::default();
+        let p = Point::new(10, 10);
+        assert_eq!(streak.press(MousePress::Left, p, 1000, 500), 1);
+        assert_eq!(streak.press(MousePress::Right, p, 1100, 500), 1);
+        assert_eq!(streak.press(MousePress::Left, p, 1200, 500), 1);
+    }
+
+    #[test]
+    fn movement_resets() {
+        let mut streak = ClickStreak::default();
+        assert_eq!(
+            streak.press(MousePress::Left, Point::new(10, 10), 1000, 500),
+            1
+        );
+        streak.moved(Point::new(12, 11));
+        assert_eq!(
+            streak.press(MousePress::Left, Point::new(12, 11), 1100, 500),
+            2
+        );
+        streak.moved(Point::new(30, 11));
+        assert_eq!(
+            streak.press(MousePress::Left, Point::new(12, 11), 1200, 500),
+            1
+        );
+    }
+
+    #[test]
+    fn timestamp_wraps() {
+        let mut streak = ClickStreak::default();
+        let p = Point::new(0, 0);
+        assert_eq!(streak.press(MousePress::Left, p, u32::MAX - 100, 500), 1);
+        assert_eq!(streak.press(MousePress::Left, p, 100, 500), 2);
+    }
+
+    #[test]
+    fn uses_cached_desktop_interval() {
+        let config = config::configuration();
+        assert_eq!(config.mouse_click_interval_ms, None);
+        let settings = UiSettings {
+            double_click_interval: Some(std::time::Duration::from_millis(250)),
+            ..UiSettings::default()
+        };
+        update_desktop_interval(&settings);
+        assert_eq!(click_interval_ms(&config), 250);
+        update_desktop_interval(&UiSettings::default());
+        assert_eq!(click_interval_ms(&config), DEFAULT_INTERVAL_MS);
+    }
+}
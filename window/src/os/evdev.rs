@@ -0,0 +1,201 @@
+//! Maps the Linux evdev key codes to the physical location of the key,
+//! so that X11 and Wayland agree on which key was pressed regardless
+//! of the keyboard layout.
+//! See </usr/include/linux/input-event-codes.h> for the codes.
+use wezterm_input_types::PhysKeyCode;
+
+/// xkb keycodes, which is what X11 reports when using the evdev driver,
+/// are the evdev code plus this offset
+pub(crate) const XKB_KEYCODE_OFFSET: u32 = 8;
+
+/// Wayland reports the evdev code; convert it to an xkb keycode
+pub(crate) fn wayland_key_to_xkb_keycode(key: u32) -> u32 {
+    key + XKB_KEYCODE_OFFSET
+}
+
+/// Returns the physical key for an xkb keycode
+pub(crate) fn xkb_keycode_to_phys(keycode: u32) -> Option<PhysKeyCode> {
+    evdev_to_phys(keycode.checked_sub(XKB_KEYCODE_OFFSET)?)
+}
+
+/// Returns the physical key for an evdev key code
+fn evdev_to_phys(code: u32) -> Option<PhysKeyCode> {
+    Some(match code {
+        1 => PhysKeyCode::Escape,          // KEY_ESC
+        2 => PhysKeyCode::K1,              // KEY_1
+        3 => PhysKeyCode::K2,              // KEY_2
+        4 => PhysKeyCode::K3,              // KEY_3
+        5 => PhysKeyCode::K4,              // KEY_4
+        6 => PhysKeyCode::K5,              // KEY_5
+        7 => PhysKeyCode::K6,              // KEY_6
+        8 => PhysKeyCode::K7,              // KEY_7
+        9 => PhysKeyCode::K8,              // KEY_8
+        10 => PhysKeyCode::K9,             // KEY_9
+        11 => PhysKeyCode::K0,             // KEY_0
+        12 => PhysKeyCode::Minus,          // KEY_MINUS
+        13 => PhysKeyCode::Equal,          // KEY_EQUAL
+        14 => PhysKeyCode::Backspace,      // KEY_BACKSPACE
+        15 => PhysKeyCode::Tab,            // KEY_TAB
+        16 => PhysKeyCode::Q,              // KEY_Q
+        17 => PhysKeyCode::W,              // KEY_W
+        18 => PhysKeyCode::E,              // KEY_E
+        19 => PhysKeyCode::R,              // KEY_R
+        20 => PhysKeyCode::T,              // KEY_T
+        21 => PhysKeyCode::Y,              // KEY_Y
+        22 => PhysKeyCode::U,              // KEY_U
+        23 => PhysKeyCode::I,              // KEY_I
+        24 => PhysKeyCode::O,              // KEY_O
+        25 => PhysKeyCode::P,              // KEY_P
+        26 => PhysKeyCode::LeftBracket,    // KEY_LEFTBRACE
+        27 => PhysKeyCode::RightBracket,   // KEY_RIGHTBRACE
+        28 => PhysKeyCode::Return,         // KEY_ENTER
+        29 => PhysKeyCode::LeftControl,    // KEY_LEFTCTRL
+        30 => PhysKeyCode::A,              // KEY_A
+        31 => PhysKeyCode::S,              // KEY_S
+        32 => PhysKeyCode::D,              // KEY_D
+        33 => PhysKeyCode::F,              // KEY_F
+        34 => PhysKeyCode::G,              // KEY_G
+        35 => PhysKeyCode::H,              // KEY_H
+        36 => PhysKeyCode::J,              // KEY_J
+        37 => PhysKeyCode::K,              // KEY_K
+        38 => PhysKeyCode::L,              // KEY_L
+        39 => PhysKeyCode::Semicolon,      // KEY_SEMICOLON
+        40 => PhysKeyCode::Quote,          // KEY_APOSTROPHE
+        41 => PhysKeyCode::Grave,          // KEY_GRAVE
+        42 => PhysKeyCode::LeftShift,      // KEY_LEFTSHIFT
+        43 => PhysKeyCode::Backslash,      // KEY_BACKSLASH
+        44 => PhysKeyCode::Z,              // KEY_Z
+        45 => PhysKeyCode::X,              // KEY_X
+        46 => PhysKeyCode::C,              // KEY_C
+        47 => PhysKeyCode::V,              // KEY_V
+        48 => PhysKeyCode::B,              // KEY_B
+        49 => PhysKeyCode::N,              // KEY_N
+        50 => PhysKeyCode::M,              // KEY_M
+        51 => PhysKeyCode::Comma,          // KEY_COMMA
+        52 => PhysKeyCode::Period,         // KEY_DOT
+        53 => PhysKeyCode::Slash,          // KEY_SLASH
+        54 => PhysKeyCode::RightShift,     // KEY_RIGHTSHIFT
+        55 => PhysKeyCode::KeypadMultiply, // KEY_KPASTERISK
+        56 => PhysKeyCode::LeftAlt,        // KEY_LEFTALT
+        57 => PhysKeyCode::Space,          // KEY_SPACE
+        58 => PhysKeyCode::CapsLock,       // KEY_CAPSLOCK
+        59 => PhysKeyCode::F1,             // KEY_F1
+        60 => PhysKeyCode::F2,             // KEY_F2
+        61 => PhysKeyCode::F3,             // KEY_F3
+        62 => PhysKeyCode::F4,             // KEY_F4
+        63 => PhysKeyCode::F5,             // KEY_F5
+        64 => PhysKeyCode::F6,             // KEY_F6
+        65 => PhysKeyCode::F7,             // KEY_F7
+        66 => PhysKeyCode::F8,             // KEY_F8
+        67 => PhysKeyCode::F9,             // KEY_F9
+        68 => PhysKeyCode::F10,            // KEY_F10
+        69 => PhysKeyCode::NumLock,        // KEY_NUMLOCK
+        71 => PhysKeyCode::Keypad7,        // KEY_KP7
+        72 => PhysKeyCode::Keypad8,        // KEY_KP8
+        73 => PhysKeyCode::Keypad9,        // KEY_KP9
+        74 => PhysKeyCode::KeypadSubtract, // KEY_KPMINUS
+        75 => PhysKeyCode::Keypad4,        // KEY_KP4
+        76 => PhysKeyCode::Keypad5,        // KEY_KP5
+        77 => PhysKeyCode::Keypad6,        // KEY_KP6
+        78 => PhysKeyCode::KeypadAdd,      // KEY_KPPLUS
+        79 => PhysKeyCode::Keypad1,        // KEY_KP1
+        80 => PhysKeyCode::Keypad2,        // KEY_KP2
+        81 => PhysKeyCode::Keypad3,        // KEY_KP3
+        82 => PhysKeyCode::Keypad0,        // KEY_KP0
+        83 => PhysKeyCode::KeypadDelete,   // KEY_KPDOT
+        87 => PhysKeyCode::F11,            // KEY_F11
+        88 => PhysKeyCode::F12,            // KEY_F12
+        96 => PhysKeyCode::KeypadEnter,    // KEY_KPENTER
+        97 => PhysKeyCode::RightControl,   // KEY_RIGHTCTRL
+        98 => PhysKeyCode::KeypadDivide,   // KEY_KPSLASH
+        100 => PhysKeyCode::RightAlt,      // KEY_RIGHTALT
+        102 => PhysKeyCode::Home,          // KEY_HOME
+        103 => PhysKeyCode::UpArrow,       // KEY_UP
+        104 => PhysKeyCode::PageUp,        // KEY_PAGEUP
+        105 => PhysKeyCode::LeftArrow,     // KEY_LEFT
+        106 => PhysKeyCode::RightArrow,    // KEY_RIGHT
+        107 => PhysKeyCode::End,           // KEY_END
+        108 => PhysKeyCode::DownArrow,     // KEY_DOWN
+        109 => PhysKeyCode::PageDown,      // KEY_PAGEDOWN
+        110 => PhysKeyCode::Insert,        // KEY_INSERT
+        111 => PhysKeyCode::Delete,        // KEY_DELETE
+        113 => PhysKeyCode::VolumeMute,    // KEY_MUTE
+        114 => PhysKeyCode::VolumeDown,    // KEY_VOLUMEDOWN
+        115 => PhysKeyCode::VolumeUp,      // KEY_VOLUMEUP
+        117 => PhysKeyCode::KeypadEquals,  // KEY_KPEQUAL
+        125 => PhysKeyCode::LeftWindows,   // KEY_LEFTMETA
+        126 => PhysKeyCode::RightWindows,  // KEY_RIGHTMETA
+        138 => PhysKeyCode::Help,          // KEY_HELP
+        183 => PhysKeyCode::F13,           // KEY_F13
+        184 => PhysKeyCode::F14,           // KEY_F14
+        185 => PhysKeyCode::F15,           // KEY_F15
+        186 => PhysKeyCode::F16,           // KEY_F16
+        187 => PhysKeyCode::F17,           // KEY_F17
+        188 => PhysKeyCode::F18,           // KEY_F18
+        189 => PhysKeyCode::F19,           // KEY_F19
+        190 => PhysKeyCode::F20,           // KEY_F20
+        191 => PhysKeyCode::F21,           // KEY_F21
+        192 => PhysKeyCode::F22,           // KEY_F22
+        193 => PhysKeyCode::F23,           // KEY_F23
+        194 => PhysKeyCode::F24,           // KEY_F24
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn x11_and_wayland_agree() {
+        // The X11 keycodes are from </usr/share/X11/xkb/keycodes/evdev>
+        // and the Wayland keys are the evdev codes for the same key
+        for &(name, x11_keycode, wayland_key, phys) in &[
+            ("ESC", 9, 1, PhysKeyCode::Escape),
+            ("AE01", 10, 2, PhysKeyCode::K1),
+            ("AE10", 19, 11, PhysKeyCode::K0),
+            ("AD01", 24, 16, PhysKeyCode::Q),
+            ("AC01", 38, 30, PhysKeyCode::A),
+            ("AB01", 52, 44, PhysKeyCode::Z),
+            ("AB10", 61, 53, PhysKeyCode::Slash),
+            ("TLDE", 49, 41, PhysKeyCode::Grave),
+            ("BKSL", 51, 43, PhysKeyCode::Backslash),
+            ("FK01", 67, 59, PhysKeyCode::F1),
+            ("FK05", 71, 63, PhysKeyCode::F5),
+            ("FK11", 95, 87, PhysKeyCode::F11),
+            ("FK13", 191, 183, PhysKeyCode::F13),
+            ("FK24", 202, 194, PhysKeyCode::F24),
+            ("LFSH", 50, 42, PhysKeyCode::LeftShift),
+            ("RCTL", 105, 97, PhysKeyCode::RightControl),
+            ("LWIN", 133, 125, PhysKeyCode::LeftWindows),
+            ("KP7", 79, 71, PhysKeyCode::Keypad7),
+            ("KPDL", 91, 83, PhysKeyCode::KeypadDelete),
+            ("KPEN", 104, 96, PhysKeyCode::KeypadEnter),
+            ("KPEQ", 125, 117, PhysKeyCode::KeypadEquals),
+            ("UP", 111, 103, PhysKeyCode::UpArrow),
+            ("DELE", 119, 111, PhysKeyCode::Delete),
+            ("MUTE", 121, 113, PhysKeyCode::VolumeMute),
+            ("HELP", 146, 138, PhysKeyCode::Help),
+        ] {
+            assert_eq!(xkb_keycode_to_phys(x11_keycode), Some(phys), "x11 {}", name);
+            assert_eq!(
+                xkb_keycode_to_phys(wayland_key_to_xkb_keycode(wayland_key)),
+                Some(phys),
+                "wayland {}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_keys() {
+        // Below the offset; X11 never reports these
+        for keycode in 0..XKB_KEYCODE_OFFSET {
+            assert_eq!(xkb_keycode_to_phys(keycode), None);
+        }
+        // KEY_RESERVED
+        assert_eq!(xkb_keycode_to_phys(wayland_key_to_xkb_keycode(0)), None);
+        // KEY_102ND, which has no location on an ANSI keyboard
+        assert_eq!(xkb_keycode_to_phys(wayland_key_to_xkb_keycode(86)), None);
+    }
+}
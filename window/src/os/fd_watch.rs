@@ -0,0 +1,303 @@
+//! Lets applications have the event loop on the main thread monitor
+//! their own file descriptors, rather than having to dedicate a thread
+//! to each of them and relay the results via the spawn queue.
+use mio::event::Event;
+use mio::unix::SourceFd;
+use mio::{Interest, Registry, Token};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::os::unix::io::RawFd;
+use std::rc::{Rc, Weak};
+
+/// The kind of io that a watched file descriptor is monitored for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FdInterest {
+    Readable,
+    Writable,
+    ReadWrite,
+}
+
+impl FdInterest {
+    fn to_mio(self) -> Interest {
+        match self {
+            Self::Readable => Interest::READABLE,
+            Self::Writable => Interest::WRITABLE,
+            Self::ReadWrite => Interest::READABLE | Interest::WRITABLE,
+        }
+    }
+}
+
+/// Passed to the callback of a watched file descriptor
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FdReadiness {
+    pub readable: bool,
+    pub writable: bool,
+    /// The peer hung up, or there is a pending error
+    pub closed: bool,
+}
+
+impl FdReadiness {
+    fn merge(&mut self, other: Self) {
+        self.readable |= other.readable;
+        self.writable |= other.writable;
+        self.closed |= other.closed;
+    }
+}
+
+impl From<&Event> for FdReadiness {
+    fn from(event: &Event) -> Self {
+        Self {
+            readable: event.is_readable(),
+            writable: event.is_writable(),
+            closed: event.is_read_closed() || event.is_write_closed() || event.is_error(),
+        }
+    }
+}
+
+type WatchCallback = Rc<RefCell<dyn FnMut(FdReadiness)>>;
+
+struct Watch {
+    fd: RawFd,
+    interest: FdInterest,
+    callback: WatchCallback,
+}
+
+/// The file descriptors that an application has asked the event
+/// loop of a connection to watch.
+/// Watches can be added before the event loop has started; they are
+/// registered with its poll instance once it has.
+#[derive(Default)]
+pub(crate) struct FdWatchers {
+    watches: RefCell<HashMap<Token, Watch>>,
+    /// Readiness that arrived while the callback of its watch was
+    /// already running further up the stack
+    deferred: RefCell<HashMap<Token, FdReadiness>>,
+    next_token: Cell<usize>,
+    registry: RefCell<Option<Registry>>,
+}
+
+impl FdWatchers {
+    pub(crate) fn watch(
+        self: &Rc<Self>,
+        fd: RawFd,
+        interest: FdInterest,
+        callback: impl FnMut(FdReadiness) + 'static,
+    ) -> anyhow::Result<FdWatch> {
+        let token = Token(self.next_token.get());
+        self.next_token.set(token.0 + 1);
+
+        if let Some(registry) = self.registry.borrow().as_ref() {
+            registry.register(&mut SourceFd(&fd), token, interest.to_mio())?;
+        }
+        let callback: WatchCallback = Rc::new(RefCell::new(callback));
+        self.watches.borrow_mut().insert(
+            token,
+            Watch {
+                fd,
+                interest,
+                callback,
+            },
+        );
+
+        Ok(FdWatch {
+            watchers: Rc::downgrade(self),
+            token,
+        })
+    }
+
+    fn unwatch(&self, token: Token) {
+        self.deferred.borrow_mut().remove(&token);
+        let Some(watch) = self.watches.borrow_mut().remove(&token) else {
+            return;
+        };
+        if let Some(registry) = self.registry.borrow().as_ref() {
+            if let Err(err) = registry.deregister(&mut SourceFd(&watch.fd)) {
+                // Most likely the application closed the fd already
+                log::debug!("failed to stop watching fd {}: {err:#}", watch.fd);
+            }
+        }
+    }
+
+    /// Register the watches with the poll instance of an event loop
+    /// that is about to start running
+    pub(crate) fn attach(&self, registry: &Registry) -> anyhow::Result<()> {
+        let registry = registry.try_clone()?;
+        for (token, watch) in self.watches.borrow().iter() {
+            registry.register(&mut SourceFd(&watch.fd), *token, watch.interest.to_mio())?;
+        }
+        self.registry.replace(Some(registry));
+        Ok(())
+    }
+
+    /// Call the callback of the watch that produced `event`, if any.
+    /// Returns false if the event didn't belong to a watch.
+    pub(crate) fn dispatch(&self, event: &Event) -> bool {
+        // The borrow is released before calling the callback,
+        // as it is free to add or remove watches
+        let callback = match self.watches.borrow().get(&event.token()) {
+            Some(watch) => Rc::clone(&watch.callback),
+            None => return false,
+        };
+        let token = event.token();
+        let mut readiness = FdReadiness::from(event);
+        loop {
+            // The callback may run a nested event loop (for example, by
+            // blocking on a future); don't re-enter it from there.
+            // The events are edge triggered, so hold on to the readiness
+            // and deliver it from here once the callback has returned.
+            match callback.try_borrow_mut() {
+                Ok(mut callback) => (&mut *callback)(readiness),
+                Err(_) => {
+                    self.deferred
+                        .borrow_mut()
+                        .entry(token)
+                        .or_default()
+                        .merge(readiness);
+                    return true;
+                }
+            }
+            match self.deferred.borrow_mut().remove(&token) {
+                Some(deferred) => readiness = deferred,
+                None => return true,
+            }
+        }
+    }
+}
+
+/// Returned by `Connection::watch_fd`.
+/// The file descriptor is watched for as long as this is kept alive.
+#[must_use = "the file descriptor is no longer watched once the FdWatch is dropped"]
+pub struct FdWatch {
+    watchers: Weak<FdWatchers>,
+    token: Token,
+}
+
+impl FdWatch {
+    /// Stop watching the file descriptor. This is equivalent to
+    /// dropping the FdWatch.
+    pub fn unwatch(self) {}
+}
+
+impl Drop for FdWatch {
+    fn drop(&mut self) {
+        if let Some(watchers) = self.watchers.upgrade() {
+            watchers.unwatch(self.token);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use filedescriptor::Pipe;
+    use mio::{Events, Poll};
+    use std::io::Write;
+    use std::os::unix::io::AsRawFd;
+    use std::time::Duration;
+
+    fn poll_once(poll: &mut Poll, watchers: &FdWatchers) -> Vec<bool> {
+        let mut events = Events::with_capacity(8);
+        poll.poll(&mut events, Some(Duration::from_millis(100)))
+            .unwrap();
+        events
+            .iter()
+            .map(|event| watchers.dispatch(event))
+            .collect()
+    }
+
+    #[test]
+    fn readable_fd_invokes_callback() {
+        let mut poll = Poll::new().unwrap();
+        let watchers = Rc::new(FdWatchers::default());
+        let mut pipe = Pipe::new().unwrap();
+
+        let readiness = Rc::new(RefCell::new(vec![]));
+        // Watching before the loop is attached defers the registration
+        let watch = {
+            let readiness = Rc::clone(&readiness);
+            watchers
+                .watch(pipe.read.as_raw_fd(), FdInterest::Readable, move |r| {
+                    readiness.borrow_mut().push(r)
+                })
+                .unwrap()
+        };
+        watchers.attach(poll.registry()).unwrap();
+
+        pipe.write.write_all(b"x").unwrap();
+        assert_eq!(poll_once(&mut poll, &watchers), vec![true]);
+        assert_eq!(readiness.borrow().len(), 1);
+        assert!(readiness.borrow()[0].readable);
+
+        watch.unwatch();
+        assert!(watchers.watches.borrow().is_empty());
+        pipe.write.write_all(b"x").unwrap();
+        assert_eq!(poll_once(&mut poll, &watchers), vec![]);
+        assert_eq!(readiness.borrow().len(), 1);
+    }
+
+    #[test]
+    fn callback_can_unwatch_itself() {
+        let mut poll = Poll::new().unwrap();
+        let watchers = Rc::new(FdWatchers::default());
+        watchers.attach(poll.registry()).unwrap();
+        let mut pipe = Pipe::new().unwrap();
+
+        let slot: Rc<RefCell<Option<FdWatch>>> = Rc::new(RefCell::new(None));
+        let watch = {
+            let slot = Rc::clone(&slot);
+            watchers
+                .watch(pipe.read.as_raw_fd(), FdInterest::Readable, move |_| {
+                    slot.borrow_mut().take();
+                })
+                .unwrap()
+        };
+        slot.borrow_mut().replace(watch);
+
+        pipe.write.write_all(b"x").unwrap();
+        assert_eq!(poll_once(&mut poll, &watchers), vec![true]);
+        assert!(slot.borrow().is_none());
+        assert!(watchers.watches.borrow().is_empty());
+    }
+
+    #[test]
+    fn readiness_during_nested_loop_is_deferred() {
+        let poll = Rc::new(RefCell::new(Poll::new().unwrap()));
+        let watchers = Rc::new(FdWatchers::default());
+        watchers.attach(poll.borrow().registry()).unwrap();
+        let mut pipe = Pipe::new().unwrap();
+        let write = Rc::new(RefCell::new(pipe.write.try_clone().unwrap()));
+
+        let calls = Rc::new(Cell::new(0));
+        let nested = Rc::new(RefCell::new(vec![]));
+        let _watch = {
+            let calls = Rc::clone(&calls);
+            let nested = Rc::clone(&nested);
+            let poll = Rc::clone(&poll);
+            let weak = Rc::downgrade(&watchers);
+            watchers
+                .watch(pipe.read.as_raw_fd(), FdInterest::Readable, move |_| {
+                    calls.set(calls.get() + 1);
+                    if calls.get() == 1 {
+                        // Simulate blocking on a future from within the
+                        // callback, during which the fd becomes ready again
+                        write.borrow_mut().write_all(b"y").unwrap();
+                        let watchers = weak.upgrade().unwrap();
+                        *nested.borrow_mut() = poll_once(&mut poll.borrow_mut(), &watchers);
+                    }
+                })
+                .unwrap()
+        };
+
+        pipe.write.write_all(b"x").unwrap();
+        let mut events = Events::with_capacity(8);
+        poll.borrow_mut()
+            .poll(&mut events, Some(Duration::from_millis(100)))
+            .unwrap();
+        for event in events.iter() {
+            assert!(watchers.dispatch(event));
+        }
+        assert_eq!(*nested.borrow(), vec![true]);
+        assert_eq!(calls.get(), 2);
+        assert!(watchers.deferred.borrow().is_empty());
+    }
+}
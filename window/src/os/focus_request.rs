@@ -0,0 +1,88 @@
+use crate::FocusOutcome;
+use promise::Promise;
+use std::time::Duration;
+
+/// How long we wait for the window system to give us the focus
+/// before assuming that it declined to do so
+pub(crate) const FOCUS_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Tracks calls to WindowOps::focus that are waiting to find out
+/// whether the window system will move the focus to the window.
+/// Neither X11 nor Wayland tell us when they decline, so requests
+/// that are still pending after FOCUS_TIMEOUT are considered deferred.
+#[derive(Default)]
+pub(crate) struct FocusRequests {
+    pending: Vec<(usize, Promise<FocusOutcome>)>,
+    next_id: usize,
+}
+
+impl FocusRequests {
+    /// Returns the id to pass to `timed_out` once FOCUS_TIMEOUT
+    /// has elapsed
+    pub(crate) fn push(&mut self, promise: Promise<FocusOutcome>) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.pending.push((id, promise));
+        id
+    }
+
+    /// The window gained the focus; resolve all pending requests.
+    /// Returns true if there were any, in which case the focus
+    /// was most likely gained because of them.
+    pub(crate) fn focused(&mut self) -> bool {
+        let requested = !self.pending.is_empty();
+        for (_, mut promise) in self.pending.drain(..) {
+            promise.ok(FocusOutcome::Focused);
+        }
+        requested
+    }
+
+    /// Resolves the request as deferred if it is still pending.
+    /// Returns true if it was, in which case the caller should
+    /// request attention instead.
+    pub(crate) fn timed_out(&mut self, id: usize) -> bool {
+        let Some(idx) = self.pending.iter().position(|(i, _)| *i == id) else {
+            return false;
+        };
+        let (_, mut promise) = self.pending.remove(idx);
+        promise.ok(FocusOutcome::Deferred);
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures_lite::future::block_on;
+
+    #[test]
+    fn focus_resolves_pending() {
+        let mut requests = FocusRequests::default();
+        let mut promise = Promise::new();
+        let future = promise.get_future().unwrap();
+        let id = requests.push(promise);
+
+        assert!(requests.focused());
+        assert_eq!(block_on(future).unwrap(), FocusOutcome::Focused);
+        // Already resolved, so the timeout has nothing to do
+        assert!(!requests.timed_out(id));
+        assert!(!requests.focused());
+    }
+
+    #[test]
+    fn timeout_defers() {
+        let mut requests = FocusRequests::default();
+        let mut first = Promise::new();
+        let first_future = first.get_future().unwrap();
+        let first_id = requests.push(first);
+        let mut second = Promise::new();
+        let second_future = second.get_future().unwrap();
+        requests.push(second);
+
+        assert!(requests.timed_out(first_id));
+        assert_eq!(block_on(first_future).unwrap(), FocusOutcome::Deferred);
+
+        requests.focused();
+        assert_eq!(block_on(second_future).unwrap(), FocusOutcome::Focused);
+    }
+}
@@ -0,0 +1,187 @@
+use crate::WindowState;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Windowed {
+    width: usize,
+    height: usize,
+    maximized: bool,
+}
+
+/// Remembers the size of a window while it is not full screen, so that
+/// it can be put back when leaving full screen. Neither X11 window
+/// managers nor Wayland compositors reliably restore it themselves,
+/// and some propose an arbitrary size instead.
+/// It also holds on to sizes that the application requests while the
+/// window is full screen or maximized, until the window is back in a
+/// state where they can be applied.
+#[derive(Debug, Default)]
+pub(crate) struct FullScreenRestore {
+    /// The size and state prior to entering full screen
+    windowed: Option<Windowed>,
+    in_full_screen: bool,
+    maximized: bool,
+    /// The size from defer_resize
+    deferred: Option<(usize, usize)>,
+}
+
+impl FullScreenRestore {
+    /// Call this whenever the window system has configured the window
+    /// with a new size or state. Returns the size that should be
+    /// requested if the window just left full screen and didn't get
+    /// back the size that it had before, or if a resize was deferred
+    /// until the window returned to its normal state.
+    pub(crate) fn configured(
+        &mut self,
+        state: WindowState,
+        width: usize,
+        height: usize,
+    ) -> Option<(usize, usize)> {
+        if state.contains(WindowState::FULL_SCREEN) {
+            self.in_full_screen = true;
+            return None;
+        }
+
+        let current = Windowed {
+            width,
+            height,
+            maximized: state.contains(WindowState::MAXIMIZED),
+        };
+        self.maximized = current.maximized;
+        let left_full_screen = std::mem::take(&mut self.in_full_screen);
+        let prior = self.windowed.replace(current);
+        if self.maximized {
+            return None;
+        }
+
+        // A deferred size only gets one chance: if it can't be applied
+        // now, the user is sizing the window and it is forgotten
+        let deferred = self.deferred.take();
+        if !state.can_resize() || state.intersects(WindowState::TILED) {
+            return None;
+        }
+        if let Some(size) = deferred {
+            if size == (width, height) {
+                return None;
+            }
+            self.windowed.replace(Windowed {
+                width: size.0,
+                height: size.1,
+                maximized: false,
+            });
+            return Some(size);
+        }
+        if !left_full_screen {
+            return None;
+        }
+
+        match prior {
+            // If we were maximized, the window system is responsible
+            // for sizing the window again
+            Some(prior) if !prior.maximized && (prior.width, prior.height) != (width, height) => {
+                self.windowed.replace(prior);
+                Some((prior.width, prior.height))
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether the window system is dictating the size of the window,
+    /// so that there is no point in asking it for a different one
+    pub(crate) fn constrained(&self) -> bool {
+        self.in_full_screen || self.maximized
+    }
+
+    /// Resizing a full screen or maximized window doesn't make sense,
+    /// so call this from set_inner_size. If it returns true, the size
+    /// has been recorded so that it is applied when the window returns
+    /// to its normal state, and the caller should do nothing more.
+    pub(crate) fn defer_resize(&mut self, width: usize, height: usize) -> bool {
+        if !self.constrained() {
+            return false;
+        }
+        self.deferred.replace((width, height));
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn restores_size() {
+        let mut restore = FullScreenRestore::default();
+        assert_eq!(restore.configured(WindowState::default(), 800, 600), None);
+        assert_eq!(
+            restore.configured(WindowState::FULL_SCREEN, 1920, 1080),
+            None
+        );
+        // The window system proposed some other size
+        assert_eq!(
+            restore.configured(WindowState::default(), 1000, 700),
+            Some((800, 600))
+        );
+        // which is what we see once our request is applied
+        assert_eq!(restore.configured(WindowState::default(), 800, 600), None);
+    }
+
+    #[test]
+    fn size_already_restored() {
+        let mut restore = FullScreenRestore::default();
+        restore.configured(WindowState::default(), 800, 600);
+        restore.configured(WindowState::FULL_SCREEN, 1920, 1080);
+        assert_eq!(restore.configured(WindowState::default(), 800, 600), None);
+    }
+
+    #[test]
+    fn maximized_is_left_alone() {
+        let mut restore = FullScreenRestore::default();
+        restore.configured(WindowState::MAXIMIZED, 1920, 1050);
+        restore.configured(WindowState::FULL_SCREEN, 1920, 1080);
+        assert_eq!(restore.configured(WindowState::MAXIMIZED, 1920, 1050), None);
+
+        // Un-maximizing while full screen is also the window system's business
+        restore.configured(WindowState::FULL_SCREEN, 1920, 1080);
+        assert_eq!(restore.configured(WindowState::default(), 1000, 700), None);
+    }
+
+    #[test]
+    fn deferred_resize() {
+        let mut restore = FullScreenRestore::default();
+        assert!(!restore.defer_resize(640, 480));
+        restore.configured(WindowState::default(), 800, 600);
+        restore.configured(WindowState::FULL_SCREEN, 1920, 1080);
+        assert!(restore.defer_resize(640, 480));
+        assert_eq!(
+            restore.configured(WindowState::default(), 800, 600),
+            Some((640, 480))
+        );
+        assert!(!restore.defer_resize(1024, 768));
+    }
+
+    #[test]
+    fn deferred_while_maximized() {
+        let mut restore = FullScreenRestore::default();
+        restore.configured(WindowState::default(), 800, 600);
+        restore.configured(WindowState::MAXIMIZED, 1920, 1050);
+        assert!(restore.constrained());
+        assert!(restore.defer_resize(640, 480));
+        assert_eq!(
+            restore.configured(WindowState::default(), 800, 600),
+            Some((640, 480))
+        );
+        assert!(!restore.constrained());
+    }
+
+    #[test]
+    fn deferred_is_forgotten_after_manual_resize() {
+        let mut restore = FullScreenRestore::default();
+        restore.configured(WindowState::default(), 800, 600);
+        restore.configured(WindowState::FULL_SCREEN, 1920, 1080);
+        assert!(restore.defer_resize(640, 480));
+        // Leaving full screen for a tiled state, where we don't resize
+        assert_eq!(restore.configured(WindowState::TILED, 960, 1080), None);
+        // and then the user sizes the window themselves
+        assert_eq!(restore.configured(WindowState::default(), 1000, 700), None);
+    }
+}
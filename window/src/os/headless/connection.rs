@@ -0,0 +1,175 @@
+use super::window::HeadlessWindowInner;
+use crate::connection::{take_terminate_request, ConnectionOps};
+use crate::os::last_window::LastWindowTermination;
+use crate::screen::{ScreenInfo, Screens};
+use crate::spawn::SPAWN_QUEUE;
+use crate::{Appearance, Connection, ScreenRect, UiSettings, WindowEvent};
+use anyhow::{anyhow, bail};
+use filedescriptor::{poll, pollfd, POLLIN};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// The size of the single virtual screen that we report
+const SCREEN_WIDTH: isize = 1920;
+const SCREEN_HEIGHT: isize = 1080;
+
+pub struct HeadlessConnection {
+    next_window_id: RefCell<usize>,
+    pub(crate) windows: RefCell<HashMap<usize, Rc<RefCell<HeadlessWindowInner>>>>,
+    pub(crate) last_window: LastWindowTermination,
+    appearance: RefCell<Appearance>,
+}
+
+impl HeadlessConnection {
+    pub fn create_new() -> anyhow::Result<Self> {
+        Ok(Self {
+            next_window_id: RefCell::new(1),
+            windows: RefCell::new(HashMap::new()),
+            last_window: LastWindowTermination::default(),
+            appearance: RefCell::new(Appearance::Light),
+        })
+    }
+
+    pub(crate) fn next_window_id(&self) -> usize {
+        let mut next = self.next_window_id.borrow_mut();
+        let id = *next;
+        *next += 1;
+        id
+    }
+
+    pub(crate) fn window_by_id(
+        &self,
+        window_id: usize,
+    ) -> Option<Rc<RefCell<HeadlessWindowInner>>> {
+        self.windows.borrow().get(&window_id).map(Rc::clone)
+    }
+
+    pub(crate) fn advise_of_appearance_change(&self, appearance: Appearance) {
+        *self.appearance.borrow_mut() = appearance;
+        for win in self.windows.borrow().values() {
+            win.borrow_mut()
+                .events
+                .dispatch(WindowEvent::AppearanceChanged(appearance));
+        }
+    }
+
+    pub(crate) fn advise_of_ui_settings_change(&self, settings: UiSettings) {
+        for win in self.windows.borrow().values() {
+            win.borrow_mut()
+                .events
+                .dispatch(WindowEvent::UiSettingsChanged(settings));
+        }
+    }
+
+    pub(crate) fn with_window_inner<
+        R,
+        F: FnOnce(&mut HeadlessWindowInner) -> anyhow::Result<R> + 'static,
+    >(
+        window: usize,
+        f: F,
+    ) -> promise::Future<R>
+    where
+        R: Send + 'static,
+    {
+        let mut prom = promise::Promise::new();
+        let future = prom.get_future().unwrap();
+
+        // The headless connection is only ever used from the main thread,
+        // so we can use a local spawn and avoid requiring that f is Send
+        promise::spawn::spawn(async move {
+            let f = match Connection::get().unwrap().headless().window_by_id(window) {
+                Some(handle) => match handle.try_borrow_mut() {
+                    Ok(mut inner) => {
+                        prom.result(f(&mut inner));
+                        return;
+                    }
+                    Err(_) => f,
+                },
+                None => {
+                    prom.err(anyhow!("window {window} is gone"));
+                    return;
+                }
+            };
+
+            // The window is busy dispatching an event further up the
+            // stack; run once that has returned, as the other backends do
+            log::trace!("window {window} is busy; deferring");
+            prom.result(Self::with_window_inner(window, f).await);
+        })
+        .detach();
+
+        future
+    }
+}
+
+impl ConnectionOps for HeadlessConnection {
+    fn name(&self) -> String {
+        "Headless".to_string()
+    }
+
+    fn terminate_message_loop(&self) {
+        Self::request_terminate();
+    }
+
+    fn set_terminate_when_last_window_closed(&self, terminate: bool) {
+        self.last_window.set_enabled(terminate);
+    }
+
+    fn get_appearance(&self) -> Appearance {
+        *self.appearance.borrow()
+    }
+
+    fn run_message_loop(&self) -> anyhow::Result<()> {
+        let mut pfd = [pollfd {
+            fd: SPAWN_QUEUE.raw_fd(),
+            events: POLLIN,
+            revents: 0,
+        }];
+
+        while !take_terminate_request() {
+            if SPAWN_QUEUE.run() {
+                continue;
+            }
+
+            if self
+                .last_window
+                .should_terminate(!self.windows.borrow().is_empty())
+            {
+                break;
+            }
+
+            // There are no other event sources, so we can sleep until
+            // something is spawned
+            if let Err(err) = poll(&mut pfd, None) {
+                if let filedescriptor::Error::Poll(ref io) = err {
+                    if io.kind() == std::io::ErrorKind::Interrupted {
+                        continue;
+                    }
+                }
+                bail!("polling for events: {:?}", err);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn screens(&self) -> anyhow::Result<Screens> {
+        let rect: ScreenRect = euclid::rect(0, 0, SCREEN_WIDTH, SCREEN_HEIGHT);
+        let main = ScreenInfo {
+            name: "headless".to_string(),
+            rect,
+            scale: 1.0,
+            max_fps: None,
+            effective_dpi: Some(self.default_dpi()),
+        };
+        let mut by_name = HashMap::new();
+        by_name.insert(main.name.clone(), main.clone());
+        Ok(Screens {
+            main: main.clone(),
+            active: main,
+            by_name,
+            virtual_rect: rect,
+        })
+    }
+}
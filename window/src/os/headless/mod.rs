@@ -0,0 +1,12 @@
+#![cfg(all(unix, not(target_os = "macos")))]
+//! A windowing backend that doesn't talk to any display server.
+//! Windows created through it exist only in memory: synthetic input
+//! can be injected into them and the operations that the application
+//! performs on them are recorded, which allows exercising the window
+//! event plumbing in tests and on CI without X11 or a compositor.
+//! It is selected by setting `WEZTERM_WINDOW_BACKEND=headless`.
+
+pub mod connection;
+pub mod window;
+pub use self::window::*;
+pub use connection::*;
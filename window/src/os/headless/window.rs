@@ -0,0 +1,1179 @@
+use super::connection::HeadlessConnection;
+use crate::{
+    Clipboard, Connection, ConnectionOps, ConstrainedResize, Dimensions, FocusOutcome, FocusReason,
+    InputSerial, KeyEvent, MouseCursor, MouseEvent, Rect, RequestedWindowGeometry, ResizeRejected,
+    ResolvedGeometry, ScreenPoint, Window, WindowEvent, WindowEventSender, WindowOps,
+    WindowOptions, WindowState,
+};
+use anyhow::anyhow;
+use async_trait::async_trait;
+use config::window::WindowLevel;
+use config::ConfigHandle;
+use promise::Future;
+use raw_window_handle::{
+    DisplayHandle, HandleError, HasDisplayHandle, HasWindowHandle, WindowHandle,
+};
+use std::any::Any;
+use std::cell::RefCell;
+use std::rc::Rc;
+use wezterm_font::FontConfiguration;
+
+/// An operation that the application performed on a headless window.
+/// These are recorded so that the effect of injected events on the
+/// application can be verified.
+#[derive(Debug, Clone, PartialEq)]
+pub enum HeadlessWindowOp {
+    Show,
+    Hide,
+    Minimize,
+    Close,
+    Focus,
+    RequestAttention,
+    Invalidate,
+    Maximize,
+    Restore,
+    ToggleFullScreen,
+    SetCursor(Option<MouseCursor>),
+    SetTitle(String),
+    SetAlwaysOnTop(bool),
+    SetWindowLevel(WindowLevel),
+    SetSkipTaskbar(bool),
+    SetWindowClass {
+        class_name: String,
+        instance_name: Option<String>,
+    },
+    SetInnerSize {
+        width: usize,
+        height: usize,
+    },
+    SetWindowPosition(ScreenPoint),
+    SetTextCursorPosition(Rect),
+    SetClipboard(Clipboard, String),
+}
+
+pub(crate) struct HeadlessWindowInner {
+    window_id: usize,
+    pub(crate) events: WindowEventSender,
+    dimensions: Dimensions,
+    window_state: WindowState,
+    position: ScreenPoint,
+    // None until the window is first shown
+    has_focus: Option<bool>,
+    clipboard: String,
+    primary_selection: String,
+    ops: Vec<HeadlessWindowOp>,
+}
+
+impl HeadlessWindowInner {
+    fn record(&mut self, op: HeadlessWindowOp) {
+        log::trace!("headless window {} {:?}", self.window_id, op);
+        self.ops.push(op);
+    }
+
+    fn resize(&mut self, dimensions: Dimensions) {
+        self.dimensions = dimensions;
+        self.events.dispatch(WindowEvent::Resized {
+            dimensions,
+            window_state: self.window_state,
+            live_resizing: false,
+        });
+    }
+
+    fn set_window_state(&mut self, window_state: WindowState) {
+        if window_state != self.window_state {
+            self.window_state = window_state;
+            self.resize(self.dimensions);
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Ord, PartialOrd)]
+pub struct HeadlessWindow(usize);
+
+impl HeadlessWindow {
+    pub async fn new_window<F>(
+        _class_name: &str,
+        name: &str,
+        geometry: RequestedWindowGeometry,
+        config: Option<&ConfigHandle>,
+        _font_config: Rc<FontConfiguration>,
+        options: WindowOptions<'_>,
+        event_handler: F,
+    ) -> anyhow::Result<Window>
+    where
+        F: 'static + FnMut(WindowEvent, &Window),
+    {
+        let config = match config {
+            Some(c) => c.clone(),
+            None => config::configuration(),
+        };
+
+        let conn = Connection::get()
+            .ok_or_else(|| {
+                anyhow!(
+                    "new_window must be called on the gui thread after Connection:init has succeed",
+                )
+            })?
+            .headless();
+
+        let window_id = conn.next_window_id();
+
+        let ResolvedGeometry {
+            x: _,
+            y: _,
+            width,
+            height,
+        } = conn.resolve_geometry(geometry);
+
+        let dimensions = Dimensions {
+            pixel_width: width,
+            pixel_height: height,
+            dpi: config.dpi.unwrap_or(crate::DEFAULT_DPI) as usize,
+        };
+
+        let ops = vec![HeadlessWindowOp::SetTitle(name.to_string())];
+        Ok(Self::insert_window(
+            &conn,
+            window_id,
+            dimensions,
+            options.initial_state.window_state(),
+            ops,
+            event_handler,
+        ))
+    }
+
+    /// Popups have nowhere to be placed, so this just creates a window
+    /// of the requested size
+    pub async fn new_popup<F>(
+        parent: &HeadlessWindow,
+        _anchor: Rect,
+        size: (usize, usize),
+        event_handler: F,
+    ) -> anyhow::Result<Window>
+    where
+        F: 'static + FnMut(WindowEvent, &Window),
+    {
+        let conn = Connection::get()
+            .ok_or_else(|| {
+                anyhow!(
+                    "new_popup must be called on the gui thread after Connection:init has succeed",
+                )
+            })?
+            .headless();
+
+        let dpi = conn
+            .window_by_id(parent.0)
+            .ok_or_else(|| anyhow!("parent window is gone"))?
+            .borrow()
+            .dimensions
+            .dpi;
+
+        let window_id = conn.next_window_id();
+        let dimensions = Dimensions {
+            pixel_width: size.0,
+            pixel_height: size.1,
+            dpi,
+        };
+
+        Ok(Self::insert_window(
+            &conn,
+            window_id,
+            dimensions,
+            WindowState::default(),
+            vec![],
+            event_handler,
+        ))
+    }
+
+    fn insert_window<F>(
+        conn: &HeadlessConnection,
+        window_id: usize,
+        dimensions: Dimensions,
+        window_state: WindowState,
+        ops: Vec<HeadlessWindowOp>,
+        event_handler: F,
+    ) -> Window
+    where
+        F: 'static + FnMut(WindowEvent, &Window),
+    {
+        let inner = Rc::new(RefCell::new(HeadlessWindowInner {
+            window_id,
+            events: WindowEventSender::new(event_handler),
+            dimensions,
+            window_state,
+            position: ScreenPoint::default(),
+            has_focus: None,
+            clipboard: String::new(),
+            primary_selection: String::new(),
+            ops,
+        }));
+
+        let window_handle = Window::Headless(HeadlessWindow(window_id));
+
+        inner
+            .borrow_mut()
+            .events
+            .assign_window(window_handle.clone());
+
+        conn.windows.borrow_mut().insert(window_id, inner);
+        conn.last_window.window_added();
+
+        window_handle
+    }
+
+    /// Deliver a synthetic event to the window's event handler, as
+    /// though it had been generated by the windowing system.
+    /// The returned future resolves once the handler has been called,
+    /// or to an error if the window has been closed.
+    pub fn inject_event(&self, event: WindowEvent) -> Future<()> {
+        HeadlessConnection::with_window_inner(self.0, move |inner| {
+            inner.events.dispatch(event);
+            Ok(())
+        })
+    }
+
+    /// Simulate the user clicking the close button
+    pub fn inject_close_request(&self) -> Future<()> {
+        HeadlessConnection::with_window_inner(self.0, |inner| {
+            inner.events.request_close();
+            Ok(())
+        })
+    }
+
+    pub fn inject_key_event(&self, key: KeyEvent) -> Future<()> {
+        self.inject_event(WindowEvent::KeyEvent(key))
+    }
+
+    pub fn inject_mouse_event(&self, event: MouseEvent) -> Future<()> {
+        self.inject_event(WindowEvent::MouseEvent(event))
+    }
+
+    /// Simulate the user resizing the window
+    pub fn inject_resize(&self, dimensions: Dimensions) -> Future<()> {
+        HeadlessConnection::with_window_inner(self.0, move |inner| {
+            inner.resize(dimensions);
+            Ok(())
+        })
+    }
+
+    /// Returns the operations that were performed on the window since
+    /// the last call to take_ops
+    pub fn take_ops(&self) -> Future<Vec<HeadlessWindowOp>> {
+        HeadlessConnection::with_window_inner(self.0, |inner| Ok(std::mem::take(&mut inner.ops)))
+    }
+}
+
+impl HasDisplayHandle for HeadlessWindow {
+    fn display_handle(&self) -> Result<DisplayHandle<'_>, HandleError> {
+        Err(HandleError::NotSupported)
+    }
+}
+
+impl HasWindowHandle for HeadlessWindow {
+    fn window_handle(&self) -> Result<WindowHandle<'_>, HandleError> {
+        Err(HandleError::NotSupported)
+    }
+}
+
+#[async_trait(?Send)]
+impl WindowOps for HeadlessWindow {
+    async fn enable_opengl(&self) -> anyhow::Result<Rc<glium::backend::Context>> {
+        anyhow::bail!("OpenGL is not available with the headless backend");
+    }
+
+    fn notify<T: Any + Send + Sync>(&self, t: T)
+    where
+        Self: Sized,
+    {
+        HeadlessConnection::with_window_inner(self.0, move |inner| {
+            inner
+                .events
+                .dispatch(WindowEvent::Notification(Box::new(t)));
+            Ok(())
+        });
+    }
+
+    fn close(&self) {
+        HeadlessConnection::with_window_inner(self.0, |inner| {
+            inner.events.request_close();
+            Ok(())
+        });
+    }
+
+    fn force_close(&self) {
+        let window_id = self.0;
+        HeadlessConnection::with_window_inner(window_id, move |inner| {
+            inner.record(HeadlessWindowOp::Close);
+            inner.events.dispatch(WindowEvent::Destroyed);
+            Connection::get()
+                .unwrap()
+                .headless()
+                .windows
+                .borrow_mut()
+                .remove(&window_id);
+            Ok(())
+        });
+    }
+
+    fn show(&self) {
+        HeadlessConnection::with_window_inner(self.0, |inner| {
+            inner.record(HeadlessWindowOp::Show);
+            inner.set_window_state(inner.window_state - WindowState::HIDDEN);
+            if inner.has_focus.is_none() {
+                // Like most window managers, focus newly shown windows
+                inner.has_focus = Some(true);
+                inner
+                    .events
+                    .dispatch(WindowEvent::FocusChanged(true, FocusReason::Unknown));
+            }
+            Ok(())
+        });
+    }
+
+    fn hide(&self) {
+        HeadlessConnection::with_window_inner(self.0, |inner| {
+            inner.record(HeadlessWindowOp::Hide);
+            inner.set_window_state(inner.window_state | WindowState::HIDDEN);
+            Ok(())
+        });
+    }
+
+    fn minimize(&self) {
+        HeadlessConnection::with_window_inner(self.0, |inner| {
+            inner.record(HeadlessWindowOp::Minimize);
+            inner.set_window_state(inner.window_state | WindowState::HIDDEN);
+            Ok(())
+        });
+    }
+
+    fn focus(&self, _serial: Option<InputSerial>) -> Future<FocusOutcome> {
+        HeadlessConnection::with_window_inner(self.0, |inner| {
+            inner.record(HeadlessWindowOp::Focus);
+            if inner.has_focus != Some(true) {
+                inner.has_focus = Some(true);
+                inner
+                    .events
+                    .dispatch(WindowEvent::FocusChanged(true, FocusReason::Activation));
+            }
+            Ok(FocusOutcome::Focused)
+        })
+    }
+
+    fn request_attention(&self) {
+        HeadlessConnection::with_window_inner(self.0, |inner| {
+            inner.record(HeadlessWindowOp::RequestAttention);
+            Ok(())
+        });
+    }
+
+    fn set_cursor(&self, cursor: Option<MouseCursor>) {
+        HeadlessConnection::with_window_inner(self.0, move |inner| {
+            inner.record(HeadlessWindowOp::SetCursor(cursor));
+            Ok(())
+        });
+    }
+
+    fn invalidate(&self) {
+        HeadlessConnection::with_window_inner(self.0, |inner| {
+            inner.record(HeadlessWindowOp::Invalidate);
+            inner.events.dispatch(WindowEvent::NeedRepaint);
+            Ok(())
+        });
+    }
+
+    fn set_title(&self, title: &str) {
+        let title = title.to_owned();
+        HeadlessConnection::with_window_inner(self.0, move |inner| {
+            inner.record(HeadlessWindowOp::SetTitle(title));
+            Ok(())
+        });
+    }
+
+    fn set_always_on_top(&self, enable: bool) -> Future<()> {
+        HeadlessConnection::with_window_inner(self.0, move |inner| {
+            inner.record(HeadlessWindowOp::SetAlwaysOnTop(enable));
+            let mut window_state = inner.window_state;
+            window_state.set(WindowState::ALWAYS_ON_TOP, enable);
+            inner.set_window_state(window_state);
+            Ok(())
+        })
+    }
+
+    fn set_window_level(&self, level: WindowLevel) {
+        HeadlessConnection::with_window_inner(self.0, move |inner| {
+            let mut window_state = inner.window_state;
+            window_state.set(
+                WindowState::ALWAYS_ON_TOP,
+                level == WindowLevel::AlwaysOnTop,
+            );
+            window_state.set(
+                WindowState::ALWAYS_ON_BOTTOM,
+                level == WindowLevel::AlwaysOnBottom,
+            );
+            inner.record(HeadlessWindowOp::SetWindowLevel(level));
+            inner.set_window_state(window_state);
+            Ok(())
+        });
+    }
+
+    fn set_skip_taskbar(&self, enable: bool) -> Future<()> {
+        HeadlessConnection::with_window_inner(self.0, move |inner| {
+            inner.record(HeadlessWindowOp::SetSkipTaskbar(enable));
+            Ok(())
+        })
+    }
+
+    fn set_window_class(&self, class_name: &str, instance_name: Option<&str>) {
+        let class_name = class_name.to_owned();
+        let instance_name = instance_name.map(str::to_owned);
+        HeadlessConnection::with_window_inner(self.0, move |inner| {
+            inner.record(HeadlessWindowOp::SetWindowClass {
+                class_name,
+                instance_name,
+            });
+            Ok(())
+        });
+    }
+
+    fn set_inner_size(
+        &self,
+        width: usize,
+        height: usize,
+        constrained: ConstrainedResize,
+    ) -> Future<Dimensions> {
+        HeadlessConnection::with_window_inner(self.0, move |inner| {
+            inner.record(HeadlessWindowOp::SetInnerSize { width, height });
+            if constrained == ConstrainedResize::Reject && !inner.window_state.can_resize() {
+                inner.events.dispatch(WindowEvent::SetInnerSizeCompleted);
+                return Err(ResizeRejected {
+                    state: inner.window_state,
+                }
+                .into());
+            }
+            // There is no window system to fight with here, so a
+            // deferred resize is simply applied
+            let dimensions = Dimensions {
+                pixel_width: width,
+                pixel_height: height,
+                dpi: inner.dimensions.dpi,
+            };
+            // As with the other backends, asking for the current size
+            // doesn't produce a Resized event
+            if dimensions != inner.dimensions {
+                inner.resize(dimensions);
+            }
+            inner.events.dispatch(WindowEvent::SetInnerSizeCompleted);
+            Ok(inner.dimensions)
+        })
+    }
+
+    fn set_window_position(&self, coords: ScreenPoint) {
+        HeadlessConnection::with_window_inner(self.0, move |inner| {
+            inner.record(HeadlessWindowOp::SetWindowPosition(coords));
+            if coords != inner.position {
+                inner.position = coords;
+                inner.events.dispatch(WindowEvent::Moved(coords));
+            }
+            Ok(())
+        });
+    }
+
+    fn get_window_position(&self) -> Future<Option<ScreenPoint>> {
+        HeadlessConnection::with_window_inner(self.0, |inner| Ok(Some(inner.position)))
+    }
+
+    fn set_text_cursor_position(&self, cursor: Rect) {
+        HeadlessConnection::with_window_inner(self.0, move |inner| {
+            inner.record(HeadlessWindowOp::SetTextCursorPosition(cursor));
+            Ok(())
+        });
+    }
+
+    fn maximize(&self) {
+        HeadlessConnection::with_window_inner(self.0, |inner| {
+            inner.record(HeadlessWindowOp::Maximize);
+            inner.set_window_state(inner.window_state | WindowState::MAXIMIZED);
+            Ok(())
+        });
+    }
+
+    fn restore(&self) {
+        HeadlessConnection::with_window_inner(self.0, |inner| {
+            inner.record(HeadlessWindowOp::Restore);
+            inner.set_window_state(inner.window_state - WindowState::MAXIMIZED);
+            Ok(())
+        });
+    }
+
+    fn toggle_fullscreen(&self) {
+        HeadlessConnection::with_window_inner(self.0, |inner| {
+            inner.record(HeadlessWindowOp::ToggleFullScreen);
+            inner.set_window_state(inner.window_state ^ WindowState::FULL_SCREEN);
+            Ok(())
+        });
+    }
+
+    fn get_clipboard(&self, clipboard: Clipboard) -> Future<String> {
+        HeadlessConnection::with_window_inner(self.0, move |inner| {
+            Ok(match clipboard {
+                Clipboard::Clipboard => inner.clipboard.clone(),
+                Clipboard::PrimarySelection => inner.primary_selection.clone(),
+            })
+        })
+    }
+
+    fn get_dimensions(&self) -> Future<Dimensions> {
+        HeadlessConnection::with_window_inner(self.0, |inner| Ok(inner.dimensions))
+    }
+
+    fn get_window_state(&self) -> Future<WindowState> {
+        HeadlessConnection::with_window_inner(self.0, |inner| {
+            let mut state = inner.window_state;
+            state.set(WindowState::FOCUSED, inner.has_focus == Some(true));
+            Ok(state)
+        })
+    }
+
+    fn set_clipboard(&self, clipboard: Clipboard, text: String) {
+        HeadlessConnection::with_window_inner(self.0, move |inner| {
+            inner.record(HeadlessWindowOp::SetClipboard(clipboard, text.clone()));
+            match clipboard {
+                Clipboard::Clipboard => inner.clipboard = text,
+                Clipboard::PrimarySelection => inner.primary_selection = text,
+            }
+            Ok(())
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::spawn::TEST_MESSAGE_LOOP;
+    use crate::{
+        CloseRequest, InitialWindowState, KeyCode, KeyboardLedStatus, Modifiers, Point, Size,
+    };
+
+    /// Runs `test` against a new headless connection until it completes
+    fn run_headless<F>(test: F)
+    where
+        F: std::future::Future<Output = anyhow::Result<()>> + 'static,
+    {
+        let _guard = TEST_MESSAGE_LOOP
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+        std::env::set_var("WEZTERM_WINDOW_BACKEND", "headless");
+        let conn = Connection::init().unwrap();
+        k9::assert_equal!(conn.name(), "Headless");
+
+        let result = Rc::new(RefCell::new(None));
+        {
+            let result = Rc::clone(&result);
+            promise::spawn::spawn(async move {
+                result.borrow_mut().replace(test.await);
+                Connection::get().unwrap().terminate_message_loop();
+            })
+            .detach();
+        }
+        conn.run_message_loop().unwrap();
+        result.borrow_mut().take().unwrap().unwrap();
+    }
+
+    async fn new_window<F>(event_handler: F) -> anyhow::Result<(Window, HeadlessWindow)>
+    where
+        F: 'static + FnMut(WindowEvent, &Window),
+    {
+        let font_config = Rc::new(FontConfiguration::new(None, crate::DEFAULT_DPI as usize)?);
+        let win = Window::new_window(
+            "class",
+            "initial",
+            RequestedWindowGeometry::default(),
+            None,
+            font_config,
+            event_handler,
+        )
+        .await?;
+        let headless = match &win {
+            Window::Headless(h) => h.clone(),
+            _ => unreachable!(),
+        };
+        Ok((win, headless))
+    }
+
+    #[test]
+    fn focus_is_reported_once() {
+        run_headless(async {
+            let focus_changes = Rc::new(RefCell::new(vec![]));
+            let (win, _headless) = new_window({
+                let focus_changes = Rc::clone(&focus_changes);
+                move |event, _window| {
+                    if let WindowEvent::FocusChanged(focused, reason) = event {
+                        focus_changes.borrow_mut().push((focused, reason));
+                    }
+                }
+            })
+            .await?;
+
+            // The initial focus state is reported exactly once, when the
+            // window is shown, and focusing it again reports nothing new
+            k9::assert_equal!(focus_changes.borrow().len(), 0);
+            win.show();
+            k9::assert_equal!(win.focus(None).await?, FocusOutcome::Focused);
+            k9::assert_equal!(*focus_changes.borrow(), vec![(true, FocusReason::Unknown)]);
+            k9::assert_equal!(win.focus(None).await?, FocusOutcome::Focused);
+            k9::assert_equal!(focus_changes.borrow().len(), 1);
+
+            win.force_close();
+            Ok::<(), anyhow::Error>(())
+        });
+    }
+
+    /// Returns a window whose handler holds on to the close requests
+    async fn new_closable_window(
+    ) -> anyhow::Result<(Window, HeadlessWindow, Rc<RefCell<Vec<CloseRequest>>>)> {
+        let close_requests = Rc::new(RefCell::new(vec![]));
+        let (win, headless) = new_window({
+            let close_requests = Rc::clone(&close_requests);
+            move |event, window| match event {
+                WindowEvent::Resized { dimensions, .. } => {
+                    window.set_title(&format!("{}", dimensions.pixel_width));
+                }
+                WindowEvent::CloseRequested(request) => {
+                    close_requests.borrow_mut().push(request);
+                }
+                _ => {}
+            }
+        })
+        .await?;
+        headless.take_ops().await?;
+        Ok((win, headless, close_requests))
+    }
+
+    #[test]
+    fn close_request_can_be_decided_later() {
+        run_headless(async {
+            let (win, headless, close_requests) = new_closable_window().await?;
+
+            // A close request that is denied leaves the window open,
+            // and while one is outstanding no other is reported
+            headless.inject_close_request().await?;
+            headless.inject_close_request().await?;
+            k9::assert_equal!(close_requests.borrow().len(), 1);
+            close_requests.borrow_mut().pop().unwrap().deny();
+            k9::assert_equal!(headless.take_ops().await?, vec![]);
+
+            // Dropping a request is the same as denying it
+            headless.inject_close_request().await?;
+            k9::assert_equal!(close_requests.borrow_mut().drain(..).count(), 1);
+            k9::assert_equal!(headless.take_ops().await?, vec![]);
+
+            // A request can be allowed after the fact
+            headless.inject_close_request().await?;
+            let request = close_requests.borrow_mut().pop().unwrap();
+            k9::assert_equal!(headless.take_ops().await?, vec![]);
+            win.get_dimensions().await?;
+            request.allow();
+            assert!(win.get_dimensions().await.is_err());
+
+            Ok::<(), anyhow::Error>(())
+        });
+    }
+
+    #[test]
+    fn programmatic_close_can_be_vetoed() {
+        run_headless(async {
+            let (win, headless, close_requests) = new_closable_window().await?;
+
+            // A programmatic close is subject to the same veto as one
+            // from the window system, and while a request is outstanding
+            // no other is reported
+            win.close();
+            win.close();
+            headless.inject_close_request().await?;
+            k9::assert_equal!(close_requests.borrow().len(), 1);
+
+            // The window keeps working while the decision is pending
+            headless
+                .inject_resize(Dimensions {
+                    pixel_width: 800,
+                    pixel_height: 600,
+                    dpi: 96,
+                })
+                .await?;
+            k9::assert_equal!(
+                headless.take_ops().await?,
+                vec![HeadlessWindowOp::SetTitle("800".to_string())]
+            );
+
+            close_requests.borrow_mut().pop().unwrap().deny();
+            k9::assert_equal!(headless.take_ops().await?, vec![]);
+            win.get_dimensions().await?;
+
+            // but force_close doesn't ask
+            win.force_close();
+            assert!(win.get_dimensions().await.is_err());
+            k9::assert_equal!(close_requests.borrow().len(), 0);
+
+            Ok::<(), anyhow::Error>(())
+        });
+    }
+
+    #[test]
+    fn closed_windows_are_forgotten() {
+        run_headless(async {
+            let (win, headless) = new_window(|_, _| {}).await?;
+
+            // Windows that have been closed don't linger in the connection
+            let window_count = || Connection::get().unwrap().headless().windows.borrow().len();
+            let before = window_count();
+            for _ in 0..1000 {
+                let popup = Window::new_popup(
+                    &win,
+                    Rect::new(Point::new(10, 10), Size::new(20, 20)),
+                    (20, 20),
+                    |_, _| {},
+                )
+                .await?;
+                popup.force_close();
+            }
+            headless.take_ops().await?;
+            k9::assert_equal!(window_count(), before);
+
+            win.force_close();
+            assert!(win.get_dimensions().await.is_err());
+            k9::assert_equal!(window_count(), before - 1);
+
+            Ok::<(), anyhow::Error>(())
+        });
+    }
+
+    #[test]
+    fn requests_from_a_busy_handler_wait_their_turn() {
+        run_headless(async {
+            let (_win, headless) = new_window(|event, window| {
+                if let WindowEvent::KeyEvent(_) = event {
+                    // Behave like a handler that blocks on the
+                    // outcome of its own requests, by running the
+                    // spawn queue while the window is still busy
+                    // dispatching this event
+                    window.set_title("nested");
+                    window.invalidate();
+                    crate::spawn::SPAWN_QUEUE.run();
+                }
+            })
+            .await?;
+            headless.take_ops().await?;
+
+            // Requests made by a handler that are run before it returns
+            // wait their turn instead of hitting the borrowed window
+            headless
+                .inject_key_event(KeyEvent {
+                    key: KeyCode::Char('r'),
+                    modifiers: Modifiers::NONE,
+                    leds: KeyboardLedStatus::empty(),
+                    repeat_count: 1,
+                    key_is_down: true,
+                    phys: None,
+                    time: 0,
+                    serial: None,
+                    raw: None,
+                })
+                .await?;
+            k9::assert_equal!(
+                headless.take_ops().await?,
+                vec![
+                    HeadlessWindowOp::SetTitle("nested".to_string()),
+                    HeadlessWindowOp::Invalidate,
+                ]
+            );
+
+            Ok::<(), anyhow::Error>(())
+        });
+    }
+
+    #[test]
+    fn set_inner_size_reports_the_applied_size() {
+        run_headless(async {
+            let (win, _headless) = new_window(|_, _| {}).await?;
+
+            let dimensions = win
+                .set_inner_size(800, 600, ConstrainedResize::Defer)
+                .await?;
+            k9::assert_equal!(
+                (dimensions.pixel_width, dimensions.pixel_height),
+                (800, 600)
+            );
+            k9::assert_equal!(win.get_dimensions().await?, dimensions);
+
+            // The window going away doesn't leave the caller waiting
+            win.force_close();
+            assert!(win
+                .set_inner_size(640, 480, ConstrainedResize::Defer)
+                .await
+                .is_err());
+
+            Ok::<(), anyhow::Error>(())
+        });
+    }
+
+    #[test]
+    fn constrained_resize() {
+        run_headless(async {
+            let (win, _headless) = new_window(|_, _| {}).await?;
+            win.set_inner_size(800, 600, ConstrainedResize::Defer)
+                .await?;
+
+            // The window system is in charge of the size of a maximized
+            // window, so the request can be rejected
+            win.maximize();
+            let err = win
+                .set_inner_size(640, 480, ConstrainedResize::Reject)
+                .await
+                .unwrap_err();
+            assert!(err.is::<ResizeRejected>(), "{err:#}");
+            k9::assert_equal!(win.get_dimensions().await?.pixel_width, 800);
+
+            // or deferred, which isn't an error
+            win.set_inner_size(640, 480, ConstrainedResize::Defer)
+                .await?;
+
+            Ok::<(), anyhow::Error>(())
+        });
+    }
+
+    #[test]
+    fn resizing_to_the_current_size() {
+        run_headless(async {
+            let (win, headless) = new_window(|event, window| {
+                if let WindowEvent::Resized { dimensions, .. } = event {
+                    window.set_title(&format!("{}", dimensions.pixel_width));
+                }
+            })
+            .await?;
+            headless.take_ops().await?;
+
+            // One Resized, and so one title change, per distinct size
+            win.set_inner_size(800, 600, ConstrainedResize::Defer)
+                .await?;
+            let dimensions = win
+                .set_inner_size(800, 600, ConstrainedResize::Defer)
+                .await?;
+            k9::assert_equal!(dimensions.pixel_width, 800);
+            k9::assert_equal!(
+                headless.take_ops().await?,
+                vec![
+                    HeadlessWindowOp::SetInnerSize {
+                        width: 800,
+                        height: 600
+                    },
+                    HeadlessWindowOp::SetTitle("800".to_string()),
+                    HeadlessWindowOp::SetInnerSize {
+                        width: 800,
+                        height: 600
+                    },
+                ]
+            );
+
+            Ok::<(), anyhow::Error>(())
+        });
+    }
+
+    #[test]
+    fn window_position() {
+        run_headless(async {
+            let (win, headless) = new_window(|event, window| {
+                if let WindowEvent::Moved(position) = event {
+                    window.set_title(&format!("{},{}", position.x, position.y));
+                }
+            })
+            .await?;
+            headless.take_ops().await?;
+
+            // The position round trips, and only a change is reported
+            k9::assert_equal!(
+                win.get_window_position().await?,
+                Some(ScreenPoint::new(0, 0))
+            );
+            win.set_window_position(ScreenPoint::new(10, 20));
+            win.set_window_position(ScreenPoint::new(10, 20));
+            k9::assert_equal!(
+                win.get_window_position().await?,
+                Some(ScreenPoint::new(10, 20))
+            );
+            k9::assert_equal!(
+                headless.take_ops().await?,
+                vec![
+                    HeadlessWindowOp::SetWindowPosition(ScreenPoint::new(10, 20)),
+                    HeadlessWindowOp::SetTitle("10,20".to_string()),
+                    HeadlessWindowOp::SetWindowPosition(ScreenPoint::new(10, 20)),
+                ]
+            );
+
+            Ok::<(), anyhow::Error>(())
+        });
+    }
+
+    #[test]
+    fn initial_window_state() {
+        run_headless(async {
+            let font_config = Rc::new(FontConfiguration::new(None, crate::DEFAULT_DPI as usize)?);
+            let maximized = Window::new_window_with_options(
+                "class",
+                "maximized",
+                RequestedWindowGeometry::default(),
+                None,
+                font_config,
+                WindowOptions {
+                    initial_state: InitialWindowState::Maximized,
+                    ..Default::default()
+                },
+                |_, _| {},
+            )
+            .await?;
+            k9::assert_equal!(maximized.get_window_state().await?, WindowState::MAXIMIZED);
+            maximized.force_close();
+
+            Ok::<(), anyhow::Error>(())
+        });
+    }
+
+    #[test]
+    fn window_class() {
+        run_headless(async {
+            let (win, headless) = new_window(|_, _| {}).await?;
+            headless.take_ops().await?;
+
+            win.set_window_class("scratchpad", None);
+            k9::assert_equal!(
+                headless.take_ops().await?,
+                vec![HeadlessWindowOp::SetWindowClass {
+                    class_name: "scratchpad".to_string(),
+                    instance_name: None,
+                }]
+            );
+
+            win.set_window_class("scratchpad", Some("notes"));
+            k9::assert_equal!(
+                headless.take_ops().await?,
+                vec![HeadlessWindowOp::SetWindowClass {
+                    class_name: "scratchpad".to_string(),
+                    instance_name: Some("notes".to_string()),
+                }]
+            );
+
+            Ok::<(), anyhow::Error>(())
+        });
+    }
+
+    #[test]
+    fn window_level() {
+        run_headless(async {
+            let (win, headless) = new_window(|_, _| {}).await?;
+            win.show();
+
+            // The hints outlast hiding the window
+            win.set_always_on_top(true).await?;
+            win.hide();
+            win.show();
+            k9::assert_equal!(
+                win.get_window_state().await?.as_window_level(),
+                WindowLevel::AlwaysOnTop
+            );
+
+            // A window is either above or below the others
+            win.set_window_level(WindowLevel::AlwaysOnBottom);
+            let state = win.get_window_state().await?;
+            assert!(!state.contains(WindowState::ALWAYS_ON_TOP));
+            k9::assert_equal!(state.as_window_level(), WindowLevel::AlwaysOnBottom);
+
+            win.set_window_level(WindowLevel::Normal);
+            k9::assert_equal!(
+                win.get_window_state().await?.as_window_level(),
+                WindowLevel::Normal
+            );
+            headless.take_ops().await?;
+
+            Ok::<(), anyhow::Error>(())
+        });
+    }
+
+    #[test]
+    fn inject_events_and_capture_ops() {
+        run_headless(async {
+            let font_config = Rc::new(FontConfiguration::new(None, crate::DEFAULT_DPI as usize)?);
+            let win = Window::new_window(
+                "class",
+                "initial",
+                RequestedWindowGeometry::default(),
+                None,
+                Rc::clone(&font_config),
+                move |event, window| match event {
+                    WindowEvent::KeyEvent(key) => {
+                        window.set_title(&format!("{:?}", key.key));
+                    }
+                    WindowEvent::Resized { dimensions, .. } => {
+                        window.set_title(&format!("{}", dimensions.pixel_width));
+                    }
+                    _ => {}
+                },
+            )
+            .await?;
+
+            let headless = match &win {
+                Window::Headless(h) => h.clone(),
+                _ => unreachable!(),
+            };
+
+            win.show();
+            k9::assert_equal!(win.focus(None).await?, FocusOutcome::Focused);
+
+            headless
+                .inject_key_event(KeyEvent {
+                    key: KeyCode::Char('a'),
+                    modifiers: Modifiers::NONE,
+                    leds: KeyboardLedStatus::empty(),
+                    repeat_count: 1,
+                    key_is_down: true,
+                    phys: None,
+                    time: 0,
+                    serial: None,
+                    raw: None,
+                })
+                .await?;
+            headless
+                .inject_resize(Dimensions {
+                    pixel_width: 640,
+                    pixel_height: 480,
+                    dpi: 96,
+                })
+                .await?;
+
+            // The ops requested by the handler are deferred, but they are
+            // queued ahead of this task being woken up, so they have all
+            // been applied by the time we get here
+
+            let ops = headless.take_ops().await?;
+            k9::assert_equal!(
+                ops,
+                vec![
+                    HeadlessWindowOp::SetTitle("initial".to_string()),
+                    HeadlessWindowOp::Show,
+                    HeadlessWindowOp::Focus,
+                    HeadlessWindowOp::SetTitle("Char('a')".to_string()),
+                    HeadlessWindowOp::SetTitle("640".to_string()),
+                ]
+            );
+            k9::assert_equal!(win.get_dimensions().await?.pixel_width, 640);
+
+            win.maximize();
+            k9::assert_equal!(win.focus(None).await?, FocusOutcome::Focused);
+            k9::assert_equal!(win.get_current_screen().await?.name, "headless");
+            k9::assert_equal!(win.get_dimensions().await?.pixel_width, 640);
+            k9::assert_equal!(
+                win.get_window_state().await?,
+                WindowState::MAXIMIZED | WindowState::FOCUSED
+            );
+            headless.take_ops().await?;
+
+            win.minimize();
+            k9::assert_equal!(
+                win.get_window_state().await?,
+                WindowState::MAXIMIZED | WindowState::FOCUSED | WindowState::HIDDEN
+            );
+            win.show();
+            k9::assert_equal!(
+                win.get_window_state().await?,
+                WindowState::MAXIMIZED | WindowState::FOCUSED
+            );
+            k9::assert_equal!(
+                headless.take_ops().await?,
+                vec![HeadlessWindowOp::Minimize, HeadlessWindowOp::Show]
+            );
+
+            let popup = Window::new_popup(
+                &win,
+                Rect::new(Point::new(10, 10), Size::new(20, 20)),
+                (200, 100),
+                |_, _| {},
+            )
+            .await?;
+            let popup_dims = popup.get_dimensions().await?;
+            k9::assert_equal!(
+                (popup_dims.pixel_width, popup_dims.pixel_height),
+                (200, 100)
+            );
+
+            popup.force_close();
+
+            win.force_close();
+
+            // Operations on a window that has been closed must resolve
+            // with an error rather than leaving the future pending forever
+            win.set_title("too late");
+            let err = headless.take_ops().await.unwrap_err();
+            assert!(err.to_string().contains("is gone"), "{err:#}");
+
+            Ok::<(), anyhow::Error>(())
+        });
+    }
+
+    #[test]
+    fn terminate_from_another_thread() {
+        let _guard = TEST_MESSAGE_LOOP
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+        std::env::set_var("WEZTERM_WINDOW_BACKEND", "headless");
+        let conn = Connection::init().unwrap();
+
+        // Another thread can stop the loop, and doesn't have to wait
+        // for anything else to wake it up
+        let start = std::time::Instant::now();
+        let thread = std::thread::spawn(|| {
+            std::thread::sleep(std::time::Duration::from_millis(100));
+            Connection::request_terminate();
+        });
+        conn.run_message_loop().unwrap();
+        thread.join().unwrap();
+        let elapsed = start.elapsed();
+        assert!(
+            elapsed < std::time::Duration::from_secs(2),
+            "took {elapsed:?}"
+        );
+    }
+
+    #[test]
+    fn timers_can_start_and_cancel_timers() {
+        run_headless(async {
+            use std::time::Duration;
+            let fired = Rc::new(RefCell::new(vec![]));
+
+            let cancelled = {
+                let fired = Rc::clone(&fired);
+                promise::spawn::spawn(async move {
+                    async_io::Timer::after(Duration::from_millis(50)).await;
+                    fired.borrow_mut().push("cancelled");
+                })
+            };
+            let first = {
+                let fired = Rc::clone(&fired);
+                promise::spawn::spawn(async move {
+                    async_io::Timer::after(Duration::from_millis(1)).await;
+                    fired.borrow_mut().push("first");
+                    // Cancel a timer, and start another, from a timer
+                    drop(cancelled);
+                    promise::spawn::spawn(async move {
+                        async_io::Timer::after(Duration::from_millis(1)).await;
+                        fired.borrow_mut().push("second");
+                    })
+                })
+            };
+            first.await.await;
+
+            // Give the cancelled timer the chance to fire, if it were going to
+            async_io::Timer::after(Duration::from_millis(100)).await;
+            assert_eq!(*fired.borrow(), vec!["first", "second"]);
+
+            Ok::<(), anyhow::Error>(())
+        });
+    }
+}
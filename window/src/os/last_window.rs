@@ -0,0 +1,58 @@
+use std::cell::Cell;
+
+/// Decides whether the message loop should stop because the last
+/// window has gone away; see
+/// `ConnectionOps::set_terminate_when_last_window_closed`.
+/// The loop only stops if a window has existed at some point, so that
+/// an application that hasn't got around to creating its first window
+/// doesn't exit straight away.
+#[derive(Default)]
+pub(crate) struct LastWindowTermination {
+    enabled: Cell<bool>,
+    had_window: Cell<bool>,
+}
+
+impl LastWindowTermination {
+    pub(crate) fn set_enabled(&self, enabled: bool) {
+        self.enabled.set(enabled);
+    }
+
+    /// A window was added to the connection. It counts from this
+    /// point on, whether or not it has been shown.
+    pub(crate) fn window_added(&self) {
+        self.had_window.set(true);
+    }
+
+    /// Called by the message loop once it has run the pending spawned
+    /// work, so that anything that was going to create a new window
+    /// has had the chance to do so.
+    pub(crate) fn should_terminate(&self, have_windows: bool) -> bool {
+        let terminate = self.enabled.get() && self.had_window.get() && !have_windows;
+        if terminate {
+            log::trace!("Last window closed; terminating the message loop");
+        }
+        terminate
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn disabled_by_default() {
+        let last = LastWindowTermination::default();
+        last.window_added();
+        assert!(!last.should_terminate(false));
+    }
+
+    #[test]
+    fn waits_for_the_first_window() {
+        let last = LastWindowTermination::default();
+        last.set_enabled(true);
+        assert!(!last.should_terminate(false));
+        last.window_added();
+        assert!(!last.should_terminate(true));
+        assert!(last.should_terminate(false));
+    }
+}
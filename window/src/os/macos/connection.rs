@@ -71,9 +71,12 @@ impl Connection {
         let mut prom = promise::Promise::new();
         let future = prom.get_future().unwrap();
         promise::spawn::spawn_into_main_thread(async move {
-            if let Some(handle) = Connection::get().unwrap().window_by_id(window_id) {
-                let mut inner = handle.borrow_mut();
-                prom.result(f(&mut inner));
+            match Connection::get().unwrap().window_by_id(window_id) {
+                Some(handle) => {
+                    let mut inner = handle.borrow_mut();
+                    prom.result(f(&mut inner));
+                }
+                None => prom.err(anyhow::anyhow!("window {window_id} is gone")),
             }
         })
         .detach();
@@ -8,10 +8,11 @@ use crate::connection::ConnectionOps;
 use crate::os::macos::menu::{MenuItem, RepresentedItem};
 use crate::parameters::{Border, Parameters, TitleBar};
 use crate::{
-    Clipboard, Connection, DeadKeyStatus, Dimensions, Handled, KeyCode, KeyEvent, Modifiers,
-    MouseButtons, MouseCursor, MouseEvent, MouseEventKind, MousePress, Point, RawKeyEvent, Rect,
-    RequestedWindowGeometry, ResizeIncrement, ResolvedGeometry, ScreenPoint, Size, ULength,
-    WindowDecorations, WindowEvent, WindowEventSender, WindowOps, WindowState,
+    Clipboard, Connection, ConstrainedResize, DeadKeyStatus, Dimensions, FocusOutcome, FocusReason,
+    Handled, InputSerial, KeyCode, KeyEvent, Modifiers, MouseButtons, MouseCursor, MouseEvent,
+    MouseEventKind, MousePress, Point, RawKeyEvent, Rect, RequestedWindowGeometry, ResizeIncrement,
+    ResizeRejected, ResolvedGeometry, ScreenPoint, Size, ULength, WindowDecorations, WindowEvent,
+    WindowEventSender, WindowOps, WindowOptions, WindowState,
 };
 use anyhow::{anyhow, bail, ensure};
 use async_trait::async_trait;
@@ -431,11 +432,48 @@ fn set_window_position(window: *mut Object, coords: ScreenPoint) {
 
 impl Window {
     pub async fn new_window<F>(
+        class_name: &str,
+        name: &str,
+        geometry: RequestedWindowGeometry,
+        config: Option<&ConfigHandle>,
+        font_config: Rc<FontConfiguration>,
+        event_handler: F,
+    ) -> anyhow::Result<Window>
+    where
+        F: 'static + FnMut(WindowEvent, &Window),
+    {
+        Self::new_window_with_options(
+            class_name,
+            name,
+            geometry,
+            config,
+            font_config,
+            WindowOptions::default(),
+            event_handler,
+        )
+        .await
+    }
+
+    /// Popups are not yet implemented on this platform
+    pub async fn new_popup<F>(
+        _parent: &Window,
+        _anchor: Rect,
+        _size: (usize, usize),
+        _event_handler: F,
+    ) -> anyhow::Result<Window>
+    where
+        F: 'static + FnMut(WindowEvent, &Window),
+    {
+        bail!("popup windows are not supported on this platform");
+    }
+
+    pub async fn new_window_with_options<F>(
         _class_name: &str,
         name: &str,
         geometry: RequestedWindowGeometry,
         config: Option<&ConfigHandle>,
         _font_config: Rc<FontConfiguration>,
+        _options: WindowOptions<'_>,
         event_handler: F,
     ) -> anyhow::Result<Window>
     where
@@ -737,18 +775,27 @@ impl WindowOps for Window {
 
     fn close(&self) {
         Connection::with_window_inner(self.id, |inner| {
-            inner.close();
+            if let Some(window_view) = WindowView::get_this(unsafe { &**inner.view }) {
+                window_view.inner.borrow_mut().events.request_close();
+            }
             Ok(())
         });
     }
 
-    fn focus(&self) {
+    fn force_close(&self) {
         Connection::with_window_inner(self.id, |inner| {
-            inner.focus();
+            inner.close();
             Ok(())
         });
     }
 
+    fn focus(&self, _serial: Option<InputSerial>) -> Future<FocusOutcome> {
+        Connection::with_window_inner(self.id, |inner| {
+            inner.focus();
+            Ok(FocusOutcome::Focused)
+        })
+    }
+
     fn hide(&self) {
         Connection::with_window_inner(self.id, |inner| {
             inner.hide();
@@ -792,9 +839,21 @@ impl WindowOps for Window {
         });
     }
 
-    fn set_inner_size(&self, width: usize, height: usize) {
+    fn set_inner_size(
+        &self,
+        width: usize,
+        height: usize,
+        constrained: ConstrainedResize,
+    ) -> Future<Dimensions> {
         Connection::with_window_inner(self.id, move |inner| {
-            inner.set_inner_size(width, height);
+            let dimensions = if constrained == ConstrainedResize::Reject && inner.is_fullscreen() {
+                Err(ResizeRejected {
+                    state: WindowState::FULL_SCREEN,
+                }
+                .into())
+            } else {
+                Ok(inner.set_inner_size(width, height))
+            };
             if let Some(window_view) = WindowView::get_this(unsafe { &**inner.view }) {
                 window_view
                     .inner
@@ -802,8 +861,8 @@ impl WindowOps for Window {
                     .events
                     .dispatch(WindowEvent::SetInnerSizeCompleted);
             }
-            Ok(())
-        });
+            dimensions
+        })
     }
 
     fn set_window_position(&self, coords: ScreenPoint) {
@@ -1176,6 +1235,16 @@ impl WindowInner {
                     MouseCursor::Hand => msg_send![ns_cursor_cls, pointingHandCursor],
                     MouseCursor::SizeUpDown => msg_send![ns_cursor_cls, resizeUpDownCursor],
                     MouseCursor::SizeLeftRight => msg_send![ns_cursor_cls, resizeLeftRightCursor],
+                    MouseCursor::Crosshair => msg_send![ns_cursor_cls, crosshairCursor],
+                    MouseCursor::NotAllowed => {
+                        msg_send![ns_cursor_cls, operationNotAllowedCursor]
+                    }
+                    MouseCursor::Grab => msg_send![ns_cursor_cls, openHandCursor],
+                    MouseCursor::Grabbing => msg_send![ns_cursor_cls, closedHandCursor],
+                    // AppKit has no public diagonal resize or busy cursors
+                    MouseCursor::SizeNWSE | MouseCursor::SizeNESW | MouseCursor::Wait => {
+                        msg_send![ns_cursor_cls, arrowCursor]
+                    }
                 };
                 let () = msg_send![ns_cursor_cls, setHiddenUntilMouseMoves: NO];
                 let () = msg_send![instance, set];
@@ -1208,7 +1277,9 @@ impl WindowInner {
         }
     }
 
-    fn set_inner_size(&mut self, width: usize, height: usize) {
+    /// Returns the dimensions that the content view has once the
+    /// window has been resized, which happens synchronously
+    fn set_inner_size(&mut self, width: usize, height: usize) -> Dimensions {
         unsafe {
             let frame = NSView::frame(*self.view as *mut _);
             let backing_frame = NSView::convertRectToBacking(*self.view as *mut _, frame);
@@ -1222,6 +1293,16 @@ impl WindowInner {
             // setContentSize_ doesn't explicitly invalidate,
             // so we need to do it ourselves
             self.invalidate();
+
+            let frame = NSView::frame(*self.view as *mut _);
+            let backing_frame = NSView::convertRectToBacking(*self.view as *mut _, frame);
+            let scale = backing_frame.size.width / frame.size.width;
+            Dimensions {
+                pixel_width: backing_frame.size.width as usize,
+                pixel_height: backing_frame.size.height as usize,
+                dpi: dpi_for_window_screen(*self.window, &self.config)
+                    .unwrap_or(crate::DEFAULT_DPI * scale) as usize,
+            }
         }
     }
 
@@ -1881,6 +1962,9 @@ impl WindowView {
                 leds: KeyboardLedStatus::empty(),
                 repeat_count: 1,
                 key_is_down,
+                phys: None,
+                time: 0,
+                serial: None,
                 raw: None,
             };
 
@@ -2067,10 +2151,7 @@ impl WindowView {
         }
 
         if let Some(this) = Self::get_this(this) {
-            this.inner
-                .borrow_mut()
-                .events
-                .dispatch(WindowEvent::CloseRequested);
+            this.inner.borrow_mut().events.request_close();
             NO
         } else {
             YES
@@ -2118,7 +2199,7 @@ impl WindowView {
             this.inner
                 .borrow_mut()
                 .events
-                .dispatch(WindowEvent::FocusChanged(true));
+                .dispatch(WindowEvent::FocusChanged(true, FocusReason::Unknown));
             this.update_application_presentation(true);
         }
     }
@@ -2128,7 +2209,7 @@ impl WindowView {
             this.inner
                 .borrow_mut()
                 .events
-                .dispatch(WindowEvent::FocusChanged(false));
+                .dispatch(WindowEvent::FocusChanged(false, FocusReason::Unknown));
             this.update_application_presentation(true);
         }
     }
@@ -2191,6 +2272,7 @@ impl WindowView {
         let mouse_buttons;
         let modifiers;
         let screen_coords;
+        let streak;
         unsafe {
             let point = NSView::convertPoint_fromView_(view, nsevent.locationInWindow(), nil);
             let rect = NSRect::new(NSPoint::new(0., 0.), NSSize::new(point.x, point.y));
@@ -2204,13 +2286,20 @@ impl WindowView {
             mouse_buttons = decode_mouse_buttons(NSEvent::pressedMouseButtons(nsevent));
             modifiers = key_modifiers(nsevent.modifierFlags());
             screen_coords = NSEvent::mouseLocation(nsevent);
+            streak = match kind {
+                MouseEventKind::Press(_) => nsevent.clickCount().max(1) as u16,
+                _ => 0,
+            };
         }
         let event = MouseEvent {
             kind,
             coords: Point::new(coords.x as isize, coords.y as isize),
-            screen_coords: cartesian_to_screen_point(screen_coords),
+            screen_coords: Some(cartesian_to_screen_point(screen_coords)),
             mouse_buttons,
             modifiers,
+            streak,
+            time: 0,
+            serial: None,
         };
 
         if let Some(myself) = Self::get_this(this) {
@@ -2452,6 +2541,9 @@ impl WindowView {
                             leds: KeyboardLedStatus::empty(),
                             repeat_count: 1,
                             key_is_down,
+                            phys: None,
+                            time: 0,
+                            serial: None,
                             raw: None,
                         };
                         inner.events.dispatch(WindowEvent::KeyEvent(event));
@@ -2662,8 +2754,12 @@ impl WindowView {
                 }
             };
 
+            // When the text differs from the unmodified key, the modifiers
+            // that produced it must not also be applied on top of it, but
+            // the others (SUPER, or any held with a non-character key)
+            // are retained so that they can still be bound
             let modifiers = if raw_key.is_some() {
-                Modifiers::NONE
+                modifiers - key.modifiers_reflected_in_text()
             } else {
                 modifiers
             };
@@ -2674,6 +2770,9 @@ impl WindowView {
                 leds,
                 repeat_count: 1,
                 key_is_down,
+                phys: raw_key_event.phys_code,
+                time: 0,
+                serial: None,
                 raw: Some(raw_key_event),
             }
             .normalize_shift()
@@ -3,6 +3,23 @@ pub mod windows;
 #[cfg(windows)]
 pub use self::windows::*;
 
+#[cfg(all(unix, not(target_os = "macos")))]
+pub(crate) mod animation;
+#[cfg(all(unix, not(target_os = "macos")))]
+pub(crate) mod click_streak;
+#[cfg(all(unix, not(target_os = "macos")))]
+pub(crate) mod evdev;
+#[cfg(all(unix, not(target_os = "macos")))]
+pub mod fd_watch;
+#[cfg(all(unix, not(target_os = "macos")))]
+pub(crate) mod focus_request;
+#[cfg(all(unix, not(target_os = "macos")))]
+pub(crate) mod fullscreen_restore;
+pub mod headless;
+#[cfg(all(unix, not(target_os = "macos")))]
+pub(crate) mod last_window;
+#[cfg(all(unix, not(target_os = "macos")))]
+pub(crate) mod size_constraints;
 #[cfg(feature = "wayland")]
 pub mod wayland;
 pub mod x11;
@@ -10,6 +27,8 @@ pub mod x_and_wayland;
 pub mod xdg_desktop_portal;
 pub mod xkeysyms;
 
+#[cfg(all(unix, not(target_os = "macos")))]
+pub use fd_watch::{FdInterest, FdReadiness, FdWatch};
 #[cfg(all(unix, not(target_os = "macos")))]
 pub use x_and_wayland::*;
 
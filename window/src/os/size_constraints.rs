@@ -0,0 +1,110 @@
+use crate::ResizeIncrement;
+
+/// The minimum and maximum inner size, in pixels, requested via
+/// WindowOps::set_min_inner_size and WindowOps::set_max_inner_size
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct SizeConstraints {
+    pub min: Option<(usize, usize)>,
+    pub max: Option<(usize, usize)>,
+}
+
+impl SizeConstraints {
+    pub(crate) fn set_min(&mut self, width: usize, height: usize) {
+        self.min = if width == 0 && height == 0 {
+            None
+        } else {
+            Some((width, height))
+        };
+    }
+
+    pub(crate) fn set_max(&mut self, size: Option<(usize, usize)>) {
+        self.max = size;
+    }
+
+    /// Returns the size closest to the requested one that satisfies the
+    /// constraints. Where they conflict, the minimum wins, so that
+    /// the application isn't made to render at a size it can't handle.
+    pub(crate) fn clamp(&self, width: usize, height: usize) -> (usize, usize) {
+        let (mut width, mut height) = (width, height);
+        if let Some((max_width, max_height)) = self.max {
+            width = width.min(max_width);
+            height = height.min(max_height);
+        }
+        if let Some((min_width, min_height)) = self.min {
+            width = width.max(min_width);
+            height = height.max(min_height);
+        }
+        (width, height)
+    }
+}
+
+/// Round a size down to the nearest multiple of the increments above
+/// the base size, while leaving room for at least one increment
+pub(crate) fn snap_to_increments(
+    incr: &ResizeIncrement,
+    width: usize,
+    height: usize,
+) -> (usize, usize) {
+    fn snap(size: usize, base: u16, step: u16) -> usize {
+        let base = base as usize;
+        let step = (step as usize).max(1);
+        let steps = (size.saturating_sub(base) / step).max(1);
+        base + steps * step
+    }
+    (
+        snap(width, incr.base_width, incr.x),
+        snap(height, incr.base_height, incr.y),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn unconstrained() {
+        let constraints = SizeConstraints::default();
+        assert_eq!(constraints.clamp(1, 2), (1, 2));
+        assert_eq!(constraints.clamp(10_000, 20_000), (10_000, 20_000));
+    }
+
+    #[test]
+    fn clamps_to_range() {
+        let mut constraints = SizeConstraints::default();
+        constraints.set_min(80, 40);
+        constraints.set_max(Some((800, 400)));
+        assert_eq!(constraints.clamp(10, 500), (80, 400));
+        assert_eq!(constraints.clamp(1000, 20), (800, 40));
+        assert_eq!(constraints.clamp(100, 100), (100, 100));
+
+        constraints.set_max(None);
+        assert_eq!(constraints.clamp(1000, 20), (1000, 40));
+        constraints.set_min(0, 0);
+        assert_eq!(constraints.min, None);
+    }
+
+    #[test]
+    fn min_wins_conflicts() {
+        let mut constraints = SizeConstraints::default();
+        constraints.set_min(100, 100);
+        constraints.set_max(Some((50, 200)));
+        assert_eq!(constraints.clamp(300, 300), (100, 200));
+    }
+
+    #[test]
+    fn snapping() {
+        let incr = ResizeIncrement {
+            x: 10,
+            y: 20,
+            base_width: 5,
+            base_height: 7,
+        };
+        assert_eq!(snap_to_increments(&incr, 5 + 35, 7 + 45), (5 + 30, 7 + 40));
+        assert_eq!(snap_to_increments(&incr, 5 + 30, 7 + 40), (5 + 30, 7 + 40));
+        // Never smaller than a single increment
+        assert_eq!(snap_to_increments(&incr, 2, 3), (5 + 10, 7 + 20));
+
+        let disabled = ResizeIncrement::disabled();
+        assert_eq!(snap_to_increments(&disabled, 123, 456), (123, 456));
+    }
+}
@@ -0,0 +1,87 @@
+//! Implements xdg_activation_v1 so that the application can move the
+//! focus to one of its windows, or have it request the user's attention
+use smithay_client_toolkit::globals::GlobalData;
+use wayland_client::globals::{BindError, GlobalList};
+use wayland_client::protocol::wl_seat::WlSeat;
+use wayland_client::protocol::wl_surface::WlSurface;
+use wayland_client::{Connection, Dispatch, QueueHandle};
+use wayland_protocols::xdg::activation::v1::client::xdg_activation_token_v1::{
+    Event as TokenEvent, XdgActivationTokenV1,
+};
+use wayland_protocols::xdg::activation::v1::client::xdg_activation_v1::XdgActivationV1;
+
+use super::state::WaylandState;
+
+pub(super) struct ActivationState {
+    activation: XdgActivationV1,
+}
+
+impl ActivationState {
+    pub(super) fn bind(
+        globals: &GlobalList,
+        queue_handle: &QueueHandle<WaylandState>,
+    ) -> Result<Self, BindError> {
+        let activation = globals.bind(queue_handle, 1..=1, GlobalData)?;
+        Ok(Self { activation })
+    }
+
+    /// Request a token with which to activate `target`, and use it
+    /// as soon as the compositor hands it over.
+    /// The compositor only moves the focus if `input` identifies a
+    /// recent input event received by the application's `surface`.
+    /// Without that, it will typically flag `target` as requesting
+    /// attention instead.
+    pub(super) fn activate(
+        &self,
+        target: &WlSurface,
+        input: Option<(&WlSeat, u32, &WlSurface)>,
+        queue_handle: &QueueHandle<WaylandState>,
+    ) {
+        let token = self.activation.get_activation_token(
+            queue_handle,
+            TokenData {
+                target: target.clone(),
+            },
+        );
+        if let Some((seat, serial, surface)) = input {
+            token.set_serial(serial, seat);
+            token.set_surface(surface);
+        }
+        token.commit();
+    }
+}
+
+pub(super) struct TokenData {
+    target: WlSurface,
+}
+
+impl Dispatch<XdgActivationV1, GlobalData> for WaylandState {
+    fn event(
+        _state: &mut Self,
+        _proxy: &XdgActivationV1,
+        _event: <XdgActivationV1 as wayland_client::Proxy>::Event,
+        _data: &GlobalData,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        // No events
+    }
+}
+
+impl Dispatch<XdgActivationTokenV1, TokenData> for WaylandState {
+    fn event(
+        state: &mut Self,
+        proxy: &XdgActivationTokenV1,
+        event: TokenEvent,
+        data: &TokenData,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        if let TokenEvent::Done { token } = event {
+            if let Some(activation) = state.activation.as_ref() {
+                activation.activation.activate(token, &data.target);
+            }
+            proxy.destroy();
+        }
+    }
+}
@@ -4,44 +4,95 @@ use std::os::fd::AsRawFd;
 use std::rc::Rc;
 use std::sync::atomic::AtomicUsize;
 
-use anyhow::{bail, Context};
+use anyhow::{anyhow, bail, Context};
 use mio::unix::SourceFd;
 use mio::{Events, Interest, Poll, Token};
+use thiserror::Error;
 use wayland_client::backend::WaylandError;
-use wayland_client::globals::registry_queue_init;
-use wayland_client::{Connection as WConnection, EventQueue};
-
-use crate::screen::{ScreenInfo, Screens};
+use wayland_client::globals::{registry_queue_init, BindError};
+use wayland_client::{ConnectError, Connection as WConnection, EventQueue};
+
+use crate::connection::take_terminate_request;
+use crate::os::click_streak::update_desktop_interval;
+use crate::os::fd_watch::FdWatchers;
+use crate::os::last_window::LastWindowTermination;
+use crate::screen::Screens;
 use crate::spawn::SPAWN_QUEUE;
-use crate::{Appearance, Connection, ConnectionOps, ScreenRect};
+use crate::{
+    Appearance, Connection, ConnectionLost, ConnectionOps, KeyboardLayout, ScreenRect, UiSettings,
+    WindowEvent,
+};
 
+use super::output::screen_info_for_output;
 use super::state::WaylandState;
 use super::WaylandWindowInner;
 
+// Fields are dropped in declaration order: the windows and seat state
+// in wayland_state refer to the EGL display and to the connection, so
+// they go first and the connection goes last.
 pub struct WaylandConnection {
-    pub(crate) should_terminate: RefCell<bool>,
+    pub(crate) last_window: LastWindowTermination,
     pub(crate) next_window_id: AtomicUsize,
+    pub(crate) fd_watchers: Rc<FdWatchers>,
+    pub(super) wayland_state: RefCell<WaylandState>,
+    pub(super) event_queue: RefCell<EventQueue<WaylandState>>,
     pub(super) gl_connection: RefCell<Option<Rc<crate::egl::GlConnection>>>,
     pub(super) connection: WConnection,
-    pub(super) event_queue: RefCell<EventQueue<WaylandState>>,
-    pub(super) wayland_state: RefCell<WaylandState>,
+}
+
+/// Why a connection to the wayland compositor could not be established
+#[derive(Debug, Error)]
+pub enum WaylandInitError {
+    /// There is no compositor to connect to, which is the normal state
+    /// of affairs in an X11 session
+    #[error("no wayland compositor socket was found")]
+    NoWaylandSocket,
+    #[error("failed to connect to the wayland compositor: {0:#}")]
+    ConnectFailed(anyhow::Error),
+    /// The compositor doesn't provide a global that we depend upon,
+    /// or only provides a version of it that is too old
+    #[error("the wayland compositor lacks a usable {which}")]
+    MissingGlobals {
+        which: &'static str,
+        #[source]
+        source: BindError,
+    },
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+impl WaylandInitError {
+    /// Returns a closure to map the failure to bind a required
+    /// global into a MissingGlobals error
+    pub(super) fn missing(which: &'static str) -> impl FnOnce(BindError) -> Self {
+        move |source| Self::MissingGlobals { which, source }
+    }
 }
 
 impl WaylandConnection {
-    pub(crate) fn create_new() -> anyhow::Result<Self> {
-        let conn = WConnection::connect_to_env()?;
-        let (globals, event_queue) = registry_queue_init::<WaylandState>(&conn)?;
+    pub(crate) fn create_new() -> Result<Self, WaylandInitError> {
+        let conn = WConnection::connect_to_env().map_err(|err| match err {
+            ConnectError::NoCompositor => WaylandInitError::NoWaylandSocket,
+            err => WaylandInitError::ConnectFailed(err.into()),
+        })?;
+        let (globals, event_queue) = registry_queue_init::<WaylandState>(&conn)
+            .map_err(|err| WaylandInitError::ConnectFailed(err.into()))?;
         let qh = event_queue.handle();
 
+        // If this fails, the locals are dropped in the reverse order of
+        // their creation, so the queue goes before the connection, just
+        // as it does when the fields of the WaylandConnection are dropped
         let wayland_state = WaylandState::new(&globals, &qh)?;
         let wayland_connection = WaylandConnection {
-            connection: conn,
-            should_terminate: RefCell::new(false),
+            last_window: LastWindowTermination::default(),
             next_window_id: AtomicUsize::new(1),
-            gl_connection: RefCell::new(None),
-            event_queue: RefCell::new(event_queue),
+            fd_watchers: Rc::new(FdWatchers::default()),
             wayland_state: RefCell::new(wayland_state),
+            event_queue: RefCell::new(event_queue),
+            gl_connection: RefCell::new(None),
+            connection: conn,
         };
+        update_desktop_interval(&wayland_connection.get_ui_settings());
 
         Ok(wayland_connection)
     }
@@ -52,6 +103,15 @@ impl WaylandConnection {
         }
     }
 
+    pub(crate) fn advise_of_ui_settings_change(&self, settings: UiSettings) {
+        update_desktop_interval(&settings);
+        for win in self.wayland_state.borrow().windows.borrow().values() {
+            win.borrow_mut()
+                .events
+                .dispatch(WindowEvent::UiSettingsChanged(settings));
+        }
+    }
+
     fn run_message_loop_impl(&self) -> anyhow::Result<()> {
         const TOK_WL: usize = 0xffff_fffc;
         const TOK_SPAWN: usize = 0xffff_fffd;
@@ -73,25 +133,44 @@ impl WaylandConnection {
             tok_spawn,
             Interest::READABLE,
         )?;
+        self.fd_watchers.attach(poll.registry())?;
 
-        while !*self.should_terminate.borrow() {
+        while !take_terminate_request() {
             let timeout = if SPAWN_QUEUE.run() {
                 Some(std::time::Duration::from_secs(0))
             } else {
                 None
             };
 
+            if timeout.is_none() {
+                // Only count windows that haven't been closed; one that
+                // is closing may still be in the map
+                let have_windows = self
+                    .wayland_state
+                    .borrow()
+                    .windows
+                    .borrow()
+                    .values()
+                    .any(|win| win.try_borrow().map(|win| win.is_open()).unwrap_or(true));
+                if self.last_window.should_terminate(have_windows) {
+                    break;
+                }
+            }
+
             let mut event_q = self.event_queue.borrow_mut();
             {
                 let mut wayland_state = self.wayland_state.borrow_mut();
                 if let Err(err) = event_q.dispatch_pending(&mut wayland_state) {
-                    // TODO: show the protocol error in the display
-                    return Err(err)
-                        .with_context(|| format!("error during event_q.dispatch protcol_error"));
+                    let reason = format!("{err:#}");
+                    return Err(ConnectionLost { reason }).context("error during event_q.dispatch");
                 }
             }
 
-            event_q.flush()?;
+            if let Err(err) = event_q.flush() {
+                if let Some(lost) = connection_lost(err) {
+                    return Err(lost).context("error during event_q.flush");
+                }
+            }
             if let Err(err) = poll.poll(&mut events, timeout) {
                 if err.kind() == std::io::ErrorKind::Interrupted {
                     continue;
@@ -107,22 +186,61 @@ impl WaylandConnection {
                 if let Some(guard) = event_q.prepare_read() {
                     if let Err(err) = guard.read() {
                         log::trace!("Event Q error: {:?}", err);
-                        if let WaylandError::Protocol(perr) = err {
-                            return Err(perr.into());
-                            // TODO
-                            // return Err(perr).with_context(|| {
-                            //     format!("error during event_q.read protocol_error={:?}",
-                            //             perr)
-                            // })
+                        if let Some(lost) = connection_lost(err) {
+                            return Err(lost).context("error during event_q.read");
                         }
                     }
                 }
+
+                if event.is_read_closed() || event.is_error() {
+                    return Err(ConnectionLost {
+                        reason: "the compositor closed the connection".to_string(),
+                    }
+                    .into());
+                }
+            }
+
+            // Callbacks for the application's file descriptors may
+            // need the event queue, so release it before calling them
+            drop(event_q);
+            for event in &events {
+                if event.token() != tok_wl && event.token() != tok_spawn {
+                    self.fd_watchers.dispatch(event);
+                }
             }
         }
 
         Ok(())
     }
 
+    /// Let each window know that it has been destroyed along with
+    /// the connection
+    fn destroy_windows(&self) {
+        let windows: Vec<_> = self
+            .wayland_state
+            .borrow()
+            .windows
+            .borrow()
+            .values()
+            .cloned()
+            .collect();
+        for window in windows {
+            window.borrow_mut().connection_lost();
+        }
+    }
+
+    /// Destroy any remaining windows and tell the compositor about it,
+    /// while everything that they depend upon is still alive.
+    /// run_message_loop normally takes care of the windows, but not if
+    /// it never ran, or if the connection is dropped on an error path.
+    fn shutdown(&self) {
+        self.destroy_windows();
+        self.wayland_state.borrow().windows.borrow_mut().clear();
+        if let Err(err) = self.connection.flush() {
+            log::trace!("flushing wayland connection during shutdown: {err:#}");
+        }
+    }
+
     pub(crate) fn next_window_id(&self) -> usize {
         self.next_window_id
             .fetch_add(1, ::std::sync::atomic::Ordering::Relaxed)
@@ -146,10 +264,23 @@ impl WaylandConnection {
         let future = prom.get_future().unwrap();
 
         promise::spawn::spawn_into_main_thread(async move {
-            if let Some(handle) = Connection::get().unwrap().wayland().window_by_id(window) {
-                let mut inner = handle.borrow_mut();
-                prom.result(f(&mut inner));
-            }
+            let f = match Connection::get().unwrap().wayland().window_by_id(window) {
+                Some(handle) => match handle.try_borrow_mut() {
+                    Ok(mut inner) => {
+                        prom.result(f(&mut inner));
+                        return;
+                    }
+                    Err(_) => f,
+                },
+                None => {
+                    prom.err(anyhow!("window {window} is gone"));
+                    return;
+                }
+            };
+
+            // See XConnection::with_window_inner
+            log::trace!("window {window} is busy; deferring");
+            prom.result(Self::with_window_inner(window, f).await);
         })
         .detach();
 
@@ -157,6 +288,23 @@ impl WaylandConnection {
     }
 }
 
+impl Drop for WaylandConnection {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+/// Errors from the connection are fatal, with the exception of
+/// WouldBlock which indicates that the socket is not ready
+fn connection_lost(err: WaylandError) -> Option<ConnectionLost> {
+    match err {
+        WaylandError::Io(err) if err.kind() == std::io::ErrorKind::WouldBlock => None,
+        err => Some(ConnectionLost {
+            reason: format!("{err:#}"),
+        }),
+    }
+}
+
 impl ConnectionOps for WaylandConnection {
     fn name(&self) -> String {
         "Wayland".to_string()
@@ -164,15 +312,29 @@ impl ConnectionOps for WaylandConnection {
 
     fn terminate_message_loop(&self) {
         log::trace!("Terminating Message Loop");
-        *self.should_terminate.borrow_mut() = true;
+        Self::request_terminate();
+    }
+
+    fn set_terminate_when_last_window_closed(&self, terminate: bool) {
+        self.last_window.set_enabled(terminate);
     }
 
     fn run_message_loop(&self) -> anyhow::Result<()> {
         let res = self.run_message_loop_impl();
+        let lost = matches!(&res, Err(err) if err.is::<ConnectionLost>());
+        if lost {
+            self.destroy_windows();
+        }
         // Ensure that we drop these eagerly, to avoid
         // noisy errors wrt. global destructors unwinding
         // in unexpected places
         self.wayland_state.borrow().windows.borrow_mut().clear();
+        if lost {
+            // Now that the windows are gone, anything that was spawned
+            // via with_window_inner will resolve its promise with an error
+            // rather than leaving its future pending forever
+            SPAWN_QUEUE.run();
+        }
         res
     }
 
@@ -188,6 +350,24 @@ impl ConnectionOps for WaylandConnection {
         Appearance::Light
     }
 
+    fn get_ui_settings(&self) -> UiSettings {
+        match promise::spawn::block_on(crate::os::xdg_desktop_portal::get_ui_settings()) {
+            Ok(settings) => settings,
+            Err(err) => {
+                log::debug!("Unable to read UI settings using xdg-desktop-portal: {err:#}");
+                UiSettings::default()
+            }
+        }
+    }
+
+    fn keyboard_layout(&self) -> Option<KeyboardLayout> {
+        self.wayland_state
+            .borrow()
+            .keyboard_mapper
+            .as_ref()
+            .map(|mapper| mapper.active_layout())
+    }
+
     fn screens(&self) -> anyhow::Result<crate::screen::Screens> {
         log::trace!("Getting screens for wayland connection");
 
@@ -204,46 +384,11 @@ impl ConnectionOps for WaylandConnection {
         let output_state = &self.wayland_state.borrow().output;
 
         for output in output_state.outputs() {
-            let info = match output_state.info(&output) {
-                Some(i) => i,
-                None => continue,
+            let Some(screen) = screen_info_for_output(output_state, &output, &config) else {
+                continue;
             };
-            let name = match info.name {
-                Some(n) => n.clone(),
-                None => format!("{} {}", info.model, info.make),
-            };
-
-            let (width, height) = info
-                .modes
-                .iter()
-                .find(|mode| mode.current)
-                .map(|mode| mode.dimensions)
-                .unwrap_or((info.physical_size.0, info.physical_size.1));
-
-            let rect = euclid::rect(
-                info.location.0 as isize,
-                info.location.1 as isize,
-                width as isize,
-                height as isize,
-            );
-
-            let scale = info.scale_factor as f64;
-
-            // FIXME: teach this how to resolve dpi_by_screen once
-            // dispatch_pending_event knows how to do the same
-            let effective_dpi = Some(config.dpi.unwrap_or(scale * crate::DEFAULT_DPI));
-
-            virtual_rect = virtual_rect.union(&rect);
-            by_name.insert(
-                name.clone(),
-                ScreenInfo {
-                    name,
-                    rect,
-                    scale,
-                    max_fps: None,
-                    effective_dpi,
-                },
-            );
+            virtual_rect = virtual_rect.union(&screen.rect);
+            by_name.insert(screen.name.clone(), screen);
         }
 
         // // The main screen is the one either at the origin of
@@ -274,3 +419,18 @@ impl ConnectionOps for WaylandConnection {
         })
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::{Error, ErrorKind};
+
+    #[test]
+    fn would_block_is_not_fatal() {
+        assert!(connection_lost(WaylandError::Io(Error::from(ErrorKind::WouldBlock))).is_none());
+        assert!(
+            connection_lost(WaylandError::Io(Error::from(ErrorKind::ConnectionReset))).is_some()
+        );
+        assert!(connection_lost(WaylandError::Io(Error::from(ErrorKind::BrokenPipe))).is_some());
+    }
+}
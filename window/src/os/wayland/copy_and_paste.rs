@@ -1,8 +1,9 @@
 use anyhow::{anyhow, bail};
 use smithay_client_toolkit as toolkit;
-use std::io::Write;
-use std::os::fd::AsRawFd;
+use std::io::{Read, Write};
+use std::os::fd::{AsRawFd, RawFd};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use toolkit::data_device_manager::data_offer::SelectionOffer;
 use toolkit::data_device_manager::{ReadPipe, WritePipe};
 use toolkit::primary_selection::device::PrimarySelectionDeviceHandler;
@@ -10,11 +11,41 @@ use toolkit::primary_selection::selection::PrimarySelectionSourceHandler;
 use wayland_protocols::wp::primary_selection::zv1::client::zwp_primary_selection_device_v1::ZwpPrimarySelectionDeviceV1;
 use wayland_protocols::wp::primary_selection::zv1::client::zwp_primary_selection_source_v1::ZwpPrimarySelectionSourceV1;
 
-use crate::{Clipboard, ConnectionOps};
+use crate::{Clipboard, ConnectionOps, Unsupported};
 
 use super::data_device::TEXT_MIME_TYPE;
 use super::state::WaylandState;
 
+/// The regular clipboard depends upon the data device manager global,
+/// which a minimal compositor may not provide
+fn require_data_device_manager<T>(manager: Option<&T>) -> Result<&T, Unsupported> {
+    manager.ok_or(Unsupported {
+        what: "wl_data_device_manager",
+    })
+}
+
+/// The device that serves a clipboard request
+#[derive(Debug, PartialEq)]
+enum Selection<'a, P, M> {
+    Primary(&'a P),
+    Regular(&'a M),
+}
+
+/// The primary selection is served by its own device when the compositor
+/// provides one, falling back to the regular clipboard otherwise
+fn select<'a, P, M>(
+    clipboard: Clipboard,
+    primary_selection: Option<&'a P>,
+    data_device_manager: Option<&'a M>,
+) -> Result<Selection<'a, P, M>, Unsupported> {
+    match (clipboard, primary_selection) {
+        (Clipboard::PrimarySelection, Some(primary_selection)) => {
+            Ok(Selection::Primary(primary_selection))
+        }
+        _ => require_data_device_manager(data_device_manager).map(Selection::Regular),
+    }
+}
+
 #[derive(Default)]
 pub struct CopyAndPaste {
     data_offer: Option<SelectionOffer>,
@@ -28,46 +59,184 @@ impl std::fmt::Debug for CopyAndPaste {
     }
 }
 
+/// The mime types under which text is offered, in order of preference
+pub(super) const TEXT_MIME_TYPES: &[&str] = &[TEXT_MIME_TYPE, "UTF8_STRING", "text/plain"];
+
+pub(super) fn text_mime_types() -> Vec<String> {
+    TEXT_MIME_TYPES.iter().map(|s| s.to_string()).collect()
+}
+
+/// Returns the first of `wanted` that is present in `offered`
+fn pick_mime_type(offered: &[String], wanted: &[String]) -> Option<String> {
+    wanted
+        .iter()
+        .find(|mime| offered.contains(mime))
+        .map(|mime| mime.to_string())
+}
+
+/// Data that we are offering as a selection, along with the
+/// mime types under which it is offered
+#[derive(Debug)]
+pub(super) struct OfferedData {
+    mime_types: Vec<String>,
+    data: Vec<u8>,
+}
+
+impl OfferedData {
+    pub(super) fn text(text: String) -> Self {
+        Self {
+            mime_types: text_mime_types(),
+            data: text.into_bytes(),
+        }
+    }
+
+    /// Text is offered under all of the text mime types, so that
+    /// clients that only know about one of them can still paste it
+    pub(super) fn with_mime_type(mime: &str, data: Vec<u8>) -> Self {
+        if TEXT_MIME_TYPES.contains(&mime) {
+            Self {
+                mime_types: text_mime_types(),
+                data,
+            }
+        } else {
+            Self {
+                mime_types: vec![mime.to_string()],
+                data,
+            }
+        }
+    }
+
+    pub(super) fn get(&self, mime: &str) -> Option<&[u8]> {
+        if self.mime_types.iter().any(|m| m == mime) {
+            Some(&self.data)
+        } else {
+            None
+        }
+    }
+}
+
 impl CopyAndPaste {
     pub(super) fn create() -> Arc<Mutex<Self>> {
         Arc::new(Mutex::new(Default::default()))
     }
 
-    pub(super) fn get_clipboard_data(&mut self, clipboard: Clipboard) -> anyhow::Result<ReadPipe> {
+    /// If we are the current owner of the selection, returns the data
+    /// that we offered in the first of `mime_types` that we have.
+    /// This allows pasting our own selection without a round trip
+    /// through the compositor, where we would otherwise be both the
+    /// reader and the writer of the pipe.
+    /// Our sources are dropped when they are cancelled, which happens
+    /// when another client takes the selection.
+    pub(super) fn get_owned_clipboard_data(
+        &self,
+        clipboard: Clipboard,
+        mime_types: &[String],
+    ) -> Option<Vec<u8>> {
         let conn = crate::Connection::get().unwrap().wayland();
         let wayland_state = conn.wayland_state.borrow();
-        let primary_selection = if let Clipboard::PrimarySelection = clipboard {
-            wayland_state.primary_selection_device.as_ref()
+        let use_primary = matches!(clipboard, Clipboard::PrimarySelection)
+            && wayland_state.primary_selection_device.is_some();
+
+        let offered = if use_primary {
+            wayland_state
+                .primary_selection_source
+                .as_ref()
+                .map(|(_source, offered)| offered)
         } else {
-            None
-        };
+            wayland_state
+                .copy_paste_source
+                .as_ref()
+                .map(|(_source, offered)| offered)
+        }?;
 
-        match primary_selection {
-            Some(primary_selection) => {
+        mime_types
+            .iter()
+            .find_map(|mime| offered.get(mime))
+            .map(|data| data.to_vec())
+    }
+
+    /// Requests the clipboard contents in the first of `mime_types`
+    /// that is offered by the current selection owner
+    pub(super) fn get_clipboard_data(
+        &mut self,
+        clipboard: Clipboard,
+        mime_types: &[String],
+    ) -> anyhow::Result<ReadPipe> {
+        let conn = crate::Connection::get().unwrap().wayland();
+        let wayland_state = conn.wayland_state.borrow();
+
+        let no_match = || anyhow!("clipboard has no data of type {}", mime_types.join(", "));
+
+        match select(
+            clipboard,
+            wayland_state.primary_selection_device.as_ref(),
+            wayland_state.data_device_manager_state.as_ref(),
+        )? {
+            Selection::Primary(primary_selection) => {
                 let offer = primary_selection
                     .data()
                     .selection_offer()
                     .ok_or_else(|| anyhow!("no primary selection offer"))?;
-                let pipe = offer.receive(TEXT_MIME_TYPE.to_string())?;
+                let mime = offer
+                    .with_mime_types(|offered| pick_mime_type(offered, mime_types))
+                    .ok_or_else(no_match)?;
+                let pipe = offer.receive(mime)?;
                 Ok(pipe)
             }
-            None => {
+            Selection::Regular(_) => {
                 let offer = self
                     .data_offer
                     .as_ref()
                     .ok_or_else(|| anyhow!("no data offer"))?;
-                let pipe = offer.receive(TEXT_MIME_TYPE.to_string())?;
+                let mime = offer
+                    .with_mime_types(|offered| pick_mime_type(offered, mime_types))
+                    .ok_or_else(no_match)?;
+                let pipe = offer.receive(mime)?;
                 Ok(pipe)
             }
         }
     }
 
-    pub(super) fn set_clipboard_data(&mut self, clipboard: Clipboard, data: String) {
+    pub(super) fn set_clipboard_data(
+        &mut self,
+        clipboard: Clipboard,
+        offered: OfferedData,
+    ) -> anyhow::Result<()> {
         let conn = crate::Connection::get().unwrap().wayland();
         let qh = conn.event_queue.borrow().handle();
         let mut wayland_state = conn.wayland_state.borrow_mut();
         let last_serial = *wayland_state.last_serial.borrow();
 
+        match select(
+            clipboard,
+            wayland_state.primary_selection_device.as_ref(),
+            wayland_state.data_device_manager_state.as_ref(),
+        )? {
+            Selection::Primary(primary_selection) => {
+                let manager = wayland_state.primary_selection_manager.as_ref().unwrap();
+                let source = manager.create_selection_source(&qh, offered.mime_types.clone());
+                source.set_selection(&primary_selection, last_serial);
+                wayland_state
+                    .primary_selection_source
+                    .replace((source, offered));
+            }
+            Selection::Regular(manager) => {
+                let data_device = wayland_state
+                    .data_device
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("no seat is available to own the clipboard"))?;
+                let source = manager.create_copy_paste_source(&qh, offered.mime_types.clone());
+                source.set_selection(data_device, last_serial);
+                wayland_state.copy_paste_source.replace((source, offered));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the mime types advertised by the current selection offer
+    pub(super) fn get_clipboard_formats(&self, clipboard: Clipboard) -> Vec<String> {
+        let conn = crate::Connection::get().unwrap().wayland();
+        let wayland_state = conn.wayland_state.borrow();
         let primary_selection = if let Clipboard::PrimarySelection = clipboard {
             wayland_state.primary_selection_device.as_ref()
         } else {
@@ -75,27 +244,21 @@ impl CopyAndPaste {
         };
 
         match primary_selection {
-            Some(primary_selection) => {
-                let manager = wayland_state.primary_selection_manager.as_ref().unwrap();
-                let source = manager.create_selection_source(&qh, [TEXT_MIME_TYPE]);
-                source.set_selection(&primary_selection, last_serial);
-                wayland_state
-                    .primary_selection_source
-                    .replace((source, data));
-            }
-            None => {
-                let data_device = &wayland_state.data_device;
-                let source = wayland_state
-                    .data_device_manager_state
-                    .create_copy_paste_source(&qh, vec![TEXT_MIME_TYPE]);
-                source.set_selection(data_device.as_ref().unwrap(), last_serial);
-                wayland_state.copy_paste_source.replace((source, data));
-            }
+            Some(primary_selection) => primary_selection
+                .data()
+                .selection_offer()
+                .map(|offer| offer.with_mime_types(|offered| offered.to_vec()))
+                .unwrap_or_default(),
+            None => self
+                .data_offer
+                .as_ref()
+                .map(|offer| offer.with_mime_types(|offered| offered.to_vec()))
+                .unwrap_or_default(),
         }
     }
 
-    pub(super) fn confirm_selection(&mut self, offer: SelectionOffer) {
-        self.data_offer.replace(offer);
+    pub(super) fn confirm_selection(&mut self, offer: Option<SelectionOffer>) {
+        self.data_offer = offer;
     }
 }
 
@@ -108,44 +271,119 @@ impl WaylandState {
     }
 }
 
-pub(super) fn write_selection_to_pipe(fd: WritePipe, text: &str) {
-    if let Err(e) = write_pipe_with_timeout(fd, text.as_bytes()) {
+pub(super) fn write_selection_to_pipe(fd: WritePipe, data: &[u8]) {
+    if let Err(e) = write_pipe_with_timeout(fd, data, pipe_inactivity_timeout()) {
         log::error!("while sending primary selection to pipe: {}", e);
     }
 }
 
-fn write_pipe_with_timeout(mut file: WritePipe, data: &[u8]) -> anyhow::Result<()> {
-    // set non-blocking I/O on the pipe
-    // (adapted from FileDescriptor::set_non_blocking_impl in /filedescriptor/src/unix.rs)
-    if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_SETFL, libc::O_NONBLOCK) } != 0 {
+/// Returns the configured time that a clipboard transfer may go without
+/// making any progress before we give up on it
+pub(super) fn pipe_inactivity_timeout() -> Duration {
+    Duration::from_millis(config::configuration().clipboard_pipe_timeout_ms)
+}
+
+/// Set non-blocking I/O on the pipe
+/// (adapted from FileDescriptor::set_non_blocking_impl in /filedescriptor/src/unix.rs)
+fn set_non_blocking(fd: RawFd) -> anyhow::Result<()> {
+    if unsafe { libc::fcntl(fd, libc::F_SETFL, libc::O_NONBLOCK) } != 0 {
         bail!(
             "failed to change non-blocking mode: {}",
             std::io::Error::last_os_error()
         )
     }
+    Ok(())
+}
 
+/// Waits for `events` to become ready on the pipe.
+/// Returns false if `timeout` elapsed first.
+fn wait_for_pipe(fd: RawFd, events: libc::c_short, timeout: Duration) -> anyhow::Result<bool> {
     let mut pfd = libc::pollfd {
-        fd: file.as_raw_fd(),
-        events: libc::POLLOUT,
+        fd,
+        events,
         revents: 0,
     };
+    let timeout_ms = timeout.as_millis().min(libc::c_int::MAX as u128) as libc::c_int;
+
+    loop {
+        match unsafe { libc::poll(&mut pfd, 1, timeout_ms) } {
+            0 => return Ok(false),
+            n if n > 0 => return Ok(true),
+            _ => {
+                let err = std::io::Error::last_os_error();
+                if err.kind() != std::io::ErrorKind::Interrupted {
+                    bail!("error polling pipe: {}", err);
+                }
+            }
+        }
+    }
+}
+
+/// Reads UTF-8 text from the pipe until EOF
+pub(super) fn read_pipe_with_timeout<F: Read + AsRawFd>(
+    file: F,
+    timeout: Duration,
+) -> anyhow::Result<String> {
+    let data = read_pipe_data_with_timeout(file, timeout)?;
+    Ok(String::from_utf8(data)?)
+}
+
+/// Reads the pipe until EOF.  `timeout` applies to each individual
+/// read rather than to the transfer as a whole, so a large transfer
+/// from a slow source will succeed for as long as data keeps arriving.
+pub(super) fn read_pipe_data_with_timeout<F: Read + AsRawFd>(
+    mut file: F,
+    timeout: Duration,
+) -> anyhow::Result<Vec<u8>> {
+    let mut result = Vec::new();
+    set_non_blocking(file.as_raw_fd())?;
+
+    let mut buf = [0u8; 8192];
+
+    loop {
+        if !wait_for_pipe(file.as_raw_fd(), libc::POLLIN, timeout)? {
+            bail!("timed out reading from pipe");
+        }
+        match file.read(&mut buf) {
+            Ok(size) if size == 0 => {
+                break;
+            }
+            Ok(size) => {
+                result.extend_from_slice(&buf[..size]);
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {}
+            Err(e) => bail!("error reading from pipe: {}", e),
+        }
+    }
+
+    Ok(result)
+}
+
+/// Writes all of `data` to the pipe.  As with read_pipe_with_timeout,
+/// `timeout` is the longest that we'll wait for the reader to make progress.
+fn write_pipe_with_timeout<F: Write + AsRawFd>(
+    mut file: F,
+    data: &[u8],
+    timeout: Duration,
+) -> anyhow::Result<()> {
+    set_non_blocking(file.as_raw_fd())?;
 
     let mut buf = data;
 
     while !buf.is_empty() {
-        if unsafe { libc::poll(&mut pfd, 1, 3000) == 1 } {
-            match file.write(buf) {
-                Ok(size) if size == 0 => {
-                    bail!("zero byte write");
-                }
-                Ok(size) => {
-                    buf = &buf[size..];
-                }
-                Err(e) => bail!("error writing to pipe: {}", e),
-            }
-        } else {
+        if !wait_for_pipe(file.as_raw_fd(), libc::POLLOUT, timeout)? {
             bail!("timed out writing to pipe");
         }
+        match file.write(buf) {
+            Ok(size) if size == 0 => {
+                bail!("zero byte write");
+            }
+            Ok(size) => {
+                buf = &buf[size..];
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {}
+            Err(e) => bail!("error writing to pipe: {}", e),
+        }
     }
 
     Ok(())
@@ -171,15 +409,13 @@ impl PrimarySelectionSourceHandler for WaylandState {
         mime: String,
         write_pipe: toolkit::data_device_manager::WritePipe,
     ) {
-        if mime != TEXT_MIME_TYPE {
-            return;
-        };
-
-        if let Some((ps_source, data)) = &self.primary_selection_source {
+        if let Some((ps_source, offered)) = &self.primary_selection_source {
             if ps_source.inner() != source {
                 return;
             }
-            write_selection_to_pipe(write_pipe, data);
+            if let Some(data) = offered.get(&mime) {
+                write_selection_to_pipe(write_pipe, data);
+            }
         }
     }
 
@@ -193,3 +429,86 @@ impl PrimarySelectionSourceHandler for WaylandState {
         source.destroy();
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use filedescriptor::Pipe;
+    use std::time::Instant;
+
+    const TIMEOUT: Duration = Duration::from_millis(200);
+
+    #[test]
+    fn slow_read_outlasting_timeout() {
+        let Pipe { read, mut write } = Pipe::new().unwrap();
+        let writer = std::thread::spawn(move || {
+            for _ in 0..8 {
+                write.write_all(b"hello").unwrap();
+                std::thread::sleep(TIMEOUT / 4);
+            }
+        });
+
+        let start = Instant::now();
+        let text = read_pipe_with_timeout(read, TIMEOUT).unwrap();
+        assert!(start.elapsed() > TIMEOUT);
+        assert_eq!(text, "hello".repeat(8));
+        writer.join().unwrap();
+    }
+
+    #[test]
+    fn stalled_read_times_out() {
+        let Pipe { read, mut write } = Pipe::new().unwrap();
+        write.write_all(b"hello").unwrap();
+
+        let err = read_pipe_with_timeout(read, TIMEOUT).unwrap_err();
+        assert!(err.to_string().contains("timed out"), "{err:#}");
+        drop(write);
+    }
+
+    #[test]
+    fn missing_data_device_manager_is_unsupported() {
+        require_data_device_manager(Some(&())).unwrap();
+
+        let err: anyhow::Error = require_data_device_manager(None::<&()>).unwrap_err().into();
+        let err = err.context("reading the clipboard");
+        let unsupported = err.downcast_ref::<Unsupported>().unwrap();
+        assert_eq!(unsupported.what, "wl_data_device_manager");
+    }
+
+    #[test]
+    fn clipboard_without_data_device_manager() {
+        // The primary selection has its own device and doesn't need
+        // the data device manager
+        assert_eq!(
+            select(Clipboard::PrimarySelection, Some(&"primary"), None::<&()>).unwrap(),
+            Selection::Primary(&"primary")
+        );
+
+        // Without a primary selection device it falls back to the
+        // regular clipboard, which is unsupported without the manager
+        for (clipboard, primary_selection) in [
+            (Clipboard::Clipboard, Some(&"primary")),
+            (Clipboard::Clipboard, None),
+            (Clipboard::PrimarySelection, None),
+        ] {
+            let err = select(clipboard, primary_selection, None::<&()>).unwrap_err();
+            assert_eq!(err.what, "wl_data_device_manager");
+        }
+
+        assert_eq!(
+            select(Clipboard::PrimarySelection, None::<&()>, Some(&"manager")).unwrap(),
+            Selection::Regular(&"manager")
+        );
+    }
+
+    #[test]
+    fn stalled_write_times_out() {
+        let Pipe { read, write } = Pipe::new().unwrap();
+        // More than the pipe buffer can hold, with nobody reading
+        let data = vec![b'x'; 4 * 1024 * 1024];
+
+        let err = write_pipe_with_timeout(write, &data, TIMEOUT).unwrap_err();
+        assert!(err.to_string().contains("timed out"), "{err:#}");
+        drop(read);
+    }
+}
@@ -0,0 +1,213 @@
+use std::collections::HashMap;
+
+use anyhow::anyhow;
+use config::ConfigHandle;
+use smithay_client_toolkit::seat::pointer::CursorIcon;
+use wayland_client::protocol::wl_pointer::WlPointer;
+use wayland_client::protocol::wl_shm::WlShm;
+use wayland_client::protocol::wl_surface::WlSurface;
+use wayland_client::Connection;
+use wayland_cursor::CursorTheme;
+
+use crate::x11::cursor::xcursor_names;
+use crate::MouseCursor;
+
+/// The xcursor size to use when neither the configuration nor
+/// the environment specify one
+const DEFAULT_SIZE: u32 = 24;
+
+/// Loads cursors from the xcursor theme named by the `xcursor_theme`
+/// and `xcursor_size` configuration, falling back to the
+/// `XCURSOR_THEME` and `XCURSOR_SIZE` environment variables.
+/// Wayland leaves it to the client to render the cursor at the
+/// scale of the output, so a copy of the theme is loaded at the
+/// appropriate size for each buffer scale that is in use.
+pub(super) struct CursorThemes {
+    name: String,
+    size: u32,
+    shm: WlShm,
+    themes: HashMap<u32, CursorTheme>,
+}
+
+impl CursorThemes {
+    pub(super) fn new(config: &ConfigHandle, shm: &WlShm) -> Self {
+        let name = config
+            .xcursor_theme
+            .clone()
+            .or_else(|| std::env::var("XCURSOR_THEME").ok())
+            .unwrap_or_else(|| "default".to_string());
+        let size = theme_size(
+            config.xcursor_size,
+            std::env::var("XCURSOR_SIZE").ok().as_deref(),
+        );
+        log::trace!("Using cursor theme {name} at size {size}");
+        Self {
+            name,
+            size,
+            shm: shm.clone(),
+            themes: HashMap::new(),
+        }
+    }
+
+    fn theme(&mut self, conn: &Connection, scale: u32) -> anyhow::Result<&mut CursorTheme> {
+        if !self.themes.contains_key(&scale) {
+            let theme =
+                CursorTheme::load_from_name(conn, self.shm.clone(), &self.name, self.size * scale)
+                    .map_err(|err| anyhow!("loading cursor theme {}: {err:#}", self.name))?;
+            self.themes.insert(scale, theme);
+        }
+        Ok(self.themes.get_mut(&scale).unwrap())
+    }
+
+    /// Attach the image for `cursor` to `surface`, rendered for
+    /// the specified buffer scale, and make it the pointer cursor
+    pub(super) fn set_cursor(
+        &mut self,
+        conn: &Connection,
+        pointer: &WlPointer,
+        serial: u32,
+        surface: &WlSurface,
+        cursor: MouseCursor,
+        scale: u32,
+    ) -> anyhow::Result<()> {
+        let size = self.size;
+        let theme = self.theme(conn, scale)?;
+
+        let name = candidate_names(cursor)
+            .find(|name| theme.get_cursor(name).is_some())
+            .ok_or_else(|| anyhow!("cursor theme has no {:?} cursor", cursor))?;
+        let cursor = &theme.get_cursor(name).unwrap()[0];
+
+        let (width, height) = cursor.dimensions();
+        let (hot_x, hot_y) = cursor.hotspot();
+        let scale = image_scale((width, height), size, scale);
+
+        surface.set_buffer_scale(scale as i32);
+        surface.attach(Some(&**cursor), 0, 0);
+        surface.damage_buffer(0, 0, width as i32, height as i32);
+        surface.commit();
+
+        pointer.set_cursor(
+            serial,
+            Some(surface),
+            (hot_x / scale) as i32,
+            (hot_y / scale) as i32,
+        );
+        Ok(())
+    }
+}
+
+/// Produce the names to try when loading `cursor` from a theme.
+/// The current name is preferred, but older themes may only have
+/// the legacy X11 names for the same cursor.
+fn candidate_names(cursor: MouseCursor) -> impl Iterator<Item = &'static str> {
+    let icon = cursor_icon(cursor);
+    std::iter::once(icon.name())
+        .chain(icon.alt_names().iter().copied())
+        .chain(xcursor_names(cursor).iter().copied())
+}
+
+fn cursor_icon(cursor: MouseCursor) -> CursorIcon {
+    match cursor {
+        MouseCursor::Arrow => CursorIcon::Default,
+        MouseCursor::Hand => CursorIcon::Pointer,
+        MouseCursor::SizeUpDown => CursorIcon::NsResize,
+        MouseCursor::SizeLeftRight => CursorIcon::EwResize,
+        MouseCursor::SizeNWSE => CursorIcon::NwseResize,
+        MouseCursor::SizeNESW => CursorIcon::NeswResize,
+        MouseCursor::Text => CursorIcon::Text,
+        MouseCursor::Crosshair => CursorIcon::Crosshair,
+        MouseCursor::NotAllowed => CursorIcon::NotAllowed,
+        MouseCursor::Grab => CursorIcon::Grab,
+        MouseCursor::Grabbing => CursorIcon::Grabbing,
+        MouseCursor::Wait => CursorIcon::Wait,
+    }
+}
+
+/// Determine the nominal cursor size, in surface coordinates
+fn theme_size(config_size: Option<u32>, env_size: Option<&str>) -> u32 {
+    config_size
+        .or_else(|| env_size.and_then(|size| size.parse::<u32>().ok()))
+        .filter(|&size| size > 0)
+        .unwrap_or(DEFAULT_SIZE)
+}
+
+/// Compute the buffer scale at which to render the cursor for a window
+/// with the specified dpi factor.
+/// Cursor surfaces can't use a viewport, so fractional scales are
+/// rounded up and left for the compositor to scale down.
+pub(super) fn cursor_scale(dpi_factor: f64) -> u32 {
+    if dpi_factor.is_finite() {
+        (dpi_factor.ceil() as u32).max(1)
+    } else {
+        1
+    }
+}
+
+/// Themes don't necessarily have an image at the size that we asked
+/// for, in which case the closest available size is returned.
+/// Compute the scale that presents an image of the specified dimensions
+/// closest to the nominal size, taking care that it divides the
+/// dimensions evenly as the protocol requires.
+fn image_scale((width, height): (u32, u32), size: u32, scale: u32) -> u32 {
+    let ideal = (width as f64 / size as f64).round() as u32;
+    let mut scale = ideal.clamp(1, scale.max(1));
+    while scale > 1 && (width % scale != 0 || height % scale != 0) {
+        scale -= 1;
+    }
+    scale
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn legacy_fallbacks() {
+        let names: Vec<&str> = candidate_names(MouseCursor::SizeNWSE).collect();
+        assert_eq!(names[0], "nwse-resize");
+        assert!(names.contains(&"bd_double_arrow"));
+
+        let names: Vec<&str> = candidate_names(MouseCursor::Grabbing).collect();
+        assert_eq!(names[0], "grabbing");
+        assert!(names.contains(&"closedhand"));
+    }
+
+    #[test]
+    fn size_precedence() {
+        assert_eq!(theme_size(Some(32), Some("48")), 32);
+        assert_eq!(theme_size(None, Some("48")), 48);
+        assert_eq!(theme_size(None, Some("bogus")), DEFAULT_SIZE);
+        assert_eq!(theme_size(None, None), DEFAULT_SIZE);
+        assert_eq!(theme_size(Some(0), None), DEFAULT_SIZE);
+    }
+
+    #[test]
+    fn cursor_scale_rounds_up() {
+        assert_eq!(cursor_scale(1.0), 1);
+        assert_eq!(cursor_scale(1.25), 2);
+        assert_eq!(cursor_scale(2.0), 2);
+        assert_eq!(cursor_scale(0.5), 1);
+        assert_eq!(cursor_scale(f64::NAN), 1);
+    }
+
+    #[test]
+    fn image_scale_matches_available_image() {
+        // The theme has an image at the requested size
+        assert_eq!(image_scale((48, 48), 24, 2), 2);
+        // Only the unscaled image is available; presenting it at
+        // scale 1 keeps it at the intended size
+        assert_eq!(image_scale((24, 24), 24, 2), 1);
+        // A larger image than we need is scaled down no further than
+        // the output scale
+        assert_eq!(image_scale((96, 96), 24, 2), 2);
+        assert_eq!(image_scale((24, 24), 24, 1), 1);
+    }
+
+    #[test]
+    fn image_scale_divides_dimensions() {
+        assert_eq!(image_scale((72, 72), 24, 3), 3);
+        assert_eq!(image_scale((71, 72), 24, 3), 1);
+        assert_eq!(image_scale((64, 64), 16, 3), 2);
+    }
+}
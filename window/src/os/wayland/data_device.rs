@@ -38,21 +38,33 @@ impl DataDeviceHandler for WaylandState {
 
         let offer = data.drag_offer().unwrap();
 
-        offer.with_mime_types(|mime_types| {
+        let has_uri_list = offer.with_mime_types(|mime_types| {
             log::trace!(
                 "Data offer entered: {:?}, mime_types: {:?}",
                 offer,
                 mime_types
             );
 
-            if let Some(mime) = mime_types.iter().find(|s| *s == URI_MIME_TYPE) {
-                offer.accept_mime_type(*self.last_serial.borrow(), Some(mime.clone()));
-            }
+            mime_types.iter().any(|s| s == URI_MIME_TYPE)
         });
 
-        offer.set_actions(DndAction::None | DndAction::Copy, DndAction::None);
+        if !has_uri_list {
+            // We only know how to accept files; tell the source that
+            // we won't take this one so that the user gets feedback
+            offer.accept_mime_type(*self.last_serial.borrow(), None);
+            offer.set_actions(DndAction::None, DndAction::None);
+            return;
+        }
 
-        let pointer = self.pointer.as_mut().unwrap();
+        offer.accept_mime_type(*self.last_serial.borrow(), Some(URI_MIME_TYPE.to_string()));
+        // Copy must be the preferred action, otherwise the compositor
+        // is free to negotiate no action at all and the drop is refused
+        offer.set_actions(DndAction::Copy, DndAction::Copy);
+
+        let Some(pointer) = self.pointers.active_mut() else {
+            log::debug!("ignoring drag and drop event as there is no pointer");
+            return;
+        };
         let mut pstate = pointer
             .pointer()
             .data::<PointerUserData>()
@@ -72,7 +84,10 @@ impl DataDeviceHandler for WaylandState {
         _qh: &wayland_client::QueueHandle<Self>,
         _data_device: &WlDataDevice,
     ) {
-        let pointer = self.pointer.as_mut().unwrap();
+        let Some(pointer) = self.pointers.active_mut() else {
+            log::debug!("ignoring drag and drop event as there is no pointer");
+            return;
+        };
         let mut pstate = pointer
             .pointer()
             .data::<PointerUserData>()
@@ -107,14 +122,9 @@ impl DataDeviceHandler for WaylandState {
                 return;
             }
         };
-        if let Some(offer) = offer {
-            if !offer.with_mime_types(|mime_types| mime_types.iter().any(|s| s == TEXT_MIME_TYPE)) {
-                return;
-            }
-
-            if let Some(copy_and_paste) = self.resolve_copy_and_paste() {
-                copy_and_paste.lock().unwrap().confirm_selection(offer);
-            }
+        // The offer is None when the selection has been cleared
+        if let Some(copy_and_paste) = self.resolve_copy_and_paste() {
+            copy_and_paste.lock().unwrap().confirm_selection(offer);
         }
     }
 
@@ -124,7 +134,10 @@ impl DataDeviceHandler for WaylandState {
         _qh: &wayland_client::QueueHandle<Self>,
         _data_device: &WlDataDevice,
     ) {
-        let pointer = self.pointer.as_mut().unwrap();
+        let Some(pointer) = self.pointers.active_mut() else {
+            log::debug!("ignoring drag and drop event as there is no pointer");
+            return;
+        };
         let mut pstate = pointer
             .pointer()
             .data::<PointerUserData>()
@@ -184,15 +197,13 @@ impl DataSourceHandler for WaylandState {
         mime: String,
         fd: WritePipe,
     ) {
-        if mime != TEXT_MIME_TYPE {
-            return;
-        }
-
-        if let Some((cp_source, data)) = &self.copy_paste_source {
+        if let Some((cp_source, offered)) = &self.copy_paste_source {
             if cp_source.inner() != source {
                 return;
             }
-            write_selection_to_pipe(fd, data);
+            if let Some(data) = offered.get(&mime) {
+                write_selection_to_pipe(fd, data);
+            }
         }
     }
 
@@ -0,0 +1,67 @@
+//! Implements xdg_wm_dialog_v1 so that the compositor can treat child
+//! windows as dialogs, and modal ones as blocking their parent
+use smithay_client_toolkit::globals::GlobalData;
+use wayland_client::globals::{BindError, GlobalList};
+use wayland_client::{Connection, Dispatch, QueueHandle};
+use wayland_protocols::xdg::dialog::v1::client::xdg_dialog_v1::XdgDialogV1;
+use wayland_protocols::xdg::dialog::v1::client::xdg_wm_dialog_v1::XdgWmDialogV1;
+use wayland_protocols::xdg::shell::client::xdg_toplevel::XdgToplevel;
+
+use super::state::WaylandState;
+
+pub(super) struct DialogState {
+    wm_dialog: XdgWmDialogV1,
+}
+
+impl DialogState {
+    pub(super) fn bind(
+        globals: &GlobalList,
+        queue_handle: &QueueHandle<WaylandState>,
+    ) -> Result<Self, BindError> {
+        let wm_dialog = globals.bind(queue_handle, 1..=1, GlobalData)?;
+        Ok(Self { wm_dialog })
+    }
+
+    /// Mark `toplevel` as a dialog. The returned object must be
+    /// destroyed before the toplevel is
+    pub(super) fn dialog(
+        &self,
+        toplevel: &XdgToplevel,
+        modal: bool,
+        queue_handle: &QueueHandle<WaylandState>,
+    ) -> XdgDialogV1 {
+        let dialog = self
+            .wm_dialog
+            .get_xdg_dialog(toplevel, queue_handle, GlobalData);
+        if modal {
+            dialog.set_modal();
+        }
+        dialog
+    }
+}
+
+impl Dispatch<XdgWmDialogV1, GlobalData> for WaylandState {
+    fn event(
+        _state: &mut Self,
+        _proxy: &XdgWmDialogV1,
+        _event: <XdgWmDialogV1 as wayland_client::Proxy>::Event,
+        _data: &GlobalData,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        // No events
+    }
+}
+
+impl Dispatch<XdgDialogV1, GlobalData> for WaylandState {
+    fn event(
+        _state: &mut Self,
+        _proxy: &XdgDialogV1,
+        _event: <XdgDialogV1 as wayland_client::Proxy>::Event,
+        _data: &GlobalData,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        // No events
+    }
+}
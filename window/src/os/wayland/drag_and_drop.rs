@@ -1,4 +1,3 @@
-use crate::wayland::read_pipe_with_timeout;
 use crate::ConnectionOps;
 use smithay_client_toolkit as toolkit;
 use std::path::PathBuf;
@@ -6,6 +5,7 @@ use toolkit::data_device_manager::data_offer::DragOffer;
 use toolkit::data_device_manager::ReadPipe;
 use url::Url;
 
+use super::copy_and_paste::{pipe_inactivity_timeout, read_pipe_with_timeout};
 use super::data_device::URI_MIME_TYPE;
 use super::WaylandConnection;
 
@@ -38,7 +38,7 @@ impl DragAndDrop {
     }
 
     pub(super) fn read_paths_from_pipe(read: ReadPipe) -> Option<Vec<PathBuf>> {
-        read_pipe_with_timeout(read)
+        read_pipe_with_timeout(read, pipe_inactivity_timeout())
             .map_err(|err| {
                 log::error!("Error while reading pipe from drop result: {:#}", err);
             })
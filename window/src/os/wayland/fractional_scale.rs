@@ -0,0 +1,126 @@
+//! Implements wp_fractional_scale_v1 and wp_viewporter so that we can
+//! render at non-integer scale factors such as 1.5
+use smithay_client_toolkit::globals::GlobalData;
+use wayland_client::globals::{BindError, GlobalList};
+use wayland_client::protocol::wl_surface::WlSurface;
+use wayland_client::{Connection, Dispatch, QueueHandle};
+use wayland_protocols::wp::fractional_scale::v1::client::wp_fractional_scale_manager_v1::WpFractionalScaleManagerV1;
+use wayland_protocols::wp::fractional_scale::v1::client::wp_fractional_scale_v1::{
+    Event as FractionalScaleEvent, WpFractionalScaleV1,
+};
+use wayland_protocols::wp::viewporter::client::wp_viewport::WpViewport;
+use wayland_protocols::wp::viewporter::client::wp_viewporter::WpViewporter;
+
+use super::state::WaylandState;
+use super::WaylandConnection;
+
+/// The preferred_scale event reports the scale as a fraction of 120
+const SCALE_DENOMINATOR: f64 = 120.;
+
+pub(super) struct FractionalScaleState {
+    manager: WpFractionalScaleManagerV1,
+    viewporter: WpViewporter,
+}
+
+impl FractionalScaleState {
+    /// Both globals are required; fractional scaling is only useful if
+    /// we can also tell the compositor the logical size of our buffer.
+    pub(super) fn bind(
+        globals: &GlobalList,
+        queue_handle: &QueueHandle<WaylandState>,
+    ) -> Result<Self, BindError> {
+        let manager = globals.bind(queue_handle, 1..=1, GlobalData)?;
+        let viewporter = globals.bind(queue_handle, 1..=1, GlobalData)?;
+        Ok(Self {
+            manager,
+            viewporter,
+        })
+    }
+
+    /// Subscribe to scale changes for the surface belonging to window_id
+    /// and create its viewport
+    pub(super) fn attach(
+        &self,
+        surface: &WlSurface,
+        window_id: usize,
+        queue_handle: &QueueHandle<WaylandState>,
+    ) -> (WpFractionalScaleV1, WpViewport) {
+        let fractional_scale = self.manager.get_fractional_scale(
+            surface,
+            queue_handle,
+            FractionalScaleData { window_id },
+        );
+        let viewport = self
+            .viewporter
+            .get_viewport(surface, queue_handle, GlobalData);
+        (fractional_scale, viewport)
+    }
+}
+
+pub(super) struct FractionalScaleData {
+    window_id: usize,
+}
+
+impl Dispatch<WpFractionalScaleManagerV1, GlobalData> for WaylandState {
+    fn event(
+        _state: &mut Self,
+        _proxy: &WpFractionalScaleManagerV1,
+        _event: <WpFractionalScaleManagerV1 as wayland_client::Proxy>::Event,
+        _data: &GlobalData,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        // No events
+    }
+}
+
+impl Dispatch<WpViewporter, GlobalData> for WaylandState {
+    fn event(
+        _state: &mut Self,
+        _proxy: &WpViewporter,
+        _event: <WpViewporter as wayland_client::Proxy>::Event,
+        _data: &GlobalData,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        // No events
+    }
+}
+
+impl Dispatch<WpViewport, GlobalData> for WaylandState {
+    fn event(
+        _state: &mut Self,
+        _proxy: &WpViewport,
+        _event: <WpViewport as wayland_client::Proxy>::Event,
+        _data: &GlobalData,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        // No events
+    }
+}
+
+impl Dispatch<WpFractionalScaleV1, FractionalScaleData> for WaylandState {
+    fn event(
+        state: &mut Self,
+        _proxy: &WpFractionalScaleV1,
+        event: <WpFractionalScaleV1 as wayland_client::Proxy>::Event,
+        data: &FractionalScaleData,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        if let FractionalScaleEvent::PreferredScale { scale } = event {
+            let scale = scale as f64 / SCALE_DENOMINATOR;
+            log::trace!("window {} preferred scale is {scale}", data.window_id);
+            let window_id = data.window_id;
+            if let Some(inner) = state.window_by_id(window_id) {
+                let pending = inner.borrow().pending_event.clone();
+                pending.lock().unwrap().fractional_scale.replace(scale);
+                WaylandConnection::with_window_inner(window_id, |inner| {
+                    inner.dispatch_pending_event();
+                    Ok(())
+                });
+            }
+        }
+    }
+}
@@ -0,0 +1,61 @@
+//! Implements zwp_idle_inhibit_manager_v1 so that the application can
+//! keep the screen from blanking or locking while its window is shown
+use smithay_client_toolkit::globals::GlobalData;
+use wayland_client::globals::{BindError, GlobalList};
+use wayland_client::protocol::wl_surface::WlSurface;
+use wayland_client::{Connection, Dispatch, QueueHandle};
+use wayland_protocols::wp::idle_inhibit::zv1::client::zwp_idle_inhibit_manager_v1::ZwpIdleInhibitManagerV1;
+use wayland_protocols::wp::idle_inhibit::zv1::client::zwp_idle_inhibitor_v1::ZwpIdleInhibitorV1;
+
+use super::state::WaylandState;
+
+pub(super) struct IdleInhibitState {
+    manager: ZwpIdleInhibitManagerV1,
+}
+
+impl IdleInhibitState {
+    pub(super) fn bind(
+        globals: &GlobalList,
+        queue_handle: &QueueHandle<WaylandState>,
+    ) -> Result<Self, BindError> {
+        let manager = globals.bind(queue_handle, 1..=1, GlobalData)?;
+        Ok(Self { manager })
+    }
+
+    /// The compositor honors the inhibitor only while the surface is
+    /// visible; it must be destroyed to lift the inhibition
+    pub(super) fn inhibit(
+        &self,
+        surface: &WlSurface,
+        queue_handle: &QueueHandle<WaylandState>,
+    ) -> ZwpIdleInhibitorV1 {
+        self.manager
+            .create_inhibitor(surface, queue_handle, GlobalData)
+    }
+}
+
+impl Dispatch<ZwpIdleInhibitManagerV1, GlobalData> for WaylandState {
+    fn event(
+        _state: &mut Self,
+        _proxy: &ZwpIdleInhibitManagerV1,
+        _event: <ZwpIdleInhibitManagerV1 as wayland_client::Proxy>::Event,
+        _data: &GlobalData,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        // No events
+    }
+}
+
+impl Dispatch<ZwpIdleInhibitorV1, GlobalData> for WaylandState {
+    fn event(
+        _state: &mut Self,
+        _proxy: &ZwpIdleInhibitorV1,
+        _event: <ZwpIdleInhibitorV1 as wayland_client::Proxy>::Event,
+        _data: &GlobalData,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        // No events
+    }
+}
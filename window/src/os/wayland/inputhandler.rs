@@ -186,13 +186,16 @@ impl Dispatch<ZwpTextInputV3, TextInputData, WaylandState> for TextInputState {
                         leds: KeyboardLedStatus::empty(),
                         repeat_count: 1,
                         key_is_down: true,
+                        phys: None,
+                        time: 0,
+                        serial: None,
                         raw: None,
                     }));
                 }
-                let status = if let Some(text) = pending_state.pre_edit.take() {
-                    DeadKeyStatus::Composing(text)
-                } else {
-                    DeadKeyStatus::None
+                // An empty preedit string means that composition has ended
+                let status = match pending_state.pre_edit.take() {
+                    Some(text) if !text.is_empty() => DeadKeyStatus::Composing(text),
+                    _ => DeadKeyStatus::None,
                 };
                 state.dispatch_to_focused_window(WindowEvent::AdviseDeadKeyStatus(status));
             }
@@ -1,38 +1,87 @@
 use std::borrow::BorrowMut;
 
 use wayland_client::protocol::wl_keyboard::{Event as WlKeyboardEvent, KeymapFormat, WlKeyboard};
+use wayland_client::protocol::wl_seat::WlSeat;
 use wayland_client::{Dispatch, Proxy};
+use wayland_protocols::wp::text_input::zv3::client::zwp_text_input_v3::{
+    ContentHint, ContentPurpose,
+};
 use xkbcommon::xkb;
 use xkbcommon::xkb::CONTEXT_NO_FLAGS;
 
 use crate::x11::KeyboardWithFallback;
+use crate::{DeadKeyStatus, WindowEvent};
 
 use super::state::WaylandState;
 use super::SurfaceUserData;
 
+/// How many key events to hold on to while waiting for a keymap
+const MAX_QUEUED_KEY_EVENTS: usize = 64;
+
+/// Key and modifier events that arrived before we had a keymap
+/// to resolve them with
+#[derive(Default)]
+pub(super) struct QueuedKeyEvents {
+    events: Vec<WlKeyboardEvent>,
+}
+
+impl QueuedKeyEvents {
+    /// Hold on to a key or modifier event until the keymap arrives.
+    /// Other events only make sense at the time that they happen.
+    fn queue(&mut self, event: WlKeyboardEvent) {
+        if !matches!(
+            event,
+            WlKeyboardEvent::Key { .. } | WlKeyboardEvent::Modifiers { .. }
+        ) {
+            return;
+        }
+        if self.events.len() < MAX_QUEUED_KEY_EVENTS {
+            log::debug!("queueing {event:?} until a keymap has been received");
+            self.events.push(event);
+        } else {
+            log::warn!("ignoring {event:?} as no keymap has been received");
+        }
+    }
+
+    /// The events were destined for the window that had the keyboard
+    /// focus; once it loses it, or the keyboard goes away, they are stale
+    pub(super) fn clear(&mut self) {
+        self.events.clear();
+    }
+
+    fn take(&mut self) -> Vec<WlKeyboardEvent> {
+        std::mem::take(&mut self.events)
+    }
+}
+
 // We can't use the xkbcommon feature because it is too abstract for us
 impl Dispatch<WlKeyboard, KeyboardData> for WaylandState {
     fn event(
         state: &mut WaylandState,
         keyboard: &WlKeyboard,
         event: <WlKeyboard as wayland_client::Proxy>::Event,
-        _data: &KeyboardData,
+        data: &KeyboardData,
         _conn: &wayland_client::Connection,
         _qhandle: &wayland_client::QueueHandle<WaylandState>,
     ) {
         log::trace!("We reached an event here: {:?}???", event);
+        let mut keymap_replaced = false;
         match &event {
             WlKeyboardEvent::Enter {
                 serial, surface, ..
             } => {
                 *state.active_surface_id.borrow_mut() = Some(surface.id());
                 *state.last_serial.borrow_mut() = *serial;
+                state.input_serials.record(&data.seat, *serial);
                 if let Some(sud) = SurfaceUserData::try_from_wl(&surface) {
                     let window_id = sud.window_id;
                     state.keyboard_window_id.borrow_mut().replace(window_id);
                     if let Some(text_input) = &state.text_input {
                         if let Some(input) = text_input.get_text_input_for_keyboard(keyboard) {
                             input.enable();
+                            // Let the input method know that it is feeding a terminal,
+                            // so that it can avoid things like auto-capitalization
+                            input.set_content_type(ContentHint::None, ContentPurpose::Terminal);
                             input.commit();
                         }
                         text_input.advise_surface(surface, keyboard);
@@ -43,6 +92,8 @@ impl Dispatch<WlKeyboard, KeyboardData> for WaylandState {
             }
             WlKeyboardEvent::Leave { serial, .. } => {
                 *state.last_serial.borrow_mut() = *serial;
+                state.input_serials.record(&data.seat, *serial);
+                state.queued_key_events.clear();
                 if let Some(text_input) = &state.text_input {
                     if let Some(input) = text_input.get_text_input_for_keyboard(keyboard) {
                         input.disable();
@@ -52,14 +103,15 @@ impl Dispatch<WlKeyboard, KeyboardData> for WaylandState {
             }
             WlKeyboardEvent::Key { serial, .. } | WlKeyboardEvent::Modifiers { serial, .. } => {
                 *state.last_serial.borrow_mut() = *serial;
+                state.input_serials.record(&data.seat, *serial);
             }
             WlKeyboardEvent::RepeatInfo { rate, delay } => {
                 *state.key_repeat_rate.borrow_mut() = *rate;
                 *state.key_repeat_delay.borrow_mut() = *delay;
             }
             WlKeyboardEvent::Keymap { format, fd, size } => {
-                match format.into_result().unwrap() {
-                    KeymapFormat::XkbV1 => {
+                match format.into_result() {
+                    Ok(KeymapFormat::XkbV1) => {
                         // In later protocol versions, the fd must be privately mmap'd.
                         // We let xkb handle this and then turn it back into a string.
                         #[allow(unused_unsafe)] // Upstream release will change this
@@ -78,7 +130,11 @@ impl Dispatch<WlKeyboard, KeyboardData> for WaylandState {
                                 let s = keymap.get_as_string(xkb::KEYMAP_FORMAT_TEXT_V1);
                                 match KeyboardWithFallback::new_from_string(s) {
                                     Ok(k) => {
+                                        if let Some(previous) = state.keyboard_mapper.as_ref() {
+                                            k.inherit_layout(previous);
+                                        }
                                         state.keyboard_mapper.replace(k);
+                                        keymap_replaced = true;
                                     }
                                     Err(err) => {
                                         log::error!("Error processing keymap change: {:#}", err);
@@ -94,25 +150,213 @@ impl Dispatch<WlKeyboard, KeyboardData> for WaylandState {
                             }
                         }
                     }
-                    _ => {}
+                    format => {
+                        log::debug!("keyboard has no usable keymap: {format:?}");
+                    }
                 }
             }
-            _ => {
-                unimplemented!()
+            _ => {}
+        }
+
+        route_keyboard_event(state, event);
+
+        if keymap_replaced {
+            // The modifier and repeat state was tracked in terms of
+            // the old keymap; start afresh, as we do when the focus
+            // changes.  The compositor follows up with the current
+            // modifiers for the new keymap.
+            for win in state.windows.borrow().values() {
+                win.borrow_mut().keymap_changed();
+            }
+            // Now that we can resolve them, process any key events
+            // that arrived before the keymap did
+            for event in state.queued_key_events.take() {
+                route_keyboard_event(state, event);
             }
         }
+    }
+}
 
-        let Some(&window_id) = state.keyboard_window_id.as_ref() else {
-            return;
+fn route_keyboard_event(state: &mut WaylandState, event: WlKeyboardEvent) {
+    let Some(&window_id) = state.keyboard_window_id.as_ref() else {
+        return;
+    };
+    let Some(win) = state.window_by_id(window_id) else {
+        return;
+    };
+    let mut inner = win.as_ref().borrow_mut();
+    let mapper = state.keyboard_mapper.borrow_mut();
+    let Some(mapper) = mapper.as_mut() else {
+        state.queued_key_events.queue(event);
+        return;
+    };
+    // The compositor reports a change of group in the modifiers,
+    // and a change of layout may also come with a new keymap
+    let check_layout = matches!(
+        event,
+        WlKeyboardEvent::Modifiers { .. } | WlKeyboardEvent::Keymap { .. }
+    );
+    inner.keyboard_event(mapper, event);
+    drop(inner);
+
+    if check_layout {
+        if let Some(layout) = mapper.layout_changed() {
+            for win in state.windows.borrow().values() {
+                let mut win = win.borrow_mut();
+                win.events
+                    .dispatch(WindowEvent::AdviseDeadKeyStatus(DeadKeyStatus::None));
+                win.events
+                    .dispatch(WindowEvent::KeyboardLayoutChanged(layout.clone()));
+            }
+        }
+    }
+}
+
+pub(super) struct KeyboardData {
+    pub(super) seat: WlSeat,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::x11::RawKeyMode;
+    use crate::{KeyCode, Modifiers, WindowEventSender, WindowKeyEvent};
+    use wayland_client::protocol::wl_keyboard::KeyState;
+    use wayland_client::WEnum;
+
+    const KEY_Q: u32 = 16;
+    const SHIFT_MASK: u32 = 1;
+
+    /// Just enough of a keymap to type q and Q, so that the test
+    /// doesn't depend on the xkeyboard-config data of the host
+    const KEYMAP: &str = r#"xkb_keymap {
+        xkb_keycodes {
+            minimum = 8;
+            maximum = 255;
+            <LFSH> = 50;
+            <AD01> = 24;
         };
-        let Some(win) = state.window_by_id(window_id) else {
-            return;
+        xkb_types {
+            type "ONE_LEVEL" {
+                modifiers = none;
+                level_name[Level1] = "Any";
+            };
+            type "ALPHABETIC" {
+                modifiers = Shift+Lock;
+                map[Shift] = Level2;
+                map[Lock] = Level2;
+                level_name[Level1] = "Base";
+                level_name[Level2] = "Caps";
+            };
         };
-        let mut inner = win.as_ref().borrow_mut();
-        let mapper = state.keyboard_mapper.borrow_mut();
-        let mapper = mapper.as_mut().expect("no keymap");
-        inner.keyboard_event(mapper, event);
+        xkb_compatibility {
+            interpret Shift_L {
+                action = SetMods(modifiers = Shift);
+            };
+        };
+        xkb_symbols {
+            key <LFSH> { [ Shift_L ] };
+            key <AD01> { type = "ALPHABETIC", [ q, Q ] };
+            modifier_map Shift { <LFSH> };
+        };
+    };"#;
+
+    fn modifiers(mods_depressed: u32) -> WlKeyboardEvent {
+        WlKeyboardEvent::Modifiers {
+            serial: 1,
+            mods_depressed,
+            mods_latched: 0,
+            mods_locked: 0,
+            group: 0,
+        }
     }
-}
 
-pub(super) struct KeyboardData {}
+    fn key(key: u32, state: KeyState) -> WlKeyboardEvent {
+        WlKeyboardEvent::Key {
+            serial: 2,
+            time: 0,
+            key,
+            state: WEnum::Value(state),
+        }
+    }
+
+    /// Feeds events to the mapper the same way that
+    /// WaylandWindowInner::keyboard_event does
+    fn replay(mapper: &KeyboardWithFallback, events: Vec<WlKeyboardEvent>) -> Vec<WindowKeyEvent> {
+        let mut raw_mode = RawKeyMode::default();
+        let mut sender = WindowEventSender::new(|_, _| {});
+        let mut resolved = vec![];
+        for event in events {
+            match event {
+                WlKeyboardEvent::Modifiers {
+                    mods_depressed,
+                    mods_latched,
+                    mods_locked,
+                    group,
+                    ..
+                } => mapper.update_modifier_state(mods_depressed, mods_latched, mods_locked, group),
+                WlKeyboardEvent::Key {
+                    key, state, time, ..
+                } => resolved.extend(mapper.process_wayland_key(
+                    key,
+                    state.into_result().unwrap() == KeyState::Pressed,
+                    time,
+                    None,
+                    &mut raw_mode,
+                    &mut sender,
+                )),
+                _ => unreachable!(),
+            }
+        }
+        resolved
+    }
+
+    #[test]
+    fn only_key_events_are_queued() {
+        let mut queued = QueuedKeyEvents::default();
+        queued.queue(WlKeyboardEvent::RepeatInfo { rate: 1, delay: 2 });
+        assert!(queued.take().is_empty());
+
+        for _ in 0..MAX_QUEUED_KEY_EVENTS + 10 {
+            queued.queue(key(KEY_Q, KeyState::Pressed));
+        }
+        assert_eq!(queued.take().len(), MAX_QUEUED_KEY_EVENTS);
+        assert!(queued.take().is_empty());
+    }
+
+    #[test]
+    fn queued_events_are_replayed_in_order() {
+        let mut queued = QueuedKeyEvents::default();
+        queued.queue(modifiers(SHIFT_MASK));
+        queued.queue(key(KEY_Q, KeyState::Pressed));
+        queued.queue(key(KEY_Q, KeyState::Released));
+
+        // The keymap arrives, and the shift that was held
+        // beforehand applies to the key that followed it
+        let mapper = KeyboardWithFallback::new_from_string(KEYMAP.to_string()).unwrap();
+        let resolved = replay(&mapper, queued.take());
+        assert_eq!(resolved.len(), 1);
+        match &resolved[0] {
+            WindowKeyEvent::KeyEvent(event) => {
+                assert_eq!(event.key, KeyCode::Char('Q'));
+                assert_eq!(event.modifiers, Modifiers::SHIFT);
+            }
+            _ => panic!("expected a KeyEvent"),
+        }
+        assert_eq!(mapper.get_key_modifiers(), Modifiers::SHIFT);
+    }
+
+    #[test]
+    fn focus_loss_discards_queued_events() {
+        let mut queued = QueuedKeyEvents::default();
+        queued.queue(modifiers(SHIFT_MASK));
+        queued.queue(key(KEY_Q, KeyState::Pressed));
+        queued.clear();
+
+        // Nothing that was typed into the window that lost the
+        // focus is delivered to whichever one has it now
+        let mapper = KeyboardWithFallback::new_from_string(KEYMAP.to_string()).unwrap();
+        assert!(replay(&mapper, queued.take()).is_empty());
+        assert_eq!(mapper.get_key_modifiers(), Modifiers::NONE);
+    }
+}
@@ -7,11 +7,20 @@ pub mod window;
 pub use self::window::*;
 pub use connection::*;
 pub use output::*;
+mod activation;
 mod copy_and_paste;
-mod drag_and_drop;
-// mod frame;
+mod cursor;
 mod data_device;
+mod dialog;
+mod drag_and_drop;
+mod fractional_scale;
+mod frame;
+mod idle_inhibit;
 mod keyboard;
 mod pointer;
+mod pointer_gestures;
+mod presentation;
 mod seat;
+mod shortcuts_inhibit;
 mod state;
+mod touch;
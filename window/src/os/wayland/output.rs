@@ -2,6 +2,9 @@
 
 use crate::screen::{ScreenInfo, Screens};
 use crate::ScreenRect;
+use config::ConfigHandle;
+use super::window::base_dpi;
+use smithay_client_toolkit::output::OutputState;
 use smithay_client_toolkit::globals::GlobalData;
 use smithay_client_toolkit::reexports::protocols_wlr::output_management::v1::client::zwlr_output_head_v1::{ZwlrOutputHeadV1, self, Event as ZwlrOutputHeadEvent};
 use smithay_client_toolkit::reexports::protocols_wlr::output_management::v1::client::zwlr_output_manager_v1::{ZwlrOutputManagerV1, self, Event as ZwlrOutputEvent};
@@ -11,7 +14,7 @@ use wayland_client::globals::{GlobalList, BindError};
 use std::collections::HashMap;
 use std::sync::Mutex;
 use wayland_client::backend::ObjectId;
-use wayland_client::protocol::wl_output::Transform;
+use wayland_client::protocol::wl_output::{Transform, WlOutput};
 
 use super::state::WaylandState;
 
@@ -98,7 +101,7 @@ impl OutputManagerState {
             virtual_rect = virtual_rect.union(&rect);
             // FIXME: teach this how to resolve dpi_by_screen once
             // dispatch_pending_event knows how to do the same
-            let effective_dpi = Some(config.dpi.unwrap_or(scale * crate::DEFAULT_DPI));
+            let effective_dpi = Some(config.dpi.unwrap_or(scale * base_dpi(&config)));
             by_name.insert(
                 name.clone(),
                 ScreenInfo {
@@ -143,6 +146,50 @@ impl OutputManagerState {
     }
 }
 
+/// Describe a wl_output; returns None if the compositor hasn't yet
+/// told us about it
+pub(super) fn screen_info_for_output(
+    output_state: &OutputState,
+    output: &WlOutput,
+    config: &ConfigHandle,
+) -> Option<ScreenInfo> {
+    let info = output_state.info(output)?;
+    let name = match info.name {
+        Some(n) => n.clone(),
+        None => format!("{} {}", info.model, info.make),
+    };
+
+    let current_mode = info.modes.iter().find(|mode| mode.current);
+    let (width, height) = current_mode
+        .map(|mode| mode.dimensions)
+        .unwrap_or((info.physical_size.0, info.physical_size.1));
+    // refresh_rate is expressed in mHz
+    let max_fps = current_mode
+        .filter(|mode| mode.refresh_rate > 0)
+        .map(|mode| (mode.refresh_rate as f64 / 1000.).round() as usize);
+
+    let rect = euclid::rect(
+        info.location.0 as isize,
+        info.location.1 as isize,
+        width as isize,
+        height as isize,
+    );
+
+    let scale = info.scale_factor as f64;
+
+    // FIXME: teach this how to resolve dpi_by_screen once
+    // dispatch_pending_event knows how to do the same
+    let effective_dpi = Some(config.dpi.unwrap_or(scale * base_dpi(config)));
+
+    Some(ScreenInfo {
+        name,
+        rect,
+        scale,
+        max_fps,
+        effective_dpi,
+    })
+}
+
 #[derive(Default)]
 pub(super) struct OutputManagerData {}
 
@@ -1,19 +1,27 @@
 use std::cell::RefCell;
+use std::convert::TryInto;
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 use smithay_client_toolkit::compositor::SurfaceData;
 use smithay_client_toolkit::reexports::csd_frame::{DecorationsFrame, FrameClick};
 use smithay_client_toolkit::seat::pointer::{
-    PointerData, PointerDataExt, PointerEvent, PointerEventKind, PointerHandler,
+    AxisScroll, PointerData, PointerDataExt, PointerEvent, PointerEventKind, PointerHandler,
 };
+use smithay_client_toolkit::seat::pointer_constraints::PointerConstraintsHandler;
+use smithay_client_toolkit::seat::relative_pointer::{RelativeMotionEvent, RelativePointerHandler};
 use wayland_client::backend::ObjectId;
-use wayland_client::protocol::wl_pointer::{ButtonState, WlPointer};
+use wayland_client::protocol::wl_pointer::{AxisSource, ButtonState, WlPointer};
 use wayland_client::protocol::wl_seat::WlSeat;
+use wayland_client::protocol::wl_surface::WlSurface;
 use wayland_client::{Connection, Proxy, QueueHandle};
-use wezterm_input_types::MousePress;
+use wayland_protocols::wp::pointer_constraints::zv1::client::zwp_confined_pointer_v1::ZwpConfinedPointerV1;
+use wayland_protocols::wp::pointer_constraints::zv1::client::zwp_locked_pointer_v1::ZwpLockedPointerV1;
+use wayland_protocols::wp::relative_pointer::zv1::client::zwp_relative_pointer_v1::ZwpRelativePointerV1;
+use wezterm_input_types::{MousePress, ScrollSource};
 
 use crate::wayland::SurfaceUserData;
+use crate::{GestureEvent, GestureKind, GesturePhase};
 
 use super::copy_and_paste::CopyAndPaste;
 use super::drag_and_drop::DragAndDrop;
@@ -28,12 +36,12 @@ impl PointerHandler for WaylandState {
         pointer: &WlPointer,
         events: &[PointerEvent],
     ) {
-        let mut pstate = pointer
-            .data::<PointerUserData>()
-            .unwrap()
-            .state
-            .lock()
-            .unwrap();
+        let data = pointer.data::<PointerUserData>().unwrap();
+        let seat_id = data.pdata.seat().id();
+        // Operations such as setting the cursor apply to the pointer
+        // that was most recently used
+        self.pointers.set_active(&seat_id);
+        let mut pstate = data.state.lock().unwrap();
 
         for evt in events {
             if let PointerEventKind::Enter { .. } = &evt.kind {
@@ -43,6 +51,7 @@ impl PointerHandler for WaylandState {
             }
             if let Some(serial) = event_serial(&evt) {
                 *self.last_serial.borrow_mut() = serial;
+                self.input_serials.record(data.pdata.seat(), serial);
                 pstate.serial = serial;
             }
             if let Some(pending) = self
@@ -50,6 +59,9 @@ impl PointerHandler for WaylandState {
                 .get(&self.active_surface_id.borrow().as_ref().unwrap())
             {
                 let mut pending = pending.lock().unwrap();
+                if let PointerEventKind::Enter { .. } = &evt.kind {
+                    pending.focus_seat.replace(seat_id.clone());
+                }
                 if pending.queue(evt) {
                     WaylandConnection::with_window_inner(pending.window_id, move |inner| {
                         inner.dispatch_pending_mouse();
@@ -62,6 +74,91 @@ impl PointerHandler for WaylandState {
     }
 }
 
+impl RelativePointerHandler for WaylandState {
+    fn relative_pointer_motion(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _relative_pointer: &ZwpRelativePointerV1,
+        _pointer: &WlPointer,
+        event: RelativeMotionEvent,
+    ) {
+        let active_surface_id = self.active_surface_id.borrow();
+        let Some(surface_id) = active_surface_id.as_ref() else {
+            return;
+        };
+        if let Some(pending) = self.surface_to_pending.get(surface_id) {
+            let mut pending = pending.lock().unwrap();
+            // Report raw deltas; acceleration doesn't make sense
+            // for a pointer that isn't moving
+            if pending.queue_relative(event.delta_unaccel) {
+                WaylandConnection::with_window_inner(pending.window_id, move |inner| {
+                    inner.dispatch_pending_mouse();
+                    Ok(())
+                });
+            }
+        }
+    }
+}
+
+impl PointerConstraintsHandler for WaylandState {
+    fn confined(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _confined_pointer: &ZwpConfinedPointerV1,
+        _surface: &WlSurface,
+        _pointer: &WlPointer,
+    ) {
+        log::trace!("pointer confined");
+    }
+
+    fn unconfined(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        confined_pointer: &ZwpConfinedPointerV1,
+        surface: &WlSurface,
+        _pointer: &WlPointer,
+    ) {
+        constraint_ended(surface, confined_pointer.id());
+    }
+
+    fn locked(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _locked_pointer: &ZwpLockedPointerV1,
+        _surface: &WlSurface,
+        _pointer: &WlPointer,
+    ) {
+        log::trace!("pointer locked");
+    }
+
+    fn unlocked(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        locked_pointer: &ZwpLockedPointerV1,
+        surface: &WlSurface,
+        _pointer: &WlPointer,
+    ) {
+        constraint_ended(surface, locked_pointer.id());
+    }
+}
+
+/// We use oneshot constraints, which become defunct once the compositor
+/// deactivates them, so forget about it in order to allow the window
+/// to request a new one
+fn constraint_ended(surface: &WlSurface, constraint_id: ObjectId) {
+    if let Some(sud) = SurfaceUserData::try_from_wl(surface) {
+        WaylandConnection::with_window_inner(sud.window_id, move |inner| {
+            inner.pointer_constraint_ended(&constraint_id);
+            Ok(())
+        });
+    }
+}
+
 pub(super) struct PointerUserData {
     pub(super) pdata: PointerData,
     pub(super) state: Mutex<PointerState>,
@@ -80,7 +177,7 @@ impl PointerUserData {
 pub(super) struct PointerState {
     active_surface_id: Option<ObjectId>,
     pub(super) drag_and_drop: DragAndDrop,
-    serial: u32,
+    pub(super) serial: u32,
 }
 
 impl PointerDataExt for PointerUserData {
@@ -91,12 +188,25 @@ impl PointerDataExt for PointerUserData {
 
 #[derive(Clone, Debug)]
 pub struct PendingMouse {
-    window_id: usize,
+    pub(super) window_id: usize,
     pub(super) copy_and_paste: Arc<Mutex<CopyAndPaste>>,
     surface_coords: Option<(f64, f64)>,
-    button: Vec<(MousePress, ButtonState)>,
+    button: Vec<(MousePress, ButtonState, u32, u32)>,
+    // The time of the most recent event that carried one
+    time: u32,
     scroll: Option<(f64, f64)>,
+    // High resolution wheel movement, in 1/120ths of a detent
+    wheel120: Option<(i32, i32)>,
+    // The source of the most recent scroll; it is only reported
+    // at the start of each sequence
+    scroll_source: ScrollSource,
+    scroll_stopped: bool,
+    relative: Option<(f64, f64)>,
+    gestures: Vec<GestureEvent>,
+    entered: Option<(f64, f64)>,
     in_window: bool,
+    // The seat whose pointer is in the window
+    focus_seat: Option<ObjectId>,
 }
 
 impl PendingMouse {
@@ -108,67 +218,172 @@ impl PendingMouse {
             window_id,
             copy_and_paste: Arc::clone(copy_and_paste),
             button: vec![],
+            time: 0,
             scroll: None,
+            wheel120: None,
+            scroll_source: ScrollSource::Unknown,
+            scroll_stopped: false,
+            relative: None,
+            gestures: vec![],
             surface_coords: None,
+            entered: None,
             in_window: false,
+            focus_seat: None,
         }))
     }
 
     pub(super) fn queue(&mut self, evt: &PointerEvent) -> bool {
-        match evt.kind {
+        self.queue_kind(&evt.kind, evt.position)
+    }
+
+    /// Queues an event at `position`, in surface coordinates
+    pub(super) fn queue_kind(&mut self, kind: &PointerEventKind, position: (f64, f64)) -> bool {
+        match *kind {
             PointerEventKind::Enter { .. } => {
+                let changed = !self.in_window;
                 self.in_window = true;
-                false
+                self.entered.replace(position);
+                changed
             }
             PointerEventKind::Leave { .. } => {
                 let changed = self.in_window;
                 self.surface_coords = None;
+                self.entered = None;
                 self.in_window = false;
+                self.focus_seat = None;
                 changed
             }
-            PointerEventKind::Motion { .. } => {
+            PointerEventKind::Motion { time } => {
+                self.time = time;
                 let changed = self.surface_coords.is_none();
-                self.surface_coords.replace(evt.position);
+                self.surface_coords.replace(position);
                 changed
             }
-            PointerEventKind::Press { button, .. } | PointerEventKind::Release { button, .. } => {
+            PointerEventKind::Press {
+                button,
+                time,
+                serial,
+            }
+            | PointerEventKind::Release {
+                button,
+                time,
+                serial,
+            } => {
                 fn linux_button(b: u32) -> Option<MousePress> {
                     // See BTN_LEFT and friends in <linux/input-event-codes.h>
                     match b {
                         0x110 => Some(MousePress::Left),
                         0x111 => Some(MousePress::Right),
                         0x112 => Some(MousePress::Middle),
-                        _ => None,
+                        // BTN_SIDE and BTN_BACK
+                        0x113 | 0x116 => Some(MousePress::Back),
+                        // BTN_EXTRA and BTN_FORWARD
+                        0x114 | 0x115 => Some(MousePress::Forward),
+                        _ => b.try_into().ok().map(MousePress::Other),
                     }
                 }
                 let button = match linux_button(button) {
                     Some(button) => button,
                     None => return false,
                 };
+                self.time = time;
                 let changed = self.button.is_empty();
-                let button_state = match evt.kind {
+                let button_state = match kind {
                     PointerEventKind::Press { .. } => ButtonState::Pressed,
                     PointerEventKind::Release { .. } => ButtonState::Released,
                     _ => unreachable!(),
                 };
-                self.button.push((button, button_state));
+                self.button.push((button, button_state, time, serial));
                 changed
             }
             PointerEventKind::Axis {
                 horizontal,
                 vertical,
-                ..
+                source,
+                time,
             } => {
-                let changed = self.scroll.is_none();
-                let (x, y) = self.scroll.take().unwrap_or((0., 0.));
-                self.scroll
-                    .replace((x + horizontal.absolute, y + vertical.absolute));
+                self.time = time;
+                let mut changed = false;
+                if let Some(source) = source {
+                    self.scroll_source = match source {
+                        AxisSource::Wheel => ScrollSource::Wheel,
+                        AxisSource::Finger => ScrollSource::Finger,
+                        AxisSource::Continuous => ScrollSource::Continuous,
+                        AxisSource::WheelTilt => ScrollSource::WheelTilt,
+                        _ => ScrollSource::Unknown,
+                    };
+                }
+
+                // The frame that reports the end of a finger scroll
+                // usually carries no motion
+                if horizontal.absolute != 0. || vertical.absolute != 0. {
+                    changed |= self.scroll.is_none();
+                    let (x, y) = self.scroll.take().unwrap_or((0., 0.));
+                    self.scroll
+                        .replace((x + horizontal.absolute, y + vertical.absolute));
+                }
+
+                let (wheel_x, wheel_y) = (axis_value120(&horizontal), axis_value120(&vertical));
+                if wheel_x != 0 || wheel_y != 0 {
+                    changed |= self.wheel120.is_none();
+                    let (x, y) = self.wheel120.take().unwrap_or((0, 0));
+                    self.wheel120.replace((x + wheel_x, y + wheel_y));
+                }
+
+                if horizontal.stop || vertical.stop {
+                    changed |= !self.scroll_stopped;
+                    self.scroll_stopped = true;
+                }
                 changed
             }
         }
     }
 
-    pub(super) fn next_button(pending: &Arc<Mutex<Self>>) -> Option<(MousePress, ButtonState)> {
+    /// The pointer of `seat` was removed; if it was over the window,
+    /// treat it as though it left the window
+    pub(super) fn pointer_lost(&mut self, seat: &ObjectId) -> bool {
+        if self.focus_seat.as_ref() != Some(seat) {
+            return false;
+        }
+        self.focus_seat = None;
+        let changed = self.in_window;
+        self.surface_coords = None;
+        self.entered = None;
+        self.button.clear();
+        self.in_window = false;
+        changed
+    }
+
+    pub(super) fn queue_relative(&mut self, (dx, dy): (f64, f64)) -> bool {
+        let changed = self.relative.is_none();
+        let (x, y) = self.relative.take().unwrap_or((0., 0.));
+        self.relative.replace((x + dx, y + dy));
+        changed
+    }
+
+    pub(super) fn queue_gesture(&mut self, event: GestureEvent) -> bool {
+        let changed = self.gestures.is_empty();
+        // Coalesce consecutive updates, as we only need to report
+        // the accumulated change since the prior dispatch
+        if let Some(prior) = self.gestures.last_mut() {
+            if prior.phase == GesturePhase::Update
+                && event.phase == GesturePhase::Update
+                && prior.kind == event.kind
+            {
+                prior.scale_delta *= event.scale_delta;
+                prior.rotation += event.rotation;
+                prior.dx += event.dx;
+                prior.dy += event.dy;
+                return changed;
+            }
+        }
+        self.gestures.push(event);
+        changed
+    }
+
+    pub(super) fn next_button(
+        pending: &Arc<Mutex<Self>>,
+    ) -> Option<(MousePress, ButtonState, u32, u32)> {
         let mut pending = pending.lock().unwrap();
         if pending.button.is_empty() {
             None
@@ -177,6 +392,14 @@ impl PendingMouse {
         }
     }
 
+    pub(super) fn time(pending: &Arc<Mutex<Self>>) -> u32 {
+        pending.lock().unwrap().time
+    }
+
+    pub(super) fn entered(pending: &Arc<Mutex<Self>>) -> Option<(f64, f64)> {
+        pending.lock().unwrap().entered.take()
+    }
+
     pub(super) fn coords(pending: &Arc<Mutex<Self>>) -> Option<(f64, f64)> {
         pending.lock().unwrap().surface_coords.take()
     }
@@ -185,11 +408,105 @@ impl PendingMouse {
         pending.lock().unwrap().scroll.take()
     }
 
+    pub(super) fn scroll_source(pending: &Arc<Mutex<Self>>) -> ScrollSource {
+        pending.lock().unwrap().scroll_source
+    }
+
+    pub(super) fn wheel120(pending: &Arc<Mutex<Self>>) -> Option<(i32, i32)> {
+        pending.lock().unwrap().wheel120.take()
+    }
+
+    /// Returns true if the scroll sequence ended since the last call
+    pub(super) fn scroll_stopped(pending: &Arc<Mutex<Self>>) -> bool {
+        std::mem::take(&mut pending.lock().unwrap().scroll_stopped)
+    }
+
+    pub(super) fn relative(pending: &Arc<Mutex<Self>>) -> Option<(f64, f64)> {
+        pending.lock().unwrap().relative.take()
+    }
+
+    pub(super) fn gestures(pending: &Arc<Mutex<Self>>) -> Vec<GestureEvent> {
+        std::mem::take(&mut pending.lock().unwrap().gestures)
+    }
+
     pub(super) fn in_window(pending: &Arc<Mutex<Self>>) -> bool {
         pending.lock().unwrap().in_window
     }
 }
 
+/// Accumulates fractional scroll distances so that slow touchpad
+/// scrolling eventually adds up to whole wheel events rather than
+/// being truncated away.
+#[derive(Debug, Default, Clone, Copy)]
+pub(super) struct ScrollAccumulator {
+    remainder: f64,
+}
+
+impl ScrollAccumulator {
+    /// Adds `value` to the accumulator and returns the whole number of
+    /// units that are now ready to be reported; the fractional part is
+    /// retained for next time.
+    pub(super) fn accumulate(&mut self, value: f64) -> f64 {
+        if value == 0.0 {
+            return 0.0;
+        }
+        if self.remainder != 0.0 && value.signum() != self.remainder.signum() {
+            // reset accumulator when changing scroll direction
+            self.remainder = 0.0;
+        }
+        let total = self.remainder + value;
+        // Allow a little slop so that eg: ten steps of 0.3 add up to 3
+        // rather than 2.9999999999999996
+        let discrete = (total + total.signum() * 1e-9).trunc();
+        self.remainder = total - discrete;
+        discrete
+    }
+
+    pub(super) fn reset(&mut self) {
+        self.remainder = 0.0;
+    }
+}
+
+/// Accumulates the movement of a high resolution scroll wheel, which
+/// is reported in 1/120ths of a detent, so that a whole wheel event is
+/// produced each time the wheel has moved by a full detent.
+#[derive(Debug, Default, Clone, Copy)]
+pub(super) struct WheelAccumulator {
+    remainder: i32,
+}
+
+impl WheelAccumulator {
+    /// Adds `value120` to the accumulator and returns the number of
+    /// whole detents that are now ready to be reported
+    pub(super) fn accumulate(&mut self, value120: i32) -> i32 {
+        if value120 == 0 {
+            return 0;
+        }
+        if self.remainder != 0 && value120.signum() != self.remainder.signum() {
+            // reset accumulator when changing scroll direction
+            self.remainder = 0;
+        }
+        let total = self.remainder + value120;
+        let detents = total / 120;
+        self.remainder = total % 120;
+        detents
+    }
+
+    pub(super) fn reset(&mut self) {
+        self.remainder = 0;
+    }
+}
+
+/// Compositors that predate wl_pointer.axis_value120 report whole
+/// detents via axis_discrete instead
+fn axis_value120(axis: &AxisScroll) -> i32 {
+    if axis.value120 != 0 {
+        axis.value120
+    } else {
+        axis.discrete * 120
+    }
+}
+
 fn event_serial(event: &PointerEvent) -> Option<u32> {
     Some(match event.kind {
         PointerEventKind::Enter { serial, .. } => serial,
@@ -259,7 +576,124 @@ impl WaylandState {
                     }
                     _ => {}
                 }
+                // Redraw to reflect the hovered button
+                if inner.window_frame.is_dirty() {
+                    inner.refresh_frame();
+                }
             }
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn scroll_accumulates_fractions() {
+        let mut acc = ScrollAccumulator::default();
+        let total: f64 = (0..10).map(|_| acc.accumulate(0.3)).sum();
+        assert_eq!(total, 3.0);
+
+        let mut acc = ScrollAccumulator::default();
+        let steps: Vec<f64> = (0..4).map(|_| acc.accumulate(-0.5)).collect();
+        assert_eq!(steps, vec![0.0, -1.0, 0.0, -1.0]);
+    }
+
+    #[test]
+    fn relative_motion_accumulates() {
+        let pending = PendingMouse::create(1, &CopyAndPaste::create());
+        {
+            let mut pending = pending.lock().unwrap();
+            assert!(pending.queue_relative((1.5, -2.0)));
+            assert!(!pending.queue_relative((0.5, 1.0)));
+        }
+        assert_eq!(PendingMouse::relative(&pending), Some((2.0, -1.0)));
+        assert_eq!(PendingMouse::relative(&pending), None);
+    }
+
+    #[test]
+    fn gesture_updates_coalesce() {
+        fn gesture(phase: GesturePhase, scale_delta: f64, dx: f64) -> GestureEvent {
+            GestureEvent {
+                kind: GestureKind::Pinch,
+                phase,
+                fingers: 2,
+                scale_delta,
+                rotation: 1.0,
+                dx,
+                dy: 0.0,
+            }
+        }
+
+        let pending = PendingMouse::create(1, &CopyAndPaste::create());
+        {
+            let mut pending = pending.lock().unwrap();
+            assert!(pending.queue_gesture(gesture(GesturePhase::Begin, 1.0, 0.0)));
+            assert!(!pending.queue_gesture(gesture(GesturePhase::Update, 1.5, 1.0)));
+            assert!(!pending.queue_gesture(gesture(GesturePhase::Update, 2.0, 2.0)));
+            assert!(!pending.queue_gesture(gesture(GesturePhase::End, 1.0, 0.0)));
+        }
+        let gestures = PendingMouse::gestures(&pending);
+        assert_eq!(
+            gestures,
+            vec![
+                gesture(GesturePhase::Begin, 1.0, 0.0),
+                GestureEvent {
+                    rotation: 2.0,
+                    ..gesture(GesturePhase::Update, 3.0, 3.0)
+                },
+                gesture(GesturePhase::End, 1.0, 0.0),
+            ]
+        );
+        assert!(PendingMouse::gestures(&pending).is_empty());
+    }
+
+    #[test]
+    fn wheel_value120_accumulates_detents() {
+        let mut acc = WheelAccumulator::default();
+        let steps: Vec<i32> = [30, 30, 30, 30, 30]
+            .iter()
+            .map(|&v| acc.accumulate(v))
+            .collect();
+        assert_eq!(steps, vec![0, 0, 0, 1, 0]);
+
+        let mut acc = WheelAccumulator::default();
+        let steps: Vec<i32> = [60, 120, 60, 240]
+            .iter()
+            .map(|&v| acc.accumulate(v))
+            .collect();
+        assert_eq!(steps, vec![0, 1, 1, 2]);
+
+        let mut acc = WheelAccumulator::default();
+        let steps: Vec<i32> = [-40, -40, -40, -100, -20]
+            .iter()
+            .map(|&v| acc.accumulate(v))
+            .collect();
+        assert_eq!(steps, vec![0, 0, -1, 0, -1]);
+    }
+
+    #[test]
+    fn wheel_direction_change_and_stop_reset() {
+        let mut acc = WheelAccumulator::default();
+        assert_eq!(acc.accumulate(90), 0);
+        // Reversing discards the partial detent in the other direction
+        assert_eq!(acc.accumulate(-60), 0);
+        assert_eq!(acc.accumulate(-60), -1);
+
+        assert_eq!(acc.accumulate(90), 0);
+        // axis_stop or leaving the window drops the remainder
+        acc.reset();
+        assert_eq!(acc.accumulate(60), 0);
+        assert_eq!(acc.accumulate(30), 0);
+        assert_eq!(acc.accumulate(30), 1);
+    }
+
+    #[test]
+    fn scroll_direction_change_resets() {
+        let mut acc = ScrollAccumulator::default();
+        assert_eq!(acc.accumulate(0.9), 0.0);
+        assert_eq!(acc.accumulate(-0.5), 0.0);
+        assert_eq!(acc.accumulate(-0.5), -1.0);
+    }
+}
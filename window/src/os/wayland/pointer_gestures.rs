@@ -0,0 +1,256 @@
+//! Implements zwp_pointer_gestures_v1 so that touchpad pinch and swipe
+//! gestures can be reported to the application
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use smithay_client_toolkit::globals::GlobalData;
+use wayland_client::backend::ObjectId;
+use wayland_client::globals::{BindError, GlobalList};
+use wayland_client::protocol::wl_pointer::WlPointer;
+use wayland_client::{Connection, Dispatch, QueueHandle};
+use wayland_protocols::wp::pointer_gestures::zv1::client::zwp_pointer_gesture_pinch_v1::{
+    Event as PinchEvent, ZwpPointerGesturePinchV1,
+};
+use wayland_protocols::wp::pointer_gestures::zv1::client::zwp_pointer_gesture_swipe_v1::{
+    Event as SwipeEvent, ZwpPointerGestureSwipeV1,
+};
+use wayland_protocols::wp::pointer_gestures::zv1::client::zwp_pointer_gestures_v1::ZwpPointerGesturesV1;
+
+use crate::{GestureEvent, GestureKind, GesturePhase};
+
+use super::state::WaylandState;
+use super::WaylandConnection;
+
+pub(super) struct PointerGesturesState {
+    manager: ZwpPointerGesturesV1,
+    // Keyed by the id of the seat that the pointer belongs to
+    gestures: HashMap<ObjectId, (ZwpPointerGesturePinchV1, ZwpPointerGestureSwipeV1)>,
+}
+
+impl PointerGesturesState {
+    pub(super) fn bind(
+        globals: &GlobalList,
+        queue_handle: &QueueHandle<WaylandState>,
+    ) -> Result<Self, BindError> {
+        let manager = globals.bind(queue_handle, 1..=1, GlobalData)?;
+        Ok(Self {
+            manager,
+            gestures: HashMap::new(),
+        })
+    }
+
+    /// Subscribe to the gestures performed with the pointer of the
+    /// specified seat
+    pub(super) fn attach(
+        &mut self,
+        seat_id: ObjectId,
+        pointer: &WlPointer,
+        queue_handle: &QueueHandle<WaylandState>,
+    ) {
+        self.detach(&seat_id);
+        let pinch = self
+            .manager
+            .get_pinch_gesture(pointer, queue_handle, GestureData::default());
+        let swipe = self
+            .manager
+            .get_swipe_gesture(pointer, queue_handle, GestureData::default());
+        self.gestures.insert(seat_id, (pinch, swipe));
+    }
+
+    pub(super) fn detach(&mut self, seat_id: &ObjectId) {
+        if let Some((pinch, swipe)) = self.gestures.remove(seat_id) {
+            pinch.destroy();
+            swipe.destroy();
+        }
+    }
+}
+
+/// The protocol only reports the finger count when the gesture begins,
+/// and reports the pinch scale relative to that point, whereas we report
+/// both with every event and the scale relative to the prior event.
+#[derive(Debug)]
+struct GestureTracking {
+    fingers: u32,
+    scale: f64,
+}
+
+#[derive(Debug)]
+pub(super) struct GestureData {
+    tracking: Mutex<GestureTracking>,
+}
+
+impl Default for GestureData {
+    fn default() -> Self {
+        Self {
+            tracking: Mutex::new(GestureTracking {
+                fingers: 0,
+                scale: 1.0,
+            }),
+        }
+    }
+}
+
+impl GestureData {
+    fn begin(&self, kind: GestureKind, fingers: u32) -> GestureEvent {
+        let mut tracking = self.tracking.lock().unwrap();
+        tracking.fingers = fingers;
+        tracking.scale = 1.0;
+        GestureEvent {
+            kind,
+            phase: GesturePhase::Begin,
+            fingers,
+            scale_delta: 1.0,
+            rotation: 0.0,
+            dx: 0.0,
+            dy: 0.0,
+        }
+    }
+
+    fn update(
+        &self,
+        kind: GestureKind,
+        (dx, dy): (f64, f64),
+        scale: f64,
+        rotation: f64,
+    ) -> GestureEvent {
+        let mut tracking = self.tracking.lock().unwrap();
+        let scale_delta = if tracking.scale > 0.0 {
+            scale / tracking.scale
+        } else {
+            1.0
+        };
+        tracking.scale = scale;
+        GestureEvent {
+            kind,
+            phase: GesturePhase::Update,
+            fingers: tracking.fingers,
+            scale_delta,
+            rotation,
+            dx,
+            dy,
+        }
+    }
+
+    fn end(&self, kind: GestureKind, cancelled: bool) -> GestureEvent {
+        let tracking = self.tracking.lock().unwrap();
+        GestureEvent {
+            kind,
+            phase: if cancelled {
+                GesturePhase::Cancel
+            } else {
+                GesturePhase::End
+            },
+            fingers: tracking.fingers,
+            scale_delta: 1.0,
+            rotation: 0.0,
+            dx: 0.0,
+            dy: 0.0,
+        }
+    }
+}
+
+impl WaylandState {
+    /// Gestures are reported for the surface that has the pointer focus
+    fn queue_gesture(&self, event: GestureEvent) {
+        let active_surface_id = self.active_surface_id.borrow();
+        let Some(surface_id) = active_surface_id.as_ref() else {
+            return;
+        };
+        if let Some(pending) = self.surface_to_pending.get(surface_id) {
+            let mut pending = pending.lock().unwrap();
+            if pending.queue_gesture(event) {
+                WaylandConnection::with_window_inner(pending.window_id, move |inner| {
+                    inner.dispatch_pending_mouse();
+                    Ok(())
+                });
+            }
+        }
+    }
+}
+
+impl Dispatch<ZwpPointerGesturesV1, GlobalData> for WaylandState {
+    fn event(
+        _state: &mut Self,
+        _proxy: &ZwpPointerGesturesV1,
+        _event: <ZwpPointerGesturesV1 as wayland_client::Proxy>::Event,
+        _data: &GlobalData,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        // No events
+    }
+}
+
+impl Dispatch<ZwpPointerGesturePinchV1, GestureData> for WaylandState {
+    fn event(
+        state: &mut Self,
+        _proxy: &ZwpPointerGesturePinchV1,
+        event: PinchEvent,
+        data: &GestureData,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        let event = match event {
+            PinchEvent::Begin { fingers, .. } => data.begin(GestureKind::Pinch, fingers),
+            PinchEvent::Update {
+                dx,
+                dy,
+                scale,
+                rotation,
+                ..
+            } => data.update(GestureKind::Pinch, (dx, dy), scale, rotation),
+            PinchEvent::End { cancelled, .. } => data.end(GestureKind::Pinch, cancelled != 0),
+            _ => return,
+        };
+        state.queue_gesture(event);
+    }
+}
+
+impl Dispatch<ZwpPointerGestureSwipeV1, GestureData> for WaylandState {
+    fn event(
+        state: &mut Self,
+        _proxy: &ZwpPointerGestureSwipeV1,
+        event: SwipeEvent,
+        data: &GestureData,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        let event = match event {
+            SwipeEvent::Begin { fingers, .. } => data.begin(GestureKind::Swipe, fingers),
+            SwipeEvent::Update { dx, dy, .. } => {
+                data.update(GestureKind::Swipe, (dx, dy), 1.0, 0.0)
+            }
+            SwipeEvent::End { cancelled, .. } => data.end(GestureKind::Swipe, cancelled != 0),
+            _ => return,
+        };
+        state.queue_gesture(event);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn pinch_scale_is_relative_to_previous_event() {
+        let data = GestureData::default();
+        let begin = data.begin(GestureKind::Pinch, 2);
+        assert_eq!(begin.phase, GesturePhase::Begin);
+        assert_eq!(begin.fingers, 2);
+
+        let update = data.update(GestureKind::Pinch, (1.0, 0.0), 1.5, 2.0);
+        assert_eq!(update.scale_delta, 1.5);
+        assert_eq!(update.fingers, 2);
+        let update = data.update(GestureKind::Pinch, (0.0, 0.0), 3.0, 0.0);
+        assert_eq!(update.scale_delta, 2.0);
+
+        let end = data.end(GestureKind::Pinch, true);
+        assert_eq!(end.phase, GesturePhase::Cancel);
+        assert_eq!(end.fingers, 2);
+
+        // A new gesture starts from a scale of 1 again
+        data.begin(GestureKind::Pinch, 2);
+        let update = data.update(GestureKind::Pinch, (0.0, 0.0), 0.5, 0.0);
+        assert_eq!(update.scale_delta, 0.5);
+    }
+}
@@ -0,0 +1,212 @@
+//! Implements wp_presentation so that we can tell the application when
+//! the frames that it painted were actually shown on the display
+use std::time::{Duration, Instant};
+
+use smithay_client_toolkit::globals::GlobalData;
+use wayland_client::globals::{BindError, GlobalList};
+use wayland_client::protocol::wl_surface::WlSurface;
+use wayland_client::{Connection, Dispatch, QueueHandle, WEnum};
+use wayland_protocols::wp::presentation_time::client::wp_presentation::{
+    Event as PresentationEvent, WpPresentation,
+};
+use wayland_protocols::wp::presentation_time::client::wp_presentation_feedback::{
+    Event as FeedbackEvent, Kind, WpPresentationFeedback,
+};
+
+use crate::{FrameTiming, PresentationFlags};
+
+use super::state::WaylandState;
+use super::WaylandConnection;
+
+pub(super) struct PresentationState {
+    presentation: WpPresentation,
+    /// The clock that presentation timestamps are taken from.
+    /// The compositor announces it as soon as we bind the global,
+    /// so it will be known by the time that any feedback arrives.
+    clock_id: Option<libc::clockid_t>,
+}
+
+impl PresentationState {
+    pub(super) fn bind(
+        globals: &GlobalList,
+        queue_handle: &QueueHandle<WaylandState>,
+    ) -> Result<Self, BindError> {
+        let presentation = globals.bind(queue_handle, 1..=1, GlobalData)?;
+        Ok(Self {
+            presentation,
+            clock_id: None,
+        })
+    }
+
+    /// Ask to be told when the content of the next commit of the
+    /// surface belonging to window_id is presented
+    pub(super) fn request_feedback(
+        &self,
+        surface: &WlSurface,
+        window_id: usize,
+        requested: Instant,
+        queue_handle: &QueueHandle<WaylandState>,
+    ) {
+        self.presentation.feedback(
+            surface,
+            queue_handle,
+            FeedbackData {
+                window_id,
+                requested,
+            },
+        );
+    }
+
+    /// Translate a presentation timestamp into an Instant
+    fn presented_at(&self, timestamp: Duration) -> Option<Instant> {
+        let clock_now = clock_now(self.clock_id?)?;
+        Some(timestamp_to_instant(timestamp, clock_now, Instant::now()))
+    }
+}
+
+fn clock_now(clock_id: libc::clockid_t) -> Option<Duration> {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    if unsafe { libc::clock_gettime(clock_id, &mut ts) } != 0 {
+        return None;
+    }
+    Some(Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32))
+}
+
+/// The compositor's clock isn't necessarily the one that Instant is
+/// based upon, so work out how long ago the timestamp was according
+/// to that clock and subtract that from the current Instant.
+fn timestamp_to_instant(timestamp: Duration, clock_now: Duration, now: Instant) -> Instant {
+    let age = clock_now.saturating_sub(timestamp);
+    now.checked_sub(age).unwrap_or(now)
+}
+
+fn presentation_flags(kind: WEnum<Kind>) -> PresentationFlags {
+    let kind = match kind {
+        WEnum::Value(kind) => kind,
+        WEnum::Unknown(bits) => Kind::from_bits_truncate(bits),
+    };
+    let mut flags = PresentationFlags::empty();
+    flags.set(PresentationFlags::VSYNC, kind.contains(Kind::Vsync));
+    flags.set(PresentationFlags::HW_CLOCK, kind.contains(Kind::HwClock));
+    flags.set(
+        PresentationFlags::HW_COMPLETION,
+        kind.contains(Kind::HwCompletion),
+    );
+    flags.set(PresentationFlags::ZERO_COPY, kind.contains(Kind::ZeroCopy));
+    flags
+}
+
+pub(super) struct FeedbackData {
+    window_id: usize,
+    requested: Instant,
+}
+
+impl Dispatch<WpPresentation, GlobalData> for WaylandState {
+    fn event(
+        state: &mut Self,
+        _proxy: &WpPresentation,
+        event: PresentationEvent,
+        _data: &GlobalData,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        if let PresentationEvent::ClockId { clk_id } = event {
+            log::trace!("presentation clock is {clk_id}");
+            if let Some(presentation) = state.presentation.as_mut() {
+                presentation.clock_id.replace(clk_id as libc::clockid_t);
+            }
+        }
+    }
+}
+
+impl Dispatch<WpPresentationFeedback, FeedbackData> for WaylandState {
+    fn event(
+        state: &mut Self,
+        _proxy: &WpPresentationFeedback,
+        event: FeedbackEvent,
+        data: &FeedbackData,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        let timing = match event {
+            FeedbackEvent::Presented {
+                tv_sec_hi,
+                tv_sec_lo,
+                tv_nsec,
+                refresh,
+                flags,
+                ..
+            } => {
+                let secs = (u64::from(tv_sec_hi) << 32) | u64::from(tv_sec_lo);
+                let timestamp = Duration::new(secs, tv_nsec);
+                FrameTiming {
+                    requested: data.requested,
+                    presented: state
+                        .presentation
+                        .as_ref()
+                        .and_then(|p| p.presented_at(timestamp)),
+                    refresh: if refresh == 0 {
+                        None
+                    } else {
+                        Some(Duration::from_nanos(refresh.into()))
+                    },
+                    flags: presentation_flags(flags),
+                    estimated: false,
+                }
+            }
+            FeedbackEvent::Discarded => FrameTiming {
+                requested: data.requested,
+                presented: None,
+                refresh: None,
+                flags: PresentationFlags::empty(),
+                estimated: false,
+            },
+            // The output that the frame is shown on; we don't need it
+            _ => return,
+        };
+        WaylandConnection::with_window_inner(data.window_id, move |inner| {
+            inner.record_frame_timing(timing);
+            Ok(())
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn timestamps_are_relative_to_now() {
+        let now = Instant::now();
+        let presented = timestamp_to_instant(
+            Duration::from_millis(9_990),
+            Duration::from_millis(10_000),
+            now,
+        );
+        assert_eq!(now - presented, Duration::from_millis(10));
+
+        // A timestamp from the future (eg: a predicted presentation
+        // time) is clamped to now
+        let presented = timestamp_to_instant(
+            Duration::from_millis(10_010),
+            Duration::from_millis(10_000),
+            now,
+        );
+        assert_eq!(presented, now);
+    }
+
+    #[test]
+    fn flags() {
+        assert_eq!(
+            presentation_flags(WEnum::Value(Kind::Vsync | Kind::HwClock)),
+            PresentationFlags::VSYNC | PresentationFlags::HW_CLOCK
+        );
+        assert_eq!(
+            presentation_flags(WEnum::Unknown(0x8 | 0x100)),
+            PresentationFlags::ZERO_COPY
+        );
+    }
+}
@@ -1,21 +1,23 @@
 use smithay_client_toolkit::seat::pointer::ThemeSpec;
 use smithay_client_toolkit::seat::{Capability, SeatHandler, SeatState};
 use wayland_client::protocol::wl_seat::WlSeat;
-use wayland_client::{Connection, QueueHandle};
+use wayland_client::{Connection, Proxy, QueueHandle};
 
 use crate::wayland::keyboard::KeyboardData;
 use crate::wayland::pointer::PointerUserData;
 use crate::wayland::SurfaceUserData;
 
 use super::state::WaylandState;
+use super::WaylandConnection;
 
 impl SeatHandler for WaylandState {
     fn seat_state(&mut self) -> &mut SeatState {
         &mut self.seat
     }
 
-    fn new_seat(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, _seat: WlSeat) {
-        todo!()
+    fn new_seat(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, seat: WlSeat) {
+        // The devices are set up as the capabilities are announced
+        log::trace!("New seat {}", seat.id());
     }
 
     fn new_capability(
@@ -28,17 +30,17 @@ impl SeatHandler for WaylandState {
         match capability {
             Capability::Keyboard if self.keyboard.is_none() => {
                 log::trace!("Setting keyboard capability");
-                let keyboard = seat.get_keyboard(qh, KeyboardData {});
+                let keyboard = seat.get_keyboard(qh, KeyboardData { seat: seat.clone() });
                 self.keyboard = Some(keyboard.clone());
 
                 if let Some(text_input) = &self.text_input {
                     text_input.advise_seat(&seat, &keyboard, qh);
                 }
             }
-            Capability::Pointer if self.pointer.is_none() => {
-                log::trace!("Setting pointer capability");
+            Capability::Pointer if !self.pointers.contains(&seat.id()) => {
+                log::trace!("Setting pointer capability for seat {}", seat.id());
                 let surface = self.compositor.create_surface(qh);
-                let pointer = self
+                match self
                     .seat
                     .get_pointer_with_theme_and_data::<WaylandState, SurfaceUserData, PointerUserData>(
                         qh,
@@ -47,22 +49,37 @@ impl SeatHandler for WaylandState {
                         surface,
                         ThemeSpec::System,
                         PointerUserData::new(seat.clone()),
-                    )
-                    .expect("Failed to create pointer");
-                self.pointer = Some(pointer);
+                    ) {
+                    Ok(pointer) => {
+                        if let Some(gestures) = self.pointer_gestures.as_mut() {
+                            gestures.attach(seat.id(), pointer.pointer(), qh);
+                        }
+                        self.pointers.insert(seat.id(), pointer);
+                    }
+                    // Carry on without mouse input
+                    Err(err) => log::debug!("Failed to create pointer: {err:#}"),
+                }
             }
-            Capability::Touch /* if self.touch.is_none() */ => {
+            Capability::Touch if self.touch.is_none() => {
                 log::trace!("Setting touch capability");
-                // TODO
+                match self.seat.get_touch(qh, &seat) {
+                    Ok(touch) => self.touch = Some(touch),
+                    Err(err) => log::error!("Failed to create touch: {err:#}"),
+                }
             }
             _ => {}
         }
 
         // TODO: is there a better place to put this? It only needs to be run once. (presumably per-seat)
+        // Either manager may be missing on a minimal compositor, in which
+        // case the corresponding clipboard is unavailable
         if self.data_device.is_none() {
-            let data_device_manager = &self.data_device_manager_state;
-            self.data_device = Some(data_device_manager.get_data_device(qh, &seat));
-
+            self.data_device = self
+                .data_device_manager_state
+                .as_ref()
+                .map(|m| m.get_data_device(qh, &seat));
+        }
+        if self.primary_selection_device.is_none() {
             self.primary_selection_device = self
                 .primary_selection_manager
                 .as_ref()
@@ -74,27 +91,253 @@ impl SeatHandler for WaylandState {
         &mut self,
         _conn: &Connection,
         _qh: &QueueHandle<Self>,
-        _seat: WlSeat,
+        seat: WlSeat,
         capability: Capability,
     ) {
         match capability {
             Capability::Keyboard => {
                 log::trace!("Lost keyboard capability");
                 self.keyboard.take().map(|k| k.release());
+                self.queued_key_events.clear();
             }
             Capability::Pointer => {
-                log::trace!("Lost pointer capability");
-                self.pointer.take(); // ThemedPointer's drop implementation calls wl_pointer.release() already.
+                log::trace!("Lost pointer capability for seat {}", seat.id());
+                self.remove_pointer(&seat);
             }
             Capability::Touch => {
                 log::trace!("Lost touch capability");
-                // Nothing to do here. (yet)
+                self.touch.take().map(|t| t.release());
+                self.touch_to_window.clear();
             }
             _ => {}
         }
     }
 
-    fn remove_seat(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, _seat: WlSeat) {
-        todo!()
+    fn remove_seat(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, seat: WlSeat) {
+        log::trace!("Seat {} removed", seat.id());
+        self.remove_pointer(&seat);
+        self.input_serials.remove(&seat);
+    }
+}
+
+impl WaylandState {
+    fn remove_pointer(&mut self, seat: &WlSeat) {
+        let seat_id = seat.id();
+        if let Some(gestures) = self.pointer_gestures.as_mut() {
+            gestures.detach(&seat_id);
+        }
+        // ThemedPointer's drop implementation calls wl_pointer.release() already.
+        if self.pointers.remove(&seat_id).is_none() {
+            return;
+        }
+
+        // The compositor won't send a leave event for a pointer that
+        // has gone away, so synthesize one for the window that it was in
+        for pending in self.surface_to_pending.values() {
+            let mut pending = pending.lock().unwrap();
+            if pending.pointer_lost(&seat_id) {
+                WaylandConnection::with_window_inner(pending.window_id, move |inner| {
+                    inner.dispatch_pending_mouse();
+                    Ok(())
+                });
+            }
+        }
+    }
+}
+
+/// Tracks the pointer of each seat.
+/// Requests that need a pointer, such as setting the cursor, are made
+/// with the pointer that most recently produced events.
+pub(super) struct SeatPointers<K, P> {
+    pointers: Vec<(K, P)>,
+    active: Option<K>,
+}
+
+impl<K, P> Default for SeatPointers<K, P> {
+    fn default() -> Self {
+        Self {
+            pointers: vec![],
+            active: None,
+        }
+    }
+}
+
+impl<K: Clone + PartialEq, P> SeatPointers<K, P> {
+    pub(super) fn contains(&self, seat: &K) -> bool {
+        self.pointers.iter().any(|(k, _)| k == seat)
+    }
+
+    /// Registers the pointer for a seat, replacing any that it
+    /// previously had. It becomes the active pointer if there
+    /// wasn't one already.
+    pub(super) fn insert(&mut self, seat: K, pointer: P) {
+        self.remove(&seat);
+        if self.active.is_none() {
+            self.active.replace(seat.clone());
+        }
+        self.pointers.push((seat, pointer));
+    }
+
+    /// Removes the pointer for a seat. If it was the active pointer,
+    /// the pointer of another seat takes its place.
+    pub(super) fn remove(&mut self, seat: &K) -> Option<P> {
+        let idx = self.pointers.iter().position(|(k, _)| k == seat)?;
+        let (_, pointer) = self.pointers.remove(idx);
+        if self.active.as_ref() == Some(seat) {
+            self.active = self.pointers.first().map(|(k, _)| k.clone());
+        }
+        Some(pointer)
+    }
+
+    pub(super) fn set_active(&mut self, seat: &K) {
+        if self.contains(seat) {
+            self.active.replace(seat.clone());
+        }
+    }
+
+    pub(super) fn active(&self) -> Option<&P> {
+        let active = self.active.as_ref()?;
+        self.pointers
+            .iter()
+            .find_map(|(k, p)| if k == active { Some(p) } else { None })
+    }
+
+    pub(super) fn active_mut(&mut self) -> Option<&mut P> {
+        let active = self.active.as_ref()?;
+        self.pointers
+            .iter_mut()
+            .find_map(|(k, p)| if k == active { Some(p) } else { None })
+    }
+}
+
+/// Tracks the serial of the most recent input event from each seat.
+/// Requests that the compositor only honors in response to user input,
+/// such as popup grabs and activation, must name the seat that
+/// produced that input along with its serial.
+pub(super) struct SeatSerials<K> {
+    serials: Vec<(K, u32)>,
+    latest: Option<K>,
+}
+
+impl<K> Default for SeatSerials<K> {
+    fn default() -> Self {
+        Self {
+            serials: vec![],
+            latest: None,
+        }
+    }
+}
+
+impl<K: Clone + PartialEq> SeatSerials<K> {
+    pub(super) fn record(&mut self, seat: &K, serial: u32) {
+        match self.serials.iter_mut().find(|(k, _)| k == seat) {
+            Some((_, s)) => *s = serial,
+            None => self.serials.push((seat.clone(), serial)),
+        }
+        self.latest.replace(seat.clone());
+    }
+
+    pub(super) fn remove(&mut self, seat: &K) {
+        self.serials.retain(|(k, _)| k != seat);
+        if self.latest.as_ref() == Some(seat) {
+            self.latest = None;
+        }
+    }
+
+    pub(super) fn get(&self, seat: &K) -> Option<u32> {
+        self.serials
+            .iter()
+            .find_map(|(k, s)| if k == seat { Some(*s) } else { None })
+    }
+
+    /// The seat that most recently produced input, and its serial
+    pub(super) fn latest(&self) -> Option<(&K, u32)> {
+        let latest = self.latest.as_ref()?;
+        Some((latest, self.get(latest)?))
+    }
+
+    /// The seat whose most recent input event had this serial
+    pub(super) fn seat_for(&self, serial: u32) -> Option<&K> {
+        self.serials
+            .iter()
+            .find_map(|(k, s)| if *s == serial { Some(k) } else { None })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn first_pointer_is_active() {
+        let mut pointers = SeatPointers::default();
+        assert_eq!(pointers.active(), None);
+        pointers.insert(1, "one");
+        pointers.insert(2, "two");
+        assert!(pointers.contains(&1));
+        assert!(pointers.contains(&2));
+        assert_eq!(pointers.active(), Some(&"one"));
+
+        pointers.set_active(&2);
+        assert_eq!(pointers.active(), Some(&"two"));
+        // Unknown seats are ignored
+        pointers.set_active(&3);
+        assert_eq!(pointers.active(), Some(&"two"));
+    }
+
+    #[test]
+    fn removing_active_pointer_falls_back() {
+        let mut pointers = SeatPointers::default();
+        pointers.insert(1, "one");
+        pointers.insert(2, "two");
+        pointers.set_active(&2);
+
+        assert_eq!(pointers.remove(&2), Some("two"));
+        assert!(!pointers.contains(&2));
+        assert_eq!(pointers.active(), Some(&"one"));
+
+        assert_eq!(pointers.remove(&2), None);
+        assert_eq!(pointers.remove(&1), Some("one"));
+        assert_eq!(pointers.active(), None);
+    }
+
+    #[test]
+    fn pointer_can_be_replaced() {
+        let mut pointers = SeatPointers::default();
+        pointers.insert(1, "one");
+        pointers.remove(&1);
+        pointers.insert(1, "replugged");
+        assert_eq!(pointers.active(), Some(&"replugged"));
+
+        pointers.insert(1, "replaced");
+        assert_eq!(pointers.active(), Some(&"replaced"));
+        if let Some(p) = pointers.active_mut() {
+            *p = "mutated";
+        }
+        assert_eq!(pointers.active(), Some(&"mutated"));
+    }
+
+    #[test]
+    fn serials_are_tracked_per_seat() {
+        let mut serials = SeatSerials::default();
+        assert_eq!(serials.latest(), None);
+
+        serials.record(&1, 10);
+        serials.record(&2, 11);
+        assert_eq!(serials.latest(), Some((&2, 11)));
+        assert_eq!(serials.get(&1), Some(10));
+
+        // Input from the first seat makes it the latest again,
+        // without disturbing the serial of the second
+        serials.record(&1, 12);
+        assert_eq!(serials.latest(), Some((&1, 12)));
+        assert_eq!(serials.get(&2), Some(11));
+        assert_eq!(serials.seat_for(11), Some(&2));
+        assert_eq!(serials.seat_for(10), None);
+
+        serials.remove(&1);
+        assert_eq!(serials.latest(), None);
+        assert_eq!(serials.get(&1), None);
+        assert_eq!(serials.seat_for(11), Some(&2));
     }
 }
@@ -0,0 +1,83 @@
+//! Implements zwp_keyboard_shortcuts_inhibit_manager_v1 so that keys
+//! which the compositor would normally act upon, such as Super or
+//! Alt-Tab, can be delivered to the window instead
+use smithay_client_toolkit::globals::GlobalData;
+use wayland_client::globals::{BindError, GlobalList};
+use wayland_client::protocol::wl_seat::WlSeat;
+use wayland_client::protocol::wl_surface::WlSurface;
+use wayland_client::{Connection, Dispatch, QueueHandle};
+use wayland_protocols::wp::keyboard_shortcuts_inhibit::zv1::client::zwp_keyboard_shortcuts_inhibit_manager_v1::ZwpKeyboardShortcutsInhibitManagerV1;
+use wayland_protocols::wp::keyboard_shortcuts_inhibit::zv1::client::zwp_keyboard_shortcuts_inhibitor_v1::{
+    Event as InhibitorEvent, ZwpKeyboardShortcutsInhibitorV1,
+};
+
+use super::state::WaylandState;
+
+pub(super) struct ShortcutsInhibitState {
+    manager: ZwpKeyboardShortcutsInhibitManagerV1,
+}
+
+pub(super) struct ShortcutsInhibitorData {
+    window_id: usize,
+}
+
+impl ShortcutsInhibitState {
+    pub(super) fn bind(
+        globals: &GlobalList,
+        queue_handle: &QueueHandle<WaylandState>,
+    ) -> Result<Self, BindError> {
+        let manager = globals.bind(queue_handle, 1..=1, GlobalData)?;
+        Ok(Self { manager })
+    }
+
+    /// The compositor responds with an active or inactive event to say
+    /// whether it is honoring the inhibitor; it must be destroyed to
+    /// restore the shortcuts
+    pub(super) fn inhibit(
+        &self,
+        surface: &WlSurface,
+        seat: &WlSeat,
+        window_id: usize,
+        queue_handle: &QueueHandle<WaylandState>,
+    ) -> ZwpKeyboardShortcutsInhibitorV1 {
+        self.manager.inhibit_shortcuts(
+            surface,
+            seat,
+            queue_handle,
+            ShortcutsInhibitorData { window_id },
+        )
+    }
+}
+
+impl Dispatch<ZwpKeyboardShortcutsInhibitManagerV1, GlobalData> for WaylandState {
+    fn event(
+        _state: &mut Self,
+        _proxy: &ZwpKeyboardShortcutsInhibitManagerV1,
+        _event: <ZwpKeyboardShortcutsInhibitManagerV1 as wayland_client::Proxy>::Event,
+        _data: &GlobalData,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        // No events
+    }
+}
+
+impl Dispatch<ZwpKeyboardShortcutsInhibitorV1, ShortcutsInhibitorData> for WaylandState {
+    fn event(
+        state: &mut Self,
+        proxy: &ZwpKeyboardShortcutsInhibitorV1,
+        event: <ZwpKeyboardShortcutsInhibitorV1 as wayland_client::Proxy>::Event,
+        data: &ShortcutsInhibitorData,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        let active = match event {
+            InhibitorEvent::Active => true,
+            InhibitorEvent::Inactive => false,
+            _ => return,
+        };
+        if let Some(inner) = state.window_by_id(data.window_id) {
+            inner.borrow_mut().shortcut_inhibitor_changed(proxy, active);
+        }
+    }
+}
@@ -17,27 +17,44 @@ use smithay_client_toolkit::reexports::protocols_wlr::output_management::v1::cli
 use smithay_client_toolkit::reexports::protocols_wlr::output_management::v1::client::zwlr_output_mode_v1::ZwlrOutputModeV1;
 use smithay_client_toolkit::registry::{ProvidesRegistryState, RegistryState};
 use smithay_client_toolkit::seat::pointer::ThemedPointer;
+use smithay_client_toolkit::seat::pointer_constraints::PointerConstraintsState;
+use smithay_client_toolkit::seat::relative_pointer::RelativePointerState;
 use smithay_client_toolkit::seat::SeatState;
 use smithay_client_toolkit::shell::xdg::XdgShell;
 use smithay_client_toolkit::shm::slot::SlotPool;
 use smithay_client_toolkit::shm::{Shm, ShmHandler};
 use smithay_client_toolkit::subcompositor::SubcompositorState;
 use smithay_client_toolkit::{
-    delegate_compositor, delegate_data_device, delegate_output, delegate_pointer, delegate_primary_selection, delegate_registry, delegate_seat, delegate_shm, delegate_subcompositor, delegate_xdg_shell, delegate_xdg_window, registry_handlers
+    delegate_compositor, delegate_data_device, delegate_output, delegate_pointer, delegate_pointer_constraints, delegate_primary_selection, delegate_registry, delegate_relative_pointer, delegate_seat, delegate_shm, delegate_subcompositor, delegate_touch, delegate_xdg_popup, delegate_xdg_shell, delegate_xdg_window, registry_handlers
 };
 use wayland_client::backend::ObjectId;
 use wayland_client::globals::GlobalList;
 use wayland_client::protocol::wl_keyboard::WlKeyboard;
 use wayland_client::protocol::wl_output::WlOutput;
+use wayland_client::protocol::wl_seat::WlSeat;
+use wayland_client::protocol::wl_touch::WlTouch;
 use wayland_client::{delegate_dispatch, Connection, QueueHandle};
 use wayland_protocols::wp::text_input::zv3::client::zwp_text_input_manager_v3::ZwpTextInputManagerV3;
 use wayland_protocols::wp::text_input::zv3::client::zwp_text_input_v3::ZwpTextInputV3;
 
 use crate::x11::KeyboardWithFallback;
 
+use super::activation::ActivationState;
+use super::copy_and_paste::OfferedData;
+use super::cursor::CursorThemes;
+use super::dialog::DialogState;
+use super::fractional_scale::FractionalScaleState;
+use super::idle_inhibit::IdleInhibitState;
 use super::inputhandler::{TextInputData, TextInputState};
+use super::keyboard::QueuedKeyEvents;
 use super::pointer::{PendingMouse, PointerUserData};
-use super::{OutputManagerData, OutputManagerState, SurfaceUserData, WaylandWindowInner};
+use super::pointer_gestures::PointerGesturesState;
+use super::presentation::PresentationState;
+use super::seat::{SeatPointers, SeatSerials};
+use super::shortcuts_inhibit::ShortcutsInhibitState;
+use super::{
+    OutputManagerData, OutputManagerState, SurfaceUserData, WaylandInitError, WaylandWindowInner,
+};
 
 // We can't combine WaylandState and WaylandConnection together because
 // the run_message_loop has &self(WaylandConnection) and needs to update WaylandState as mut
@@ -47,6 +64,12 @@ pub(super) struct WaylandState {
     pub(super) compositor: CompositorState,
     pub(super) subcompositor: Arc<SubcompositorState>,
     pub(super) text_input: Option<TextInputState>,
+    pub(super) fractional_scale: Option<FractionalScaleState>,
+    pub(super) presentation: Option<PresentationState>,
+    pub(super) idle_inhibit: Option<IdleInhibitState>,
+    pub(super) shortcuts_inhibit: Option<ShortcutsInhibitState>,
+    pub(super) activation: Option<ActivationState>,
+    pub(super) dialog: Option<DialogState>,
     pub(super) output_manager: Option<OutputManagerState>,
     pub(super) seat: SeatState,
     pub(super) xdg: XdgShell,
@@ -54,33 +77,50 @@ pub(super) struct WaylandState {
 
     pub(super) active_surface_id: RefCell<Option<ObjectId>>,
     pub(super) last_serial: RefCell<u32>,
+    pub(super) input_serials: SeatSerials<WlSeat>,
     pub(super) keyboard: Option<WlKeyboard>,
     pub(super) keyboard_mapper: Option<KeyboardWithFallback>,
+    pub(super) queued_key_events: QueuedKeyEvents,
     pub(super) key_repeat_delay: i32,
     pub(super) key_repeat_rate: i32,
     pub(super) keyboard_window_id: Option<usize>,
 
-    pub(super) pointer: Option<ThemedPointer<PointerUserData>>,
+    // One pointer for each seat that has the pointer capability
+    pub(super) pointers: SeatPointers<ObjectId, ThemedPointer<PointerUserData>>,
     pub(super) surface_to_pending: HashMap<ObjectId, Arc<Mutex<PendingMouse>>>,
-
-    pub(super) data_device_manager_state: DataDeviceManagerState,
+    pub(super) touch: Option<WlTouch>,
+    // Which window each touch point went down on, as the subsequent
+    // events for the touch point don't identify the surface
+    pub(super) touch_to_window: HashMap<i32, usize>,
+    pub(super) pointer_constraints: PointerConstraintsState,
+    pub(super) relative_pointer: RelativePointerState,
+    pub(super) pointer_gestures: Option<PointerGesturesState>,
+
+    pub(super) data_device_manager_state: Option<DataDeviceManagerState>,
     pub(super) data_device: Option<DataDevice>,
-    pub(super) copy_paste_source: Option<(CopyPasteSource, String)>,
+    pub(super) copy_paste_source: Option<(CopyPasteSource, OfferedData)>,
     pub(super) primary_selection_manager: Option<PrimarySelectionManagerState>,
     pub(super) primary_selection_device: Option<PrimarySelectionDevice>,
-    pub(super) primary_selection_source: Option<(PrimarySelectionSource, String)>,
+    pub(super) primary_selection_source: Option<(PrimarySelectionSource, OfferedData)>,
     pub(super) shm: Shm,
     pub(super) mem_pool: RefCell<SlotPool>,
+    pub(super) cursor_themes: RefCell<CursorThemes>,
 }
 
 impl WaylandState {
-    pub(super) fn new(globals: &GlobalList, qh: &QueueHandle<Self>) -> anyhow::Result<Self> {
-        let shm = Shm::bind(&globals, qh)?;
-        let mem_pool = SlotPool::new(1, &shm)?;
-
-        let compositor = CompositorState::bind(globals, qh)?;
+    pub(super) fn new(
+        globals: &GlobalList,
+        qh: &QueueHandle<Self>,
+    ) -> Result<Self, WaylandInitError> {
+        let shm = Shm::bind(&globals, qh).map_err(WaylandInitError::missing("wl_shm"))?;
+        let mem_pool = SlotPool::new(1, &shm).map_err(anyhow::Error::from)?;
+        let cursor_themes = CursorThemes::new(&config::configuration(), shm.wl_shm());
+
+        let compositor = CompositorState::bind(globals, qh)
+            .map_err(WaylandInitError::missing("wl_compositor"))?;
         let subcompositor =
-            SubcompositorState::bind(compositor.wl_compositor().clone(), globals, qh)?;
+            SubcompositorState::bind(compositor.wl_compositor().clone(), globals, qh)
+                .map_err(WaylandInitError::missing("wl_subcompositor"))?;
 
         let wayland_state = WaylandState {
             registry: RegistryState::new(globals),
@@ -88,24 +128,40 @@ impl WaylandState {
             compositor,
             subcompositor: Arc::new(subcompositor),
             text_input: TextInputState::bind(globals, qh).ok(),
+            fractional_scale: FractionalScaleState::bind(globals, qh).ok(),
+            presentation: PresentationState::bind(globals, qh).ok(),
+            idle_inhibit: IdleInhibitState::bind(globals, qh).ok(),
+            shortcuts_inhibit: ShortcutsInhibitState::bind(globals, qh).ok(),
+            activation: ActivationState::bind(globals, qh).ok(),
+            dialog: DialogState::bind(globals, qh).ok(),
             output_manager: if config::configuration().enable_zwlr_output_manager {
-                Some(OutputManagerState::bind(globals, qh)?)
+                Some(
+                    OutputManagerState::bind(globals, qh)
+                        .map_err(WaylandInitError::missing("zwlr_output_manager_v1"))?,
+                )
             } else {
                 None
             },
             windows: RefCell::new(HashMap::new()),
             seat: SeatState::new(globals, qh),
-            xdg: XdgShell::bind(globals, qh)?,
+            xdg: XdgShell::bind(globals, qh).map_err(WaylandInitError::missing("xdg_wm_base"))?,
             active_surface_id: RefCell::new(None),
             last_serial: RefCell::new(0),
+            input_serials: SeatSerials::default(),
             keyboard: None,
             keyboard_mapper: None,
+            queued_key_events: QueuedKeyEvents::default(),
             key_repeat_rate: 25,
             key_repeat_delay: 400,
             keyboard_window_id: None,
-            pointer: None,
+            pointers: SeatPointers::default(),
             surface_to_pending: HashMap::new(),
-            data_device_manager_state: DataDeviceManagerState::bind(globals, qh)?,
+            touch: None,
+            touch_to_window: HashMap::new(),
+            pointer_constraints: PointerConstraintsState::bind(globals, qh),
+            relative_pointer: RelativePointerState::bind(globals, qh),
+            pointer_gestures: PointerGesturesState::bind(globals, qh).ok(),
+            data_device_manager_state: DataDeviceManagerState::bind(globals, qh).ok(),
             data_device: None,
             copy_paste_source: None,
             primary_selection_manager: PrimarySelectionManagerState::bind(globals, qh).ok(),
@@ -113,6 +169,7 @@ impl WaylandState {
             primary_selection_source: None,
             shm,
             mem_pool: RefCell::new(mem_pool),
+            cursor_themes: RefCell::new(cursor_themes),
         };
         Ok(wayland_state)
     }
@@ -163,8 +220,12 @@ delegate_seat!(WaylandState);
 delegate_data_device!(WaylandState);
 
 delegate_pointer!(WaylandState, pointer: [PointerUserData]);
+delegate_pointer_constraints!(WaylandState);
+delegate_relative_pointer!(WaylandState);
+delegate_touch!(WaylandState);
 
 delegate_xdg_shell!(WaylandState);
+delegate_xdg_popup!(WaylandState);
 delegate_xdg_window!(WaylandState);
 
 delegate_primary_selection!(WaylandState);
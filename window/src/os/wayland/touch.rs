@@ -0,0 +1,264 @@
+use std::collections::HashMap;
+
+use smithay_client_toolkit::seat::touch::{TouchData, TouchHandler};
+use wayland_client::protocol::wl_surface::WlSurface;
+use wayland_client::protocol::wl_touch::WlTouch;
+use wayland_client::{Connection, Proxy, QueueHandle};
+
+use crate::{Point, TouchEvent, TouchPhase};
+
+use super::state::WaylandState;
+use super::{SurfaceUserData, WaylandConnection};
+
+impl TouchHandler for WaylandState {
+    fn down(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        touch: &WlTouch,
+        serial: u32,
+        _time: u32,
+        surface: WlSurface,
+        id: i32,
+        position: (f64, f64),
+    ) {
+        *self.last_serial.borrow_mut() = serial;
+        if let Some(data) = touch.data::<TouchData>() {
+            self.input_serials.record(data.seat(), serial);
+        }
+        let Some(sud) = SurfaceUserData::try_from_wl(&surface) else {
+            // Most likely a touch on the window decorations
+            return;
+        };
+        let window_id = sud.window_id;
+        self.touch_to_window.insert(id, window_id);
+        dispatch_touch(window_id, id, TouchPhase::Down, position);
+    }
+
+    fn up(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        touch: &WlTouch,
+        serial: u32,
+        _time: u32,
+        id: i32,
+    ) {
+        *self.last_serial.borrow_mut() = serial;
+        if let Some(data) = touch.data::<TouchData>() {
+            self.input_serials.record(data.seat(), serial);
+        }
+        if let Some(window_id) = self.touch_to_window.remove(&id) {
+            WaylandConnection::with_window_inner(window_id, move |inner| {
+                inner.touch_up(id);
+                Ok(())
+            });
+        }
+    }
+
+    fn motion(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _touch: &WlTouch,
+        _time: u32,
+        id: i32,
+        position: (f64, f64),
+    ) {
+        if let Some(&window_id) = self.touch_to_window.get(&id) {
+            dispatch_touch(window_id, id, TouchPhase::Motion, position);
+        }
+    }
+
+    fn shape(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _touch: &WlTouch,
+        _id: i32,
+        _major: f64,
+        _minor: f64,
+    ) {
+    }
+
+    fn orientation(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _touch: &WlTouch,
+        _id: i32,
+        _orientation: f64,
+    ) {
+    }
+
+    fn cancel(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, _touch: &WlTouch) {
+        let mut window_ids: Vec<usize> = self.touch_to_window.drain().map(|(_, w)| w).collect();
+        window_ids.sort();
+        window_ids.dedup();
+        for window_id in window_ids {
+            WaylandConnection::with_window_inner(window_id, move |inner| {
+                inner.touch_cancel();
+                Ok(())
+            });
+        }
+    }
+}
+
+fn dispatch_touch(window_id: usize, id: i32, phase: TouchPhase, (x, y): (f64, f64)) {
+    WaylandConnection::with_window_inner(window_id, move |inner| {
+        inner.touch_event(id, phase, x, y);
+        Ok(())
+    });
+}
+
+/// The mouse events that are emulated for touch input
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) enum EmulatedMouse {
+    Press(Point),
+    Move(Point),
+    Release(Point),
+}
+
+/// Tracks the touch points on a window, and maps the first of them
+/// to left button mouse events.
+/// The press is deferred until that touch point either moves or is
+/// lifted, so that a second finger landing straight away (as happens
+/// with a two finger scroll) doesn't produce a spurious click.
+#[derive(Debug, Default)]
+pub(super) struct TouchState {
+    points: HashMap<i32, Point>,
+    emulating: Option<i32>,
+    pressed: bool,
+}
+
+impl TouchState {
+    /// Records the touch event and returns the mouse events that
+    /// should be emulated for it
+    pub(super) fn update(&mut self, event: &TouchEvent) -> Vec<EmulatedMouse> {
+        let mut emulated = vec![];
+        match event.phase {
+            TouchPhase::Down => {
+                self.points.insert(event.id, event.coords);
+                if self.points.len() == 1 {
+                    self.emulating = Some(event.id);
+                    self.pressed = false;
+                } else {
+                    // A multi-finger gesture is not a mouse action
+                    emulated.extend(self.stop_emulating());
+                }
+            }
+            TouchPhase::Motion => {
+                let previous = self.points.insert(event.id, event.coords);
+                if self.emulating == Some(event.id) {
+                    if !self.pressed {
+                        self.pressed = true;
+                        emulated.push(EmulatedMouse::Press(previous.unwrap_or(event.coords)));
+                    }
+                    emulated.push(EmulatedMouse::Move(event.coords));
+                }
+            }
+            TouchPhase::Up => {
+                self.points.remove(&event.id);
+                if self.emulating == Some(event.id) {
+                    if !self.pressed {
+                        emulated.push(EmulatedMouse::Press(event.coords));
+                    }
+                    emulated.push(EmulatedMouse::Release(event.coords));
+                    self.emulating = None;
+                    self.pressed = false;
+                }
+            }
+            TouchPhase::Cancel => {
+                emulated.extend(self.stop_emulating());
+                self.points.remove(&event.id);
+            }
+        }
+        emulated
+    }
+
+    /// Returns the position of the touch point with the specified id
+    pub(super) fn position(&self, id: i32) -> Option<Point> {
+        self.points.get(&id).copied()
+    }
+
+    /// Returns the ids of the touch points that are currently down
+    pub(super) fn ids(&self) -> Vec<i32> {
+        self.points.keys().copied().collect()
+    }
+
+    fn stop_emulating(&mut self) -> Option<EmulatedMouse> {
+        let id = self.emulating.take()?;
+        let pressed = std::mem::take(&mut self.pressed);
+        if pressed {
+            self.points.get(&id).map(|&p| EmulatedMouse::Release(p))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn touch(id: i32, phase: TouchPhase, x: isize, y: isize) -> TouchEvent {
+        TouchEvent {
+            id,
+            phase,
+            coords: Point::new(x, y),
+        }
+    }
+
+    #[test]
+    fn tap_is_a_click() {
+        let mut state = TouchState::default();
+        assert_eq!(state.update(&touch(1, TouchPhase::Down, 10, 10)), vec![]);
+        assert_eq!(
+            state.update(&touch(1, TouchPhase::Up, 10, 10)),
+            vec![
+                EmulatedMouse::Press(Point::new(10, 10)),
+                EmulatedMouse::Release(Point::new(10, 10))
+            ]
+        );
+    }
+
+    #[test]
+    fn drag_presses_at_start() {
+        let mut state = TouchState::default();
+        state.update(&touch(1, TouchPhase::Down, 10, 10));
+        assert_eq!(
+            state.update(&touch(1, TouchPhase::Motion, 20, 15)),
+            vec![
+                EmulatedMouse::Press(Point::new(10, 10)),
+                EmulatedMouse::Move(Point::new(20, 15))
+            ]
+        );
+        assert_eq!(
+            state.update(&touch(1, TouchPhase::Up, 20, 15)),
+            vec![EmulatedMouse::Release(Point::new(20, 15))]
+        );
+    }
+
+    #[test]
+    fn two_fingers_do_not_click() {
+        let mut state = TouchState::default();
+        state.update(&touch(1, TouchPhase::Down, 10, 10));
+        assert_eq!(state.update(&touch(2, TouchPhase::Down, 30, 10)), vec![]);
+        assert_eq!(state.update(&touch(1, TouchPhase::Motion, 10, 20)), vec![]);
+        assert_eq!(state.update(&touch(2, TouchPhase::Motion, 30, 20)), vec![]);
+        assert_eq!(state.update(&touch(1, TouchPhase::Up, 10, 20)), vec![]);
+        assert_eq!(state.update(&touch(2, TouchPhase::Up, 30, 20)), vec![]);
+    }
+
+    #[test]
+    fn second_finger_releases_drag() {
+        let mut state = TouchState::default();
+        state.update(&touch(1, TouchPhase::Down, 10, 10));
+        state.update(&touch(1, TouchPhase::Motion, 12, 10));
+        assert_eq!(
+            state.update(&touch(2, TouchPhase::Down, 30, 10)),
+            vec![EmulatedMouse::Release(Point::new(12, 10))]
+        );
+        assert_eq!(state.update(&touch(1, TouchPhase::Up, 12, 10)), vec![]);
+    }
+}
@@ -1,17 +1,16 @@
 use std::any::Any;
 use std::cell::RefCell;
-use std::cmp::max;
+use std::collections::{HashMap, VecDeque};
 use std::convert::TryInto;
-use std::io::Read;
+use std::hash::Hash;
 use std::num::NonZeroU32;
-use std::os::fd::AsRawFd;
 use std::path::PathBuf;
 use std::ptr::NonNull;
 use std::rc::Rc;
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
-use anyhow::{anyhow, bail};
+use anyhow::{anyhow, bail, ensure, Context};
 use async_io::Timer;
 use async_trait::async_trait;
 use config::ConfigHandle;
@@ -20,43 +19,87 @@ use raw_window_handle::{
     DisplayHandle, HandleError, HasDisplayHandle, HasWindowHandle, RawWindowHandle,
     WaylandWindowHandle, WindowHandle,
 };
-use smithay_client_toolkit::compositor::{CompositorHandler, SurfaceData, SurfaceDataExt};
-use smithay_client_toolkit::data_device_manager::ReadPipe;
+use smithay_client_toolkit::compositor::{CompositorHandler, Region, SurfaceData, SurfaceDataExt};
 use smithay_client_toolkit::reexports::csd_frame::{
-    DecorationsFrame, FrameAction, ResizeEdge, WindowState as SCTKWindowState,
+    DecorationsFrame, FrameAction, ResizeEdge, WindowManagerCapabilities,
+    WindowState as SCTKWindowState,
 };
+use smithay_client_toolkit::reexports::protocols::xdg::shell::client::xdg_positioner::{
+    Anchor, ConstraintAdjustment, Gravity,
+};
+use smithay_client_toolkit::reexports::protocols::xdg::shell::client::xdg_surface;
 use smithay_client_toolkit::reexports::protocols::xdg::shell::client::xdg_toplevel::ResizeEdge as XdgResizeEdge;
-use smithay_client_toolkit::seat::pointer::CursorIcon;
-use smithay_client_toolkit::shell::xdg::fallback_frame::FallbackFrame;
+use smithay_client_toolkit::shell::xdg::popup::{Popup, PopupConfigure, PopupHandler};
 use smithay_client_toolkit::shell::xdg::window::{
     DecorationMode, Window as XdgWindow, WindowConfigure, WindowDecorations as Decorations,
     WindowHandler,
 };
-use smithay_client_toolkit::shell::xdg::XdgSurface;
+use smithay_client_toolkit::shell::xdg::{XdgPositioner, XdgSurface};
 use smithay_client_toolkit::shell::WaylandSurface;
+use smithay_client_toolkit::shm::slot::Buffer;
+use wayland_client::backend::ObjectId;
 use wayland_client::protocol::wl_callback::WlCallback;
 use wayland_client::protocol::wl_keyboard::{Event as WlKeyboardEvent, KeyState};
+use wayland_client::protocol::wl_output::WlOutput;
 use wayland_client::protocol::wl_pointer::{ButtonState, WlPointer};
 use wayland_client::protocol::wl_surface::WlSurface;
 use wayland_client::{Connection as WConnection, Proxy};
 use wayland_egl::{is_available as egl_is_available, WlEglSurface};
+use wayland_protocols::wp::fractional_scale::v1::client::wp_fractional_scale_v1::WpFractionalScaleV1;
+use wayland_protocols::wp::idle_inhibit::zv1::client::zwp_idle_inhibitor_v1::ZwpIdleInhibitorV1;
+use wayland_protocols::wp::keyboard_shortcuts_inhibit::zv1::client::zwp_keyboard_shortcuts_inhibitor_v1::ZwpKeyboardShortcutsInhibitorV1;
+use wayland_protocols::wp::pointer_constraints::zv1::client::zwp_confined_pointer_v1::ZwpConfinedPointerV1;
+use wayland_protocols::wp::pointer_constraints::zv1::client::zwp_locked_pointer_v1::ZwpLockedPointerV1;
+use wayland_protocols::wp::pointer_constraints::zv1::client::zwp_pointer_constraints_v1::Lifetime;
+use wayland_protocols::wp::relative_pointer::zv1::client::zwp_relative_pointer_v1::ZwpRelativePointerV1;
+use wayland_protocols::wp::viewporter::client::wp_viewport::WpViewport;
+use wayland_protocols::xdg::dialog::v1::client::xdg_dialog_v1::XdgDialogV1;
 use wezterm_font::FontConfiguration;
 use wezterm_input_types::{
     KeyboardLedStatus, Modifiers, MouseButtons, MouseEvent, MouseEventKind, MousePress,
-    ScreenPoint, WindowDecorations,
+    WindowDecorations,
 };
 
+use crate::egl::SwapDamage;
+use crate::os::animation::Animation;
+use crate::os::click_streak::{click_interval_ms, ClickStreak};
+use crate::os::focus_request::{FocusRequests, FOCUS_TIMEOUT};
+use crate::os::fullscreen_restore::FullScreenRestore;
+use crate::os::size_constraints::{snap_to_increments, SizeConstraints};
+use crate::screen::ScreenInfo;
 use crate::wayland::WaylandConnection;
-use crate::x11::KeyboardWithFallback;
+use crate::x11::{KeyboardWithFallback, RawKeyMode};
 use crate::{
-    Appearance, Clipboard, Connection, ConnectionOps, Dimensions, MouseCursor, Point, Rect,
-    RequestedWindowGeometry, ResizeIncrement, ResolvedGeometry, Window, WindowEvent,
-    WindowEventSender, WindowKeyEvent, WindowOps, WindowState,
+    AnimationHint, Appearance, BitmapImage, Clipboard, Connection, ConnectionOps,
+    ConstrainedResize, Dimensions, FocusOutcome, FocusReason, FrameTiming, Image,
+    InitialWindowState, InputSerial, MouseCursor, Point, PresentationFlags, Rect,
+    RequestedWindowGeometry, ResizeIncrement, ResizeRejected, ResolvedGeometry, Size, TouchEvent,
+    TouchPhase, Window, WindowEvent, WindowEventSender, WindowKeyEvent, WindowOps, WindowOptions,
+    WindowResizeEdge, WindowState, WmCapabilities,
 };
 
-use super::copy_and_paste::CopyAndPaste;
-use super::pointer::{PendingMouse, PointerUserData};
+use super::copy_and_paste::{
+    pipe_inactivity_timeout, read_pipe_data_with_timeout, text_mime_types, CopyAndPaste,
+    OfferedData,
+};
+use super::cursor::cursor_scale;
+use super::frame::{ConceptConfig, ConceptFrame};
+use super::keyboard::KeyboardData;
+use super::output::screen_info_for_output;
+use super::pointer::{PendingMouse, PointerUserData, ScrollAccumulator, WheelAccumulator};
 use super::state::WaylandState;
+use super::touch::{EmulatedMouse, TouchState};
+
+/// How many frame timings we remember for WindowOps::frame_timings
+const FRAME_TIMING_HISTORY: usize = 120;
+
+/// The smallest size, in surface coordinates, that we let the window
+/// be resized to, regardless of what the application asked for
+const MIN_SURFACE_SIZE: (u32, u32) = (32, 32);
+
+/// How long set_inner_size waits for the compositor to respond with a
+/// different size before reporting the size that we applied
+const INNER_SIZE_SETTLE_TIME: Duration = Duration::from_millis(100);
 
 #[derive(Debug)]
 pub(super) struct KeyRepeatState {
@@ -113,7 +156,9 @@ impl KeyRepeatState {
 
                     let mut elapsed = st.when.elapsed();
                     if initial {
-                        elapsed -= delay;
+                        // The timer may fire a little early relative to
+                        // when the key was pressed; don't let that panic
+                        elapsed = elapsed.saturating_sub(delay);
                         initial = false;
                     }
 
@@ -153,6 +198,41 @@ enum WaylandWindowEvent {
     Request(WindowConfigure),
 }
 
+/// The role of the window's surface
+#[derive(Debug)]
+pub(super) enum XdgRole {
+    Toplevel(XdgWindow),
+    /// Created via Window::new_popup
+    Popup(Popup),
+}
+
+impl XdgRole {
+    fn toplevel(&self) -> Option<&XdgWindow> {
+        match self {
+            Self::Toplevel(window) => Some(window),
+            Self::Popup(_) => None,
+        }
+    }
+}
+
+impl WaylandSurface for XdgRole {
+    fn wl_surface(&self) -> &WlSurface {
+        match self {
+            Self::Toplevel(window) => window.wl_surface(),
+            Self::Popup(popup) => popup.wl_surface(),
+        }
+    }
+}
+
+impl XdgSurface for XdgRole {
+    fn xdg_surface(&self) -> &xdg_surface::XdgSurface {
+        match self {
+            Self::Toplevel(window) => window.xdg_surface(),
+            Self::Popup(popup) => popup.xdg_surface(),
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Hash, Ord, PartialOrd)]
 pub struct WaylandWindow(usize);
 
@@ -162,7 +242,8 @@ impl WaylandWindow {
         name: &str,
         geometry: RequestedWindowGeometry,
         config: Option<&ConfigHandle>,
-        _font_config: Rc<FontConfiguration>,
+        font_config: Rc<FontConfiguration>,
+        options: WindowOptions<'_>,
         event_handler: F,
     ) -> anyhow::Result<Window>
     where
@@ -181,22 +262,15 @@ impl WaylandWindow {
             })?
             .wayland();
 
-        let window_id = conn.next_window_id();
-        let pending_event = Arc::new(Mutex::new(PendingEvent::default()));
-
-        let (pending_first_configure, wait_configure) = async_channel::bounded(1);
+        let parent = match options.parent {
+            Some(Window::Wayland(parent)) => Some(parent.0),
+            Some(parent) => bail!("{parent:?} is not a Wayland window"),
+            None => None,
+        };
 
+        let window_id = conn.next_window_id();
         let qh = conn.event_queue.borrow().handle();
-
-        // We need user data so we can get the window_id => WaylandWindowInner during a handler
-        let surface_data = SurfaceUserData {
-            surface_data: SurfaceData::default(),
-            window_id,
-        };
-        let surface = {
-            let compositor = &conn.wayland_state.borrow().compositor;
-            compositor.create_surface_with_data(&qh, surface_data)
-        };
+        let surface = create_surface(&conn, window_id);
 
         let ResolvedGeometry {
             x: _,
@@ -208,7 +282,7 @@ impl WaylandWindow {
         let dimensions = Dimensions {
             pixel_width: width,
             pixel_height: height,
-            dpi: config.dpi.unwrap_or(crate::DEFAULT_DPI) as usize,
+            dpi: config.dpi.unwrap_or(base_dpi(&config)) as usize,
         };
 
         let window = {
@@ -218,24 +292,23 @@ impl WaylandWindow {
 
         window.set_app_id(class_name.to_string());
         window.set_title(name.to_string());
-        let decorations = config.window_decorations;
-
-        let decor_mode = if decorations == WindowDecorations::NONE {
-            None
-        } else if decorations == WindowDecorations::default() {
-            Some(DecorationMode::Server)
-        } else {
-            Some(DecorationMode::Client)
-        };
+        let decorations = options.decorations.unwrap_or(config.window_decorations);
+        let decor_mode = requested_decoration_mode(decorations);
         window.request_decoration_mode(decor_mode);
 
         let mut window_frame = {
             let wayland_state = &conn.wayland_state.borrow();
             let shm = &wayland_state.shm;
             let subcompositor = wayland_state.subcompositor.clone();
-            FallbackFrame::new(&window, shm, subcompositor, qh.clone())
-                .expect("failed to create csd frame")
+            let frame_config = ConceptConfig {
+                font_config: Some(Rc::clone(&font_config)),
+                config: config.clone(),
+                decorations,
+            };
+            ConceptFrame::new(&window, shm, subcompositor, qh.clone(), frame_config)
+                .context("failed to create csd frame")?
         };
+        window_frame.set_title(name.to_string());
         let hidden = match decor_mode {
             Some(DecorationMode::Client) => false,
             _ => true,
@@ -250,7 +323,30 @@ impl WaylandWindow {
             );
         }
 
-        window.set_min_size(Some((32, 32)));
+        // There's no protocol for keeping a window out of the task bar,
+        // but shells generally leave out windows that have a parent
+        if let Some(parent) = parent.and_then(|parent| conn.window_by_id(parent)) {
+            if let Some(parent) = parent.borrow().toplevel() {
+                window.set_parent(Some(parent));
+            }
+        }
+        let dialog = match (&conn.wayland_state.borrow().dialog, parent) {
+            (Some(dialog), Some(_)) => {
+                Some(dialog.dialog(window.xdg_toplevel(), options.modal, &qh))
+            }
+            _ => None,
+        };
+
+        // The initial state must be requested before the first commit
+        // for the first configure to reflect it. Wayland doesn't let
+        // us position the window, so any x,y in the geometry is ignored.
+        match options.initial_state {
+            InitialWindowState::Maximized => window.set_maximized(),
+            InitialWindowState::FullScreen => window.set_fullscreen(None),
+            InitialWindowState::Normal | InitialWindowState::Hidden => {}
+        }
+
+        window.set_min_size(Some(MIN_SURFACE_SIZE));
         let (x, y) = window_frame.location();
         window.xdg_surface().set_window_geometry(
             x,
@@ -260,6 +356,186 @@ impl WaylandWindow {
         );
         window.commit();
 
+        let window_handle = Self::finish_window(
+            window_id,
+            surface,
+            XdgRole::Toplevel(window),
+            window_frame,
+            dimensions,
+            config,
+            Some(font_config),
+            parent,
+            dialog,
+            options.decorations,
+            event_handler,
+        )
+        .await?;
+
+        if let Some(inner) = conn.window_by_id(window_id) {
+            let mut inner = inner.borrow_mut();
+            if options.fixed_size {
+                inner.size_constraints.set_min(width, height);
+                inner.size_constraints.set_max(Some((width, height)));
+                inner.apply_size_constraints();
+            }
+            if options.initial_state == InitialWindowState::Hidden {
+                inner.hide();
+            }
+        }
+
+        Ok(window_handle)
+    }
+
+    /// Create a popup, such as a menu, that is positioned relative to
+    /// `anchor`, a rectangle in the parent's pixel coordinates
+    pub async fn new_popup<F>(
+        parent: &WaylandWindow,
+        anchor: Rect,
+        size: (usize, usize),
+        event_handler: F,
+    ) -> anyhow::Result<Window>
+    where
+        F: 'static + FnMut(WindowEvent, &Window),
+    {
+        let conn = WaylandConnection::get()
+            .ok_or_else(|| {
+                anyhow!(
+                    "new_popup must be called on the gui thread after Connection:init has succeed",
+                )
+            })?
+            .wayland();
+        let parent_inner = conn
+            .window_by_id(parent.0)
+            .ok_or_else(|| anyhow!("parent window is gone"))?;
+        let parent_inner = parent_inner.borrow();
+        let parent_role = parent_inner
+            .window
+            .as_ref()
+            .ok_or_else(|| anyhow!("parent window is being destroyed"))?;
+        let config = parent_inner.config.clone();
+
+        let window_id = conn.next_window_id();
+        let qh = conn.event_queue.borrow().handle();
+        let surface = create_surface(&conn, window_id);
+
+        let dimensions = Dimensions {
+            pixel_width: size.0,
+            pixel_height: size.1,
+            dpi: parent_inner.dimensions.dpi,
+        };
+
+        let positioner = XdgPositioner::new(&conn.wayland_state.borrow().xdg)?;
+        let to_surface = |pixels: isize| parent_inner.pixels_to_surface(pixels as i32);
+        positioner.set_size(
+            to_surface(size.0 as isize).max(1),
+            to_surface(size.1 as isize).max(1),
+        );
+        // The anchor is relative to the window geometry of the parent,
+        // which starts at the top left of any client side decorations
+        let (frame_x, frame_y) = parent_inner.window_frame.location();
+        positioner.set_anchor_rect(
+            to_surface(anchor.min_x()) - frame_x,
+            to_surface(anchor.min_y()) - frame_y,
+            to_surface(anchor.width()).max(1),
+            to_surface(anchor.height()).max(1),
+        );
+        // Below the anchor, like a menu, but let the compositor flip
+        // or slide it if that would place it partly off screen
+        positioner.set_anchor(Anchor::BottomLeft);
+        positioner.set_gravity(Gravity::BottomRight);
+        positioner.set_constraint_adjustment(
+            ConstraintAdjustment::FlipX
+                | ConstraintAdjustment::FlipY
+                | ConstraintAdjustment::SlideX
+                | ConstraintAdjustment::SlideY,
+        );
+
+        let popup = Popup::from_surface(
+            Some(parent_role.xdg_surface()),
+            &positioner,
+            &qh,
+            surface.clone(),
+            &conn.wayland_state.borrow().xdg,
+        )?;
+
+        {
+            // The grab gives the popup the keyboard focus, and has the
+            // compositor dismiss it when the user clicks elsewhere.
+            // It must be tied to the input event that opened the popup,
+            // and so to the seat that produced it.
+            let state = conn.wayland_state.borrow();
+            if let Some((seat, serial)) = state.input_serials.latest() {
+                popup.xdg_popup().grab(seat, serial);
+            }
+        }
+
+        let mut window_frame = {
+            let wayland_state = &conn.wayland_state.borrow();
+            let shm = &wayland_state.shm;
+            let subcompositor = wayland_state.subcompositor.clone();
+            let frame_config = ConceptConfig {
+                font_config: None,
+                config: config.clone(),
+                decorations: WindowDecorations::NONE,
+            };
+            ConceptFrame::new(&popup, shm, subcompositor, qh.clone(), frame_config)
+                .context("failed to create csd frame")?
+        };
+        // Popups are never decorated
+        window_frame.set_hidden(true);
+        popup.wl_surface().commit();
+        drop(parent_inner);
+
+        Self::finish_window(
+            window_id,
+            surface,
+            XdgRole::Popup(popup),
+            window_frame,
+            dimensions,
+            config,
+            None,
+            Some(parent.0),
+            None,
+            None,
+            event_handler,
+        )
+        .await
+    }
+
+    /// The part of window creation that is common to toplevels and popups.
+    /// The surface has been committed with its role assigned; wait for
+    /// the compositor to configure it.
+    #[allow(clippy::too_many_arguments)]
+    async fn finish_window<F>(
+        window_id: usize,
+        surface: WlSurface,
+        role: XdgRole,
+        window_frame: ConceptFrame,
+        dimensions: Dimensions,
+        config: ConfigHandle,
+        font_config: Option<Rc<FontConfiguration>>,
+        parent: Option<usize>,
+        dialog: Option<XdgDialogV1>,
+        decorations: Option<WindowDecorations>,
+        event_handler: F,
+    ) -> anyhow::Result<Window>
+    where
+        F: 'static + FnMut(WindowEvent, &Window),
+    {
+        let conn = WaylandConnection::get().unwrap().wayland();
+        let qh = conn.event_queue.borrow().handle();
+        let pending_event = Arc::new(Mutex::new(PendingEvent::default()));
+        let (pending_first_configure, wait_configure) = async_channel::bounded(1);
+
+        let (fractional_scale, viewport) = match &conn.wayland_state.borrow().fractional_scale {
+            Some(fractional_scale) => {
+                let (fractional_scale, viewport) =
+                    fractional_scale.attach(&surface, window_id, &qh);
+                (Some(fractional_scale), Some(viewport))
+            }
+            None => (None, None),
+        };
+
         let copy_and_paste = CopyAndPaste::create();
         let pending_mouse = PendingMouse::create(window_id, &copy_and_paste);
 
@@ -273,17 +549,60 @@ impl WaylandWindow {
         let inner = Rc::new(RefCell::new(WaylandWindowInner {
             events: WindowEventSender::new(event_handler),
             surface_factor: 1.0,
+            effective_dpi: base_dpi(&config),
+            fractional_scale,
+            viewport,
+            preferred_scale: None,
             copy_and_paste,
             invalidated: false,
-            window: Some(window),
+            painted: false,
+            damage: vec![],
+            swap_damage: None,
+            frame_timings: VecDeque::with_capacity(FRAME_TIMING_HISTORY),
+            animation: Animation::default(),
+            opaque_region: None,
+            idle_inhibit: false,
+            idle_inhibitor: None,
+            shortcut_inhibit: false,
+            shortcut_inhibitor: None,
+            shortcut_inhibit_active: false,
+            shortcut_inhibit_requests: vec![],
+            focus_requests: FocusRequests::default(),
+            hidden: false,
+            suspended: false,
+            visible: true,
+            awaiting_remap: false,
+            interactive_resize: false,
+            last_interactive_resize: None,
+            deferred_configure: false,
+            pointer_constraint: None,
+            relative_pointer: None,
+            touch: TouchState::default(),
+            click_streak: ClickStreak::default(),
+            raw_key_mode: RawKeyMode::default(),
+            outputs: vec![],
+            parent,
+            dialog,
+            decorations,
+            font_config,
+            custom_cursor: None,
+            mouse_cursor: Some(MouseCursor::Arrow),
+            window: Some(role),
             window_frame,
             dimensions,
             resize_increments: None,
+            size_constraints: SizeConstraints::default(),
+            full_screen_restore: FullScreenRestore::default(),
+            configure_bounds: None,
+            wm_capabilities: WmCapabilities::default(),
             window_state: WindowState::default(),
+            has_focus: None,
             last_mouse_coords: Point::new(0, 0),
             mouse_buttons: MouseButtons::NONE,
-            hscroll_remainder: 0.0,
-            vscroll_remainder: 0.0,
+            hscroll: ScrollAccumulator::default(),
+            vscroll: ScrollAccumulator::default(),
+            hwheel: WheelAccumulator::default(),
+            vwheel: WheelAccumulator::default(),
 
             modifiers: Modifiers::NONE,
             leds: KeyboardLedStatus::empty(),
@@ -293,6 +612,8 @@ impl WaylandWindow {
             pending_mouse,
 
             pending_first_configure: Some(pending_first_configure),
+            inner_size_requests: vec![],
+            inner_size_generation: 0,
             frame_callback: None,
 
             text_cursor: None,
@@ -304,6 +625,7 @@ impl WaylandWindow {
 
             wegl_surface: None,
             gl_state: None,
+            software_buffer: None,
         }));
 
         let window_handle = Window::Wayland(WaylandWindow(window_id));
@@ -317,6 +639,7 @@ impl WaylandWindow {
             let windows = &conn.wayland_state.borrow().windows;
             windows.borrow_mut().insert(window_id, inner.clone());
         };
+        conn.last_window.window_added();
 
         wait_configure.recv().await?;
 
@@ -324,6 +647,75 @@ impl WaylandWindow {
     }
 }
 
+/// Create a surface whose user data identifies the window, so that
+/// handlers can find the WaylandWindowInner
+fn create_surface(conn: &WaylandConnection, window_id: usize) -> WlSurface {
+    let qh = conn.event_queue.borrow().handle();
+    let surface_data = SurfaceUserData {
+        surface_data: SurfaceData::default(),
+        window_id,
+    };
+    let compositor = &conn.wayland_state.borrow().compositor;
+    compositor.create_surface_with_data(&qh, surface_data)
+}
+
+impl WaylandWindow {
+    /// Reads the clipboard in the first of `mime_types` that is offered,
+    /// and resolves the returned future with the result of passing
+    /// the data through `convert`
+    fn read_clipboard<T: Send + 'static>(
+        &self,
+        clipboard: Clipboard,
+        mime_types: Vec<String>,
+        convert: fn(Vec<u8>) -> T,
+    ) -> Future<T> {
+        let mut promise = Promise::new();
+        let future = promise.get_future().unwrap();
+        let promise = Arc::new(Mutex::new(promise));
+        WaylandConnection::with_window_inner(self.0, move |inner| {
+            let mut copy_and_paste = inner.copy_and_paste.lock().unwrap();
+            if let Some(data) = copy_and_paste.get_owned_clipboard_data(clipboard, &mime_types) {
+                promise.lock().unwrap().ok(convert(data));
+                return Ok(());
+            }
+            let read = match copy_and_paste.get_clipboard_data(clipboard, &mime_types) {
+                Ok(read) => read,
+                Err(err) => {
+                    promise.lock().unwrap().err(err);
+                    return Ok(());
+                }
+            };
+            let promise = Arc::clone(&promise);
+            std::thread::spawn(move || {
+                let mut promise = promise.lock().unwrap();
+                match read_pipe_data_with_timeout(read, pipe_inactivity_timeout()) {
+                    Ok(data) => {
+                        promise.ok(convert(data));
+                    }
+                    Err(e) => {
+                        log::error!("while reading clipboard: {}", e);
+                        promise.err(anyhow!("{}", e));
+                    }
+                };
+            });
+            Ok(())
+        });
+        future
+    }
+}
+
+/// Maps the window_decorations config to the mode that we ask the
+/// compositor to use via xdg-decoration.
+fn requested_decoration_mode(decorations: WindowDecorations) -> Option<DecorationMode> {
+    if decorations == WindowDecorations::NONE {
+        None
+    } else if decorations == WindowDecorations::default() {
+        Some(DecorationMode::Server)
+    } else {
+        Some(DecorationMode::Client)
+    }
+}
+
 #[async_trait(?Send)]
 impl WindowOps for WaylandWindow {
     fn show(&self) {
@@ -360,12 +752,71 @@ impl WindowOps for WaylandWindow {
 
     fn hide(&self) {
         WaylandConnection::with_window_inner(self.0, move |inner| {
-            inner.window.as_ref().unwrap().set_minimized();
+            inner.hide();
+            Ok(())
+        });
+    }
+
+    fn start_interactive_move(&self, serial: Option<InputSerial>) -> Future<()> {
+        WaylandConnection::with_window_inner(self.0, move |inner| {
+            inner.start_interactive(None, serial)
+        })
+    }
+
+    fn start_interactive_resize(
+        &self,
+        edge: WindowResizeEdge,
+        serial: Option<InputSerial>,
+    ) -> Future<()> {
+        WaylandConnection::with_window_inner(self.0, move |inner| {
+            inner.start_interactive(Some(edge), serial)
+        })
+    }
+
+    fn show_window_menu(&self, coords: Point, serial: Option<InputSerial>) -> Future<()> {
+        WaylandConnection::with_window_inner(self.0, move |inner| {
+            inner.show_window_menu(coords, serial)
+        })
+    }
+
+    fn frame_timings(&self) -> Future<Vec<FrameTiming>> {
+        WaylandConnection::with_window_inner(self.0, |inner| {
+            Ok(inner.frame_timings.iter().copied().collect())
+        })
+    }
+
+    fn lock_pointer(&self, position_hint: Option<Point>) -> Future<()> {
+        WaylandConnection::with_window_inner(self.0, move |inner| inner.lock_pointer(position_hint))
+    }
+
+    fn confine_pointer(&self, region: Rect) -> Future<()> {
+        WaylandConnection::with_window_inner(self.0, move |inner| inner.confine_pointer(region))
+    }
+
+    fn unlock_pointer(&self) {
+        WaylandConnection::with_window_inner(self.0, |inner| {
+            inner.unlock_pointer();
+            Ok(())
+        });
+    }
+
+    fn minimize(&self) {
+        WaylandConnection::with_window_inner(self.0, move |inner| {
+            if let Some(window) = inner.toplevel() {
+                window.set_minimized();
+            }
             Ok(())
         });
     }
 
     fn close(&self) {
+        WaylandConnection::with_window_inner(self.0, |inner| {
+            inner.events.request_close();
+            Ok(())
+        });
+    }
+
+    fn force_close(&self) {
         WaylandConnection::with_window_inner(self.0, |inner| {
             inner.close();
             Ok(())
@@ -379,6 +830,16 @@ impl WindowOps for WaylandWindow {
         });
     }
 
+    fn set_custom_cursor(&self, image: Image, hotspot: Point) -> Future<()> {
+        WaylandConnection::with_window_inner(self.0, move |inner| {
+            inner.set_custom_cursor(image, hotspot)
+        })
+    }
+
+    fn present_software(&self, image: Image) -> Future<()> {
+        WaylandConnection::with_window_inner(self.0, move |inner| inner.present_software(image))
+    }
+
     fn invalidate(&self) {
         WaylandConnection::with_window_inner(self.0, |inner| {
             inner.invalidate();
@@ -386,6 +847,61 @@ impl WindowOps for WaylandWindow {
         });
     }
 
+    fn invalidate_rect(&self, rect: Rect) {
+        WaylandConnection::with_window_inner(self.0, move |inner| {
+            inner.invalidate_rect(rect);
+            Ok(())
+        });
+    }
+
+    fn set_opaque_region(&self, region: Option<Rect>) {
+        WaylandConnection::with_window_inner(self.0, move |inner| {
+            inner.set_opaque_region(region);
+            Ok(())
+        });
+    }
+
+    fn set_animation(&self, hint: Option<AnimationHint>) {
+        WaylandConnection::with_window_inner(self.0, move |inner| {
+            inner.set_animation(hint);
+            Ok(())
+        });
+    }
+
+    fn focus(&self, serial: Option<InputSerial>) -> Future<FocusOutcome> {
+        let mut outcome = Promise::new();
+        let future = outcome.get_future().unwrap();
+        WaylandConnection::with_window_inner(self.0, move |inner| {
+            inner.focus(outcome, serial);
+            Ok(())
+        });
+        future
+    }
+
+    fn request_attention(&self) {
+        WaylandConnection::with_window_inner(self.0, |inner| {
+            inner.request_attention();
+            Ok(())
+        });
+    }
+
+    fn set_idle_inhibited(&self, inhibit: bool) {
+        WaylandConnection::with_window_inner(self.0, move |inner| {
+            inner.set_idle_inhibited(inhibit);
+            Ok(())
+        });
+    }
+
+    fn set_shortcut_inhibition(&self, inhibit: bool) -> Future<()> {
+        let mut outcome = Promise::new();
+        let future = outcome.get_future().unwrap();
+        WaylandConnection::with_window_inner(self.0, move |inner| {
+            inner.set_shortcut_inhibition(inhibit, outcome);
+            Ok(())
+        });
+        future
+    }
+
     fn set_text_cursor_position(&self, cursor: Rect) {
         WaylandConnection::with_window_inner(self.0, move |inner| {
             inner.set_text_cursor_position(cursor);
@@ -401,11 +917,38 @@ impl WindowOps for WaylandWindow {
         });
     }
 
-    fn set_inner_size(&self, width: usize, height: usize) {
+    /// There is no instance name on Wayland
+    fn set_window_class(&self, class_name: &str, _instance_name: Option<&str>) {
+        let class_name = class_name.to_owned();
+        WaylandConnection::with_window_inner(self.0, |inner| {
+            if let Some(window) = inner.toplevel() {
+                window.set_app_id(class_name);
+            }
+            Ok(())
+        });
+    }
+
+    fn set_inner_size(
+        &self,
+        width: usize,
+        height: usize,
+        constrained: ConstrainedResize,
+    ) -> Future<Dimensions> {
+        let mut prom = Promise::new();
+        let future = prom.get_future().unwrap();
         WaylandConnection::with_window_inner(self.0, move |inner| {
-            inner.set_inner_size(width, height);
+            inner.set_inner_size(width, height, constrained, prom);
             Ok(())
         });
+        future
+    }
+
+    fn get_current_screen(&self) -> Future<ScreenInfo> {
+        WaylandConnection::with_window_inner(self.0, |inner| match inner.current_screen() {
+            Some(screen) => Ok(screen),
+            // Not yet mapped; assume that it will appear on the active screen
+            None => Ok(Connection::get().unwrap().screens()?.active),
+        })
     }
 
     fn set_resize_increments(&self, incr: ResizeIncrement) {
@@ -414,58 +957,144 @@ impl WindowOps for WaylandWindow {
         });
     }
 
-    fn get_clipboard(&self, clipboard: Clipboard) -> Future<String> {
-        let mut promise = Promise::new();
-        let future = promise.get_future().unwrap();
-        let promise = Arc::new(Mutex::new(promise));
+    fn set_min_inner_size(&self, width: usize, height: usize) {
         WaylandConnection::with_window_inner(self.0, move |inner| {
-            let read = inner
-                .copy_and_paste
-                .lock()
-                .unwrap()
-                .get_clipboard_data(clipboard)?;
-            let promise = Arc::clone(&promise);
-            std::thread::spawn(move || {
-                let mut promise = promise.lock().unwrap();
-                match read_pipe_with_timeout(read) {
-                    Ok(result) => {
-                        // Normalize the text to unix line endings, otherwise
-                        // copying from eg: firefox inserts a lot of blank
-                        // lines, and that is super annoying.
-                        promise.ok(result.replace("\r\n", "\n"));
-                    }
-                    Err(e) => {
-                        log::error!("while reading clipboard: {}", e);
-                        promise.err(anyhow!("{}", e));
-                    }
-                };
-            });
+            inner.set_min_inner_size(width, height);
             Ok(())
         });
-        future
     }
 
-    fn set_clipboard(&self, clipboard: Clipboard, text: String) {
+    fn set_max_inner_size(&self, size: Option<(usize, usize)>) {
         WaylandConnection::with_window_inner(self.0, move |inner| {
-            inner
-                .copy_and_paste
-                .lock()
-                .unwrap()
-                .set_clipboard_data(clipboard, text);
+            inner.set_max_inner_size(size);
             Ok(())
         });
     }
 
-    fn toggle_fullscreen(&self) {
+    fn set_raw_key_mode(&self, enable: bool) {
         WaylandConnection::with_window_inner(self.0, move |inner| {
-            if inner.window_state.contains(WindowState::FULL_SCREEN) {
-                inner.window.as_ref().unwrap().unset_fullscreen();
-            } else {
-                inner.window.as_ref().unwrap().set_fullscreen(None);
-            }
+            inner.raw_key_mode.set_enabled(enable);
             Ok(())
         });
     }
+
+    fn get_clipboard(&self, clipboard: Clipboard) -> Future<String> {
+        self.read_clipboard(clipboard, text_mime_types(), |data| {
+            // Normalize the text to unix line endings, otherwise
+            // copying from eg: firefox inserts a lot of blank
+            // lines, and that is super annoying.
+            String::from_utf8_lossy(&data).replace("\r\n", "\n")
+        })
+    }
+
+    fn set_clipboard(&self, clipboard: Clipboard, text: String) {
+        WaylandConnection::with_window_inner(self.0, move |inner| {
+            if let Err(err) = inner
+                .copy_and_paste
+                .lock()
+                .unwrap()
+                .set_clipboard_data(clipboard, OfferedData::text(text))
+            {
+                log::warn!("Unable to set the clipboard: {err:#}");
+            }
+            Ok(())
+        });
+    }
+
+    fn get_clipboard_data(&self, clipboard: Clipboard, mime: &str) -> Future<Vec<u8>> {
+        self.read_clipboard(clipboard, vec![mime.to_string()], |data| data)
+    }
+
+    fn list_clipboard_formats(&self, clipboard: Clipboard) -> Future<Vec<String>> {
+        WaylandConnection::with_window_inner(self.0, move |inner| {
+            Ok(inner
+                .copy_and_paste
+                .lock()
+                .unwrap()
+                .get_clipboard_formats(clipboard))
+        })
+    }
+
+    fn set_clipboard_data(&self, clipboard: Clipboard, mime: &str, data: Vec<u8>) {
+        let offered = OfferedData::with_mime_type(mime, data);
+        WaylandConnection::with_window_inner(self.0, move |inner| {
+            if let Err(err) = inner
+                .copy_and_paste
+                .lock()
+                .unwrap()
+                .set_clipboard_data(clipboard, offered)
+            {
+                log::warn!("Unable to set the clipboard: {err:#}");
+            }
+            Ok(())
+        });
+    }
+
+    fn maximize(&self) {
+        WaylandConnection::with_window_inner(self.0, move |inner| {
+            inner.maximize();
+            Ok(())
+        });
+    }
+
+    fn restore(&self) {
+        WaylandConnection::with_window_inner(self.0, move |inner| {
+            inner.restore();
+            Ok(())
+        });
+    }
+
+    fn config_did_change(&self, config: &ConfigHandle) {
+        let config = config.clone();
+        WaylandConnection::with_window_inner(self.0, move |inner| {
+            inner.config_did_change(config);
+            Ok(())
+        });
+    }
+
+    fn toggle_fullscreen(&self) {
+        WaylandConnection::with_window_inner(self.0, move |inner| {
+            let Some(window) = inner.toplevel() else {
+                return Ok(());
+            };
+            if inner.window_state.contains(WindowState::FULL_SCREEN) {
+                window.unset_fullscreen();
+            } else {
+                window.set_fullscreen(None);
+            }
+            Ok(())
+        });
+    }
+
+    fn get_dimensions(&self) -> Future<Dimensions> {
+        WaylandConnection::with_window_inner(self.0, |inner| {
+            // Apply any configure that has been received but not yet
+            // processed, so that we don't report stale values
+            inner.dispatch_pending_event();
+            Ok(inner.dimensions)
+        })
+    }
+
+    fn get_wm_capabilities(&self) -> Future<WmCapabilities> {
+        WaylandConnection::with_window_inner(self.0, |inner| {
+            inner.dispatch_pending_event();
+            Ok(inner.wm_capabilities)
+        })
+    }
+
+    fn get_window_state(&self) -> Future<WindowState> {
+        WaylandConnection::with_window_inner(self.0, |inner| {
+            inner.dispatch_pending_event();
+            let mut state = inner.window_state;
+            state.set(WindowState::FOCUSED, inner.has_focus == Some(true));
+            state.set(WindowState::IDLE_INHIBITED, inner.idle_inhibitor.is_some());
+            state.set(
+                WindowState::SHORTCUTS_INHIBITED,
+                inner.shortcut_inhibit_active,
+            );
+            Ok(state)
+        })
+    }
 }
 #[derive(Default, Clone, Debug)]
 pub(crate) struct PendingEvent {
@@ -477,70 +1106,151 @@ pub(crate) struct PendingEvent {
     pub(crate) configure: Option<(u32, u32)>,
     pub(crate) window_configure: Option<WindowConfigure>,
     pub(crate) dpi: Option<i32>,
+    pub(crate) fractional_scale: Option<f64>,
     pub(crate) window_state: Option<WindowState>,
+    pub(crate) configure_bounds: Option<Option<(u32, u32)>>,
+    pub(crate) wm_capabilities: Option<WmCapabilities>,
 }
 
-pub(crate) fn read_pipe_with_timeout(mut file: ReadPipe) -> anyhow::Result<String> {
-    let mut result = Vec::new();
-
-    // set non-blocking I/O on the pipe
-    // (adapted from FileDescriptor::set_non_blocking_impl in /filedescriptor/src/unix.rs)
-    if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_SETFL, libc::O_NONBLOCK) } != 0 {
-        bail!(
-            "failed to change non-blocking mode: {}",
-            std::io::Error::last_os_error()
-        )
-    }
-
-    let mut pfd = libc::pollfd {
-        fd: file.as_raw_fd(),
-        events: libc::POLLIN,
-        revents: 0,
-    };
+enum PointerConstraint {
+    Locked(ZwpLockedPointerV1),
+    Confined(ZwpConfinedPointerV1, Rect),
+}
 
-    let mut buf = [0u8; 8192];
+/// A cursor image set via set_custom_cursor.
+/// The buffer is kept alive alongside the surface that it is attached
+/// to so that the compositor can continue to display it.
+struct CustomCursor {
+    surface: WlSurface,
+    _buffer: Buffer,
+    // In surface coordinates
+    hotspot: (i32, i32),
+}
 
-    loop {
-        if unsafe { libc::poll(&mut pfd, 1, 3000) == 1 } {
-            match file.read(&mut buf) {
-                Ok(size) if size == 0 => {
-                    break;
-                }
-                Ok(size) => {
-                    result.extend_from_slice(&buf[..size]);
-                }
-                Err(e) => bail!("error reading from pipe: {}", e),
-            }
-        } else {
-            bail!("timed out reading from pipe");
+impl PointerConstraint {
+    fn id(&self) -> ObjectId {
+        match self {
+            Self::Locked(locked) => locked.id(),
+            Self::Confined(confined, _) => confined.id(),
         }
     }
 
-    Ok(String::from_utf8(result)?)
+    fn destroy(self) {
+        match self {
+            Self::Locked(locked) => locked.destroy(),
+            Self::Confined(confined, _) => confined.destroy(),
+        }
+    }
 }
 
 pub struct WaylandWindowInner {
     pub(crate) events: WindowEventSender,
     surface_factor: f64,
+    // The dpi implied by the compositor scale; this is what the buffer
+    // is sized for, even when the config overrides the reported dpi
+    effective_dpi: f64,
+    // Present when the compositor supports wp_fractional_scale_v1;
+    // the viewport is used to present our buffer at the logical size
+    fractional_scale: Option<WpFractionalScaleV1>,
+    viewport: Option<WpViewport>,
+    // The most recent scale advised via wp_fractional_scale_v1
+    preferred_scale: Option<f64>,
     copy_and_paste: Arc<Mutex<CopyAndPaste>>,
-    window: Option<XdgWindow>,
-    pub(super) window_frame: FallbackFrame<WaylandState>,
+    window: Option<XdgRole>,
+    pub(super) window_frame: ConceptFrame,
     dimensions: Dimensions,
     resize_increments: Option<ResizeIncrement>,
+    size_constraints: SizeConstraints,
+    full_screen_restore: FullScreenRestore,
+    /// The largest window geometry that the compositor recommends,
+    /// in surface coordinates
+    configure_bounds: Option<(u32, u32)>,
+    wm_capabilities: WmCapabilities,
     window_state: WindowState,
+    // None until the initial focus state has been reported
+    has_focus: Option<bool>,
     last_mouse_coords: Point,
     mouse_buttons: MouseButtons,
-    hscroll_remainder: f64,
-    vscroll_remainder: f64,
+    hscroll: ScrollAccumulator,
+    vscroll: ScrollAccumulator,
+    hwheel: WheelAccumulator,
+    vwheel: WheelAccumulator,
     modifiers: Modifiers,
     leds: KeyboardLedStatus,
     pub(super) key_repeat: Option<(u32, Arc<Mutex<KeyRepeatState>>)>,
     pub(super) pending_event: Arc<Mutex<PendingEvent>>,
     pub(super) pending_mouse: Arc<Mutex<PendingMouse>>,
     pending_first_configure: Option<async_channel::Sender<()>>,
+    // The promises returned by set_inner_size, which are resolved by
+    // the next configure from the compositor, or once the most recent
+    // request has had INNER_SIZE_SETTLE_TIME to settle
+    inner_size_requests: Vec<Promise<Dimensions>>,
+    inner_size_generation: usize,
     frame_callback: Option<WlCallback>,
+    // Set when a paint is needed, and cleared when it is done
     invalidated: bool,
-    // font_config: Rc<FontConfiguration>,
+    // Whether we have painted the first frame
+    painted: bool,
+    // The regions passed to invalidate_rect since the last paint
+    damage: Vec<Rect>,
+    // How we tell EGL about the damage when it presents the frame
+    swap_damage: Option<SwapDamage>,
+    // The most recently presented frames, oldest first
+    frame_timings: VecDeque<FrameTiming>,
+    animation: Animation,
+    // In pixels, as passed to set_opaque_region
+    opaque_region: Option<Rect>,
+    // As passed to set_idle_inhibited, and the inhibitor that is
+    // in place while that is set and the window is shown
+    idle_inhibit: bool,
+    idle_inhibitor: Option<ZwpIdleInhibitorV1>,
+    // As passed to set_shortcut_inhibition, the inhibitor that is in
+    // place while that is set and we have the keyboard focus, whether
+    // the compositor is honoring it, and the calls that are waiting to
+    // find that out
+    shortcut_inhibit: bool,
+    shortcut_inhibitor: Option<ZwpKeyboardShortcutsInhibitorV1>,
+    shortcut_inhibit_active: bool,
+    shortcut_inhibit_requests: Vec<Promise<()>>,
+    // Calls to focus() that are waiting for the compositor
+    focus_requests: FocusRequests,
+    // Set by hide(); the surface has no buffer and we don't paint
+    hidden: bool,
+    // The compositor told us that we're not visible, eg: because we're
+    // minimized or on another workspace, so we don't paint
+    suspended: bool,
+    // As last reported via WindowEvent::VisibilityChanged
+    visible: bool,
+    // Set by show() after a hide(); we need to wait for the compositor
+    // to configure the re-mapped surface before we can paint it
+    awaiting_remap: bool,
+    // Whether the compositor reports that the user is dragging the
+    // window edge, and when we last applied a resize during the drag
+    interactive_resize: bool,
+    last_interactive_resize: Option<Instant>,
+    // A deferred configure is waiting for its timer to fire
+    deferred_configure: bool,
+    // The lock or confinement requested via lock_pointer/confine_pointer,
+    // and the relative pointer that reports motion while it is active
+    pointer_constraint: Option<PointerConstraint>,
+    relative_pointer: Option<ZwpRelativePointerV1>,
+    touch: TouchState,
+    click_streak: ClickStreak,
+    raw_key_mode: RawKeyMode,
+    // The outputs that the surface is on, in the order that it
+    // entered them; the first is considered to be its screen
+    outputs: Vec<WlOutput>,
+    // Set for child windows created via WindowOptions::parent
+    parent: Option<usize>,
+    dialog: Option<XdgDialogV1>,
+    // Overrides config.window_decorations; from WindowOptions::decorations
+    decorations: Option<WindowDecorations>,
+    custom_cursor: Option<CustomCursor>,
+    // The most recent cursor passed to set_cursor, so that it
+    // can be re-rendered when the scale changes
+    mouse_cursor: Option<MouseCursor>,
+    // Used to render the title in our client side frame
+    font_config: Option<Rc<FontConfiguration>>,
     text_cursor: Option<Rect>,
     appearance: Appearance,
     config: ConfigHandle,
@@ -552,10 +1262,89 @@ pub struct WaylandWindowInner {
     // libraries will segfault on shutdown
     wegl_surface: Option<WlEglSurface>,
     gl_state: Option<Rc<glium::backend::Context>>,
+    // The wl_shm buffer most recently attached by present_software;
+    // kept alive for as long as the compositor may be showing it
+    software_buffer: Option<Buffer>,
+}
+
+impl Drop for WaylandWindowInner {
+    fn drop(&mut self) {
+        log::trace!("dropping WaylandWindowInner {:?}", self.title);
+        for mut prom in self.inner_size_requests.drain(..) {
+            prom.err(anyhow!("window was closed before it was resized"));
+        }
+    }
 }
 
 impl WaylandWindowInner {
     fn close(&mut self) {
+        self.unlock_pointer();
+        self.release_custom_cursor();
+        self.idle_inhibit = false;
+        self.update_idle_inhibitor();
+        self.shortcut_inhibit = false;
+        self.update_shortcut_inhibitor();
+        for mut outcome in self.shortcut_inhibit_requests.drain(..) {
+            outcome.err(anyhow!("window is closed"));
+        }
+        self.events.dispatch(WindowEvent::Destroyed);
+        if let Some(viewport) = self.viewport.take() {
+            viewport.destroy();
+        }
+        if let Some(fractional_scale) = self.fractional_scale.take() {
+            fractional_scale.destroy();
+        }
+        if let Some(dialog) = self.dialog.take() {
+            dialog.destroy();
+        }
+        if self.window.is_some() {
+            self.close_children();
+
+            // Stop routing input to this window, and let the connection
+            // release its reference so that we can be dropped
+            let surface = self.surface();
+            let window_id = SurfaceUserData::from_wl(surface).window_id;
+            let surface_id = surface.id();
+            let conn = WaylandConnection::get().unwrap().wayland();
+            conn.wayland_state
+                .borrow_mut()
+                .forget_window(window_id, &surface_id);
+        }
+        self.window.take();
+    }
+
+    /// Returns false once the window has been closed
+    pub(super) fn is_open(&self) -> bool {
+        self.window.is_some()
+    }
+
+    /// Children are closed along with their parent
+    fn close_children(&self) {
+        let window_id = SurfaceUserData::from_wl(self.surface()).window_id;
+        let conn = WaylandConnection::get().unwrap().wayland();
+        let children: Vec<usize> = conn
+            .wayland_state
+            .borrow()
+            .windows
+            .borrow()
+            .iter()
+            .filter(|(_, child)| {
+                // We're borrowed, so skip ourselves
+                child
+                    .try_borrow()
+                    .map(|child| child.parent == Some(window_id) && child.window.is_some())
+                    .unwrap_or(false)
+            })
+            .map(|(&child, _)| child)
+            .collect();
+        for child in children {
+            WaylandWindow(child).force_close();
+        }
+    }
+
+    /// The compositor has gone away, so there is nothing to clean up
+    /// on its side; just let the application know
+    pub(super) fn connection_lost(&mut self) {
         self.events.dispatch(WindowEvent::Destroyed);
         self.window.take();
     }
@@ -566,6 +1355,17 @@ impl WaylandWindowInner {
             return;
         }
 
+        if self.hidden {
+            // Committing without a buffer asks the compositor to map
+            // the surface again; we'll paint once it has been configured
+            self.hidden = false;
+            self.awaiting_remap = true;
+            self.update_idle_inhibitor();
+            self.update_visibility();
+            self.surface().commit();
+            return;
+        }
+
         // If the do_paint function has been called previously, calling it again will not
         // send the NeedRepaint event. This results in the window not being displayed
         // correctly.
@@ -579,7 +1379,24 @@ impl WaylandWindowInner {
         self.do_paint().unwrap();
     }
 
-    fn refresh_frame(&mut self) {
+    /// Unmap the surface by attaching a null buffer.
+    /// Painting is suspended until show() is called.
+    fn hide(&mut self) {
+        if self.window.is_none() || self.hidden {
+            return;
+        }
+        self.hidden = true;
+        self.awaiting_remap = false;
+        self.update_idle_inhibitor();
+        self.update_visibility();
+        // The compositor won't call us back for a frame while we're
+        // unmapped, so don't wait for it
+        self.frame_callback.take();
+        self.surface().attach(None, 0, 0);
+        self.surface().commit();
+    }
+
+    pub(super) fn refresh_frame(&mut self) {
         if let Some(window) = self.window.as_mut() {
             if self.window_frame.is_dirty() && !self.window_frame.is_hidden() {
                 self.window_frame.draw();
@@ -588,6 +1405,64 @@ impl WaylandWindowInner {
         }
     }
 
+    /// Show or hide our client side frame to match the decoration
+    /// mode negotiated with the compositor via xdg-decoration.
+    /// When the compositor doesn't implement that protocol (eg: GNOME)
+    /// the mode is always Client and we draw the frame ourselves.
+    fn update_decoration_mode(&mut self, mode: DecorationMode) {
+        let want_frame =
+            mode == DecorationMode::Client && self.window_decorations() != WindowDecorations::NONE;
+        if want_frame != self.window_frame.is_hidden() {
+            return;
+        }
+        log::debug!("decoration mode is now {mode:?}, drawing frame: {want_frame}");
+        self.window_frame.set_hidden(!want_frame);
+
+        let width = self.pixels_to_surface(self.dimensions.pixel_width as i32) as u32;
+        let height = self.pixels_to_surface(self.dimensions.pixel_height as i32) as u32;
+        if want_frame {
+            self.window_frame.resize(
+                NonZeroU32::new(width).unwrap_or(NonZeroU32::new(1).unwrap()),
+                NonZeroU32::new(height).unwrap_or(NonZeroU32::new(1).unwrap()),
+            );
+        }
+        if let Some(window) = self.window.as_ref() {
+            let (x, y) = self.window_frame.location();
+            let (outer_width, outer_height) = self.add_frame_borders(width, height);
+            window
+                .xdg_surface()
+                .set_window_geometry(x, y, outer_width as i32, outer_height as i32);
+        }
+    }
+
+    /// Given a content area size in surface coordinates, returns the size
+    /// of the overall window geometry including our frame, if any.
+    fn add_frame_borders(&self, width: u32, height: u32) -> (u32, u32) {
+        if self.window_frame.is_hidden() {
+            (width, height)
+        } else {
+            self.window_frame.add_borders(width, height)
+        }
+    }
+
+    /// The inverse of add_frame_borders; turns a configured window geometry
+    /// size into the size of the content area.
+    fn subtract_frame_borders(&self, width: u32, height: u32) -> (u32, u32) {
+        if self.window_frame.is_hidden() {
+            return (width, height);
+        }
+        match (NonZeroU32::new(width), NonZeroU32::new(height)) {
+            (Some(w), Some(h)) => {
+                let (w, h) = self.window_frame.subtract_borders(w, h);
+                (
+                    w.map(NonZeroU32::get).unwrap_or(1),
+                    h.map(NonZeroU32::get).unwrap_or(1),
+                )
+            }
+            _ => (width, height),
+        }
+    }
+
     fn enable_opengl(&mut self) -> anyhow::Result<Rc<glium::backend::Context>> {
         let wayland_conn = Connection::get().unwrap().wayland();
         let mut wegl_surface = None;
@@ -622,12 +1497,14 @@ impl WaylandWindowInner {
                 ),
             }
         };
-        let gl_state = gl_state.map(Rc::new).and_then(|state| unsafe {
-            wayland_conn
-                .gl_connection
-                .borrow_mut()
-                .replace(Rc::clone(state.get_connection()));
-            Ok(glium::backend::Context::new(
+        let state = Rc::new(gl_state?);
+        self.swap_damage.replace(state.swap_damage());
+        wayland_conn
+            .gl_connection
+            .borrow_mut()
+            .replace(Rc::clone(state.get_connection()));
+        let gl_state = unsafe {
+            glium::backend::Context::new(
                 Rc::clone(&state),
                 true,
                 if cfg!(debug_assertions) {
@@ -635,8 +1512,8 @@ impl WaylandWindowInner {
                 } else {
                     glium::debug::DebugCallbackBehavior::Ignore
                 },
-            )?)
-        })?;
+            )?
+        };
 
         self.gl_state.replace(gl_state.clone());
         self.wegl_surface = wegl_surface;
@@ -644,124 +1521,258 @@ impl WaylandWindowInner {
         Ok(gl_state)
     }
 
+    /// Returns the scale factor that the compositor would like us to
+    /// render at.  This is fractional when wp_fractional_scale_v1 is
+    /// available, otherwise it is the integer wl_surface scale.
+    fn surface_scale_factor(&self) -> f64 {
+        match (&self.viewport, self.preferred_scale) {
+            (Some(_), Some(scale)) => scale,
+            _ => SurfaceUserData::from_wl(self.surface())
+                .surface_data
+                .scale_factor() as f64,
+        }
+    }
+
+    /// Returns true if the scale most recently advised by the compositor
+    /// differs from the one that our dimensions were computed from
+    fn preferred_scale_changed(&self) -> bool {
+        match (&self.viewport, self.preferred_scale) {
+            (Some(_), Some(scale)) => scale != self.surface_factor,
+            _ => false,
+        }
+    }
+
     fn get_dpi_factor(&self) -> f64 {
-        self.dimensions.dpi as f64 / crate::DEFAULT_DPI as f64
+        self.effective_dpi / base_dpi(&self.config)
     }
 
     fn surface_to_pixels(&self, surface: i32) -> i32 {
-        (surface as f64 * self.get_dpi_factor()).ceil() as i32
+        surface_to_pixels(surface, self.get_dpi_factor())
     }
 
     fn pixels_to_surface(&self, pixels: i32) -> i32 {
-        // Take care to round up, otherwise we can lose a pixel
-        // and that can effectively lose the final row of the
-        // terminal
-        ((pixels as f64) / self.get_dpi_factor()).ceil() as i32
+        pixels_to_surface(pixels, self.get_dpi_factor())
     }
 
     pub(super) fn dispatch_dropped_files(&mut self, paths: Vec<PathBuf>) {
         self.events.dispatch(WindowEvent::DroppedFile(paths));
     }
 
-    pub(crate) fn dispatch_pending_mouse(&mut self) {
-        let pending_mouse = Arc::clone(&self.pending_mouse);
+    pub(super) fn touch_event(&mut self, id: i32, phase: TouchPhase, x: f64, y: f64) {
+        let factor = self.get_dpi_factor();
+        let coords = Point::new((x * factor) as isize, (y * factor) as isize);
+        self.dispatch_touch(TouchEvent { id, phase, coords });
+    }
 
-        if let Some((x, y)) = PendingMouse::coords(&pending_mouse) {
-            let coords = Point::new(
-                self.surface_to_pixels(x as i32) as isize,
-                self.surface_to_pixels(y as i32) as isize,
-            );
-            self.last_mouse_coords = coords;
-            let event = MouseEvent {
-                kind: MouseEventKind::Move,
+    pub(super) fn touch_up(&mut self, id: i32) {
+        // wl_touch.up doesn't carry a position, so use the last known one
+        if let Some(coords) = self.touch.position(id) {
+            self.dispatch_touch(TouchEvent {
+                id,
+                phase: TouchPhase::Up,
                 coords,
-                screen_coords: ScreenPoint::new(
-                    coords.x + self.dimensions.pixel_width as isize,
-                    coords.y + self.dimensions.pixel_height as isize,
-                ),
-                mouse_buttons: self.mouse_buttons,
-                modifiers: self.modifiers,
-            };
-            self.events.dispatch(WindowEvent::MouseEvent(event));
-            self.refresh_frame();
+            });
+        }
+    }
+
+    pub(super) fn touch_cancel(&mut self) {
+        for id in self.touch.ids() {
+            if let Some(coords) = self.touch.position(id) {
+                self.dispatch_touch(TouchEvent {
+                    id,
+                    phase: TouchPhase::Cancel,
+                    coords,
+                });
+            }
         }
+    }
+
+    fn dispatch_touch(&mut self, event: TouchEvent) {
+        self.events.dispatch(WindowEvent::Touch(event));
 
-        while let Some((button, state)) = PendingMouse::next_button(&pending_mouse) {
-            let button_mask = match button {
-                MousePress::Left => MouseButtons::LEFT,
-                MousePress::Right => MouseButtons::RIGHT,
-                MousePress::Middle => MouseButtons::MIDDLE,
+        for emulated in self.touch.update(&event) {
+            let (kind, coords, streak) = match emulated {
+                EmulatedMouse::Press(coords) => {
+                    self.mouse_buttons |= MouseButtons::LEFT;
+                    (MouseEventKind::Press(MousePress::Left), coords, 1)
+                }
+                EmulatedMouse::Move(coords) => (MouseEventKind::Move, coords, 0),
+                EmulatedMouse::Release(coords) => {
+                    self.mouse_buttons -= MouseButtons::LEFT;
+                    (MouseEventKind::Release(MousePress::Left), coords, 0)
+                }
             };
+            self.last_mouse_coords = coords;
+            self.events.dispatch(WindowEvent::MouseEvent(MouseEvent {
+                streak,
+                ..pointer_event(kind, coords, self.mouse_buttons, self.modifiers)
+            }));
+        }
+    }
+
+    pub(crate) fn dispatch_pending_mouse(&mut self) {
+        let pending_mouse = Arc::clone(&self.pending_mouse);
+        let time = PendingMouse::time(&pending_mouse);
+
+        let (entered, events) = pending_pointer_events(
+            &pending_mouse,
+            self.get_dpi_factor(),
+            click_interval_ms(&self.config),
+            self.modifiers,
+            &mut self.last_mouse_coords,
+            &mut self.mouse_buttons,
+            &mut self.click_streak,
+        );
 
-            if state == ButtonState::Pressed {
-                self.mouse_buttons |= button_mask;
+        if let Some(coords) = entered {
+            // The compositor reverts to its own cursor when the pointer
+            // leaves, so we need to re-establish ours each time it enters
+            if self.custom_cursor.is_some() {
+                self.apply_custom_cursor();
             } else {
-                self.mouse_buttons -= button_mask;
+                self.set_cursor(self.mouse_cursor);
             }
+            self.events.dispatch(WindowEvent::MouseEnter(coords));
+        }
 
-            let event = MouseEvent {
-                kind: match state {
-                    ButtonState::Pressed => MouseEventKind::Press(button),
-                    ButtonState::Released => MouseEventKind::Release(button),
-                    _ => continue,
-                },
-                coords: self.last_mouse_coords,
-                screen_coords: ScreenPoint::new(
-                    self.last_mouse_coords.x + self.dimensions.pixel_width as isize,
-                    self.last_mouse_coords.y + self.dimensions.pixel_height as isize,
-                ),
-                mouse_buttons: self.mouse_buttons,
-                modifiers: self.modifiers,
-            };
+        for event in events {
+            let moved = event.kind == MouseEventKind::Move;
             self.events.dispatch(WindowEvent::MouseEvent(event));
+            if moved {
+                self.refresh_frame();
+            }
+        }
+
+        if let Some((dx, dy)) = PendingMouse::relative(&pending_mouse) {
+            let factor = self.get_dpi_factor();
+            self.events.dispatch(WindowEvent::MouseEvent(MouseEvent {
+                time,
+                ..pointer_event(
+                    MouseEventKind::RelativeMove {
+                        dx: dx * factor,
+                        dy: dy * factor,
+                    },
+                    self.last_mouse_coords,
+                    self.mouse_buttons,
+                    self.modifiers,
+                )
+            }));
+        }
+
+        for mut gesture in PendingMouse::gestures(&pending_mouse) {
+            let factor = self.get_dpi_factor();
+            gesture.dx *= factor;
+            gesture.dy *= factor;
+            self.events.dispatch(WindowEvent::Gesture(gesture));
         }
 
-        if let Some((value_x, value_y)) = PendingMouse::scroll(&pending_mouse) {
+        let scroll = PendingMouse::scroll(&pending_mouse);
+        let wheel120 = PendingMouse::wheel120(&pending_mouse);
+        let scroll_source = PendingMouse::scroll_source(&pending_mouse);
+
+        if let Some((value_x, value_y)) = scroll {
             let factor = self.get_dpi_factor() as f64;
+            self.events.dispatch(WindowEvent::MouseEvent(MouseEvent {
+                time,
+                ..pointer_event(
+                    MouseEventKind::PixelScroll {
+                        horizontal: -value_x * factor,
+                        vertical: -value_y * factor,
+                        source: scroll_source,
+                    },
+                    self.last_mouse_coords,
+                    self.mouse_buttons,
+                    self.modifiers,
+                )
+            }));
+        }
+
+        if let Some((wheel_x, wheel_y)) = wheel120 {
+            self.events.dispatch(WindowEvent::MouseEvent(MouseEvent {
+                time,
+                ..pointer_event(
+                    MouseEventKind::WheelValue120 {
+                        horizontal: -wheel_x,
+                        vertical: -wheel_y,
+                    },
+                    self.last_mouse_coords,
+                    self.mouse_buttons,
+                    self.modifiers,
+                )
+            }));
+        }
 
-            if value_x.signum() != self.hscroll_remainder.signum() {
-                // reset accumulator when changing scroll direction
-                self.hscroll_remainder = 0.0;
+        // A physical wheel reports its movement in fractions of a detent,
+        // so count those rather than the pixel distance, which is only
+        // a rough approximation of it; otherwise fall back to the pixel
+        // distance, as is the case for touchpad scrolling
+        let discrete = match (wheel120, scroll) {
+            (Some((wheel_x, wheel_y)), _) => Some((
+                self.hwheel.accumulate(wheel_x) as f64,
+                self.vwheel.accumulate(wheel_y) as f64,
+            )),
+            (None, Some((value_x, value_y))) => {
+                let factor = self.get_dpi_factor() as f64;
+                Some((
+                    self.hscroll.accumulate(value_x * factor),
+                    self.vscroll.accumulate(value_y * factor),
+                ))
             }
-            let scaled_x = (value_x * factor) + self.hscroll_remainder;
-            let discrete_x = scaled_x.trunc();
-            self.hscroll_remainder = scaled_x - discrete_x;
+            (None, None) => None,
+        };
+
+        if let Some((discrete_x, discrete_y)) = discrete {
             if discrete_x != 0. {
                 let event = MouseEvent {
-                    kind: MouseEventKind::HorzWheel(-discrete_x as i16),
-                    coords: self.last_mouse_coords,
-                    screen_coords: ScreenPoint::new(
-                        self.last_mouse_coords.x + self.dimensions.pixel_width as isize,
-                        self.last_mouse_coords.y + self.dimensions.pixel_height as isize,
-                    ),
-                    mouse_buttons: self.mouse_buttons,
-                    modifiers: self.modifiers,
+                    time,
+                    ..pointer_event(
+                        MouseEventKind::HorzWheel(-discrete_x as i16),
+                        self.last_mouse_coords,
+                        self.mouse_buttons,
+                        self.modifiers,
+                    )
                 };
                 self.events.dispatch(WindowEvent::MouseEvent(event));
             }
 
-            if value_y.signum() != self.vscroll_remainder.signum() {
-                self.vscroll_remainder = 0.0;
-            }
-            let scaled_y = (value_y * factor) + self.vscroll_remainder;
-            let discrete_y = scaled_y.trunc();
-            self.vscroll_remainder = scaled_y - discrete_y;
             if discrete_y != 0. {
                 let event = MouseEvent {
-                    kind: MouseEventKind::VertWheel(-discrete_y as i16),
-                    coords: self.last_mouse_coords,
-                    screen_coords: ScreenPoint::new(
-                        self.last_mouse_coords.x + self.dimensions.pixel_width as isize,
-                        self.last_mouse_coords.y + self.dimensions.pixel_height as isize,
-                    ),
-                    mouse_buttons: self.mouse_buttons,
-                    modifiers: self.modifiers,
+                    time,
+                    ..pointer_event(
+                        MouseEventKind::VertWheel(-discrete_y as i16),
+                        self.last_mouse_coords,
+                        self.mouse_buttons,
+                        self.modifiers,
+                    )
                 };
                 self.events.dispatch(WindowEvent::MouseEvent(event));
             }
         }
 
-        if !PendingMouse::in_window(&pending_mouse) {
+        let scroll_stopped = PendingMouse::scroll_stopped(&pending_mouse);
+        if scroll_stopped {
+            self.events.dispatch(WindowEvent::MouseEvent(MouseEvent {
+                time,
+                ..pointer_event(
+                    MouseEventKind::ScrollStopped(scroll_source),
+                    self.last_mouse_coords,
+                    self.mouse_buttons,
+                    self.modifiers,
+                )
+            }));
+        }
+
+        let in_window = PendingMouse::in_window(&pending_mouse);
+        if scroll_stopped || !in_window {
+            // Don't let the remainder of this scroll sequence
+            // leak into the next one
+            self.hscroll.reset();
+            self.vscroll.reset();
+            self.hwheel.reset();
+            self.vwheel.reset();
+        }
+
+        if !in_window {
             self.events.dispatch(WindowEvent::MouseLeave);
             self.refresh_frame();
         }
@@ -776,9 +1787,10 @@ impl WaylandWindowInner {
         }
 
         if pending.close {
-            self.events.dispatch(WindowEvent::CloseRequested);
+            self.events.request_close();
         }
 
+        let old_window_state = self.window_state;
         if let Some(window_state) = pending.window_state.take() {
             log::debug!(
                 "dispatch_pending_event self.window_state={:?}, pending:{:?}",
@@ -788,10 +1800,24 @@ impl WaylandWindowInner {
             self.window_state = window_state;
         }
 
+        let mut wm_hints_changed = false;
+        if let Some(bounds) = pending.configure_bounds.take() {
+            wm_hints_changed |= bounds != self.configure_bounds;
+            self.configure_bounds = bounds;
+        }
+        if let Some(capabilities) = pending.wm_capabilities.take() {
+            wm_hints_changed |= capabilities != self.wm_capabilities;
+            self.wm_capabilities = capabilities;
+        }
+
+        if let Some(scale) = pending.fractional_scale.take() {
+            self.preferred_scale.replace(scale);
+        }
+
         if pending.configure.is_none() {
-            if pending.dpi.is_some() {
+            if pending.dpi.is_some() || self.preferred_scale_changed() {
                 // Synthesize a pending configure event for the dpi change
-                pending.configure.replace((
+                pending.configure.replace(self.add_frame_borders(
                     self.pixels_to_surface(self.dimensions.pixel_width as i32) as u32,
                     self.pixels_to_surface(self.dimensions.pixel_height as i32) as u32,
                 ));
@@ -800,23 +1826,46 @@ impl WaylandWindowInner {
         }
 
         if let Some(ref window_config) = pending.window_configure {
+            self.update_decoration_mode(window_config.decoration_mode);
             self.window_frame.update_state(window_config.state);
             self.window_frame
                 .update_wm_capabilities(window_config.capabilities);
+            self.interactive_resize = window_config.state.contains(SCTKWindowState::RESIZING);
+            self.suspended = window_config.state.contains(SCTKWindowState::SUSPENDED);
+            self.update_visibility();
+        }
+
+        if let Some(configure) = pending.configure {
+            if let Some(delay) = resize_deferral(
+                self.interactive_resize,
+                self.last_interactive_resize,
+                Instant::now(),
+                Duration::from_millis(self.config.wayland_resize_debounce_ms),
+            ) {
+                pending.configure.take();
+                self.defer_configure(configure, delay);
+            }
         }
 
+        let mut notified_window_state = false;
+        let configured = pending.configure.is_some() && self.window.is_some();
         if let Some((mut w, mut h)) = pending.configure.take() {
             log::trace!("Pending configure: w:{w}, h{h} -- {:?}", self.window);
             if self.window.is_some() {
-                let surface_udata = SurfaceUserData::from_wl(self.surface());
-                let factor = surface_udata.surface_data.scale_factor() as f64;
-                let old_dimensions = self.dimensions;
+                // The configured size is that of the whole window geometry,
+                // which includes our frame when we're drawing client side
+                // decorations.  Work with just the content area from here.
+                (w, h) = self.subtract_frame_borders(w, h);
 
-                // FIXME: teach this how to resolve dpi_by_screen
-                let dpi = self.config.dpi.unwrap_or(factor * crate::DEFAULT_DPI) as usize;
+                let factor = self.surface_scale_factor();
+                let old_dimensions = self.dimensions;
+                self.window_frame.set_scaling_factor(factor);
 
                 // Do this early because this affects surface_to_pixels/pixels_to_surface
-                self.dimensions.dpi = dpi;
+                self.effective_dpi = factor * base_dpi(&self.config);
+
+                // FIXME: teach this how to resolve dpi_by_screen
+                self.dimensions.dpi = self.config.dpi.unwrap_or(self.effective_dpi) as usize;
 
                 let mut pixel_width = self.surface_to_pixels(w.try_into().unwrap());
                 let mut pixel_height = self.surface_to_pixels(h.try_into().unwrap());
@@ -824,54 +1873,75 @@ impl WaylandWindowInner {
                 if self.window_state.can_resize() {
                     self.window_frame.set_resizable(true);
                     if let Some(incr) = self.resize_increments {
-                        let min_width = incr.base_width + incr.x;
-                        let min_height = incr.base_height + incr.y;
-                        let extra_width = (pixel_width - incr.base_width as i32) % incr.x as i32;
-                        let extra_height = (pixel_height - incr.base_height as i32) % incr.y as i32;
-                        let desired_pixel_width = max(pixel_width - extra_width, min_width as i32);
-                        let desired_pixel_height =
-                            max(pixel_height - extra_height, min_height as i32);
-                        w = self.pixels_to_surface(desired_pixel_width) as u32;
-                        h = self.pixels_to_surface(desired_pixel_height) as u32;
+                        // There's no protocol for increments, so snap
+                        // the configured size to them ourselves
+                        let (desired_pixel_width, desired_pixel_height) =
+                            snap_to_increments(&incr, pixel_width as usize, pixel_height as usize);
+                        w = self.pixels_to_surface(desired_pixel_width as i32) as u32;
+                        h = self.pixels_to_surface(desired_pixel_height as i32) as u32;
                         pixel_width = self.surface_to_pixels(w.try_into().unwrap());
                         pixel_height = self.surface_to_pixels(h.try_into().unwrap());
                     }
+                    if self.config.wayland_clamp_to_size_constraints {
+                        // Not all compositors respect the min/max size
+                        let (clamped_width, clamped_height) = self
+                            .size_constraints
+                            .clamp(pixel_width as usize, pixel_height as usize);
+                        if (clamped_width as i32, clamped_height as i32)
+                            != (pixel_width, pixel_height)
+                        {
+                            w = self.pixels_to_surface(clamped_width as i32) as u32;
+                            h = self.pixels_to_surface(clamped_height as i32) as u32;
+                            pixel_width = self.surface_to_pixels(w.try_into().unwrap());
+                            pixel_height = self.surface_to_pixels(h.try_into().unwrap());
+                        }
+                    }
                 }
 
                 log::trace!("Resizing frame");
                 if !self.window_frame.is_hidden() {
                     // Clamp the size to at least one pixel.
-                    let width =
-                        NonZeroU32::new(pixel_width as u32).unwrap_or(NonZeroU32::new(1).unwrap());
-                    let height =
-                        NonZeroU32::new(pixel_height as u32).unwrap_or(NonZeroU32::new(1).unwrap());
+                    let width = NonZeroU32::new(w).unwrap_or(NonZeroU32::new(1).unwrap());
+                    let height = NonZeroU32::new(h).unwrap_or(NonZeroU32::new(1).unwrap());
                     self.window_frame.resize(width, height);
                 }
                 let (x, y) = self.window_frame.location();
+                let (outer_width, outer_height) = self.add_frame_borders(w, h);
                 self.window
                     .as_mut()
                     .unwrap()
                     .xdg_surface()
-                    .set_window_geometry(x, y, pixel_width, pixel_height);
+                    .set_window_geometry(x, y, outer_width as i32, outer_height as i32);
                 // Compute the new pixel dimensions
                 let new_dimensions = Dimensions {
                     pixel_width: pixel_width.try_into().unwrap(),
                     pixel_height: pixel_height.try_into().unwrap(),
-                    dpi,
+                    dpi: self.dimensions.dpi,
                 };
 
                 // Only trigger a resize if the new dimensions are different;
                 // this makes things more efficient and a little more smooth
                 if new_dimensions != old_dimensions {
+                    notified_window_state = true;
                     self.dimensions = new_dimensions;
+                    self.last_interactive_resize = if self.interactive_resize {
+                        Some(Instant::now())
+                    } else {
+                        None
+                    };
 
                     self.events.dispatch(WindowEvent::Resized {
                         dimensions: self.dimensions,
                         window_state: self.window_state,
-                        // We don't know if we're live resizing or not, so
-                        // assume no.
-                        live_resizing: false,
+                        live_resizing: self.interactive_resize,
                     });
+                    // Re-render the cursor to suit the new scale
+                    if new_dimensions.dpi != old_dimensions.dpi
+                        && self.custom_cursor.is_none()
+                        && PendingMouse::in_window(&self.pending_mouse)
+                    {
+                        self.set_cursor(self.mouse_cursor);
+                    }
                     // Avoid blurring by matching the scaling factor of the
                     // compositor; if it is going to double the size then
                     // we render at double the size anyway and tell it that
@@ -887,7 +1957,7 @@ impl WaylandWindowInner {
                     if let Some(wegl_surface) = self.wegl_surface.as_mut() {
                         wegl_surface.resize(pixel_width, pixel_height, 0, 0);
                     }
-                    if self.surface_factor != factor {
+                    if self.viewport.is_none() && self.surface_factor != factor {
                         let wayland_conn = Connection::get().unwrap().wayland();
                         let wayland_state = wayland_conn.wayland_state.borrow();
                         let mut pool = wayland_state.mem_pool.borrow_mut();
@@ -907,60 +1977,619 @@ impl WaylandWindowInner {
                         }
                     }
                 }
+                if let Some(viewport) = self.viewport.as_ref() {
+                    // The buffer is pixel_width x pixel_height; have the
+                    // compositor present it at the logical size.
+                    // This is done even when our dimensions are unchanged,
+                    // as a deferred configure may have stretched the
+                    // destination in the meantime.
+                    viewport.set_destination(w as i32, h as i32);
+                    self.surface_factor = factor;
+                }
+                // The region and the size constraints are in surface
+                // coordinates, which depend upon the scale
+                self.apply_opaque_region();
+                self.apply_size_constraints();
                 self.refresh_frame();
                 self.do_paint().unwrap();
             }
         }
+        if self.window_state != old_window_state && !notified_window_state {
+            // Eg: maximizing a window that already fills the screen won't
+            // change its size, but the application still needs to learn
+            // about the new state.
+            self.events.dispatch(WindowEvent::Resized {
+                dimensions: self.dimensions,
+                window_state: self.window_state,
+                live_resizing: false,
+            });
+        }
+        if configured {
+            if let Some((width, height)) = self.full_screen_restore.configured(
+                self.window_state,
+                self.dimensions.pixel_width,
+                self.dimensions.pixel_height,
+            ) {
+                log::debug!("restoring pre-fullscreen size {width}x{height}");
+                self.resize_surface(width, height);
+            }
+        }
+        if wm_hints_changed {
+            self.events.dispatch(WindowEvent::WmCapabilitiesChanged {
+                capabilities: self.wm_capabilities,
+                bounds: self.bounds_in_pixels(),
+            });
+        }
         if pending.refresh_decorations && self.window.is_some() {
             self.refresh_frame();
         }
         if pending.had_configure_event && self.window.is_some() {
             log::debug!("Had configured an event");
+            if self.pending_first_configure.is_some() && self.window_state.can_resize() {
+                // We chose the initial size without knowing the bounds
+                let (width, height) =
+                    self.clamp_to_bounds(self.dimensions.pixel_width, self.dimensions.pixel_height);
+                if (width, height) != (self.dimensions.pixel_width, self.dimensions.pixel_height) {
+                    log::debug!("clamping initial size to {width}x{height}");
+                    self.resize_surface(width, height);
+                }
+            }
             if let Some(notify) = self.pending_first_configure.take() {
                 // Allow window creation to complete
                 notify.try_send(()).ok();
             }
+            // The compositor has had its say about the size
+            self.resolve_inner_size_requests();
+            if self.has_focus.is_none() {
+                // We only hear from wl_keyboard if and when we gain the
+                // focus; until then, we don't have it
+                self.has_focus = Some(false);
+                self.events
+                    .dispatch(WindowEvent::FocusChanged(false, FocusReason::Unknown));
+            }
+            if self.awaiting_remap {
+                self.awaiting_remap = false;
+                self.do_paint().unwrap();
+            }
+        }
+    }
+
+    /// Hold on to a configure that arrived too soon after the previous
+    /// resize during an interactive resize, and apply it once `delay`
+    /// has passed. Any configure that arrives in the meantime replaces
+    /// it, so that the size that is eventually applied is the latest.
+    fn defer_configure(&mut self, configure: (u32, u32), delay: Duration) {
+        self.pending_event
+            .lock()
+            .unwrap()
+            .configure
+            .get_or_insert(configure);
+
+        // Keep presenting our current content, stretched to the new
+        // size if we are able to, so that the compositor sees the
+        // surface keep up with the pointer
+        if let (Some(window), Some(viewport)) = (self.window.as_ref(), self.viewport.as_ref()) {
+            let (x, y) = self.window_frame.location();
+            let (w, h) = configure;
+            window
+                .xdg_surface()
+                .set_window_geometry(x, y, w as i32, h as i32);
+            let (w, h) = self.subtract_frame_borders(w, h);
+            viewport.set_destination(w.max(1) as i32, h.max(1) as i32);
+        }
+        self.surface().commit();
+
+        if self.deferred_configure {
+            return;
         }
+        self.deferred_configure = true;
+        let window_id = SurfaceUserData::from_wl(self.surface()).window_id;
+        promise::spawn::spawn(async move {
+            Timer::after(delay).await;
+            WaylandConnection::with_window_inner(window_id, |inner| {
+                inner.deferred_configure = false;
+                inner.dispatch_pending_event();
+                Ok(())
+            });
+        })
+        .detach();
     }
 
     fn set_cursor(&mut self, cursor: Option<MouseCursor>) {
+        self.release_custom_cursor();
+        self.mouse_cursor = cursor;
+
         let conn = Connection::get().unwrap().wayland();
         let state = conn.wayland_state.borrow_mut();
-        let pointer = match &state.pointer {
+        let pointer = match state.pointers.active() {
             Some(pointer) => pointer,
             None => return,
         };
+        // The cursor can only be changed while the pointer is over
+        // one of our surfaces; set_cursor is called again when it enters
+        let pointer_data = pointer.pointer().data::<PointerUserData>().unwrap();
+        let serial = match pointer_data.pdata.latest_enter_serial() {
+            Some(serial) => serial,
+            None => return,
+        };
 
         match cursor {
             Some(cursor) => {
-                if let Err(err) = pointer.set_cursor(
+                if let Err(err) = state.cursor_themes.borrow_mut().set_cursor(
                     &conn.connection,
-                    match cursor {
-                        MouseCursor::Arrow => CursorIcon::Default,
-                        MouseCursor::Hand => CursorIcon::Pointer,
-                        MouseCursor::SizeUpDown => CursorIcon::NsResize,
-                        MouseCursor::SizeLeftRight => CursorIcon::EwResize,
-                        MouseCursor::Text => CursorIcon::Text,
-                    },
+                    pointer.pointer(),
+                    serial,
+                    pointer.surface(),
+                    cursor,
+                    cursor_scale(self.get_dpi_factor()),
                 ) {
-                    log::error!("set_cursor: {}", err);
+                    log::error!("set_cursor: {:#}", err);
                 }
             }
             None => {
-                if let Err(err) = pointer.hide_cursor() {
-                    log::error!("hide_cursor: {}", err)
-                }
+                // A null surface hides the pointer
+                pointer.pointer().set_cursor(serial, None, 0, 0);
             }
         }
     }
 
-    fn invalidate(&mut self) {
-        if self.frame_callback.is_some() {
-            self.invalidated = true;
-            return;
-        }
-        self.do_paint().unwrap();
-    }
+    /// Copy an image that the application rendered with the CPU into
+    /// a buffer from our wl_shm pool and present it. The pool grows to
+    /// suit as the window is resized.
+    fn present_software(&mut self, image: Image) -> anyhow::Result<()> {
+        ensure!(self.window.is_some(), "window is closed");
+        ensure!(
+            self.gl_state.is_none(),
+            "present_software can't be used once OpenGL is enabled"
+        );
+        let (width, height) = image.image_dimensions();
+        ensure!(width > 0 && height > 0, "image is empty");
+
+        let conn = WaylandConnection::get().unwrap().wayland();
+        let state = conn.wayland_state.borrow();
+        let (buffer, canvas) = state.mem_pool.borrow_mut().create_buffer(
+            width as i32,
+            height as i32,
+            (width * 4) as i32,
+            wayland_client::protocol::wl_shm::Format::Argb8888,
+        )?;
+        // `Image` is rgba32, whereas Argb8888 is stored little endian
+        for (dest, pixel) in canvas.chunks_exact_mut(4).zip(image.pixels()) {
+            let [r, g, b, a] = pixel.to_ne_bytes();
+            dest.copy_from_slice(&[b, g, r, a]);
+        }
+
+        // The buffer scale or viewport set up when we were configured
+        // presents this at our logical size
+        let surface = self.surface();
+        surface.attach(Some(buffer.wl_buffer()), 0, 0);
+        surface.damage_buffer(0, 0, width as i32, height as i32);
+        surface.commit();
+        self.software_buffer.replace(buffer);
+        Ok(())
+    }
+
+    fn set_custom_cursor(&mut self, image: Image, hotspot: Point) -> anyhow::Result<()> {
+        let conn = WaylandConnection::get().unwrap().wayland();
+        let state = conn.wayland_state.borrow();
+        let qh = conn.event_queue.borrow().handle();
+
+        let (width, height) = image.image_dimensions();
+        ensure!(width > 0 && height > 0, "cursor image is empty");
+
+        // On a HiDPI output, present the image at its native resolution
+        // rather than having the compositor scale it up, but only if it
+        // divides evenly into the buffer scale as the protocol requires
+        let factor = self.get_dpi_factor().round().max(1.) as usize;
+        let scale = if width % factor == 0 && height % factor == 0 {
+            factor
+        } else {
+            1
+        };
+
+        let (buffer, canvas) = state.mem_pool.borrow_mut().create_buffer(
+            width as i32,
+            height as i32,
+            (width * 4) as i32,
+            wayland_client::protocol::wl_shm::Format::Argb8888,
+        )?;
+        // `Image` is rgba32, whereas Argb8888 is stored little endian
+        for (dest, pixel) in canvas.chunks_exact_mut(4).zip(image.pixels()) {
+            let [r, g, b, a] = pixel.to_ne_bytes();
+            dest.copy_from_slice(&[b, g, r, a]);
+        }
+
+        let surface = state.compositor.create_surface(&qh);
+        surface.set_buffer_scale(scale as i32);
+        surface.attach(Some(buffer.wl_buffer()), 0, 0);
+        surface.damage_buffer(0, 0, width as i32, height as i32);
+        surface.commit();
+
+        let hotspot = (
+            hotspot.x as i32 / scale as i32,
+            hotspot.y as i32 / scale as i32,
+        );
+        drop(state);
+
+        self.release_custom_cursor();
+        self.custom_cursor.replace(CustomCursor {
+            surface,
+            _buffer: buffer,
+            hotspot,
+        });
+        self.apply_custom_cursor();
+        Ok(())
+    }
+
+    /// Show the custom cursor, if any, provided that the pointer is
+    /// currently over this window
+    fn apply_custom_cursor(&self) {
+        let custom = match &self.custom_cursor {
+            Some(custom) => custom,
+            None => return,
+        };
+        if !PendingMouse::in_window(&self.pending_mouse) {
+            return;
+        }
+        let conn = WaylandConnection::get().unwrap().wayland();
+        let state = conn.wayland_state.borrow();
+        let pointer = match state.pointers.active() {
+            Some(pointer) => pointer.pointer(),
+            None => return,
+        };
+        let pointer_data = pointer.data::<PointerUserData>().unwrap();
+        if let Some(serial) = pointer_data.pdata.latest_enter_serial() {
+            pointer.set_cursor(
+                serial,
+                Some(&custom.surface),
+                custom.hotspot.0,
+                custom.hotspot.1,
+            );
+        }
+    }
+
+    fn release_custom_cursor(&mut self) {
+        if let Some(custom) = self.custom_cursor.take() {
+            custom.surface.destroy();
+        }
+    }
+
+    fn invalidate(&mut self) {
+        if !self.painted {
+            // Get the first frame up without waiting
+            self.do_paint().unwrap();
+            return;
+        }
+
+        // Rather than painting straight away, which for a burst of
+        // invalidations (eg: from moving the mouse) would render
+        // faster than the display can present, wait until the
+        // compositor is ready for the next frame.
+        // next_frame_is_ready will paint then.
+        self.invalidated = true;
+        self.request_frame();
+    }
+
+    fn invalidate_rect(&mut self, rect: Rect) {
+        if !self.painted {
+            self.do_paint().unwrap();
+            return;
+        }
+
+        // Our buffer is sized in pixels, so there's no need to scale
+        // the rect; just clip it to the surface
+        let bounds = Rect::new(
+            Point::zero(),
+            Size::new(
+                self.dimensions.pixel_width as isize,
+                self.dimensions.pixel_height as isize,
+            ),
+        );
+        if let Some(rect) = rect.intersection(&bounds) {
+            self.damage.push(rect);
+            self.request_frame();
+        }
+    }
+
+    fn set_opaque_region(&mut self, region: Option<Rect>) {
+        if self.opaque_region == region {
+            return;
+        }
+        self.opaque_region = region;
+        self.apply_opaque_region();
+        // The region takes effect with the next commit
+        self.invalidate();
+    }
+
+    fn apply_opaque_region(&mut self) {
+        if self.window.is_none() {
+            return;
+        }
+        let Some(rect) = self.opaque_region else {
+            self.surface().set_opaque_region(None);
+            return;
+        };
+
+        let conn = WaylandConnection::get().unwrap().wayland();
+        let state = conn.wayland_state.borrow();
+        let region = match Region::new(&state.compositor) {
+            Ok(region) => region,
+            Err(err) => {
+                log::error!("Failed to create opaque region: {err:#}");
+                return;
+            }
+        };
+        // Unlike pixels_to_surface, round inwards, as claiming that
+        // a partially translucent pixel is opaque would be wrong
+        let factor = self.get_dpi_factor();
+        let left = (rect.min_x() as f64 / factor).ceil() as i32;
+        let top = (rect.min_y() as f64 / factor).ceil() as i32;
+        let right = (rect.max_x() as f64 / factor).floor() as i32;
+        let bottom = (rect.max_y() as f64 / factor).floor() as i32;
+        if right > left && bottom > top {
+            region.add(left, top, right - left, bottom - top);
+        }
+        self.surface().set_opaque_region(Some(region.wl_region()));
+    }
+
+    fn focus(&mut self, mut outcome: Promise<FocusOutcome>, serial: Option<InputSerial>) {
+        if self.has_focus == Some(true) {
+            outcome.ok(FocusOutcome::Focused);
+            return;
+        }
+        if self.window.is_none() {
+            outcome.err(anyhow!("window is closed"));
+            return;
+        }
+
+        let conn = WaylandConnection::get().unwrap().wayland();
+        let qh = conn.event_queue.borrow().handle();
+        let state = conn.wayland_state.borrow();
+        let Some(activation) = state.activation.as_ref() else {
+            log::debug!("compositor doesn't support xdg_activation_v1");
+            outcome.ok(FocusOutcome::Deferred);
+            return;
+        };
+
+        // The compositor wants to see that the request was prompted by
+        // the user, so we tie it to the triggering input event, or else
+        // the most recent one, along with the seat that produced it.
+        // The event was received by the window that last had the
+        // keyboard focus
+        let window_id = SurfaceUserData::from_wl(self.surface()).window_id;
+        let input_surface = match state.keyboard_window_id {
+            Some(id) if id == window_id => Some(self.surface().clone()),
+            Some(id) => state.window_by_id(id).and_then(|inner| {
+                let inner = inner.try_borrow().ok()?;
+                Some(inner.window.as_ref()?.wl_surface().clone())
+            }),
+            None => None,
+        };
+        let seat_serial = match serial.map(InputSerial::value) {
+            // The seat has since produced more input if we don't find
+            // it; that is most likely the seat that was used last
+            Some(serial) => state
+                .input_serials
+                .seat_for(serial)
+                .or_else(|| state.input_serials.latest().map(|(seat, _)| seat))
+                .map(|seat| (seat, serial)),
+            None => state.input_serials.latest(),
+        };
+        let input = match (seat_serial, &input_surface) {
+            (Some((seat, serial)), Some(surface)) => Some((seat, serial, surface)),
+            _ => None,
+        };
+        activation.activate(self.surface(), input, &qh);
+        drop(state);
+
+        // The compositor doesn't tell us if it declines to activate us;
+        // we only find out by not receiving the keyboard focus
+        let id = self.focus_requests.push(outcome);
+        promise::spawn::spawn(async move {
+            Timer::after(FOCUS_TIMEOUT).await;
+            WaylandConnection::with_window_inner(window_id, move |inner| {
+                if inner.focus_requests.timed_out(id) {
+                    inner.request_attention();
+                }
+                Ok(())
+            });
+        })
+        .detach();
+    }
+
+    /// Activating without a token tied to user input results in the
+    /// compositor flagging the window as wanting attention
+    fn request_attention(&mut self) {
+        if self.window.is_none() {
+            return;
+        }
+        let conn = WaylandConnection::get().unwrap().wayland();
+        let qh = conn.event_queue.borrow().handle();
+        if let Some(activation) = conn.wayland_state.borrow().activation.as_ref() {
+            activation.activate(self.surface(), None, &qh);
+        }
+    }
+
+    fn set_idle_inhibited(&mut self, inhibit: bool) {
+        if self.idle_inhibit == inhibit {
+            return;
+        }
+        self.idle_inhibit = inhibit;
+        self.update_idle_inhibitor();
+    }
+
+    /// Create or destroy the inhibitor so that we only hold one while
+    /// it has been requested and the window is shown
+    fn update_idle_inhibitor(&mut self) {
+        let wanted = self.idle_inhibit && !self.hidden && self.window.is_some();
+        if wanted == self.idle_inhibitor.is_some() {
+            return;
+        }
+        if let Some(inhibitor) = self.idle_inhibitor.take() {
+            inhibitor.destroy();
+            return;
+        }
+
+        let conn = WaylandConnection::get().unwrap().wayland();
+        let qh = conn.event_queue.borrow().handle();
+        match conn.wayland_state.borrow().idle_inhibit.as_ref() {
+            Some(idle_inhibit) => {
+                self.idle_inhibitor
+                    .replace(idle_inhibit.inhibit(self.surface(), &qh));
+            }
+            None => log::debug!("compositor doesn't support zwp_idle_inhibit_manager_v1"),
+        }
+    }
+
+    fn set_shortcut_inhibition(&mut self, inhibit: bool, mut outcome: Promise<()>) {
+        if !inhibit {
+            self.shortcut_inhibit = false;
+            self.update_shortcut_inhibitor();
+            for mut pending in self.shortcut_inhibit_requests.drain(..) {
+                pending.err(anyhow!("shortcut inhibition was cancelled"));
+            }
+            outcome.ok(());
+            return;
+        }
+        if self.window.is_none() {
+            outcome.err(anyhow!("window is closed"));
+            return;
+        }
+        let conn = WaylandConnection::get().unwrap().wayland();
+        if conn.wayland_state.borrow().shortcuts_inhibit.is_none() {
+            outcome.err(anyhow!(
+                "compositor doesn't support zwp_keyboard_shortcuts_inhibit_manager_v1"
+            ));
+            return;
+        }
+        self.shortcut_inhibit = true;
+        if self.shortcut_inhibit_active {
+            outcome.ok(());
+            return;
+        }
+        // Resolved when the compositor says whether it will honor the
+        // inhibitor, which only happens while we have the keyboard focus
+        self.shortcut_inhibit_requests.push(outcome);
+        self.update_shortcut_inhibitor();
+    }
+
+    /// Create or destroy the inhibitor so that we only hold one while
+    /// it has been requested and we have the keyboard focus.
+    /// Creating it borrows the WaylandState, so this must not be called
+    /// from within a dispatch when it might need to do that.
+    fn update_shortcut_inhibitor(&mut self) {
+        let wanted = self.shortcut_inhibit && self.has_focus == Some(true) && self.window.is_some();
+        if wanted == self.shortcut_inhibitor.is_some() {
+            return;
+        }
+        if let Some(inhibitor) = self.shortcut_inhibitor.take() {
+            inhibitor.destroy();
+            self.shortcut_inhibit_active = false;
+            return;
+        }
+
+        let conn = WaylandConnection::get().unwrap().wayland();
+        let qh = conn.event_queue.borrow().handle();
+        let state = conn.wayland_state.borrow();
+        let Some(shortcuts_inhibit) = state.shortcuts_inhibit.as_ref() else {
+            log::debug!("compositor doesn't support zwp_keyboard_shortcuts_inhibit_manager_v1");
+            return;
+        };
+        // Inhibition applies to the keyboard of a seat, so it must be
+        // the seat that our keyboard belongs to
+        let Some(seat) = state
+            .keyboard
+            .as_ref()
+            .and_then(|keyboard| keyboard.data::<KeyboardData>())
+            .map(|data| data.seat.clone())
+        else {
+            log::debug!("no keyboard to inhibit shortcuts for");
+            return;
+        };
+        let window_id = SurfaceUserData::from_wl(self.surface()).window_id;
+        self.shortcut_inhibitor.replace(shortcuts_inhibit.inhibit(
+            self.surface(),
+            &seat,
+            window_id,
+            &qh,
+        ));
+    }
+
+    pub(super) fn shortcut_inhibitor_changed(
+        &mut self,
+        inhibitor: &ZwpKeyboardShortcutsInhibitorV1,
+        active: bool,
+    ) {
+        if self.shortcut_inhibitor.as_ref().map(|i| i.id()) != Some(inhibitor.id()) {
+            // A response for an inhibitor that we've since destroyed
+            return;
+        }
+        self.shortcut_inhibit_active = active;
+        for mut outcome in self.shortcut_inhibit_requests.drain(..) {
+            if active {
+                outcome.ok(());
+            } else {
+                outcome.err(anyhow!(
+                    "the compositor declined to inhibit keyboard shortcuts"
+                ));
+            }
+        }
+    }
+
+    fn set_animation(&mut self, hint: Option<AnimationHint>) {
+        self.animation.set(hint);
+        if self.animation.is_active() {
+            // Get the frame callbacks going; next_frame_is_ready keeps
+            // them going for as long as we're animating.
+            // When animation is turned off, the pending callback (if
+            // any) simply finds nothing to paint.
+            self.invalidate();
+        }
+    }
+
+    /// Report a change in visibility and, upon becoming visible, catch
+    /// up with any painting that we skipped while we weren't
+    fn update_visibility(&mut self) {
+        let visible = !self.hidden && !self.suspended;
+        if visible == self.visible {
+            return;
+        }
+        self.visible = visible;
+        self.events
+            .dispatch(WindowEvent::VisibilityChanged(visible));
+        if !visible || self.awaiting_remap || self.frame_callback.is_some() {
+            // Painting resumes once the compositor calls us back
+            return;
+        }
+        if self.invalidated {
+            self.do_paint().ok();
+        } else if !self.damage.is_empty() {
+            let damage = std::mem::take(&mut self.damage);
+            self.paint(Some(damage)).ok();
+        }
+        if self.animation.is_active() {
+            self.request_frame();
+        }
+    }
+
+    /// Arrange for next_frame_is_ready to be called when the
+    /// compositor is ready for us to paint
+    fn request_frame(&mut self) {
+        if self.frame_callback.is_some()
+            || self.hidden
+            || self.suspended
+            || self.awaiting_remap
+            || self.window.is_none()
+        {
+            return;
+        }
+        let conn = WaylandConnection::get().unwrap().wayland();
+        let qh = conn.event_queue.borrow().handle();
+        let callback = self.surface().frame(&qh, self.surface().clone());
+        self.frame_callback.replace(callback);
+        // The frame callback is only triggered by a commit
+        self.surface().commit();
+    }
 
     fn set_text_cursor_position(&mut self, rect: Rect) {
         let conn = WaylandConnection::get().unwrap().wayland();
@@ -974,16 +2603,15 @@ impl WaylandWindowInner {
                 if self.text_cursor.map(|prior| prior != rect).unwrap_or(true) {
                     self.text_cursor.replace(rect);
 
-                    let surface_udata = SurfaceUserData::from_wl(&surface);
-                    let factor = surface_udata.surface_data().scale_factor();
+                    let factor = self.get_dpi_factor();
 
                     if let Some(text_input) = &state.text_input {
                         if let Some(input) = text_input.get_text_input_for_surface(&surface) {
                             input.set_cursor_rectangle(
-                                rect.min_x() as i32 / factor,
-                                rect.min_y() as i32 / factor,
-                                rect.width() as i32 / factor,
-                                rect.height() as i32 / factor,
+                                (rect.min_x() as f64 / factor) as i32,
+                                (rect.min_y() as f64 / factor) as i32,
+                                (rect.width() as f64 / factor) as i32,
+                                (rect.height() as f64 / factor) as i32,
                             );
                             input.commit();
                         }
@@ -999,19 +2627,263 @@ impl WaylandWindowInner {
                 return;
             }
         }
-        if let Some(window) = self.window.as_ref() {
+        if let Some(window) = self.toplevel() {
             window.set_title(title.clone());
         }
+        self.window_frame.set_title(title.clone());
         self.refresh_frame();
         self.title = Some(title);
     }
 
-    fn set_resize_increments(&mut self, incr: ResizeIncrement) -> anyhow::Result<()> {
-        self.resize_increments.replace(incr);
-        Ok(())
+    fn maximize(&mut self) {
+        if let Some(window) = self.toplevel() {
+            window.set_maximized();
+        }
+    }
+
+    fn restore(&mut self) {
+        if let Some(window) = self.toplevel() {
+            if self.window_state.contains(WindowState::FULL_SCREEN) {
+                window.unset_fullscreen();
+            }
+            window.unset_maximized();
+        }
+    }
+
+    fn window_decorations(&self) -> WindowDecorations {
+        self.decorations.unwrap_or(self.config.window_decorations)
+    }
+
+    fn frame_config(&self) -> ConceptConfig {
+        ConceptConfig {
+            font_config: self.font_config.clone(),
+            config: self.config.clone(),
+            decorations: self.window_decorations(),
+        }
+    }
+
+    fn config_did_change(&mut self, config: ConfigHandle) {
+        let decorations_changed = self.decorations.is_none()
+            && config.window_decorations != self.config.window_decorations;
+        let dpi_changed =
+            config.dpi != self.config.dpi || base_dpi(&config) != base_dpi(&self.config);
+        // The surface size is computed from the current base dpi,
+        // so do this before we switch to the new config
+        let surface_width = self.pixels_to_surface(self.dimensions.pixel_width as i32);
+        let surface_height = self.pixels_to_surface(self.dimensions.pixel_height as i32);
+        self.config = config;
+        if dpi_changed && self.window.is_some() {
+            // Synthesize a configure at the current size, so that
+            // the window re-lays-out at the new dpi right away
+            {
+                let mut pending = self.pending_event.lock().unwrap();
+                if pending.configure.is_none() {
+                    pending.configure.replace(
+                        self.add_frame_borders(surface_width as u32, surface_height as u32),
+                    );
+                }
+            }
+            self.dispatch_pending_event();
+        }
+        if decorations_changed {
+            if let Some(window) = self.toplevel() {
+                window.request_decoration_mode(requested_decoration_mode(
+                    self.config.window_decorations,
+                ));
+            }
+        }
+        // Restyle the frame to match the new configuration
+        self.window_frame.set_frame_config(self.frame_config());
+        self.refresh_frame();
+    }
+
+    fn set_resize_increments(&mut self, incr: ResizeIncrement) -> anyhow::Result<()> {
+        if self.resize_increments == Some(incr) {
+            return Ok(());
+        }
+        self.resize_increments.replace(incr);
+
+        // Eg: the font size changed; snap the current size to the new
+        // increments, rather than waiting for the next configure
+        if self.window.is_some()
+            && self.window_state.can_resize()
+            && !self.window_state.intersects(WindowState::TILED)
+            && !self.interactive_resize
+        {
+            let (width, height) = snap_to_increments(
+                &incr,
+                self.dimensions.pixel_width,
+                self.dimensions.pixel_height,
+            );
+            if (width, height) != (self.dimensions.pixel_width, self.dimensions.pixel_height) {
+                self.resize_surface(width, height);
+            }
+        }
+        Ok(())
+    }
+
+    fn output_entered(&mut self, output: WlOutput) {
+        if self.outputs.contains(&output) {
+            return;
+        }
+        self.outputs.push(output);
+        if self.outputs.len() == 1 {
+            self.notify_screen_changed();
+        }
+    }
+
+    fn output_left(&mut self, output: &WlOutput) {
+        let Some(idx) = self.outputs.iter().position(|o| o == output) else {
+            return;
+        };
+        self.outputs.remove(idx);
+        if idx == 0 && !self.outputs.is_empty() {
+            self.notify_screen_changed();
+        }
+    }
+
+    fn current_screen(&self) -> Option<ScreenInfo> {
+        let output = self.outputs.first()?;
+        let conn = WaylandConnection::get().unwrap().wayland();
+        let state = conn.wayland_state.borrow();
+        screen_info_for_output(&state.output, output, &self.config)
+    }
+
+    /// Changes of scale are picked up by dispatch_pending_event
+    /// via the dpi; this just lets the application know where it is
+    fn notify_screen_changed(&mut self) {
+        if let Some(screen) = self.current_screen() {
+            self.events.dispatch(WindowEvent::ScreenChanged(screen));
+        }
+    }
+
+    fn set_min_inner_size(&mut self, width: usize, height: usize) {
+        self.size_constraints.set_min(width, height);
+        self.apply_size_constraints();
+    }
+
+    fn set_max_inner_size(&mut self, size: Option<(usize, usize)>) {
+        self.size_constraints.set_max(size);
+        self.apply_size_constraints();
+    }
+
+    /// The constraints are specified in pixels, but xdg_toplevel wants
+    /// them in surface coordinates, including our frame when we're
+    /// drawing client side decorations
+    fn apply_size_constraints(&mut self) {
+        let Some(window) = self.toplevel() else {
+            return;
+        };
+        let to_surface = |(width, height): (usize, usize)| {
+            self.add_frame_borders(
+                self.pixels_to_surface(width as i32) as u32,
+                self.pixels_to_surface(height as i32) as u32,
+            )
+        };
+        let min = self
+            .size_constraints
+            .min
+            .map(to_surface)
+            .map(|(w, h)| (w.max(MIN_SURFACE_SIZE.0), h.max(MIN_SURFACE_SIZE.1)))
+            .unwrap_or(MIN_SURFACE_SIZE);
+        window.set_min_size(Some(min));
+        window.set_max_size(self.size_constraints.max.map(to_surface));
+        // The constraints take effect with the next commit
+        self.invalidate();
+    }
+
+    /// The inner size in pixels that corresponds to configure_bounds
+    fn bounds_in_pixels(&self) -> Option<(usize, usize)> {
+        let (w, h) = self.configure_bounds?;
+        let (w, h) = self.subtract_frame_borders(w, h);
+        Some((
+            self.surface_to_pixels(w as i32).max(1) as usize,
+            self.surface_to_pixels(h as i32).max(1) as usize,
+        ))
+    }
+
+    /// Limit an inner size in pixels to what the compositor recommends
+    fn clamp_to_bounds(&self, width: usize, height: usize) -> (usize, usize) {
+        match self.bounds_in_pixels() {
+            Some((max_width, max_height)) => (width.min(max_width), height.min(max_height)),
+            None => (width, height),
+        }
+    }
+
+    fn set_inner_size(
+        &mut self,
+        width: usize,
+        height: usize,
+        constrained: ConstrainedResize,
+        mut prom: Promise<Dimensions>,
+    ) {
+        // Apply anything that the compositor has sent, so that we judge
+        // the request against the current size and state
+        self.dispatch_pending_event();
+        let (width, height) = self.clamp_to_bounds(width, height);
+
+        match inner_size_action(
+            (width, height),
+            (self.dimensions.pixel_width, self.dimensions.pixel_height),
+            self.full_screen_restore.constrained(),
+            constrained,
+        ) {
+            InnerSizeAction::Reject => {
+                self.events.dispatch(WindowEvent::SetInnerSizeCompleted);
+                prom.err(
+                    ResizeRejected {
+                        state: self.window_state,
+                    }
+                    .into(),
+                );
+                return;
+            }
+            InnerSizeAction::AlreadySized => {
+                // Resizing to the current size would only lay out the frame
+                // and repaint again, and there is nothing to wait for
+                log::trace!("set_inner_size: already {width}x{height}");
+                self.events.dispatch(WindowEvent::SetInnerSizeCompleted);
+                prom.ok(self.dimensions);
+                return;
+            }
+            InnerSizeAction::Defer => {
+                // The size is applied once the window returns
+                // to its normal state
+                self.full_screen_restore.defer_resize(width, height);
+            }
+            InnerSizeAction::Resize => self.resize_surface(width, height),
+        }
+        self.events.dispatch(WindowEvent::SetInnerSizeCompleted);
+
+        // The synthetic configure has been applied, but the compositor
+        // may yet respond with a size of its own, so give it a moment
+        self.inner_size_requests.push(prom);
+        self.inner_size_generation += 1;
+        let generation = self.inner_size_generation;
+        let window_id = SurfaceUserData::from_wl(self.surface()).window_id;
+        promise::spawn::spawn(async move {
+            Timer::after(INNER_SIZE_SETTLE_TIME).await;
+            WaylandConnection::with_window_inner(window_id, move |inner| {
+                // A later request has its own timer
+                if inner.inner_size_generation == generation {
+                    inner.resolve_inner_size_requests();
+                }
+                Ok(())
+            });
+        })
+        .detach();
+    }
+
+    fn resolve_inner_size_requests(&mut self) {
+        for mut prom in self.inner_size_requests.drain(..) {
+            prom.ok(self.dimensions);
+        }
     }
 
-    fn set_inner_size(&mut self, width: usize, height: usize) {
+    /// Resize to the specified inner size in pixels. Unlike set_inner_size,
+    /// this doesn't report completion, so is suitable for resizes that we
+    /// initiate ourselves rather than at the request of the application.
+    fn resize_surface(&mut self, width: usize, height: usize) {
         let pixel_width = width as i32;
         let pixel_height = height as i32;
         let surface_width = self.pixels_to_surface(pixel_width) as u32;
@@ -1020,33 +2892,46 @@ impl WaylandWindowInner {
         // so we're going to fake one up, otherwise the window
         // contents don't reflect the real size until eg:
         // the focus is changed.
+        let configure = self.add_frame_borders(surface_width, surface_height);
         self.pending_event
             .lock()
             .unwrap()
             .configure
-            .replace((surface_width, surface_height));
+            .replace(configure);
         // apply the synthetic configure event to the inner surfaces
         self.dispatch_pending_event();
-
-        self.events.dispatch(WindowEvent::SetInnerSizeCompleted);
     }
 
     fn do_paint(&mut self) -> anyhow::Result<()> {
+        self.paint(None)
+    }
+
+    /// Paint the window. If `damage` is specified, only those regions
+    /// have changed since the last paint; otherwise everything has.
+    fn paint(&mut self, damage: Option<Vec<Rect>>) -> anyhow::Result<()> {
         if self.window.is_none() {
             // We're likely in the middle of closing/destroying
             // the window; we've nothing to do here.
             return Ok(());
         }
 
-        if self.frame_callback.is_some() {
-            // Painting now won't be productive, so skip it but
-            // remember that we need to be painted so that when
-            // the compositor is ready for us, we can paint then.
-            self.invalidated = true;
+        if self.hidden || self.suspended || self.awaiting_remap || self.frame_callback.is_some() {
+            // Either we'll paint when we're shown again, or painting
+            // now won't be productive, so skip it but remember that
+            // we need to be painted so that when the compositor is
+            // ready for us, we can paint then.
+            match damage {
+                Some(damage) => self.damage.extend(damage),
+                None => self.invalidated = true,
+            }
             return Ok(());
         }
 
         self.invalidated = false;
+        self.painted = true;
+        if damage.is_none() {
+            self.damage.clear();
+        }
 
         // Ask the compositor to wake us up when its time to paint the next frame,
         // note that this only happens _after_ the next commit
@@ -1064,11 +2949,54 @@ impl WaylandWindowInner {
         // we will get woken at the appropriate time.
         // <https://github.com/wez/wezterm/issues/3468>
         // <https://github.com/wez/wezterm/issues/3126>
+        if let Some(swap_damage) = self.swap_damage.as_ref() {
+            *swap_damage.borrow_mut() = damage.clone();
+        }
+
+        // Likewise, presentation feedback applies to the next commit
+        let requested = Instant::now();
+        let have_feedback = match conn.wayland_state.borrow().presentation.as_ref() {
+            Some(presentation) => {
+                let window_id = SurfaceUserData::from_wl(self.surface()).window_id;
+                presentation.request_feedback(self.surface(), window_id, requested, &qh);
+                true
+            }
+            None => false,
+        };
+
+        if let Some(elapsed) = self.animation.tick(requested) {
+            self.events.dispatch(WindowEvent::AnimationFrame(elapsed));
+        }
+        if let Some(damage) = damage {
+            self.events
+                .dispatch(WindowEvent::NeedRepaintRegions(damage));
+        }
         self.events.dispatch(WindowEvent::NeedRepaint);
 
+        if !have_feedback {
+            // The compositor won't tell us when the frame is shown;
+            // the application has submitted it by now, which is the
+            // closest that we can get
+            self.record_frame_timing(FrameTiming {
+                requested,
+                presented: Some(Instant::now()),
+                refresh: None,
+                flags: PresentationFlags::empty(),
+                estimated: true,
+            });
+        }
+
         Ok(())
     }
 
+    pub(super) fn record_frame_timing(&mut self, timing: FrameTiming) {
+        if self.frame_timings.len() >= FRAME_TIMING_HISTORY {
+            self.frame_timings.pop_front();
+        }
+        self.frame_timings.push_back(timing);
+        self.events.dispatch(WindowEvent::FramePresented(timing));
+    }
+
     fn surface(&self) -> &WlSurface {
         self.window
             .as_ref()
@@ -1076,25 +3004,79 @@ impl WaylandWindowInner {
             .wl_surface()
     }
 
+    /// Operations such as setting the title or maximizing only apply
+    /// to toplevels, not popups
+    fn toplevel(&self) -> Option<&XdgWindow> {
+        self.window.as_ref().and_then(XdgRole::toplevel)
+    }
+
     pub(crate) fn next_frame_is_ready(&mut self) {
         self.frame_callback.take();
+        if let Some(delay) = self.animation.delay_until_next_frame(Instant::now()) {
+            if delay.is_zero() {
+                self.invalidated = true;
+            } else if !self.invalidated && self.damage.is_empty() {
+                // It isn't time for the next animation frame yet;
+                // check again at the next vblank
+                self.request_frame();
+                return;
+            }
+        }
         if self.invalidated {
             self.do_paint().ok();
+        } else if !self.damage.is_empty() {
+            let damage = std::mem::take(&mut self.damage);
+            self.paint(Some(damage)).ok();
         }
     }
 
     pub(crate) fn emit_focus(&mut self, mapper: &mut KeyboardWithFallback, focused: bool) {
-        // Clear the modifiers when we change focus, otherwise weird
-        // things can happen.  For instance, if we lost focus because
-        // CTRL+SHIFT+N was pressed to spawn a new window, we'd be
-        // left stuck with CTRL+SHIFT held down and the window would
-        // be left in a broken state.
-
-        self.modifiers = Modifiers::NONE;
-        mapper.update_modifier_state(0, 0, 0, 0);
+        let mods = mapper.focus_changed(focused);
+        let leds = mapper.get_led_status();
+        let changed = (mods != self.modifiers) || (leds != self.leds);
+        self.modifiers = mods;
+        self.leds = leds;
         self.key_repeat.take();
-        self.events.dispatch(WindowEvent::FocusChanged(focused));
+        self.has_focus = Some(focused);
+        let reason = if focused && self.focus_requests.focused() {
+            FocusReason::Activation
+        } else {
+            FocusReason::Unknown
+        };
+        self.events
+            .dispatch(WindowEvent::FocusChanged(focused, reason));
+        if changed {
+            self.events
+                .dispatch(WindowEvent::AdviseModifiersLedStatus(mods, leds));
+        }
         self.text_cursor.take();
+
+        // The inhibitor only applies while we have the focus, so we let
+        // it go when we lose it and ask again once we get it back.
+        // We're called from within a dispatch, so creating it must wait.
+        if !focused {
+            self.update_shortcut_inhibitor();
+        } else if self.shortcut_inhibit {
+            let window_id = SurfaceUserData::from_wl(self.surface()).window_id;
+            WaylandConnection::with_window_inner(window_id, |inner| {
+                inner.update_shortcut_inhibitor();
+                Ok(())
+            });
+        }
+    }
+
+    /// Called after the keymap has been replaced.  Our modifier state and
+    /// any key being repeated were resolved using the old keymap, so
+    /// they may no longer be correct; eg: Ctrl could remain stuck down.
+    pub(crate) fn keymap_changed(&mut self) {
+        self.key_repeat.take();
+        if self.modifiers != Modifiers::NONE {
+            self.modifiers = Modifiers::NONE;
+            self.events.dispatch(WindowEvent::AdviseModifiersLedStatus(
+                self.modifiers,
+                self.leds,
+            ));
+        }
     }
 
     pub(crate) fn appearance_changed(&mut self, appearance: Appearance) {
@@ -1120,12 +3102,21 @@ impl WaylandWindowInner {
                 self.emit_focus(mapper, true);
             }
             WlKeyboardEvent::Leave { .. } => {
+                self.unlock_pointer();
                 self.emit_focus(mapper, false);
             }
-            WlKeyboardEvent::Key { key, state, .. } => {
+            WlKeyboardEvent::Key {
+                key,
+                state,
+                serial,
+                time,
+            } => {
                 if let Some(event) = mapper.process_wayland_key(
                     key,
                     state.into_result().unwrap() == KeyState::Pressed,
+                    time,
+                    Some(InputSerial::new(serial)),
+                    &mut self.raw_key_mode,
                     &mut self.events,
                 ) {
                     let rep = Arc::new(Mutex::new(KeyRepeatState {
@@ -1175,20 +3166,207 @@ impl WaylandWindowInner {
         }
     }
 
+    fn lock_pointer(&mut self, position_hint: Option<Point>) -> anyhow::Result<()> {
+        if let Some(PointerConstraint::Locked(_)) = &self.pointer_constraint {
+            return Ok(());
+        }
+        // The protocol doesn't allow a surface to have more than one
+        // constraint, so release any confinement first
+        self.unlock_pointer();
+
+        let conn = WaylandConnection::get().unwrap().wayland();
+        let qh = conn.event_queue.borrow().handle();
+        let state = conn.wayland_state.borrow();
+        let pointer = state
+            .pointers
+            .active()
+            .ok_or_else(|| anyhow!("no pointer"))?;
+
+        let locked = state.pointer_constraints.lock_pointer(
+            self.surface(),
+            pointer.pointer(),
+            None,
+            Lifetime::Oneshot,
+            &qh,
+        )?;
+        if let Some(hint) = position_hint {
+            // This is double-buffered state that will be applied
+            // when we next commit the surface
+            locked.set_cursor_position_hint(
+                self.pixels_to_surface(hint.x as i32) as f64,
+                self.pixels_to_surface(hint.y as i32) as f64,
+            );
+        }
+        self.pointer_constraint = Some(PointerConstraint::Locked(locked));
+        self.relative_pointer = state
+            .relative_pointer
+            .get_relative_pointer(pointer.pointer(), &qh)
+            .ok();
+        Ok(())
+    }
+
+    fn confine_pointer(&mut self, rect: Rect) -> anyhow::Result<()> {
+        let conn = WaylandConnection::get().unwrap().wayland();
+        let qh = conn.event_queue.borrow().handle();
+        let state = conn.wayland_state.borrow();
+
+        let region = Region::new(&state.compositor)?;
+        region.add(
+            self.pixels_to_surface(rect.origin.x as i32),
+            self.pixels_to_surface(rect.origin.y as i32),
+            self.pixels_to_surface(rect.size.width as i32),
+            self.pixels_to_surface(rect.size.height as i32),
+        );
+
+        match &mut self.pointer_constraint {
+            Some(PointerConstraint::Confined(_, current)) if *current == rect => return Ok(()),
+            Some(PointerConstraint::Confined(confined, current)) => {
+                // The region is double-buffered state that only takes
+                // effect when the surface is committed
+                confined.set_region(Some(region.wl_region()));
+                *current = rect;
+                self.surface().commit();
+                return Ok(());
+            }
+            _ => {}
+        }
+        self.unlock_pointer();
+
+        let pointer = state
+            .pointers
+            .active()
+            .ok_or_else(|| anyhow!("no pointer"))?;
+        let confined = state.pointer_constraints.confine_pointer(
+            self.surface(),
+            pointer.pointer(),
+            Some(region.wl_region()),
+            Lifetime::Oneshot,
+            &qh,
+        )?;
+        self.pointer_constraint = Some(PointerConstraint::Confined(confined, rect));
+        self.relative_pointer = state
+            .relative_pointer
+            .get_relative_pointer(pointer.pointer(), &qh)
+            .ok();
+        Ok(())
+    }
+
+    fn unlock_pointer(&mut self) {
+        if let Some(constraint) = self.pointer_constraint.take() {
+            constraint.destroy();
+        }
+        if let Some(relative_pointer) = self.relative_pointer.take() {
+            relative_pointer.destroy();
+        }
+    }
+
+    pub(super) fn pointer_constraint_ended(&mut self, constraint_id: &ObjectId) {
+        if self
+            .pointer_constraint
+            .as_ref()
+            .map(|c| c.id() == *constraint_id)
+            .unwrap_or(false)
+        {
+            self.unlock_pointer();
+        }
+    }
+
+    fn show_window_menu(
+        &mut self,
+        coords: Point,
+        serial: Option<InputSerial>,
+    ) -> anyhow::Result<()> {
+        let window = self
+            .window
+            .as_ref()
+            .ok_or_else(|| anyhow!("window is being destroyed"))?
+            .toplevel()
+            .ok_or_else(|| anyhow!("popups cannot be moved or resized"))?;
+        ensure!(
+            self.wm_capabilities.contains(WmCapabilities::WINDOW_MENU),
+            "the compositor doesn't provide a window menu"
+        );
+
+        let conn = WaylandConnection::get().unwrap().wayland();
+        let state = conn.wayland_state.borrow();
+        let pointer = state
+            .pointers
+            .active()
+            .ok_or_else(|| anyhow!("no pointer"))?;
+        let pointer_data = pointer.pointer().data::<PointerUserData>().unwrap();
+        let seat = pointer_data.pdata.seat();
+        // The compositor requires the serial of the triggering input event
+        let serial = serial
+            .map(InputSerial::value)
+            .unwrap_or_else(|| pointer_data.state.lock().unwrap().serial);
+
+        let x = self.pixels_to_surface(coords.x as i32);
+        let y = self.pixels_to_surface(coords.y as i32);
+        window.show_window_menu(seat, serial, (x, y));
+        Ok(())
+    }
+
+    /// Start an interactive move, or a resize if `edge` is specified,
+    /// in response to the button press that is currently held
+    fn start_interactive(
+        &mut self,
+        edge: Option<WindowResizeEdge>,
+        serial: Option<InputSerial>,
+    ) -> anyhow::Result<()> {
+        // The compositor will only honor the request if it is in
+        // response to a button press that is still held
+        ensure!(!self.mouse_buttons.is_empty(), "no mouse button is held");
+        let window = self
+            .window
+            .as_ref()
+            .ok_or_else(|| anyhow!("window is being destroyed"))?
+            .toplevel()
+            .ok_or_else(|| anyhow!("popups cannot be moved or resized"))?;
+
+        let conn = WaylandConnection::get().unwrap().wayland();
+        let state = conn.wayland_state.borrow();
+        let pointer = state
+            .pointers
+            .active()
+            .ok_or_else(|| anyhow!("no pointer"))?;
+        let pointer_data = pointer.pointer().data::<PointerUserData>().unwrap();
+        let seat = pointer_data.pdata.seat();
+        let serial = serial
+            .map(InputSerial::value)
+            .unwrap_or_else(|| pointer_data.state.lock().unwrap().serial);
+
+        match edge {
+            None => window.move_(seat, serial),
+            Some(edge) => {
+                let edge = match edge {
+                    WindowResizeEdge::Top => XdgResizeEdge::Top,
+                    WindowResizeEdge::Bottom => XdgResizeEdge::Bottom,
+                    WindowResizeEdge::Left => XdgResizeEdge::Left,
+                    WindowResizeEdge::Right => XdgResizeEdge::Right,
+                    WindowResizeEdge::TopLeft => XdgResizeEdge::TopLeft,
+                    WindowResizeEdge::TopRight => XdgResizeEdge::TopRight,
+                    WindowResizeEdge::BottomLeft => XdgResizeEdge::BottomLeft,
+                    WindowResizeEdge::BottomRight => XdgResizeEdge::BottomRight,
+                };
+                window.resize(seat, serial, edge)
+            }
+        }
+        Ok(())
+    }
+
     pub(super) fn frame_action(&mut self, pointer: &WlPointer, serial: u32, action: FrameAction) {
         let pointer_data = pointer.data::<PointerUserData>().unwrap();
         let seat = pointer_data.pdata.seat();
+        // Popups don't have decorations, so this is always a toplevel
+        let Some(window) = self.window.as_ref().and_then(XdgRole::toplevel) else {
+            return;
+        };
         match action {
-            FrameAction::Close => self.events.dispatch(WindowEvent::CloseRequested),
-            FrameAction::Minimize => self.window.as_ref().unwrap().set_minimized(),
-            FrameAction::Maximize => self.window.as_ref().unwrap().set_maximized(),
-            FrameAction::UnMaximize => self.window.as_ref().unwrap().unset_maximized(),
-            FrameAction::ShowMenu(x, y) => {
-                self.window
-                    .as_ref()
-                    .unwrap()
-                    .show_window_menu(seat, serial, (x, y))
-            }
+            FrameAction::Close => self.events.request_close(),
+            FrameAction::Minimize => window.set_minimized(),
+            FrameAction::Maximize => window.set_maximized(),
+            FrameAction::UnMaximize => window.unset_maximized(),
+            FrameAction::ShowMenu(x, y) => window.show_window_menu(seat, serial, (x, y)),
             FrameAction::Resize(edge) => {
                 let edge = match edge {
                     ResizeEdge::None => XdgResizeEdge::None,
@@ -1202,9 +3380,9 @@ impl WaylandWindowInner {
                     ResizeEdge::BottomRight => XdgResizeEdge::BottomRight,
                     _ => return, // Realistically, there probably won't be any new edges added.
                 };
-                self.window.as_ref().unwrap().resize(seat, serial, edge)
+                window.resize(seat, serial, edge)
             }
-            FrameAction::Move => self.window.as_ref().unwrap().move_(seat, serial),
+            FrameAction::Move => window.move_(seat, serial),
             _ => log::warn!("unhandled FrameAction: {:?}", action),
         }
     }
@@ -1215,13 +3393,27 @@ impl WaylandState {
         self.windows.borrow().get(&window_id).map(Rc::clone)
     }
 
+    /// Removes everything that refers to a window that is being closed
+    fn forget_window(&mut self, window_id: usize, surface_id: &ObjectId) {
+        forget_window(
+            window_id,
+            surface_id,
+            &mut self.windows.borrow_mut(),
+            &mut self.surface_to_pending,
+            &mut self.touch_to_window,
+            &mut self.keyboard_window_id,
+            &mut self.active_surface_id.borrow_mut(),
+        );
+    }
+
     fn handle_window_event(&self, window: &XdgWindow, event: WaylandWindowEvent) {
         let surface_data = SurfaceUserData::from_wl(window.wl_surface());
         let window_id = surface_data.window_id;
 
-        let window_inner = self
-            .window_by_id(window_id)
-            .expect("Inner Window should exist");
+        let Some(window_inner) = self.window_by_id(window_id) else {
+            log::trace!("ignoring event for window {window_id} which has been closed");
+            return;
+        };
 
         let is_frame_hidden = window_inner.borrow().window_frame.is_hidden();
         let p = window_inner.borrow().pending_event.clone();
@@ -1259,6 +3451,16 @@ impl WaylandState {
                 if configure.state.contains(SCTKWindowState::MAXIMIZED) {
                     state |= WindowState::MAXIMIZED;
                 }
+                for (tiled, flag) in [
+                    (SCTKWindowState::TILED_LEFT, WindowState::TILED_LEFT),
+                    (SCTKWindowState::TILED_RIGHT, WindowState::TILED_RIGHT),
+                    (SCTKWindowState::TILED_TOP, WindowState::TILED_TOP),
+                    (SCTKWindowState::TILED_BOTTOM, WindowState::TILED_BOTTOM),
+                ] {
+                    if configure.state.contains(tiled) {
+                        state |= flag;
+                    }
+                }
 
                 // For MAXIMIZED and FULL_SCREEN window configure contains Windowed size.
                 // Replacing it with Wayland suggested bounds.
@@ -1296,6 +3498,35 @@ impl WaylandState {
                     changed = true;
                 }
 
+                // Bounds of 0x0 mean that the compositor has no opinion
+                let bounds = configure.suggested_bounds.filter(|&(w, h)| w > 0 && h > 0);
+                pending_event.configure_bounds.replace(bounds);
+
+                let mut capabilities = WmCapabilities::empty();
+                for (cap, flag) in [
+                    (
+                        WindowManagerCapabilities::MAXIMIZE,
+                        WmCapabilities::MAXIMIZE,
+                    ),
+                    (
+                        WindowManagerCapabilities::MINIMIZE,
+                        WmCapabilities::MINIMIZE,
+                    ),
+                    (
+                        WindowManagerCapabilities::FULLSCREEN,
+                        WmCapabilities::FULL_SCREEN,
+                    ),
+                    (
+                        WindowManagerCapabilities::WINDOW_MENU,
+                        WmCapabilities::WINDOW_MENU,
+                    ),
+                ] {
+                    if configure.capabilities.contains(cap) {
+                        capabilities |= flag;
+                    }
+                }
+                pending_event.wm_capabilities.replace(capabilities);
+
                 pending_event.window_state.replace(state);
                 changed
             }
@@ -1351,18 +3582,37 @@ impl CompositorHandler for WaylandState {
         &mut self,
         _conn: &WConnection,
         _qh: &wayland_client::QueueHandle<Self>,
-        _surface: &wayland_client::protocol::wl_surface::WlSurface,
-        _output: &wayland_client::protocol::wl_output::WlOutput,
+        surface: &wayland_client::protocol::wl_surface::WlSurface,
+        output: &WlOutput,
     ) {
+        // Our decorations are drawn on subsurfaces, which don't matter here
+        let Some(surface_data) = SurfaceUserData::try_from_wl(surface) else {
+            return;
+        };
+        let window_id = surface_data.window_id;
+        let output = output.clone();
+        WaylandConnection::with_window_inner(window_id, move |inner| {
+            inner.output_entered(output);
+            Ok(())
+        });
     }
 
     fn surface_leave(
         &mut self,
         _conn: &WConnection,
         _qh: &wayland_client::QueueHandle<Self>,
-        _surface: &wayland_client::protocol::wl_surface::WlSurface,
-        _output: &wayland_client::protocol::wl_output::WlOutput,
+        surface: &wayland_client::protocol::wl_surface::WlSurface,
+        output: &WlOutput,
     ) {
+        let Some(surface_data) = SurfaceUserData::try_from_wl(surface) else {
+            return;
+        };
+        let window_id = surface_data.window_id;
+        let output = output.clone();
+        WaylandConnection::with_window_inner(window_id, move |inner| {
+            inner.output_left(&output);
+            Ok(())
+        });
     }
 }
 
@@ -1388,6 +3638,50 @@ impl WindowHandler for WaylandState {
     }
 }
 
+impl PopupHandler for WaylandState {
+    fn configure(
+        &mut self,
+        _conn: &WConnection,
+        _qh: &wayland_client::QueueHandle<Self>,
+        popup: &Popup,
+        config: PopupConfigure,
+    ) {
+        let window_id = SurfaceUserData::from_wl(popup.wl_surface()).window_id;
+        let Some(window_inner) = self.window_by_id(window_id) else {
+            return;
+        };
+        {
+            let p = window_inner.borrow().pending_event.clone();
+            let mut pending_event = p.lock().unwrap();
+            pending_event.had_configure_event = true;
+            if config.width > 0 && config.height > 0 {
+                pending_event
+                    .configure
+                    .replace((config.width as u32, config.height as u32));
+            }
+        }
+        WaylandConnection::with_window_inner(window_id, move |inner| {
+            inner.dispatch_pending_event();
+            Ok(())
+        });
+    }
+
+    fn done(
+        &mut self,
+        _conn: &WConnection,
+        _qh: &wayland_client::QueueHandle<Self>,
+        popup: &Popup,
+    ) {
+        // The compositor dismissed the popup, eg: because the user
+        // clicked outside of it. It cannot be shown again.
+        let window_id = SurfaceUserData::from_wl(popup.wl_surface()).window_id;
+        WaylandConnection::with_window_inner(window_id, move |inner| {
+            inner.close();
+            Ok(())
+        });
+    }
+}
+
 pub(super) struct SurfaceUserData {
     surface_data: SurfaceData,
     pub(super) window_id: usize,
@@ -1444,8 +3738,455 @@ impl HasWindowHandle for WaylandWindow {
             .window_by_id(self.0)
             .expect("window handle invalid!?");
 
-        let inner = handle.borrow();
+        // This is called synchronously, possibly from an event handler
+        // while the window is busy; report that rather than panicking
+        let inner = handle.try_borrow().map_err(|_| HandleError::Unavailable)?;
         let handle = inner.window_handle()?;
         unsafe { Ok(WindowHandle::borrow_raw(handle.as_raw())) }
     }
 }
+
+/// The dpi that corresponds to a compositor scale of 1
+pub(super) fn base_dpi(config: &ConfigHandle) -> f64 {
+    config.wayland_base_dpi.unwrap_or(crate::DEFAULT_DPI)
+}
+
+/// What set_inner_size does with a request
+#[derive(Debug, PartialEq, Eq)]
+enum InnerSizeAction {
+    Reject,
+    /// The window already has the requested size
+    AlreadySized,
+    /// The window is full screen or maximized; remember the size
+    /// until it returns to its normal state
+    Defer,
+    Resize,
+}
+
+/// `current` and `constrained` must reflect any configure that the
+/// compositor has sent, otherwise a request for the size that it has
+/// just picked would be made again
+fn inner_size_action(
+    requested: (usize, usize),
+    current: (usize, usize),
+    constrained: bool,
+    policy: ConstrainedResize,
+) -> InnerSizeAction {
+    if constrained {
+        match policy {
+            ConstrainedResize::Reject => InnerSizeAction::Reject,
+            ConstrainedResize::Defer => InnerSizeAction::Defer,
+        }
+    } else if requested == current {
+        InnerSizeAction::AlreadySized
+    } else {
+        InnerSizeAction::Resize
+    }
+}
+
+/// The guts of WaylandState::forget_window, separated out so that
+/// it can be tested without a compositor
+fn forget_window<S: Eq + Hash, W, P>(
+    window_id: usize,
+    surface_id: &S,
+    windows: &mut HashMap<usize, W>,
+    surface_to_pending: &mut HashMap<S, P>,
+    touch_to_window: &mut HashMap<i32, usize>,
+    keyboard_window_id: &mut Option<usize>,
+    active_surface_id: &mut Option<S>,
+) {
+    windows.remove(&window_id);
+    surface_to_pending.remove(surface_id);
+    touch_to_window.retain(|_, id| *id != window_id);
+    if *keyboard_window_id == Some(window_id) {
+        keyboard_window_id.take();
+    }
+    if active_surface_id.as_ref() == Some(surface_id) {
+        active_surface_id.take();
+    }
+}
+
+/// Converts the position of the pointer on our surface, in surface
+/// coordinates, into the window relative position in pixels
+fn pointer_position(x: f64, y: f64, factor: f64) -> Point {
+    Point::new((x * factor) as isize, (y * factor) as isize)
+}
+
+/// Apply the enter, motion and button events queued in `pending`,
+/// producing mouse events relative to the window. Returns where the
+/// pointer entered the window, if it did, along with the events.
+/// Separated from dispatch_pending_mouse so that it can be tested
+/// without a compositor.
+fn pending_pointer_events(
+    pending: &Arc<Mutex<PendingMouse>>,
+    factor: f64,
+    click_interval: u64,
+    modifiers: Modifiers,
+    last_mouse_coords: &mut Point,
+    mouse_buttons: &mut MouseButtons,
+    click_streak: &mut ClickStreak,
+) -> (Option<Point>, Vec<MouseEvent>) {
+    let time = PendingMouse::time(pending);
+    let mut events = vec![];
+
+    let entered = PendingMouse::entered(pending).map(|(x, y)| {
+        let coords = pointer_position(x, y, factor);
+        *last_mouse_coords = coords;
+        coords
+    });
+
+    if let Some((x, y)) = PendingMouse::coords(pending) {
+        let coords = pointer_position(x, y, factor);
+        *last_mouse_coords = coords;
+        click_streak.moved(coords);
+        events.push(MouseEvent {
+            time,
+            ..pointer_event(MouseEventKind::Move, coords, *mouse_buttons, modifiers)
+        });
+    }
+
+    while let Some((button, state, button_time, serial)) = PendingMouse::next_button(pending) {
+        let button_mask = button.button_mask();
+
+        if state == ButtonState::Pressed {
+            *mouse_buttons |= button_mask;
+        } else {
+            *mouse_buttons -= button_mask;
+        }
+
+        let (kind, streak) = match state {
+            ButtonState::Pressed => (
+                MouseEventKind::Press(button),
+                click_streak.press(button, *last_mouse_coords, button_time, click_interval),
+            ),
+            ButtonState::Released => (MouseEventKind::Release(button), 0),
+            _ => continue,
+        };
+        events.push(MouseEvent {
+            streak,
+            time: button_time,
+            serial: Some(InputSerial::new(serial)),
+            ..pointer_event(kind, *last_mouse_coords, *mouse_buttons, modifiers)
+        });
+    }
+
+    (entered, events)
+}
+
+/// Wayland doesn't tell clients where their surfaces are on the screen,
+/// so the pointer events that we produce never have screen coordinates;
+/// only the window relative `coords` are known.
+fn pointer_event(
+    kind: MouseEventKind,
+    coords: Point,
+    mouse_buttons: MouseButtons,
+    modifiers: Modifiers,
+) -> MouseEvent {
+    MouseEvent {
+        kind,
+        coords,
+        screen_coords: None,
+        mouse_buttons,
+        modifiers,
+        streak: 0,
+        time: 0,
+        serial: None,
+    }
+}
+
+/// Converts a length in surface coordinates to buffer pixels.
+/// Like ceil, except that values within rounding error of an integer
+/// are taken as that integer: eg: 50 * 1.1 is slightly more than 55, and
+/// we don't want that to grow the buffer by a pixel.
+fn surface_to_pixels(surface: i32, factor: f64) -> i32 {
+    round_up(surface as f64 * factor)
+}
+
+/// Converts a length in buffer pixels to surface coordinates.
+/// Take care to round up, otherwise we can lose a pixel
+/// and that can effectively lose the final row of the
+/// terminal
+fn pixels_to_surface(pixels: i32, factor: f64) -> i32 {
+    round_up(pixels as f64 / factor)
+}
+
+fn round_up(value: f64) -> i32 {
+    let rounded = value.round();
+    if (value - rounded).abs() < 1e-6 {
+        rounded as i32
+    } else {
+        value.ceil() as i32
+    }
+}
+
+/// Resizing the rendering surface is expensive, so while the window
+/// is being interactively resized we apply at most one resize per
+/// `debounce` interval. Returns how much longer a configure should be
+/// deferred, or None if it should be applied right away.
+/// The resize at the end of the drag is always applied immediately,
+/// so that the final size is exact.
+fn resize_deferral(
+    interactive_resize: bool,
+    last_resize: Option<Instant>,
+    now: Instant,
+    debounce: Duration,
+) -> Option<Duration> {
+    if !interactive_resize {
+        return None;
+    }
+    let elapsed = now.saturating_duration_since(last_resize?);
+    if elapsed >= debounce {
+        None
+    } else {
+        Some(debounce - elapsed)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use smithay_client_toolkit::seat::pointer::PointerEventKind;
+
+    #[test]
+    fn inner_size_after_pending_configure() {
+        use ConstrainedResize::{Defer, Reject};
+
+        // The compositor's configure, which arrived while the request
+        // was queued, already gave us the requested size
+        assert_eq!(
+            inner_size_action((800, 600), (800, 600), false, Reject),
+            InnerSizeAction::AlreadySized
+        );
+        assert_eq!(
+            inner_size_action((800, 600), (640, 480), false, Reject),
+            InnerSizeAction::Resize
+        );
+
+        // It maximized the window, which takes precedence even
+        // over a request for the size that we have
+        assert_eq!(
+            inner_size_action((1920, 1080), (1920, 1080), true, Defer),
+            InnerSizeAction::Defer
+        );
+        assert_eq!(
+            inner_size_action((800, 600), (1920, 1080), true, Reject),
+            InnerSizeAction::Reject
+        );
+    }
+
+    #[test]
+    fn closed_windows_are_forgotten() {
+        // Window ids and surface ids are assigned independently
+        let surface_of = |window_id: usize| window_id as u32 + 100;
+        let mut windows = HashMap::new();
+        let mut surface_to_pending = HashMap::new();
+        let mut touch_to_window = HashMap::new();
+        let mut keyboard_window_id = None;
+        let mut active_surface_id = None;
+
+        windows.insert(1, "main");
+        surface_to_pending.insert(surface_of(1), "main pointer");
+        for window_id in 2..1002 {
+            windows.insert(window_id, "popup");
+            surface_to_pending.insert(surface_of(window_id), "popup pointer");
+            touch_to_window.insert(window_id as i32, window_id);
+            touch_to_window.insert(-(window_id as i32), 1);
+            keyboard_window_id.replace(window_id);
+            active_surface_id.replace(surface_of(window_id));
+
+            forget_window(
+                window_id,
+                &surface_of(window_id),
+                &mut windows,
+                &mut surface_to_pending,
+                &mut touch_to_window,
+                &mut keyboard_window_id,
+                &mut active_surface_id,
+            );
+            assert_eq!(keyboard_window_id, None);
+            assert_eq!(active_surface_id, None);
+        }
+
+        // Only the window that remains open is still known about
+        assert_eq!(windows.into_iter().collect::<Vec<_>>(), vec![(1, "main")]);
+        assert_eq!(
+            surface_to_pending.into_iter().collect::<Vec<_>>(),
+            vec![(surface_of(1), "main pointer")]
+        );
+        assert!(touch_to_window.values().all(|&id| id == 1));
+        assert_eq!(touch_to_window.len(), 1000);
+
+        // Closing a window that doesn't have the focus leaves it alone
+        let mut windows = HashMap::from([(1, "main"), (2, "popup")]);
+        keyboard_window_id.replace(1);
+        active_surface_id.replace(surface_of(1));
+        forget_window(
+            2,
+            &surface_of(2),
+            &mut windows,
+            &mut HashMap::<u32, ()>::new(),
+            &mut HashMap::new(),
+            &mut keyboard_window_id,
+            &mut active_surface_id,
+        );
+        assert_eq!(keyboard_window_id, Some(1));
+        assert_eq!(active_surface_id, Some(surface_of(1)));
+        assert_eq!(windows.len(), 1);
+    }
+
+    #[test]
+    fn pixel_conversion_rounding() {
+        // Exact multiples are unchanged
+        assert_eq!(surface_to_pixels(100, 2.0), 200);
+        assert_eq!(pixels_to_surface(200, 2.0), 100);
+        assert_eq!(surface_to_pixels(100, 1.25), 125);
+        assert_eq!(pixels_to_surface(125, 1.25), 100);
+
+        // Floating point error must not grow the size by a pixel
+        assert_eq!(surface_to_pixels(50, 1.1), 55);
+        assert_eq!(pixels_to_surface(55, 1.1), 50);
+        assert_eq!(surface_to_pixels(120, 125. / 120.), 125);
+
+        // Partial pixels round up
+        assert_eq!(surface_to_pixels(101, 1.25), 127);
+        assert_eq!(pixels_to_surface(127, 1.25), 102);
+        assert_eq!(pixels_to_surface(425, 1.5), 284);
+    }
+
+    #[test]
+    fn pixel_conversion_keeps_last_row() {
+        // Going from pixels to surface and back must never lose
+        // any pixels, otherwise the final row of the terminal
+        // can be clipped
+        for factor in [1.0, 1.1, 1.25, 1.5, 1.75, 2.0, 125. / 120., 2.5] {
+            for pixels in 1..2000 {
+                let surface = pixels_to_surface(pixels, factor);
+                let round_trip = surface_to_pixels(surface, factor);
+                assert!(
+                    round_trip >= pixels,
+                    "{} -> {} -> {} at {}",
+                    pixels,
+                    surface,
+                    round_trip,
+                    factor
+                );
+                // and it shouldn't add more than a surface unit's worth
+                assert!((round_trip - pixels) as f64 <= factor.ceil());
+            }
+        }
+    }
+
+    #[test]
+    fn resize_deferral_during_drag() {
+        let debounce = Duration::from_millis(16);
+        let start = Instant::now();
+
+        // The first resize of a drag is applied straight away
+        assert_eq!(resize_deferral(true, None, start, debounce), None);
+        // Subsequent resizes wait out the remainder of the interval
+        assert_eq!(
+            resize_deferral(
+                true,
+                Some(start),
+                start + Duration::from_millis(6),
+                debounce
+            ),
+            Some(Duration::from_millis(10))
+        );
+        assert_eq!(
+            resize_deferral(true, Some(start), start + debounce, debounce),
+            None
+        );
+        // A debounce of 0 disables the deferral
+        assert_eq!(
+            resize_deferral(true, Some(start), start, Duration::ZERO),
+            None
+        );
+    }
+
+    #[test]
+    fn pointer_events_are_window_relative() {
+        // Entering at one scale and then moving after the scale changed
+        let entered = pointer_position(10.5, 20.25, 1.0);
+        assert_eq!(entered, Point::new(10, 20));
+        let moved = pointer_position(10.5, 20.25, 2.0);
+        assert_eq!(moved, Point::new(21, 40));
+
+        // Feed the compositor's surface relative events through the
+        // same path as dispatch_pending_mouse, at a scale of 2
+        let pending = PendingMouse::create(1, &CopyAndPaste::create());
+        let mut last_mouse_coords = Point::new(0, 0);
+        let mut mouse_buttons = MouseButtons::NONE;
+        let mut click_streak = ClickStreak::default();
+        let mut dispatch = |queued: &[(PointerEventKind, (f64, f64))]| {
+            let mut p = pending.lock().unwrap();
+            for (kind, position) in queued {
+                p.queue_kind(kind, *position);
+            }
+            drop(p);
+            pending_pointer_events(
+                &pending,
+                2.0,
+                500,
+                Modifiers::NONE,
+                &mut last_mouse_coords,
+                &mut mouse_buttons,
+                &mut click_streak,
+            )
+        };
+
+        let (entered, events) = dispatch(&[
+            (PointerEventKind::Enter { serial: 1 }, (10.5, 20.25)),
+            (PointerEventKind::Motion { time: 100 }, (10.5, 20.25)),
+            (
+                PointerEventKind::Press {
+                    button: 0x110,
+                    time: 110,
+                    serial: 2,
+                },
+                (10.5, 20.25),
+            ),
+        ]);
+        assert_eq!(entered, Some(Point::new(21, 40)));
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].kind, MouseEventKind::Move);
+        assert_eq!(events[0].coords, Point::new(21, 40));
+        assert_eq!(events[0].mouse_buttons, MouseButtons::NONE);
+        assert_eq!(events[1].kind, MouseEventKind::Press(MousePress::Left));
+        assert_eq!(events[1].coords, Point::new(21, 40));
+        assert_eq!(events[1].mouse_buttons, MouseButtons::LEFT);
+        assert_eq!(events[1].streak, 1);
+
+        // Dragging to the top left corner of the window and releasing
+        // reports the release there, rather than where it was pressed
+        let (entered, events) = dispatch(&[
+            (PointerEventKind::Motion { time: 120 }, (0.25, 0.75)),
+            (
+                PointerEventKind::Release {
+                    button: 0x110,
+                    time: 130,
+                    serial: 3,
+                },
+                (0.25, 0.75),
+            ),
+        ]);
+        assert_eq!(entered, None);
+        assert_eq!(events.len(), 2);
+        for event in &events {
+            assert_eq!(event.coords, Point::new(0, 1));
+            // We never learn where the window is
+            assert_eq!(event.screen_coords, None);
+        }
+        assert_eq!(events[1].kind, MouseEventKind::Release(MousePress::Left));
+        assert_eq!(events[1].mouse_buttons, MouseButtons::NONE);
+    }
+
+    #[test]
+    fn final_resize_is_not_deferred() {
+        let start = Instant::now();
+        assert_eq!(
+            resize_deferral(false, Some(start), start, Duration::from_millis(16)),
+            None
+        );
+    }
+}
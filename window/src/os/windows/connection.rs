@@ -1,6 +1,6 @@
 //! The connection to the GUI subsystem
 use super::{HWindow, WindowInner};
-use crate::connection::ConnectionOps;
+use crate::connection::{take_terminate_request, ConnectionOps};
 use crate::screen::{ScreenInfo, Screens};
 use crate::spawn::*;
 use crate::{Appearance, ScreenRect};
@@ -71,6 +71,9 @@ impl ConnectionOps for Connection {
         let mut msg: MSG = unsafe { std::mem::zeroed() };
         loop {
             SPAWN_QUEUE.run();
+            if take_terminate_request() {
+                self.terminate_message_loop();
+            }
 
             let res = unsafe { PeekMessageW(&mut msg, null_mut(), 0, 0, PM_REMOVE) };
             if res != 0 {
@@ -2,16 +2,17 @@ use super::*;
 use crate::connection::ConnectionOps;
 use crate::parameters::{self, Parameters};
 use crate::{
-    Appearance, Clipboard, DeadKeyStatus, Dimensions, Handled, KeyCode, KeyEvent, Modifiers,
-    MouseButtons, MouseCursor, MouseEvent, MouseEventKind, MousePress, Point, RawKeyEvent, Rect,
-    RequestedWindowGeometry, ResolvedGeometry, ScreenPoint, ScreenRect, ULength, WindowDecorations,
-    WindowEvent, WindowEventSender, WindowOps, WindowState,
+    Appearance, Clipboard, ConstrainedResize, DeadKeyStatus, Dimensions, FocusOutcome, FocusReason,
+    Handled, InputSerial, KeyCode, KeyEvent, Modifiers, MouseButtons, MouseCursor, MouseEvent,
+    MouseEventKind, MousePress, Point, RawKeyEvent, Rect, RequestedWindowGeometry, ResizeRejected,
+    ResolvedGeometry, ScreenPoint, ScreenRect, ULength, WindowDecorations, WindowEvent,
+    WindowEventSender, WindowOps, WindowOptions, WindowState,
 };
 use anyhow::{bail, Context};
 use async_trait::async_trait;
 use config::{ConfigHandle, ImePreeditRendering, SystemBackdrop};
 use lazy_static::lazy_static;
-use promise::Future;
+use promise::{Future, Promise};
 use raw_window_handle::{
     DisplayHandle, HandleError, HasDisplayHandle, HasWindowHandle, RawDisplayHandle,
     RawWindowHandle, Win32WindowHandle, WindowHandle, WindowsDisplayHandle,
@@ -511,11 +512,48 @@ impl Window {
     }
 
     pub async fn new_window<F>(
+        class_name: &str,
+        name: &str,
+        geometry: RequestedWindowGeometry,
+        config: Option<&ConfigHandle>,
+        font_config: Rc<FontConfiguration>,
+        event_handler: F,
+    ) -> anyhow::Result<Window>
+    where
+        F: 'static + FnMut(WindowEvent, &Window),
+    {
+        Self::new_window_with_options(
+            class_name,
+            name,
+            geometry,
+            config,
+            font_config,
+            WindowOptions::default(),
+            event_handler,
+        )
+        .await
+    }
+
+    /// Popups are not yet implemented on this platform
+    pub async fn new_popup<F>(
+        _parent: &Window,
+        _anchor: Rect,
+        _size: (usize, usize),
+        _event_handler: F,
+    ) -> anyhow::Result<Window>
+    where
+        F: 'static + FnMut(WindowEvent, &Window),
+    {
+        bail!("popup windows are not supported on this platform");
+    }
+
+    pub async fn new_window_with_options<F>(
         class_name: &str,
         name: &str,
         geometry: RequestedWindowGeometry,
         config: Option<&ConfigHandle>,
         _font_config: Rc<FontConfiguration>,
+        _options: WindowOptions<'_>,
         event_handler: F,
     ) -> anyhow::Result<Window>
     where
@@ -783,6 +821,13 @@ impl WindowOps for Window {
     }
 
     fn close(&self) {
+        Connection::with_window_inner(self.0, |inner| {
+            inner.events.request_close();
+            Ok(())
+        });
+    }
+
+    fn force_close(&self) {
         Connection::with_window_inner(self.0, |inner| {
             inner.close();
             Ok(())
@@ -797,9 +842,11 @@ impl WindowOps for Window {
         schedule_show_window(self.0, ShowWindowCommand::Minimize);
     }
 
-    fn focus(&self) {
+    fn focus(&self, _serial: Option<InputSerial>) -> Future<FocusOutcome> {
         let window = self.0;
         let handle = window.0;
+        let mut outcome = Promise::new();
+        let future = outcome.get_future().unwrap();
         promise::spawn::spawn(async move {
             // In some situation, calling SetForegroundWindow could not bring up the window,
             // This is a little hack which can "steal" the foreground window permission
@@ -840,10 +887,17 @@ impl WindowOps for Window {
                     std::mem::size_of::<INPUT>() as i32,
                 );
 
-                SetForegroundWindow(handle);
+                // When this is refused, the system flashes the taskbar
+                // button to request attention instead
+                outcome.ok(if SetForegroundWindow(handle) != 0 {
+                    FocusOutcome::Focused
+                } else {
+                    FocusOutcome::Deferred
+                });
             }
         })
         .detach();
+        future
     }
 
     fn maximize(&self) {
@@ -899,7 +953,14 @@ impl WindowOps for Window {
         });
     }
 
-    fn set_inner_size(&self, width: usize, height: usize) {
+    fn set_inner_size(
+        &self,
+        width: usize,
+        height: usize,
+        constrained: ConstrainedResize,
+    ) -> Future<Dimensions> {
+        let mut prom = Promise::new();
+        let future = prom.get_future().unwrap();
         Connection::with_window_inner(self.0, move |inner| {
             let hwnd = inner.hwnd;
             let decorations = inner.config.window_decorations;
@@ -926,21 +987,42 @@ impl WindowOps for Window {
                             SWP_NOACTIVATE | SWP_NOMOVE | SWP_NOZORDER,
                         );
                         wm_paint(hwnd.0, 0, 0, 0);
-                        if let Some(inner) = rc_from_hwnd(hwnd.0) {
-                            let mut inner = inner.borrow_mut();
-                            inner.events.dispatch(WindowEvent::SetInnerSizeCompleted);
-                        }
                     }
-                } else {
+                } else if constrained != ConstrainedResize::Reject {
                     log::trace!(
                         "ignoring set_inner_size({width}, {height}) call \
                                 because window_state is {window_state:?}"
                     );
                 }
+                // The request has been dealt with, even if it wasn't applied
+                if let Some(inner) = rc_from_hwnd(hwnd.0) {
+                    let mut inner = inner.borrow_mut();
+                    inner.events.dispatch(WindowEvent::SetInnerSizeCompleted);
+                }
+                if !window_state.can_resize() && constrained == ConstrainedResize::Reject {
+                    prom.err(
+                        ResizeRejected {
+                            state: window_state,
+                        }
+                        .into(),
+                    );
+                    return;
+                }
+                // SetWindowPos handles WM_SIZE before returning, so
+                // last_size is the size that was applied
+                let applied = rc_from_hwnd(hwnd.0).and_then(|inner| {
+                    let inner = inner.borrow();
+                    inner.last_size
+                });
+                match applied {
+                    Some(dimensions) => prom.ok(dimensions),
+                    None => prom.err(anyhow::anyhow!("window size is not known")),
+                };
             })
             .detach();
             Ok(())
         });
+        future
     }
 
     fn set_maximize_button_position(&self, coords: ScreenRect) {
@@ -1591,7 +1673,7 @@ unsafe fn wm_set_focus(
     rc_from_hwnd(hwnd)?
         .borrow_mut()
         .events
-        .dispatch(WindowEvent::FocusChanged(true));
+        .dispatch(WindowEvent::FocusChanged(true, FocusReason::Unknown));
     None
 }
 
@@ -1604,7 +1686,7 @@ unsafe fn wm_kill_focus(
     rc_from_hwnd(hwnd)?
         .borrow_mut()
         .events
-        .dispatch(WindowEvent::FocusChanged(false));
+        .dispatch(WindowEvent::FocusChanged(false, FocusReason::Unknown));
     None
 }
 
@@ -1677,7 +1759,12 @@ fn mods_and_buttons(wparam: WPARAM) -> (Modifiers, MouseButtons) {
     if wparam & MK_RBUTTON != 0 {
         buttons |= MouseButtons::RIGHT;
     }
-    // TODO: XBUTTON1 and XBUTTON2?
+    if wparam & MK_XBUTTON1 != 0 {
+        buttons |= MouseButtons::BACK;
+    }
+    if wparam & MK_XBUTTON2 != 0 {
+        buttons |= MouseButtons::FORWARD;
+    }
     (modifiers, buttons)
 }
 
@@ -1724,6 +1811,13 @@ fn apply_mouse_cursor(cursor: Option<MouseCursor>) {
                     MouseCursor::Text => IDC_IBEAM,
                     MouseCursor::SizeUpDown => IDC_SIZENS,
                     MouseCursor::SizeLeftRight => IDC_SIZEWE,
+                    MouseCursor::SizeNWSE => IDC_SIZENWSE,
+                    MouseCursor::SizeNESW => IDC_SIZENESW,
+                    MouseCursor::Crosshair => IDC_CROSS,
+                    MouseCursor::NotAllowed => IDC_NO,
+                    MouseCursor::Grab => IDC_HAND,
+                    MouseCursor::Grabbing => IDC_SIZEALL,
+                    MouseCursor::Wait => IDC_WAIT,
                 },
             ));
         },
@@ -1743,6 +1837,12 @@ unsafe fn mouse_button(hwnd: HWND, msg: UINT, wparam: WPARAM, lparam: LPARAM) ->
     }
     let (modifiers, mouse_buttons) = mods_and_buttons(wparam);
     let coords = mouse_coords(lparam);
+    // The high word of wparam says which of the X buttons this is
+    let xbutton = match GET_XBUTTON_WPARAM(wparam) {
+        XBUTTON1 => MousePress::Back,
+        XBUTTON2 => MousePress::Forward,
+        other => MousePress::Other(other),
+    };
     let event = MouseEvent {
         kind: match msg {
             WM_LBUTTONDOWN => MouseEventKind::Press(MousePress::Left),
@@ -1751,18 +1851,33 @@ unsafe fn mouse_button(hwnd: HWND, msg: UINT, wparam: WPARAM, lparam: LPARAM) ->
             WM_RBUTTONUP => MouseEventKind::Release(MousePress::Right),
             WM_MBUTTONDOWN => MouseEventKind::Press(MousePress::Middle),
             WM_MBUTTONUP => MouseEventKind::Release(MousePress::Middle),
+            WM_XBUTTONDOWN => MouseEventKind::Press(xbutton),
+            WM_XBUTTONUP => MouseEventKind::Release(xbutton),
             _ => return None,
         },
         coords,
-        screen_coords: client_to_screen(hwnd, coords),
+        screen_coords: Some(client_to_screen(hwnd, coords)),
         mouse_buttons,
         modifiers,
+        // We don't count consecutive clicks here (yet)
+        streak: matches!(
+            msg,
+            WM_LBUTTONDOWN | WM_RBUTTONDOWN | WM_MBUTTONDOWN | WM_XBUTTONDOWN
+        ) as u16,
+        time: GetMessageTime() as u32,
+        serial: None,
     };
     inner
         .borrow_mut()
         .events
         .dispatch(WindowEvent::MouseEvent(event));
-    Some(0)
+    // Unlike the other buttons, the X button messages are expected
+    // to return TRUE when they have been handled
+    if matches!(msg, WM_XBUTTONDOWN | WM_XBUTTONUP) {
+        Some(TRUE as LRESULT)
+    } else {
+        Some(0)
+    }
 }
 
 unsafe fn nc_mouse_button(
@@ -1804,9 +1919,12 @@ unsafe fn nc_mouse_button(
             _ => return None,
         },
         coords,
-        screen_coords: client_to_screen(hwnd, coords),
+        screen_coords: Some(client_to_screen(hwnd, coords)),
         mouse_buttons,
         modifiers,
+        streak: if msg == WM_NCLBUTTONDBLCLK { 2 } else { 1 },
+        time: GetMessageTime() as u32,
+        serial: None,
     };
     inner
         .borrow_mut()
@@ -1837,9 +1955,12 @@ unsafe fn mouse_move(hwnd: HWND, _msg: UINT, wparam: WPARAM, lparam: LPARAM) ->
     let event = MouseEvent {
         kind: MouseEventKind::Move,
         coords,
-        screen_coords: client_to_screen(hwnd, coords),
+        screen_coords: Some(client_to_screen(hwnd, coords)),
         mouse_buttons,
         modifiers,
+        streak: 0,
+        time: GetMessageTime() as u32,
+        serial: None,
     };
 
     inner.events.dispatch(WindowEvent::MouseEvent(event));
@@ -1873,9 +1994,12 @@ unsafe fn nc_mouse_move(hwnd: HWND, _msg: UINT, wparam: WPARAM, lparam: LPARAM)
     let event = MouseEvent {
         kind: MouseEventKind::Move,
         coords,
-        screen_coords: client_to_screen(hwnd, coords),
+        screen_coords: Some(client_to_screen(hwnd, coords)),
         mouse_buttons,
         modifiers,
+        streak: 0,
+        time: GetMessageTime() as u32,
+        serial: None,
     };
 
     inner.events.dispatch(WindowEvent::MouseEvent(event));
@@ -1965,9 +2089,12 @@ unsafe fn mouse_wheel(hwnd: HWND, msg: UINT, wparam: WPARAM, lparam: LPARAM) ->
             MouseEventKind::VertWheel(position)
         },
         coords,
-        screen_coords,
+        screen_coords: Some(screen_coords),
         mouse_buttons,
         modifiers,
+        streak: 0,
+        time: GetMessageTime() as u32,
+        serial: None,
     };
     inner
         .borrow_mut()
@@ -2149,6 +2276,9 @@ unsafe fn ime_composition(
                 leds: KeyboardLedStatus::empty(),
                 repeat_count: 1,
                 key_is_down: true,
+                phys: None,
+                time: 0,
+                serial: None,
                 raw: None,
                 win32_uni_char: None,
             };
@@ -2697,6 +2827,9 @@ unsafe fn key(hwnd: HWND, msg: UINT, wparam: WPARAM, lparam: LPARAM) -> Option<L
                             repeat_count: 1,
                             key_is_down: !releasing,
                             win32_uni_char: Some(c),
+                            phys: raw_key_event.phys_code,
+                            time: 0,
+                            serial: None,
                             raw: Some(RawKeyEvent {
                                 scan_code: 0,
                                 ..raw_key_event.clone()
@@ -2864,6 +2997,9 @@ unsafe fn key(hwnd: HWND, msg: UINT, wparam: WPARAM, lparam: LPARAM) -> Option<L
             repeat_count: repeat,
             key_is_down: !releasing,
             win32_uni_char,
+            phys: raw_key_event.phys_code,
+            time: 0,
+            serial: None,
             raw: Some(raw_key_event),
         }
         .normalize_shift();
@@ -2938,15 +3074,14 @@ unsafe fn do_wnd_proc(hwnd: HWND, msg: UINT, wparam: WPARAM, lparam: LPARAM) ->
         WM_MOUSELEAVE => mouse_leave(hwnd, msg, wparam, lparam),
         WM_MOUSEHWHEEL | WM_MOUSEWHEEL => mouse_wheel(hwnd, msg, wparam, lparam),
         WM_LBUTTONDBLCLK | WM_RBUTTONDBLCLK | WM_MBUTTONDBLCLK | WM_LBUTTONDOWN | WM_LBUTTONUP
-        | WM_RBUTTONDOWN | WM_RBUTTONUP | WM_MBUTTONDOWN | WM_MBUTTONUP => {
-            mouse_button(hwnd, msg, wparam, lparam)
-        }
+        | WM_RBUTTONDOWN | WM_RBUTTONUP | WM_MBUTTONDOWN | WM_MBUTTONUP | WM_XBUTTONDOWN
+        | WM_XBUTTONUP => mouse_button(hwnd, msg, wparam, lparam),
         WM_DROPFILES => drop_files(hwnd, msg, wparam, lparam),
         WM_ERASEBKGND => Some(1),
         WM_CLOSE => {
             if let Some(inner) = rc_from_hwnd(hwnd) {
                 let mut inner = inner.borrow_mut();
-                inner.events.dispatch(WindowEvent::CloseRequested);
+                inner.events.request_close();
                 // Don't let it close
                 return Some(0);
             }
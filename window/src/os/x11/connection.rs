@@ -1,11 +1,14 @@
 use super::keyboard::{Keyboard, KeyboardWithFallback};
-use crate::connection::ConnectionOps;
+use crate::connection::{take_terminate_request, ConnectionOps};
+use crate::os::click_streak::update_desktop_interval;
+use crate::os::fd_watch::FdWatchers;
+use crate::os::last_window::LastWindowTermination;
 use crate::os::x11::window::XWindowInner;
 use crate::os::x11::xsettings::*;
 use crate::os::Connection;
 use crate::screen::{ScreenInfo, Screens};
 use crate::spawn::*;
-use crate::{Appearance, DeadKeyStatus, ScreenRect};
+use crate::{Appearance, DeadKeyStatus, KeyboardLayout, ScreenRect, UiSettings};
 use anyhow::{anyhow, bail, Context as _};
 use mio::event::Source;
 use mio::unix::SourceFd;
@@ -14,7 +17,8 @@ use std::cell::RefCell;
 use std::collections::{HashMap, HashSet};
 use std::os::unix::io::AsRawFd;
 use std::rc::Rc;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, TryLockError};
+use std::time::Duration;
 use x11::xlib;
 use xcb::x::Atom;
 use xcb::{dri2, Raw, Xid};
@@ -86,20 +90,35 @@ pub struct XConnection {
     pub atom_state_maximized_horz: Atom,
     pub atom_state_hidden: Atom,
     pub atom_state_fullscreen: Atom,
+    pub atom_state_demands_attention: Atom,
+    pub atom_state_modal: Atom,
+    pub atom_state_skip_taskbar: Atom,
+    pub atom_state_above: Atom,
+    pub atom_state_below: Atom,
+    pub atom_net_wm_window_type: Atom,
+    pub atom_net_wm_window_type_dialog: Atom,
     pub atom_net_wm_state: Atom,
     pub atom_motif_wm_hints: Atom,
     pub atom_net_wm_pid: Atom,
     pub atom_net_wm_name: Atom,
     pub atom_net_wm_icon: Atom,
+    pub atom_net_wm_opaque_region: Atom,
+    pub atom_net_wm_window_opacity: Atom,
     pub atom_net_move_resize_window: Atom,
     pub atom_net_wm_moveresize: Atom,
     pub atom_net_supported: Atom,
     pub atom_net_supporting_wm_check: Atom,
     pub atom_net_active_window: Atom,
+    pub atom_wm_change_state: Atom,
+    pub atom_gtk_show_window_menu: Atom,
     pub(crate) xrm: RefCell<HashMap<String, String>>,
     pub(crate) windows: RefCell<HashMap<xcb::x::Window, Arc<Mutex<XWindowInner>>>>,
     pub(crate) child_to_parent_id: RefCell<HashMap<xcb::x::Window, xcb::x::Window>>,
-    should_terminate: RefCell<bool>,
+    // The dialogs and popups of each window, which are closed along with it.
+    // Kept here so that closing a window doesn't need to lock the others.
+    pub(crate) children_by_parent_id: RefCell<HashMap<xcb::x::Window, Vec<xcb::x::Window>>>,
+    pub(crate) last_window: LastWindowTermination,
+    pub(crate) fd_watchers: Rc<FdWatchers>,
     pub(crate) visual: xcb::x::Visualtype,
     pub(crate) depth: u8,
     pub(crate) gl_connection: RefCell<Option<Rc<crate::egl::GlConnection>>>,
@@ -155,12 +174,16 @@ fn window_id_from_event(event: &xcb::Event) -> Option<xcb::x::Window> {
         xcb::Event::X(xcb::x::Event::ButtonRelease(e)) => Some(e.event()),
         xcb::Event::X(xcb::x::Event::ClientMessage(e)) => Some(e.window()),
         xcb::Event::X(xcb::x::Event::DestroyNotify(e)) => Some(e.window()),
+        xcb::Event::X(xcb::x::Event::MapNotify(e)) => Some(e.window()),
+        xcb::Event::X(xcb::x::Event::UnmapNotify(e)) => Some(e.window()),
+        xcb::Event::X(xcb::x::Event::VisibilityNotify(e)) => Some(e.window()),
         xcb::Event::X(xcb::x::Event::SelectionClear(e)) => Some(e.owner()),
         xcb::Event::X(xcb::x::Event::SelectionNotify(e)) => Some(e.requestor()),
         xcb::Event::X(xcb::x::Event::SelectionRequest(e)) => Some(e.owner()),
         xcb::Event::X(xcb::x::Event::PropertyNotify(e)) => Some(e.window()),
         xcb::Event::X(xcb::x::Event::FocusIn(e)) => Some(e.event()),
         xcb::Event::X(xcb::x::Event::FocusOut(e)) => Some(e.event()),
+        xcb::Event::X(xcb::x::Event::EnterNotify(e)) => Some(e.event()),
         xcb::Event::X(xcb::x::Event::LeaveNotify(e)) => Some(e.event()),
         _ => None,
     }
@@ -222,7 +245,11 @@ impl ConnectionOps for XConnection {
     }
 
     fn terminate_message_loop(&self) {
-        *self.should_terminate.borrow_mut() = true;
+        Self::request_terminate();
+    }
+
+    fn set_terminate_when_last_window_closed(&self, terminate: bool) {
+        self.last_window.set_enabled(terminate);
     }
 
     fn default_dpi(&self) -> f64 {
@@ -253,8 +280,45 @@ impl ConnectionOps for XConnection {
                 }
             }
         } else {
-            Appearance::Dark
+            Appearance::Light
+        }
+    }
+
+    fn get_ui_settings(&self) -> UiSettings {
+        let mut settings =
+            match promise::spawn::block_on(crate::os::xdg_desktop_portal::get_ui_settings()) {
+                Ok(settings) => settings,
+                Err(err) => {
+                    log::debug!("Unable to read UI settings using xdg-desktop-portal: {err:#}");
+                    UiSettings::default()
+                }
+            };
+
+        // Fill in anything that the portal didn't know from xsettings
+        let xsettings = self.xsettings.borrow();
+        let integer = |name: &str| match xsettings.get(name) {
+            Some(XSetting::Integer(value)) => Some(*value),
+            _ => None,
+        };
+        let millis = |name: &str| {
+            integer(name)
+                .and_then(|ms| u64::try_from(ms).ok())
+                .map(Duration::from_millis)
+        };
+        if settings.double_click_interval.is_none() {
+            settings.double_click_interval = millis("Net/DoubleClickTime");
+        }
+        if settings.cursor_blink.is_none() {
+            settings.cursor_blink = integer("Net/CursorBlink").map(|blink| blink != 0);
+        }
+        if settings.cursor_blink_interval.is_none() {
+            settings.cursor_blink_interval = millis("Net/CursorBlinkTime");
         }
+        settings
+    }
+
+    fn keyboard_layout(&self) -> Option<KeyboardLayout> {
+        Some(self.keyboard.active_layout())
     }
 
     fn screens(&self) -> anyhow::Result<Screens> {
@@ -400,8 +464,9 @@ impl ConnectionOps for XConnection {
             tok_spawn,
             Interest::READABLE,
         )?;
+        self.fd_watchers.attach(poll.registry())?;
 
-        while !*self.should_terminate.borrow() {
+        while !take_terminate_request() {
             // Process any events that might have accumulated in the local
             // buffer (eg: due to a flush) before we potentially go to sleep.
             // The locally queued events won't mark the fd as ready, so we
@@ -413,20 +478,37 @@ impl ConnectionOps for XConnection {
             // be work pending and we don't guarantee that there is a
             // 1:1 wakeup to queued function, so we need to be assertive
             // in order to avoid missing wakeups
-            if SPAWN_QUEUE.run() {
-                // if we processed one, we don't want to sleep because
-                // there may be others to deal with
-                continue;
+            let timeout = if SPAWN_QUEUE.run() {
+                // The budget ran out with work still pending; we don't
+                // want to sleep, but we do want to keep dispatching
+                // events rather than let the queue starve them
+                Some(Duration::from_secs(0))
+            } else {
+                None
+            };
+
+            if timeout.is_none()
+                && self
+                    .last_window
+                    .should_terminate(!self.windows.borrow().is_empty())
+            {
+                break;
             }
 
             self.dispatch_pending_events()
                 .context("dispatch_pending_events")?;
-            if let Err(err) = poll.poll(&mut events, None) {
+            if let Err(err) = poll.poll(&mut events, timeout) {
                 if err.kind() == std::io::ErrorKind::Interrupted {
                     continue;
                 }
                 bail!("polling for events: {:?}", err);
             }
+
+            for event in &events {
+                if event.token() != tok_xcb && event.token() != tok_spawn {
+                    self.fd_watchers.dispatch(event);
+                }
+            }
         }
 
         Ok(())
@@ -497,6 +579,26 @@ impl XConnection {
         }
     }
 
+    fn advise_of_keyboard_layout_change(&self, layout: KeyboardLayout) {
+        for win in self.windows.borrow().values() {
+            let mut win = win.lock().unwrap();
+            win.events
+                .dispatch(crate::WindowEvent::AdviseDeadKeyStatus(DeadKeyStatus::None));
+            win.events
+                .dispatch(crate::WindowEvent::KeyboardLayoutChanged(layout.clone()));
+        }
+    }
+
+    pub(crate) fn advise_of_ui_settings_change(&self, settings: UiSettings) {
+        update_desktop_interval(&settings);
+        for win in self.windows.borrow().values() {
+            win.lock()
+                .unwrap()
+                .events
+                .dispatch(crate::WindowEvent::UiSettingsChanged(settings));
+        }
+    }
+
     fn process_queued_xcb(&self) -> anyhow::Result<()> {
         if let Some(event) = self
             .conn
@@ -589,6 +691,19 @@ impl XConnection {
                 // Clear our cache
                 self.screens.borrow_mut().take();
             }
+            xcb::Event::X(xcb::x::Event::PropertyNotify(e))
+                if e.atom() == self.atom_xsettings_settings =>
+            {
+                // The settings manager changed a setting, which may
+                // include the theme when the portal isn't available
+                let ui_settings = self.get_ui_settings();
+                self.update_xrm();
+                self.advise_of_appearance_change(self.get_appearance());
+                let new_ui_settings = self.get_ui_settings();
+                if new_ui_settings != ui_settings {
+                    self.advise_of_ui_settings_change(new_ui_settings);
+                }
+            }
             _ => {}
         }
 
@@ -611,6 +726,11 @@ impl XConnection {
                     }
                 }
             }
+            // Group changes arrive as StateNotify and a new keymap as
+            // MapNotify or NewKeyboardNotify; either can change the layout
+            if let Some(layout) = self.keyboard.layout_changed() {
+                self.advise_of_keyboard_layout_change(layout);
+            }
         }
         Ok(())
     }
@@ -704,16 +824,29 @@ impl XConnection {
         let atom_state_maximized_horz = Self::intern_atom(&conn, "_NET_WM_STATE_MAXIMIZED_HORZ")?;
         let atom_state_hidden = Self::intern_atom(&conn, "_NET_WM_STATE_HIDDEN")?;
         let atom_state_fullscreen = Self::intern_atom(&conn, "_NET_WM_STATE_FULLSCREEN")?;
+        let atom_state_demands_attention =
+            Self::intern_atom(&conn, "_NET_WM_STATE_DEMANDS_ATTENTION")?;
+        let atom_state_modal = Self::intern_atom(&conn, "_NET_WM_STATE_MODAL")?;
+        let atom_state_skip_taskbar = Self::intern_atom(&conn, "_NET_WM_STATE_SKIP_TASKBAR")?;
+        let atom_state_above = Self::intern_atom(&conn, "_NET_WM_STATE_ABOVE")?;
+        let atom_state_below = Self::intern_atom(&conn, "_NET_WM_STATE_BELOW")?;
+        let atom_net_wm_window_type = Self::intern_atom(&conn, "_NET_WM_WINDOW_TYPE")?;
+        let atom_net_wm_window_type_dialog =
+            Self::intern_atom(&conn, "_NET_WM_WINDOW_TYPE_DIALOG")?;
         let atom_net_wm_state = Self::intern_atom(&conn, "_NET_WM_STATE")?;
         let atom_motif_wm_hints = Self::intern_atom(&conn, "_MOTIF_WM_HINTS")?;
         let atom_net_wm_pid = Self::intern_atom(&conn, "_NET_WM_PID")?;
         let atom_net_wm_name = Self::intern_atom(&conn, "_NET_WM_NAME")?;
         let atom_net_wm_icon = Self::intern_atom(&conn, "_NET_WM_ICON")?;
+        let atom_net_wm_opaque_region = Self::intern_atom(&conn, "_NET_WM_OPAQUE_REGION")?;
+        let atom_net_wm_window_opacity = Self::intern_atom(&conn, "_NET_WM_WINDOW_OPACITY")?;
         let atom_net_move_resize_window = Self::intern_atom(&conn, "_NET_MOVERESIZE_WINDOW")?;
         let atom_net_wm_moveresize = Self::intern_atom(&conn, "_NET_WM_MOVERESIZE")?;
         let atom_net_supported = Self::intern_atom(&conn, "_NET_SUPPORTED")?;
         let atom_net_supporting_wm_check = Self::intern_atom(&conn, "_NET_SUPPORTING_WM_CHECK")?;
         let atom_net_active_window = Self::intern_atom(&conn, "_NET_ACTIVE_WINDOW")?;
+        let atom_wm_change_state = Self::intern_atom(&conn, "WM_CHANGE_STATE")?;
+        let atom_gtk_show_window_menu = Self::intern_atom(&conn, "_GTK_SHOW_WINDOW_MENU")?;
 
         let has_randr = conn.active_extensions().any(|e| e == xcb::Extension::RandR);
 
@@ -841,6 +974,13 @@ impl XConnection {
             atom_state_maximized_horz,
             atom_state_hidden,
             atom_state_fullscreen,
+            atom_state_demands_attention,
+            atom_state_modal,
+            atom_state_skip_taskbar,
+            atom_state_above,
+            atom_state_below,
+            atom_net_wm_window_type,
+            atom_net_wm_window_type_dialog,
             atom_net_wm_state,
             atom_motif_wm_hints,
             atom_net_wm_pid,
@@ -850,7 +990,11 @@ impl XConnection {
             atom_net_supported,
             atom_net_supporting_wm_check,
             atom_net_active_window,
+            atom_wm_change_state,
+            atom_gtk_show_window_menu,
             atom_net_wm_icon,
+            atom_net_wm_opaque_region,
+            atom_net_wm_window_opacity,
             keyboard,
             kbd_ev,
             atom_utf8_string,
@@ -858,7 +1002,9 @@ impl XConnection {
             atom_targets,
             windows: RefCell::new(HashMap::new()),
             child_to_parent_id: RefCell::new(HashMap::new()),
-            should_terminate: RefCell::new(false),
+            children_by_parent_id: RefCell::new(HashMap::new()),
+            last_window: LastWindowTermination::default(),
+            fd_watchers: Rc::new(FdWatchers::default()),
             depth,
             visual,
             gl_connection: RefCell::new(None),
@@ -924,10 +1070,32 @@ impl XConnection {
         }
 
         conn.update_net_supported();
+        conn.watch_xsettings();
+        update_desktop_interval(&conn.get_ui_settings());
 
         Ok(conn)
     }
 
+    /// Ask to be told when the xsettings manager changes its settings
+    fn watch_xsettings(&self) {
+        let manager = match self.send_and_wait_request(&xcb::x::GetSelectionOwner {
+            selection: self.atom_xsettings_selection,
+        }) {
+            Ok(reply) => reply.owner(),
+            Err(err) => {
+                log::trace!("Failed to find the xsettings manager: {:#}", err);
+                return;
+            }
+        };
+        if manager.is_none() {
+            return;
+        }
+        self.send_request_no_reply_log(&xcb::x::ChangeWindowAttributes {
+            window: manager,
+            value_list: &[xcb::x::Cw::EventMask(xcb::x::EventMask::PROPERTY_CHANGE)],
+        });
+    }
+
     pub(crate) fn send_and_wait_request<R>(
         &self,
         req: &R,
@@ -1001,14 +1169,33 @@ impl XConnection {
         let future = prom.get_future().unwrap();
 
         promise::spawn::spawn_into_main_thread(async move {
-            if let Some(handle) = Connection::get().unwrap().x11().window_by_id(window) {
-                let mut inner = handle.lock().unwrap();
-                if inner.window_id != window {
-                    prom.result(Err(anyhow!("window {window:?} has been destroyed")));
-                } else {
-                    prom.result(f(&mut inner));
+            let f = match Connection::get().unwrap().x11().window_by_id(window) {
+                Some(handle) => match handle.try_lock() {
+                    Ok(mut inner) => {
+                        if inner.window_id != window {
+                            prom.result(Err(anyhow!("window {window:?} has been destroyed")));
+                        } else {
+                            prom.result(f(&mut inner));
+                        }
+                        return;
+                    }
+                    Err(TryLockError::WouldBlock) => f,
+                    Err(err @ TryLockError::Poisoned(_)) => panic!("{err}"),
+                },
+                None => {
+                    // Resolve the promise, otherwise anything awaiting
+                    // the future for a closed window would hang forever
+                    prom.err(anyhow!("window {window:?} is gone"));
+                    return;
                 }
-            }
+            };
+
+            // The window is locked further up the stack: the spawn
+            // queue is being run from inside one of its event handlers.
+            // Go to the back of the queue and try again once that
+            // handler has returned.
+            log::trace!("window {window:?} is busy; deferring");
+            prom.result(Self::with_window_inner(window, f).await);
         })
         .detach();
 
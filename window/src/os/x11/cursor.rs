@@ -1,6 +1,6 @@
 use crate::os::x11::xcb_util::*;
 use crate::x11::XConnection;
-use crate::MouseCursor;
+use crate::{BitmapImage, Image, MouseCursor, Point};
 use anyhow::{ensure, Context};
 use config::ConfigHandle;
 use std::collections::{HashMap, HashSet};
@@ -14,13 +14,39 @@ use xcb::x::Cursor;
 use xcb::Xid;
 
 // X11 classic Cursor glyphs
+pub const BOTTOM_LEFT_CORNER: u16 = 12;
+pub const BOTTOM_RIGHT_CORNER: u16 = 14;
+pub const CIRCLE: u16 = 24;
+pub const CROSSHAIR: u16 = 34;
+pub const FLEUR: u16 = 52;
 pub const HAND1: u16 = 58;
 pub const SB_H_DOUBLE_ARROW: u16 = 108;
 pub const SB_V_DOUBLE_ARROW: u16 = 116;
 pub const TOP_LEFT_ARROW: u16 = 132;
 pub const TOP_LEFT_CORNER: u16 = 134;
+pub const WATCH: u16 = 150;
 pub const XTERM: u16 = 152;
 
+/// The names under which xcursor themes provide each cursor, most
+/// preferred first. Older themes only have the legacy X11 names.
+/// The wayland backend loads from the same themes, so shares this.
+pub(crate) fn xcursor_names(cursor: MouseCursor) -> &'static [&'static str] {
+    match cursor {
+        MouseCursor::Arrow => &["top_left_arrow", "left_ptr"],
+        MouseCursor::Hand => &["hand2", "hand1"],
+        MouseCursor::Text => &["xterm"],
+        MouseCursor::SizeUpDown => &["sb_v_double_arrow", "v_double_arrow"],
+        MouseCursor::SizeLeftRight => &["sb_h_double_arrow", "h_double_arrow"],
+        MouseCursor::SizeNWSE => &["nwse-resize", "size_fdiag", "bd_double_arrow"],
+        MouseCursor::SizeNESW => &["nesw-resize", "size_bdiag", "fd_double_arrow"],
+        MouseCursor::Crosshair => &["crosshair", "cross", "tcross"],
+        MouseCursor::NotAllowed => &["not-allowed", "crossed_circle", "circle"],
+        MouseCursor::Grab => &["grab", "openhand", "hand1"],
+        MouseCursor::Grabbing => &["grabbing", "closedhand", "fleur"],
+        MouseCursor::Wait => &["wait", "watch"],
+    }
+}
+
 pub struct XcbCursor {
     pub id: Cursor,
     pub conn: Weak<XConnection>,
@@ -37,6 +63,9 @@ impl Drop for XcbCursor {
 pub struct CursorInfo {
     cursors: HashMap<Option<MouseCursor>, XcbCursor>,
     cursor: Option<MouseCursor>,
+    // The cursor set via set_custom_cursor, if it is the one
+    // currently in effect
+    custom: Option<XcbCursor>,
     conn: Weak<XConnection>,
     size: Option<u32>,
     theme: Option<String>,
@@ -180,6 +209,7 @@ impl CursorInfo {
         Self {
             cursors: HashMap::new(),
             cursor: None,
+            custom: None,
             conn: Rc::downgrade(conn),
             size,
             theme,
@@ -197,7 +227,7 @@ impl CursorInfo {
         window_id: xcb::x::Window,
         cursor: Option<MouseCursor>,
     ) -> anyhow::Result<()> {
-        if cursor == self.cursor {
+        if cursor == self.cursor && self.custom.is_none() {
             return Ok(());
         }
 
@@ -218,17 +248,74 @@ impl CursorInfo {
         .context("set_cursor")?;
 
         self.cursor = cursor;
+        // The window no longer references the custom cursor,
+        // so it can be freed
+        self.custom.take();
 
         Ok(())
     }
 
-    fn create_blank(&mut self, conn: &Rc<XConnection>) -> anyhow::Result<Cursor> {
-        let mut pixels = [0u8; 4];
+    pub fn set_custom_cursor(
+        &mut self,
+        window_id: xcb::x::Window,
+        image: &Image,
+        hotspot: Point,
+    ) -> anyhow::Result<()> {
+        ensure!(
+            self.pict_format_id.is_some(),
+            "custom cursors require the render extension"
+        );
+        let conn = self.conn();
+
+        let (width, height) = image.image_dimensions();
+        ensure!(width > 0 && height > 0, "cursor image is empty");
+
+        // `Image` is rgba32, whereas the picture format is argb32
+        // in native byte order
+        let mut pixels = Vec::with_capacity(width * height * 4);
+        for pixel in image.pixels() {
+            let [r, g, b, a] = pixel.to_ne_bytes();
+            pixels.extend_from_slice(&u32::from_be_bytes([a, r, g, b]).to_ne_bytes());
+        }
+
+        let cursor_id = self.create_argb_cursor(
+            &conn,
+            width.try_into()?,
+            height.try_into()?,
+            &mut pixels,
+            hotspot.x.try_into()?,
+            hotspot.y.try_into()?,
+        )?;
+        let cursor = XcbCursor {
+            id: cursor_id,
+            conn: Rc::downgrade(&conn),
+        };
 
+        conn.send_request_no_reply(&xcb::x::ChangeWindowAttributes {
+            window: window_id,
+            value_list: &[xcb::x::Cw::Cursor(cursor_id)],
+        })
+        .context("set_custom_cursor")?;
+
+        // Replacing any prior custom cursor frees it
+        self.custom.replace(cursor);
+
+        Ok(())
+    }
+
+    fn create_argb_cursor(
+        &self,
+        conn: &Rc<XConnection>,
+        width: u16,
+        height: u16,
+        pixels: &mut [u8],
+        xhot: u16,
+        yhot: u16,
+    ) -> anyhow::Result<Cursor> {
         let image = XcbImage::create_native(
             conn,
-            1,
-            1,
+            width,
+            height,
             xcb::x::ImageFormat::ZPixmap as u32,
             32,
             std::ptr::null_mut(),
@@ -241,8 +328,8 @@ impl CursorInfo {
             depth: 32,
             pid: pixmap,
             drawable: xcb::x::Drawable::Window(conn.root),
-            width: 1,
-            height: 1,
+            width,
+            height,
         })
         .context("CreatePixmap")?;
 
@@ -256,7 +343,7 @@ impl CursorInfo {
 
         image.put(conn, pixmap.resource_id(), gc.resource_id(), 0, 0, 0);
 
-        conn.send_request(&xcb::x::FreeGc { gc });
+        conn.send_request_no_reply(&xcb::x::FreeGc { gc })?;
 
         let pic = conn.generate_id();
         conn.send_request_no_reply(&xcb::render::CreatePicture {
@@ -267,23 +354,28 @@ impl CursorInfo {
         })
         .context("create_picture")?;
 
-        conn.send_request(&xcb::x::FreePixmap { pixmap });
+        conn.send_request_no_reply(&xcb::x::FreePixmap { pixmap })?;
 
         let cursor_id: Cursor = conn.generate_id();
         conn.send_request_no_reply(&xcb::render::CreateCursor {
             cid: cursor_id,
             source: pic,
-            x: 0,
-            y: 0,
+            x: xhot,
+            y: yhot,
         })
         .context("create_cursor")?;
 
-        conn.send_request_no_reply(&xcb::render::FreePicture { picture: pic })
-            .context("FreePicture")?;
+        conn.send_request_no_reply(&xcb::render::FreePicture { picture: pic })?;
 
         Ok(cursor_id)
     }
 
+    fn create_blank(&mut self, conn: &Rc<XConnection>) -> anyhow::Result<Cursor> {
+        // A single fully transparent pixel
+        let mut pixels = [0u8; 4];
+        self.create_argb_cursor(conn, 1, 1, &mut pixels, 0, 0)
+    }
+
     fn load_themed(
         &mut self,
         conn: &Rc<XConnection>,
@@ -311,13 +403,7 @@ impl CursorInfo {
         let theme = self.theme.as_deref().unwrap_or("default");
         self.pict_format_id?;
 
-        let names: &[&str] = match cursor.unwrap_or(MouseCursor::Arrow) {
-            MouseCursor::Arrow => &["top_left_arrow", "left_ptr"],
-            MouseCursor::Hand => &["hand2"],
-            MouseCursor::Text => &["xterm"],
-            MouseCursor::SizeUpDown => &["sb_v_double_arrow"],
-            MouseCursor::SizeLeftRight => &["sb_h_double_arrow"],
-        };
+        let names = xcursor_names(cursor.unwrap_or(MouseCursor::Arrow));
 
         let mut theme_list = vec![theme.to_string()];
         let mut visited = HashSet::new();
@@ -376,6 +462,14 @@ impl CursorInfo {
             MouseCursor::Text => XTERM,
             MouseCursor::SizeUpDown => SB_V_DOUBLE_ARROW,
             MouseCursor::SizeLeftRight => SB_H_DOUBLE_ARROW,
+            // The cursor font has no diagonal double arrows
+            MouseCursor::SizeNWSE => BOTTOM_RIGHT_CORNER,
+            MouseCursor::SizeNESW => BOTTOM_LEFT_CORNER,
+            MouseCursor::Crosshair => CROSSHAIR,
+            MouseCursor::NotAllowed => CIRCLE,
+            MouseCursor::Grab => HAND1,
+            MouseCursor::Grabbing => FLEUR,
+            MouseCursor::Wait => WATCH,
         };
         log::trace!("loading X11 basic cursor {} for {:?}", id_no, cursor);
 
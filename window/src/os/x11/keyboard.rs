@@ -1,13 +1,14 @@
+use crate::os::evdev::{wayland_key_to_xkb_keycode, xkb_keycode_to_phys, XKB_KEYCODE_OFFSET};
 use crate::os::xkeysyms::keysym_to_keycode;
 use crate::{
-    DeadKeyStatus, Handled, KeyCode, KeyEvent, Modifiers, RawKeyEvent, WindowEvent,
-    WindowEventSender, WindowKeyEvent,
+    DeadKeyStatus, Handled, InputSerial, KeyCode, KeyEvent, KeyboardLayout, Modifiers, RawKeyEvent,
+    UntranslatedKeyEvent, WindowEvent, WindowEventSender, WindowKeyEvent,
 };
 use anyhow::{anyhow, ensure};
 use libc;
 use std::cell::RefCell;
-use std::collections::HashMap;
-use std::ffi::{CStr, OsStr};
+use std::collections::{HashMap, HashSet};
+use std::ffi::{CStr, OsStr, OsString};
 use std::os::unix::ffi::OsStrExt;
 use wezterm_input_types::{KeyboardLedStatus, PhysKeyCode};
 use xcb::x::KeyButMask;
@@ -38,9 +39,38 @@ struct StateFromXcbStateNotify {
     locked_layout: LayoutIndex,
 }
 
+/// Tracks whether a window wants UntranslatedKeyEvents, and which keys
+/// have had their press reported, so that their release is reported
+/// even if the mode is switched off while they are held down.
+#[derive(Default)]
+pub struct RawKeyMode {
+    enabled: bool,
+    held: HashSet<xkb::Keycode>,
+}
+
+impl RawKeyMode {
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    /// Returns true if this press or release should be reported
+    fn should_report(&mut self, xcode: xkb::Keycode, pressed: bool) -> bool {
+        if pressed {
+            if self.enabled {
+                self.held.insert(xcode);
+            }
+            self.enabled
+        } else {
+            self.held.remove(&xcode) || self.enabled
+        }
+    }
+}
+
 pub struct KeyboardWithFallback {
     selected: Keyboard,
     fallback: Keyboard,
+    /// The layout that was most recently reported by layout_changed
+    layout: RefCell<KeyboardLayout>,
 }
 
 struct Compose {
@@ -169,9 +199,11 @@ fn default_keymap(context: &xkb::Context) -> Option<xkb::Keymap> {
 
 impl KeyboardWithFallback {
     pub fn new(selected: Keyboard) -> anyhow::Result<Self> {
+        let layout = RefCell::new(selected.active_layout());
         Ok(Self {
             selected,
             fallback: Keyboard::new_default()?,
+            layout,
         })
     }
 
@@ -184,14 +216,20 @@ impl KeyboardWithFallback {
         &self,
         code: u32,
         pressed: bool,
+        time: u32,
+        serial: Option<InputSerial>,
+        raw_mode: &mut RawKeyMode,
         events: &mut WindowEventSender,
     ) -> Option<WindowKeyEvent> {
         let want_repeat = self.selected.wayland_key_repeats(code);
         let raw_modifiers = self.get_key_modifiers();
         self.process_key_event_impl(
-            xkb::Keycode::new(code + 8),
+            xkb::Keycode::new(wayland_key_to_xkb_keycode(code)),
             raw_modifiers,
             pressed,
+            time,
+            serial,
+            raw_mode,
             events,
             want_repeat,
         )
@@ -219,19 +257,35 @@ impl KeyboardWithFallback {
     pub fn process_key_press_event(
         &self,
         xcb_ev: &xcb::x::KeyPressEvent,
+        raw_mode: &mut RawKeyMode,
         events: &mut WindowEventSender,
     ) {
         let xcode = xkb::Keycode::from(xcb_ev.detail());
-        self.process_xcb_key_event_impl(xcode, xcb_ev.state(), true, events);
+        self.process_xcb_key_event_impl(
+            xcode,
+            xcb_ev.state(),
+            xcb_ev.time(),
+            true,
+            raw_mode,
+            events,
+        );
     }
 
     pub fn process_key_release_event(
         &self,
         xcb_ev: &xcb::x::KeyReleaseEvent,
+        raw_mode: &mut RawKeyMode,
         events: &mut WindowEventSender,
     ) {
         let xcode = xkb::Keycode::from(xcb_ev.detail());
-        self.process_xcb_key_event_impl(xcode, xcb_ev.state(), false, events);
+        self.process_xcb_key_event_impl(
+            xcode,
+            xcb_ev.state(),
+            xcb_ev.time(),
+            false,
+            raw_mode,
+            events,
+        );
     }
 
     // for X11 we always pass down raw_modifiers from the incoming
@@ -249,7 +303,9 @@ impl KeyboardWithFallback {
         &self,
         xcode: xkb::Keycode,
         state: KeyButMask,
+        time: xcb::x::Timestamp,
         pressed: bool,
+        raw_mode: &mut RawKeyMode,
         events: &mut WindowEventSender,
     ) -> Option<WindowKeyEvent> {
         // extract current modifiers
@@ -261,7 +317,18 @@ impl KeyboardWithFallback {
         self.merge_current_xcb_modifiers(raw_mod_mask);
 
         // now do the regular processing
-        let result = self.process_key_event_impl(xcode, event_modifiers, pressed, events, false);
+        // X11 has no serials; the server timestamp is what requests
+        // such as SetInputFocus expect to be handed back
+        let result = self.process_key_event_impl(
+            xcode,
+            event_modifiers,
+            pressed,
+            time,
+            Some(InputSerial::new(time)),
+            raw_mode,
+            events,
+            false,
+        );
 
         // and restore the prior modifier state
         self.reapply_last_xcb_state();
@@ -269,15 +336,22 @@ impl KeyboardWithFallback {
         result
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn process_key_event_impl(
         &self,
         xcode: xkb::Keycode,
         raw_modifiers: Modifiers,
         pressed: bool,
+        time: u32,
+        serial: Option<InputSerial>,
+        raw_mode: &mut RawKeyMode,
         events: &mut WindowEventSender,
         want_repeat: bool,
     ) -> Option<WindowKeyEvent> {
         let phys_code = self.selected.phys_code_map.borrow().get(&xcode).copied();
+        // Unlike phys_code, which depends on the keymap, this is
+        // the same for X11 and Wayland
+        let phys = xkb_keycode_to_phys(xcode.into());
 
         let leds = self.get_led_status();
 
@@ -285,6 +359,29 @@ impl KeyboardWithFallback {
         let fallback_xsym = self.fallback.state.borrow().key_get_one_sym(xcode);
         let handled = Handled::new();
 
+        if raw_mode.should_report(xcode, pressed) {
+            // The text for the keysym itself, rather than the control
+            // character that eg: CTRL-A would otherwise produce
+            let utf8 = std::char::from_u32(xkb::keysym_to_utf32(xsym))
+                .filter(|c| *c != '\0')
+                .map(String::from);
+            events.dispatch(WindowEvent::UntranslatedKeyEvent(UntranslatedKeyEvent {
+                evdev_code: u32::from(xcode).saturating_sub(XKB_KEYCODE_OFFSET),
+                keysym: xsym.into(),
+                utf8,
+                key_is_down: pressed,
+                modifiers: raw_modifiers,
+                leds,
+            }));
+        }
+
+        // Holding down a modifier must not generate a stream of presses,
+        // even if the keymap says that the key repeats
+        let want_repeat = want_repeat
+            && !keysym_to_keycode(xsym.into())
+                .map(|kc| kc.is_modifier())
+                .unwrap_or(false);
+
         let raw_key_event = RawKeyEvent {
             key: match phys_code {
                 Some(phys) => KeyCode::Physical(phys),
@@ -429,10 +526,16 @@ impl KeyboardWithFallback {
             None => match keysym_to_keycode(ksym.into()).or_else(|| keysym_to_keycode(xsym.into()))
             {
                 Some(kc) => kc,
-                None => {
+                None if u32::from(xsym) == xkb::keysyms::KEY_NoSymbol => {
                     log::trace!("keysym_to_keycode for {:?} and {:?} -> None", ksym, xsym);
                     return None;
                 }
+                None => {
+                    // We don't have a name for it, but deliver it anyway
+                    // so that it can at least be seen and bound
+                    log::trace!("keysym_to_keycode for {:?} and {:?} -> None", ksym, xsym);
+                    KeyCode::Unknown(xsym.into())
+                }
             },
         };
 
@@ -442,6 +545,9 @@ impl KeyboardWithFallback {
             modifiers: raw_modifiers,
             repeat_count: 1,
             key_is_down: pressed,
+            phys,
+            time,
+            serial,
             raw: Some(raw_key_event),
         }
         .normalize_shift()
@@ -542,6 +648,21 @@ impl KeyboardWithFallback {
             .update_modifier_state(mods_depressed, mods_latched, mods_locked, group);
     }
 
+    /// Called when a window gains or loses the keyboard focus; returns
+    /// the modifiers that the window should consider to be held.
+    /// We won't see keys being released while we don't have the focus,
+    /// so the modifiers are cleared when it is lost, otherwise we'd be
+    /// left with eg: CTRL+SHIFT stuck down after using CTRL+SHIFT+N to
+    /// spawn a new window.  When the focus is gained, the compositor
+    /// reports the modifiers that are already held (eg: shift-click to
+    /// focus), so we keep what it has told us rather than assuming none.
+    pub fn focus_changed(&self, focused: bool) -> Modifiers {
+        if !focused {
+            self.update_modifier_state(0, 0, 0, 0);
+        }
+        self.get_key_modifiers()
+    }
+
     pub fn update_state(&self, ev: &xcb::xkb::StateNotifyEvent) {
         self.selected.update_state(ev);
         self.fallback.update_state(ev);
@@ -560,6 +681,32 @@ impl KeyboardWithFallback {
     pub fn update_keymap(&self, connection: &xcb::Connection) -> anyhow::Result<()> {
         self.selected.update_keymap(connection)
     }
+
+    /// Returns the layout that is currently in effect
+    pub fn active_layout(&self) -> KeyboardLayout {
+        self.selected.active_layout()
+    }
+
+    /// Returns the new layout if it has changed since the last call.
+    /// A dead key sequence that was started in the old layout makes no
+    /// sense in the new one, so any composition in progress is abandoned.
+    pub fn layout_changed(&self) -> Option<KeyboardLayout> {
+        let layout = self.active_layout();
+        if layout == *self.layout.borrow() {
+            return None;
+        }
+        log::debug!("keyboard layout changed to {layout:?}");
+        self.layout.replace(layout.clone());
+        self.selected.compose_clear();
+        self.fallback.compose_clear();
+        Some(layout)
+    }
+
+    /// Use this when replacing `previous` with a new keymap, so that
+    /// layout_changed reports the difference between the two
+    pub fn inherit_layout(&self, previous: &Self) {
+        self.layout.replace(previous.layout.borrow().clone());
+    }
 }
 
 impl Keyboard {
@@ -569,11 +716,7 @@ impl Keyboard {
             .ok_or_else(|| anyhow!("Failed to load system default keymap"))?;
 
         let state = xkb::State::new(&keymap);
-        let locale = query_lc_ctype()?;
-
-        let table =
-            xkb::compose::Table::new_from_locale(&context, locale, xkb::compose::COMPILE_NO_FLAGS)
-                .map_err(|_| anyhow!("Failed to acquire compose table from locale"))?;
+        let table = compose_table(&context)?;
         let compose_state = xkb::compose::State::new(&table, xkb::compose::STATE_NO_FLAGS);
 
         let phys_code_map = build_physkeycode_map(&keymap);
@@ -607,11 +750,7 @@ impl Keyboard {
         .ok_or_else(|| anyhow!("Failed to parse keymap state from file"))?;
 
         let state = xkb::State::new(&keymap);
-        let locale = query_lc_ctype()?;
-
-        let table =
-            xkb::compose::Table::new_from_locale(&context, locale, xkb::compose::COMPILE_NO_FLAGS)
-                .map_err(|_| anyhow!("Failed to acquire compose table from locale"))?;
+        let table = compose_table(&context)?;
         let compose_state = xkb::compose::State::new(&table, xkb::compose::STATE_NO_FLAGS);
 
         let phys_code_map = build_physkeycode_map(&keymap);
@@ -651,12 +790,7 @@ impl Keyboard {
         );
 
         let state = xkb::x11::state_new_from_device(&keymap, connection, device_id);
-
-        let locale = query_lc_ctype()?;
-
-        let table =
-            xkb::compose::Table::new_from_locale(&context, locale, xkb::compose::COMPILE_NO_FLAGS)
-                .map_err(|_| anyhow!("Failed to acquire compose table from locale"))?;
+        let table = compose_table(&context)?;
         let compose_state = xkb::compose::State::new(&table, xkb::compose::STATE_NO_FLAGS);
 
         {
@@ -710,7 +844,7 @@ impl Keyboard {
     pub fn wayland_key_repeats(&self, code: u32) -> bool {
         self.keymap
             .borrow()
-            .key_repeats(xkb::Keycode::new(code + 8))
+            .key_repeats(xkb::Keycode::new(wayland_key_to_xkb_keycode(code)))
     }
 
     pub fn get_device_id(&self) -> i32 {
@@ -727,6 +861,15 @@ impl Keyboard {
         self.compose_state.borrow_mut().reset();
     }
 
+    pub fn active_layout(&self) -> KeyboardLayout {
+        let index = self
+            .state
+            .borrow()
+            .serialize_layout(xkb::STATE_LAYOUT_EFFECTIVE);
+        let name = self.keymap.borrow().layout_get_name(index).to_string();
+        KeyboardLayout { name, index }
+    }
+
     pub fn update_modifier_state(
         &self,
         mods_depressed: u32,
@@ -820,6 +963,17 @@ impl Keyboard {
     }
 }
 
+/// Load the compose table for the configured `compose_locale`,
+/// falling back to the LC_CTYPE locale from the environment
+fn compose_table(context: &xkb::Context) -> anyhow::Result<xkb::compose::Table> {
+    let locale: OsString = match config::configuration().compose_locale.as_ref() {
+        Some(locale) => locale.into(),
+        None => query_lc_ctype()?.to_os_string(),
+    };
+    xkb::compose::Table::new_from_locale(context, &locale, xkb::compose::COMPILE_NO_FLAGS)
+        .map_err(|_| anyhow!("Failed to acquire compose table from locale {locale:?}"))
+}
+
 fn query_lc_ctype() -> anyhow::Result<&'static OsStr> {
     let ptr = unsafe { libc::setlocale(libc::LC_CTYPE, std::ptr::null()) };
     ensure!(!ptr.is_null(), "failed to query locale");
@@ -953,3 +1107,275 @@ fn build_physkeycode_map(keymap: &xkb::Keymap) -> HashMap<xkb::Keycode, PhysKeyC
 
     map
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use xkb::keysyms::{KEY_a, KEY_q, KEY_A, KEY_Q};
+
+    const KEY_Q_POSITION: u32 = 16;
+    const KEY_LEFTCTRL: u32 = 29;
+    const KEY_LEFTSHIFT: u32 = 42;
+    const KEY_LEFTALT: u32 = 56;
+    const KEY_LEFTMETA: u32 = 125;
+    const KEY_F5: u32 = 63;
+    const KEY_1: u32 = 2;
+    const KEY_KP7: u32 = 71;
+    const KEY_KP1: u32 = 79;
+    const KEY_KPENTER: u32 = 96;
+    const KEY_HOME: u32 = 102;
+    const KEY_END: u32 = 107;
+
+    fn keymap_for_layout(layout: &str) -> String {
+        let context = xkb::Context::new(xkb::CONTEXT_NO_FLAGS);
+        let keymap = xkb::Keymap::new_from_names(
+            &context,
+            "evdev",
+            "pc105",
+            layout,
+            "",
+            None,
+            xkb::KEYMAP_COMPILE_NO_FLAGS,
+        )
+        .expect("keymap to compile");
+        keymap.get_as_string(xkb::KEYMAP_FORMAT_TEXT_V1)
+    }
+
+    /// Resolves a Wayland key the same way that process_wayland_key does
+    fn keysym(mapper: &KeyboardWithFallback, key: u32) -> u32 {
+        let xcode = xkb::Keycode::new(wayland_key_to_xkb_keycode(key));
+        mapper.selected.state.borrow().key_get_one_sym(xcode).into()
+    }
+
+    #[test]
+    fn keymap_replacement() {
+        let us = KeyboardWithFallback::new_from_string(keymap_for_layout("us")).unwrap();
+        assert_eq!(keysym(&us, KEY_Q_POSITION), KEY_q);
+
+        // Hold shift, as though the keymap changed mid chord
+        let shift = 1
+            << us
+                .selected
+                .keymap
+                .borrow()
+                .mod_get_index(xkb::MOD_NAME_SHIFT);
+        us.update_modifier_state(shift, 0, 0, 0);
+        assert_eq!(keysym(&us, KEY_Q_POSITION), KEY_Q);
+        assert_eq!(us.get_key_modifiers(), Modifiers::SHIFT);
+
+        // The compositor sends a new keymap; keys are resolved using
+        // it from then on, and shift doesn't remain stuck
+        let fr = KeyboardWithFallback::new_from_string(keymap_for_layout("fr")).unwrap();
+        fr.inherit_layout(&us);
+        assert_eq!(keysym(&fr, KEY_Q_POSITION), KEY_a);
+        assert_eq!(fr.get_key_modifiers(), Modifiers::NONE);
+
+        // followed by the modifiers in terms of the new keymap
+        let shift = 1
+            << fr
+                .selected
+                .keymap
+                .borrow()
+                .mod_get_index(xkb::MOD_NAME_SHIFT);
+        fr.update_modifier_state(shift, 0, 0, 0);
+        assert_eq!(keysym(&fr, KEY_Q_POSITION), KEY_A);
+        assert_eq!(fr.get_key_modifiers(), Modifiers::SHIFT);
+    }
+
+    #[test]
+    fn modifiers_do_not_repeat() {
+        let mapper = KeyboardWithFallback::new_from_string(keymap_for_layout("us")).unwrap();
+        let mut events = WindowEventSender::new(|_, _| {});
+        let mut raw_mode = RawKeyMode::default();
+
+        // A regular key is returned so that it can be repeated
+        assert!(mapper
+            .process_wayland_key(KEY_Q_POSITION, true, 0, None, &mut raw_mode, &mut events)
+            .is_some());
+        mapper.process_wayland_key(KEY_Q_POSITION, false, 0, None, &mut raw_mode, &mut events);
+
+        // but a modifier is not, even though the press is still delivered
+        for key in [KEY_LEFTSHIFT, KEY_LEFTCTRL, KEY_LEFTALT, KEY_LEFTMETA] {
+            assert!(
+                mapper
+                    .process_wayland_key(key, true, 0, None, &mut raw_mode, &mut events)
+                    .is_none(),
+                "key {}",
+                key
+            );
+            mapper.process_wayland_key(key, false, 0, None, &mut raw_mode, &mut events);
+        }
+    }
+
+    #[test]
+    fn modifiers_held_on_focus() {
+        let mapper = KeyboardWithFallback::new_from_string(keymap_for_layout("us")).unwrap();
+        let mut events = WindowEventSender::new(|_, _| {});
+        let mut raw_mode = RawKeyMode::default();
+        let keymap = mapper.selected.keymap.borrow().clone();
+        let shift = 1 << keymap.mod_get_index(xkb::MOD_NAME_SHIFT);
+        let ctrl = 1 << keymap.mod_get_index(xkb::MOD_NAME_CTRL);
+
+        // CTRL+SHIFT was held when we lost the focus; we won't see it
+        // being released, so it must not remain stuck
+        mapper.update_modifier_state(shift | ctrl, 0, 0, 0);
+        assert_eq!(mapper.focus_changed(false), Modifiers::NONE);
+
+        // Enter -> Modifiers -> Key, as when shift-clicking to focus
+        assert_eq!(mapper.focus_changed(true), Modifiers::NONE);
+        mapper.update_modifier_state(shift, 0, 0, 0);
+        let event = mapper
+            .process_wayland_key(KEY_F5, true, 0, None, &mut raw_mode, &mut events)
+            .expect("F5 to repeat");
+        match event {
+            WindowKeyEvent::KeyEvent(event) => {
+                assert_eq!(event.key, KeyCode::Function(5));
+                assert_eq!(event.modifiers, Modifiers::SHIFT);
+            }
+            _ => panic!("expected a KeyEvent"),
+        }
+        mapper.process_wayland_key(KEY_F5, false, 0, None, &mut raw_mode, &mut events);
+
+        // Gaining the focus doesn't discard modifiers that we already know about
+        assert_eq!(mapper.focus_changed(true), Modifiers::SHIFT);
+    }
+
+    fn key_event(event: Option<WindowKeyEvent>) -> KeyEvent {
+        match event {
+            Some(WindowKeyEvent::KeyEvent(event)) => event,
+            _ => panic!("expected a KeyEvent"),
+        }
+    }
+
+    #[test]
+    fn numpad_keys() {
+        let mapper = KeyboardWithFallback::new_from_string(keymap_for_layout("us")).unwrap();
+        let mut events = WindowEventSender::new(|_, _| {});
+        let mut raw_mode = RawKeyMode::default();
+        let num_lock = 1
+            << mapper
+                .selected
+                .keymap
+                .borrow()
+                .mod_get_index(xkb::MOD_NAME_NUM);
+
+        // (evdev code, NumLock on, expected key, expected phys)
+        for (code, locked, key, phys) in [
+            (KEY_KP1, true, KeyCode::Char('1'), PhysKeyCode::Keypad1),
+            (KEY_KP1, false, KeyCode::KeyPadEnd, PhysKeyCode::Keypad1),
+            (KEY_KP7, false, KeyCode::KeyPadHome, PhysKeyCode::Keypad7),
+            (KEY_END, true, KeyCode::End, PhysKeyCode::End),
+            (KEY_HOME, false, KeyCode::Home, PhysKeyCode::Home),
+            (KEY_1, true, KeyCode::Char('1'), PhysKeyCode::K1),
+            (
+                KEY_KPENTER,
+                true,
+                KeyCode::Char('\r'),
+                PhysKeyCode::KeypadEnter,
+            ),
+        ] {
+            let mask = if locked { num_lock } else { 0 };
+
+            // Wayland reports NumLock as a locked modifier
+            mapper.update_modifier_state(0, 0, mask, 0);
+            let wayland = key_event(mapper.process_wayland_key(
+                code,
+                true,
+                0,
+                None,
+                &mut raw_mode,
+                &mut events,
+            ));
+            mapper.process_wayland_key(code, false, 0, None, &mut raw_mode, &mut events);
+            mapper.update_modifier_state(0, 0, 0, 0);
+
+            // whereas X11 passes it in the state of the key event
+            let xcode = xkb::Keycode::new(wayland_key_to_xkb_keycode(code));
+            mapper.merge_current_xcb_modifiers(mask);
+            let x11 = key_event(mapper.process_key_event_impl(
+                xcode,
+                Modifiers::NONE,
+                true,
+                0,
+                None,
+                &mut raw_mode,
+                &mut events,
+                true,
+            ));
+            mapper.reapply_last_xcb_state();
+
+            assert_eq!(wayland.key, key, "key {code} numlock={locked}");
+            assert_eq!(wayland.phys, Some(phys), "key {code} numlock={locked}");
+            assert_eq!(
+                wayland.raw.as_ref().map(|raw| &raw.key),
+                Some(&KeyCode::Physical(phys)),
+                "key {code} numlock={locked}"
+            );
+            assert_eq!(x11.key, wayland.key, "key {code} numlock={locked}");
+            assert_eq!(x11.phys, wayland.phys, "key {code} numlock={locked}");
+            assert_eq!(
+                x11.raw.as_ref().map(|raw| &raw.key),
+                wayland.raw.as_ref().map(|raw| &raw.key),
+                "key {code} numlock={locked}"
+            );
+        }
+    }
+
+    #[test]
+    fn raw_key_mode() {
+        let a = xkb::Keycode::new(38);
+        let b = xkb::Keycode::new(56);
+        let mut mode = RawKeyMode::default();
+
+        // Off by default
+        assert!(!mode.should_report(a, true));
+        assert!(!mode.should_report(a, false));
+
+        mode.set_enabled(true);
+        assert!(mode.should_report(a, true));
+        assert!(mode.should_report(b, true));
+        assert!(mode.should_report(a, false));
+
+        // b is still held when the mode is switched off; its
+        // release is reported but subsequent presses are not
+        mode.set_enabled(false);
+        assert!(mode.should_report(b, false));
+        assert!(!mode.should_report(b, true));
+        assert!(!mode.should_report(b, false));
+
+        // A key that was already held when the mode was switched on
+        // has its release reported
+        assert!(!mode.should_report(a, true));
+        mode.set_enabled(true);
+        assert!(mode.should_report(a, false));
+    }
+
+    #[test]
+    fn keymap_replacement_changes_layout() {
+        let us = KeyboardWithFallback::new_from_string(keymap_for_layout("us")).unwrap();
+        assert_eq!(us.layout_changed(), None);
+
+        let fr = KeyboardWithFallback::new_from_string(keymap_for_layout("fr")).unwrap();
+        fr.inherit_layout(&us);
+        assert_eq!(
+            fr.layout_changed(),
+            Some(KeyboardLayout {
+                name: "French".to_string(),
+                index: 0
+            })
+        );
+        assert_eq!(fr.layout_changed(), None);
+    }
+
+    #[test]
+    fn group_change_changes_layout() {
+        let keymap = KeyboardWithFallback::new_from_string(keymap_for_layout("us,fr")).unwrap();
+        assert_eq!(keysym(&keymap, KEY_Q_POSITION), KEY_q);
+
+        // Wayland reports the group in the modifiers event
+        keymap.update_modifier_state(0, 0, 0, 1);
+        assert_eq!(keysym(&keymap, KEY_Q_POSITION), KEY_a);
+        assert_eq!(keymap.layout_changed().map(|layout| layout.index), Some(1));
+        assert_eq!(keymap.layout_changed(), None);
+    }
+}
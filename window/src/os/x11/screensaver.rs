@@ -0,0 +1,97 @@
+//! X11 has no standard way for a window to keep the screen from blanking
+//! or locking, so we ask the desktop via the org.freedesktop.ScreenSaver
+//! DBus interface.
+//! <https://specifications.freedesktop.org/idle-inhibit-spec/latest/>
+use std::cell::RefCell;
+use std::rc::Rc;
+use zbus::proxy;
+
+#[proxy(
+    interface = "org.freedesktop.ScreenSaver",
+    default_service = "org.freedesktop.ScreenSaver",
+    default_path = "/org/freedesktop/ScreenSaver"
+)]
+trait ScreenSaver {
+    fn Inhibit(&self, application_name: &str, reason_for_inhibit: &str) -> zbus::Result<u32>;
+
+    fn UnInhibit(&self, cookie: u32) -> zbus::Result<()>;
+}
+
+enum InhibitState {
+    /// Waiting for the Inhibit call to complete
+    Pending,
+    /// The desktop may lift the inhibition if the connection that
+    /// requested it goes away, so we keep it open until we're done
+    Active {
+        connection: zbus::Connection,
+        cookie: u32,
+    },
+    /// The inhibitor was dropped, or the Inhibit call failed
+    Released,
+}
+
+/// Keeps the screen from blanking or locking for as long as it is alive
+pub(crate) struct ScreenSaverInhibitor {
+    state: Rc<RefCell<InhibitState>>,
+}
+
+impl ScreenSaverInhibitor {
+    pub(crate) fn new(application_name: &str, reason: &str) -> Self {
+        let state = Rc::new(RefCell::new(InhibitState::Pending));
+        let application_name = application_name.to_string();
+        let reason = reason.to_string();
+        let task_state = Rc::clone(&state);
+        promise::spawn::spawn(async move {
+            match inhibit(&application_name, &reason).await {
+                Ok((connection, cookie)) => {
+                    let mut state = task_state.borrow_mut();
+                    if matches!(*state, InhibitState::Pending) {
+                        *state = InhibitState::Active { connection, cookie };
+                    } else {
+                        // We were dropped while the call was in flight
+                        drop(state);
+                        uninhibit(connection, cookie).await;
+                    }
+                }
+                Err(err) => {
+                    log::warn!("Unable to inhibit the screensaver: {err:#}");
+                    *task_state.borrow_mut() = InhibitState::Released;
+                }
+            }
+        })
+        .detach();
+        Self { state }
+    }
+
+    /// Whether the desktop has confirmed the inhibition
+    pub(crate) fn is_active(&self) -> bool {
+        matches!(*self.state.borrow(), InhibitState::Active { .. })
+    }
+}
+
+impl Drop for ScreenSaverInhibitor {
+    fn drop(&mut self) {
+        let prior = std::mem::replace(&mut *self.state.borrow_mut(), InhibitState::Released);
+        if let InhibitState::Active { connection, cookie } = prior {
+            promise::spawn::spawn(uninhibit(connection, cookie)).detach();
+        }
+    }
+}
+
+async fn inhibit(application_name: &str, reason: &str) -> anyhow::Result<(zbus::Connection, u32)> {
+    let connection = zbus::ConnectionBuilder::session()?.build().await?;
+    let proxy = ScreenSaverProxy::new(&connection).await?;
+    let cookie = proxy.Inhibit(application_name, reason).await?;
+    Ok((connection, cookie))
+}
+
+async fn uninhibit(connection: zbus::Connection, cookie: u32) {
+    let result = async {
+        let proxy = ScreenSaverProxy::new(&connection).await?;
+        proxy.UnInhibit(cookie).await
+    }
+    .await;
+    if let Err(err) = result {
+        log::warn!("Unable to uninhibit the screensaver: {err:#}");
+    }
+}
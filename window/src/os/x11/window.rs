@@ -1,15 +1,24 @@
 use super::*;
 use crate::bitmaps::*;
 use crate::connection::ConnectionOps;
+use crate::os::animation::Animation;
+use crate::os::click_streak::{click_interval_ms, ClickStreak};
+use crate::os::focus_request::{FocusRequests, FOCUS_TIMEOUT};
+use crate::os::fullscreen_restore::FullScreenRestore;
+use crate::os::size_constraints::{snap_to_increments, SizeConstraints};
+use crate::os::x11::screensaver::ScreenSaverInhibitor;
 use crate::os::{xkeysyms, Connection, Window};
+use crate::screen::ScreenInfo;
 use crate::{
-    Appearance, Clipboard, DeadKeyStatus, Dimensions, MouseButtons, MouseCursor, MouseEvent,
-    MouseEventKind, MousePress, Point, Rect, RequestedWindowGeometry, ResizeIncrement,
-    ResolvedGeometry, ScreenPoint, ScreenRect, WindowDecorations, WindowEvent, WindowEventSender,
-    WindowOps, WindowState,
+    AnimationHint, Appearance, Clipboard, ConstrainedResize, DeadKeyStatus, Dimensions,
+    FocusOutcome, FocusReason, InitialWindowState, InputSerial, MouseButtons, MouseCursor,
+    MouseEvent, MouseEventKind, MousePress, Point, Rect, RequestedWindowGeometry, ResizeIncrement,
+    ResizeRejected, ResolvedGeometry, ScreenPoint, ScreenRect, WindowDecorations, WindowEvent,
+    WindowEventSender, WindowOps, WindowOptions, WindowResizeEdge, WindowState,
 };
-use anyhow::{anyhow, Context as _};
+use anyhow::{anyhow, bail, ensure, Context as _};
 use async_trait::async_trait;
+use config::window::WindowLevel;
 use config::ConfigHandle;
 use promise::{Future, Promise};
 use raw_window_handle::{
@@ -17,17 +26,19 @@ use raw_window_handle::{
     RawWindowHandle, WindowHandle, XcbDisplayHandle, XcbWindowHandle,
 };
 use std::any::Any;
+use std::collections::VecDeque;
 use std::convert::TryInto;
 use std::num::NonZeroU32;
 use std::path::PathBuf;
 use std::ptr::NonNull;
 use std::rc::{Rc, Weak};
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, TryLockError};
+use std::time::Instant;
 use url::Url;
 use wezterm_font::FontConfiguration;
 use wezterm_input_types::{KeyCode, KeyEvent, KeyboardLedStatus, Modifiers};
 use xcb::x::{Atom, PropMode};
-use xcb::{Event, Xid};
+use xcb::{BaseEvent, Event, Xid};
 
 #[derive(Default)]
 struct CopyAndPaste {
@@ -35,6 +46,8 @@ struct CopyAndPaste {
     primary_selection_owned: Option<String>,
     clipboard_request: Option<Promise<String>>,
     selection_request: Option<Promise<String>>,
+    clipboard_targets_request: Option<Promise<Vec<String>>>,
+    selection_targets_request: Option<Promise<Vec<String>>>,
     time: u32,
 }
 
@@ -59,6 +72,13 @@ impl CopyAndPaste {
             Clipboard::Clipboard => &mut self.clipboard_request,
         }
     }
+
+    fn targets_request_mut(&mut self, clipboard: Clipboard) -> &mut Option<Promise<Vec<String>>> {
+        match clipboard {
+            Clipboard::PrimarySelection => &mut self.selection_targets_request,
+            Clipboard::Clipboard => &mut self.clipboard_targets_request,
+        }
+    }
 }
 
 struct DragAndDrop {
@@ -106,18 +126,114 @@ pub(crate) struct XWindowInner {
     pending: Vec<WindowEvent>,
     sure_about_geometry: bool,
     current_mouse_event: Option<MouseEvent>,
+    // The button that is currently held down, if any
+    held_button: Option<MousePress>,
     window_drag_position: Option<ScreenPoint>,
     dragging: bool,
     outstanding_configure_requests: usize,
+    // How many of the outstanding_configure_requests were made by
+    // resize_window rather than set_inner_size, and so shouldn't
+    // be reported as SetInnerSizeCompleted
+    unreported_configure_requests: usize,
     pending_finished_resizes: usize,
+    // The promises returned by set_inner_size, resolved in order as
+    // the pending_finished_resizes are reported
+    inner_size_requests: VecDeque<Promise<Dimensions>>,
+    // The most recent size requested via set_inner_size, which is
+    // reported by get_dimensions until the server confirms it
+    requested_inner_size: Option<(usize, usize)>,
+    // Set while the pointer is grabbed by lock_pointer or confine_pointer
+    pointer_grab: Option<PointerGrab>,
+    click_streak: ClickStreak,
+    raw_key_mode: RawKeyMode,
+    animation: Animation,
+    // Set while a timer is waiting for the next animation frame
+    animation_timer: bool,
+    // Used to identify us when inhibiting the screensaver
+    class_name: String,
+    // The WM_CLASS instance name, if it differs from class_name;
+    // popups inherit both so that window manager rules match them
+    instance_name: Option<String>,
+    // As passed to set_idle_inhibited, and the inhibitor that is
+    // in place while that is set and the window is shown
+    idle_inhibit: bool,
+    idle_inhibitor: Option<ScreenSaverInhibitor>,
+    // As passed to set_shortcut_inhibition, and whether we are holding
+    // the keyboard grab that approximates it while we have the focus
+    shortcut_inhibit: bool,
+    keyboard_grabbed: bool,
+    // Calls to set_shortcut_inhibition that are waiting for us to gain
+    // the focus, as we can only take the grab while we have it
+    shortcut_inhibit_requests: Vec<Promise<()>>,
+    // Set by hide() and cleared by show()
+    hidden: bool,
+    // These determine whether the window can be seen: it must be
+    // mapped, not minimized (some window managers keep minimized
+    // windows mapped) and not entirely covered by other windows.
+    // Painting is held back while it can't be seen.
+    mapped: bool,
+    minimized: bool,
+    fully_obscured: bool,
+    // As last reported via WindowEvent::VisibilityChanged
+    visible: bool,
+    // Calls to focus() that are waiting for the window manager
+    focus_requests: FocusRequests,
+    // These are combined in WM_NORMAL_HINTS
+    resize_increments: Option<ResizeIncrement>,
+    size_constraints: SizeConstraints,
+    full_screen_restore: FullScreenRestore,
+    // The screen most recently reported via WindowEvent::ScreenChanged
+    current_screen: Option<ScreenInfo>,
+    // The position most recently reported via WindowEvent::Moved
+    position: Option<ScreenPoint>,
+    // Whether the window manager has put us inside its frame window
+    reparented: bool,
+    // Set for child windows created via WindowOptions::parent
+    parent: Option<xcb::x::Window>,
+    // Overrides config.window_decorations; from WindowOptions::decorations
+    decorations: Option<WindowDecorations>,
+    // Requested via set_window_level, set_always_on_top and
+    // set_skip_taskbar. Window managers drop _NET_WM_STATE when a
+    // window is withdrawn, so these are re-applied whenever the
+    // window is mapped again
+    window_level: WindowLevel,
+    skip_taskbar: bool,
+}
+
+enum PointerGrabKind {
+    /// The pointer is warped back to `position` after each motion
+    Locked {
+        position: Point,
+        position_hint: Option<Point>,
+    },
+    /// The pointer is confined to an InputOnly child `window`
+    /// that covers `rect`
+    Confined { window: xcb::x::Window, rect: Rect },
+}
+
+struct PointerGrab {
+    kind: PointerGrabKind,
+    /// The last known pointer position, used to compute relative motion
+    last_position: Point,
 }
 
 /// <https://specifications.freedesktop.org/wm-spec/wm-spec-latest.html#idm46409506331616>
+const _NET_WM_MOVERESIZE_SIZE_TOPLEFT: u32 = 0;
+const _NET_WM_MOVERESIZE_SIZE_TOP: u32 = 1;
+const _NET_WM_MOVERESIZE_SIZE_TOPRIGHT: u32 = 2;
+const _NET_WM_MOVERESIZE_SIZE_RIGHT: u32 = 3;
+const _NET_WM_MOVERESIZE_SIZE_BOTTOMRIGHT: u32 = 4;
+const _NET_WM_MOVERESIZE_SIZE_BOTTOM: u32 = 5;
+const _NET_WM_MOVERESIZE_SIZE_BOTTOMLEFT: u32 = 6;
+const _NET_WM_MOVERESIZE_SIZE_LEFT: u32 = 7;
 const _NET_WM_MOVERESIZE_MOVE: u32 = 8;
 const _NET_WM_MOVERESIZE_CANCEL: u32 = 11;
 
 impl Drop for XWindowInner {
     fn drop(&mut self) {
+        for mut prom in self.inner_size_requests.drain(..) {
+            prom.err(anyhow!("window was closed before it was resized"));
+        }
         if self.window_id != xcb::x::Window::none() {
             if let Some(conn) = self.conn.upgrade() {
                 self.conn()
@@ -217,6 +333,7 @@ impl XWindowInner {
             if let Some(event) = self.current_mouse_event.take() {
                 self.do_mouse_event(MouseEvent {
                     kind: MouseEventKind::Release(MousePress::Left),
+                    streak: 0,
                     ..event
                 })
                 .ok();
@@ -239,6 +356,11 @@ impl XWindowInner {
         self.cursors.set_cursor(self.window_id, cursor)
     }
 
+    fn set_custom_cursor(&mut self, image: &Image, hotspot: Point) -> anyhow::Result<()> {
+        self.cursors
+            .set_custom_cursor(self.window_id, image, hotspot)
+    }
+
     fn check_dpi_and_synthesize_resize(&mut self) {
         let conn = self.conn();
         let dpi = conn.default_dpi();
@@ -316,11 +438,20 @@ impl XWindowInner {
         // above Resized events because a resize cannot finish before it occurs.
         while self.pending_finished_resizes > 0 {
             self.events.dispatch(WindowEvent::SetInnerSizeCompleted);
+            if let Some(mut prom) = self.inner_size_requests.pop_front() {
+                prom.ok(Dimensions {
+                    pixel_width: self.width as usize,
+                    pixel_height: self.height as usize,
+                    dpi: self.dpi as usize,
+                });
+            }
             self.pending_finished_resizes -= 1;
         }
 
         if need_paint {
-            if self.paint_throttled {
+            if self.paint_throttled || !self.visible {
+                // We'll paint once the throttle expires or
+                // the window can be seen again
                 self.invalidated = true;
             } else {
                 self.invalidated = false;
@@ -339,8 +470,7 @@ impl XWindowInner {
                         self.has_focus
                     );
                     if Some(focused) != self.has_focus {
-                        self.has_focus.replace(focused);
-                        self.events.dispatch(WindowEvent::FocusChanged(focused));
+                        self.focus_changed(focused, FocusReason::Unknown);
                     }
 
                     self.verify_focus = false;
@@ -378,6 +508,8 @@ impl XWindowInner {
                         self.width = geom.width();
                         self.height = geom.height();
                         self.last_wm_state = window_state;
+                        self.update_idle_inhibitor();
+                        self.restore_pre_full_screen_size();
 
                         self.events.dispatch(WindowEvent::Resized {
                             dimensions: Dimensions {
@@ -391,6 +523,9 @@ impl XWindowInner {
                     }
                 }
 
+                if let Some(elapsed) = self.animation.tick(Instant::now()) {
+                    self.events.dispatch(WindowEvent::AnimationFrame(elapsed));
+                }
                 self.events.dispatch(WindowEvent::NeedRepaint);
 
                 self.paint_throttled = true;
@@ -403,6 +538,8 @@ impl XWindowInner {
                         inner.paint_throttled = false;
                         if inner.invalidated {
                             inner.invalidate();
+                        } else {
+                            inner.schedule_animation_frame();
                         }
                         Ok(())
                     });
@@ -427,26 +564,43 @@ impl XWindowInner {
     ) -> anyhow::Result<()> {
         self.copy_and_paste.time = time;
 
+        let button = match detail {
+            1 => Some(MousePress::Left),
+            2 => Some(MousePress::Middle),
+            3 => Some(MousePress::Right),
+            // 4-7 are the vertical and horizontal scroll wheels
+            8 => Some(MousePress::Back),
+            9 => Some(MousePress::Forward),
+            b @ 10..=u8::MAX => Some(MousePress::Other(b.into())),
+            _ => None,
+        };
+        if let Some(button) = button {
+            if pressed {
+                self.held_button.replace(button);
+            } else if self.held_button == Some(button) {
+                self.held_button.take();
+            }
+        }
+
         if self.cancel_drag() {
             log::debug!("cancel drag due to button {detail} {state:?}");
             return Ok(());
         }
 
-        let kind = match detail {
-            b @ 1..=3 => {
-                let button = match b {
-                    1 => MousePress::Left,
-                    2 => MousePress::Middle,
-                    3 => MousePress::Right,
-                    _ => unreachable!(),
-                };
+        let kind = match (detail, button) {
+            (_, Some(button)) => {
                 if pressed {
                     MouseEventKind::Press(button)
                 } else {
                     MouseEventKind::Release(button)
                 }
             }
-            b @ 4..=5 => {
+            // The core protocol reports scrolling as presses of buttons
+            // 4-7 without saying which device produced them, so X11
+            // only ever produces wheel events: there is no PixelScroll
+            // or ScrollStopped, and consumers should treat these as
+            // coming from a ScrollSource::Wheel.
+            (b @ 4..=7, None) => {
                 if !pressed {
                     return Ok(());
                 }
@@ -456,11 +610,12 @@ impl XWindowInner {
                 // default for now!
                 const LINES_PER_TICK: i16 = 5;
 
-                MouseEventKind::VertWheel(if b == 4 {
-                    LINES_PER_TICK
-                } else {
-                    -LINES_PER_TICK
-                })
+                match b {
+                    4 => MouseEventKind::VertWheel(LINES_PER_TICK),
+                    5 => MouseEventKind::VertWheel(-LINES_PER_TICK),
+                    6 => MouseEventKind::HorzWheel(1),
+                    _ => MouseEventKind::HorzWheel(-1),
+                }
             }
             _ => {
                 log::trace!("button {} is not implemented", detail);
@@ -468,51 +623,64 @@ impl XWindowInner {
             }
         };
 
+        let (coords, screen_coords) = pointer_position((event_x, event_y), (root_x, root_y));
+        let streak = match kind {
+            MouseEventKind::Press(button) => {
+                let interval = click_interval_ms(&self.config);
+                self.click_streak.press(button, coords, time, interval)
+            }
+            _ => 0,
+        };
         let event = MouseEvent {
             kind,
-            coords: Point::new(event_x.try_into().unwrap(), event_y.try_into().unwrap()),
-            screen_coords: ScreenPoint::new(root_x.try_into().unwrap(), root_y.try_into().unwrap()),
+            coords,
+            screen_coords: Some(screen_coords),
             modifiers: xkeysyms::modifiers_from_state(state.bits()),
             mouse_buttons: MouseButtons::default(),
+            streak,
+            time,
+            serial: Some(InputSerial::new(time)),
         };
         self.do_mouse_event(event)
     }
 
-    fn configure_notify(&mut self, source: &str, width: u16, height: u16) -> anyhow::Result<()> {
+    /// `origin` is the position of the window on the screen, if the
+    /// event that prompted this told us what it is
+    fn configure_notify(
+        &mut self,
+        source: &str,
+        width: u16,
+        height: u16,
+        origin: Option<ScreenPoint>,
+    ) -> anyhow::Result<()> {
         let conn = self.conn();
 
         self.update_ime_position();
 
         let mut dpi = conn.default_dpi();
 
+        // Window managers send a synthetic ConfigureNotify when they move
+        // the frame, so this is also where we learn about moves
+        let origin = match origin {
+            Some(origin) => Ok(origin),
+            None => self.window_origin(),
+        };
+        if let Ok(origin) = &origin {
+            self.update_position(*origin);
+        }
+        let screen = match origin.and_then(|origin| self.screen_for_window(origin, width, height)) {
+            Ok(screen) => screen,
+            // Only fatal if we need the screen to determine the dpi
+            Err(err) if self.config.dpi_by_screen.is_empty() => {
+                log::debug!("Unable to determine the screen of the window: {err:#}");
+                None
+            }
+            Err(err) => return Err(err),
+        };
         if !self.config.dpi_by_screen.is_empty() {
-            let coords = conn
-                .send_and_wait_request(&xcb::x::TranslateCoordinates {
-                    src_window: self.window_id,
-                    dst_window: conn.root,
-                    src_x: 0,
-                    src_y: 0,
-                })
-                .context("querying window coordinates")?;
-            let screens = conn.get_cached_screens()?;
-            let window_rect: ScreenRect = euclid::rect(
-                coords.dst_x().into(),
-                coords.dst_y().into(),
-                width as isize,
-                height as isize,
-            );
-            let screen = screens
-                .by_name
-                .values()
-                .filter_map(|screen| {
-                    screen
-                        .rect
-                        .intersection(&window_rect)
-                        .map(|r| (screen, r.area()))
-                })
-                .max_by_key(|s| s.1)
-                .ok_or_else(|| anyhow::anyhow!("window is not in any screen"))?
-                .0;
+            let screen = screen
+                .as_ref()
+                .ok_or_else(|| anyhow::anyhow!("window is not in any screen"))?;
 
             if let Some(value) = self.config.dpi_by_screen.get(&screen.name).copied() {
                 dpi = value;
@@ -520,6 +688,7 @@ impl XWindowInner {
                 dpi = value;
             }
         }
+        self.update_current_screen(screen);
 
         if width == self.width && height == self.height && dpi == self.dpi {
             // Effectively unchanged; perhaps it was simply moved?
@@ -547,6 +716,8 @@ impl XWindowInner {
         self.height = height;
         self.dpi = dpi;
         self.last_wm_state = self.get_window_state().unwrap_or(WindowState::default());
+        self.update_idle_inhibitor();
+        self.restore_pre_full_screen_size();
 
         let dimensions = Dimensions {
             pixel_width: self.width as usize,
@@ -564,6 +735,72 @@ impl XWindowInner {
         Ok(())
     }
 
+    /// The position of the top left of the client area on the screen.
+    /// This is where set_window_position puts it, as it asks for
+    /// static gravity, so the frame extents don't come into it.
+    /// Returns the screen position of the client window.
+    /// This deliberately ignores _NET_FRAME_EXTENTS: set_window_position
+    /// moves the client window using static gravity, and
+    /// get_window_position must report the same point.
+    fn window_origin(&self) -> anyhow::Result<ScreenPoint> {
+        let conn = self.conn();
+        let coords = conn
+            .send_and_wait_request(&xcb::x::TranslateCoordinates {
+                src_window: self.window_id,
+                dst_window: conn.root,
+                src_x: 0,
+                src_y: 0,
+            })
+            .context("querying window coordinates")?;
+        Ok(ScreenPoint::new(
+            coords.dst_x().into(),
+            coords.dst_y().into(),
+        ))
+    }
+
+    fn update_position(&mut self, position: ScreenPoint) {
+        if self.position != Some(position) {
+            self.position.replace(position);
+            self.queue_pending(WindowEvent::Moved(position));
+        }
+    }
+
+    /// Returns the screen that holds most of the window, which has
+    /// the specified origin and size
+    fn screen_for_window(
+        &self,
+        origin: ScreenPoint,
+        width: u16,
+        height: u16,
+    ) -> anyhow::Result<Option<ScreenInfo>> {
+        let conn = self.conn();
+        let screens = conn.get_cached_screens()?;
+        let window_rect: ScreenRect =
+            euclid::rect(origin.x, origin.y, width as isize, height as isize);
+        Ok(screens
+            .by_name
+            .values()
+            .filter_map(|screen| {
+                screen
+                    .rect
+                    .intersection(&window_rect)
+                    .map(|r| (screen, r.area()))
+            })
+            .max_by_key(|s| s.1)
+            .map(|(screen, _)| screen.clone()))
+    }
+
+    fn update_current_screen(&mut self, screen: Option<ScreenInfo>) {
+        let Some(screen) = screen else {
+            // Off screen; keep reporting the last one
+            return;
+        };
+        if self.current_screen.as_ref().map(|s| &s.name) != Some(&screen.name) {
+            self.current_screen.replace(screen.clone());
+            self.queue_pending(WindowEvent::ScreenChanged(screen));
+        }
+    }
+
     fn xdnd_event(&mut self, msgtype: Atom, data: &[u32]) -> anyhow::Result<()> {
         use xcb::XidNew;
         let conn = self.conn();
@@ -699,40 +936,73 @@ impl XWindowInner {
                 );
             }
             Event::Present(xcb::present::Event::ConfigureNotify(cfg)) => {
-                self.configure_notify("Present::ConfigureNotify", cfg.width(), cfg.height())?;
+                self.configure_notify(
+                    "Present::ConfigureNotify",
+                    cfg.width(),
+                    cfg.height(),
+                    self.position,
+                )?;
             }
             Event::X(xcb::x::Event::ConfigureNotify(cfg)) => {
-                self.configure_notify("X::ConfigureNotify", cfg.width(), cfg.height())?;
+                // The coordinates are relative to our parent, which is
+                // the root window unless the window manager has put us
+                // inside its frame. Synthetic events from the window
+                // manager are supposed to be in root coordinates, but
+                // not all of them get that right, so we ask the server
+                // in both of those cases.
+                let origin = if self.reparented || cfg.is_from_send_event() {
+                    None
+                } else {
+                    Some(ScreenPoint::new(cfg.x().into(), cfg.y().into()))
+                };
+                self.configure_notify("X::ConfigureNotify", cfg.width(), cfg.height(), origin)?;
                 if self.outstanding_configure_requests > 0 {
                     self.outstanding_configure_requests -= 1;
-                    self.pending_finished_resizes += 1;
+                    if self.unreported_configure_requests > 0 {
+                        self.unreported_configure_requests -= 1;
+                    } else {
+                        self.pending_finished_resizes += 1;
+                    }
+                    if self.outstanding_configure_requests == 0 {
+                        self.requested_inner_size.take();
+                    }
                 }
             }
             Event::X(xcb::x::Event::KeyPress(key_press)) => {
                 self.copy_and_paste.time = key_press.time();
-                conn.keyboard
-                    .process_key_press_event(key_press, &mut self.events);
+                conn.keyboard.process_key_press_event(
+                    key_press,
+                    &mut self.raw_key_mode,
+                    &mut self.events,
+                );
             }
             Event::X(xcb::x::Event::KeyRelease(key_release)) => {
                 self.copy_and_paste.time = key_release.time();
-                conn.keyboard
-                    .process_key_release_event(key_release, &mut self.events);
+                conn.keyboard.process_key_release_event(
+                    key_release,
+                    &mut self.raw_key_mode,
+                    &mut self.events,
+                );
             }
             Event::X(xcb::x::Event::MotionNotify(motion)) => {
+                let (coords, screen_coords) = pointer_position(
+                    (motion.event_x(), motion.event_y()),
+                    (motion.root_x(), motion.root_y()),
+                );
                 let event = MouseEvent {
                     kind: MouseEventKind::Move,
-                    coords: Point::new(
-                        motion.event_x().try_into().unwrap(),
-                        motion.event_y().try_into().unwrap(),
-                    ),
-                    screen_coords: ScreenPoint::new(
-                        motion.root_x().try_into().unwrap(),
-                        motion.root_y().try_into().unwrap(),
-                    ),
+                    coords,
+                    screen_coords: Some(screen_coords),
                     modifiers: xkeysyms::modifiers_from_state(motion.state().bits()),
                     mouse_buttons: MouseButtons::default(),
+                    streak: 0,
+                    time: motion.time(),
+                    serial: None,
                 };
-                self.do_mouse_event(event)?;
+                self.click_streak.moved(event.coords);
+                if !self.grabbed_pointer_motion(&event) {
+                    self.do_mouse_event(event)?;
+                }
             }
             Event::X(xcb::x::Event::ButtonPress(e)) => {
                 self.button_event(
@@ -784,7 +1054,7 @@ impl XWindowInner {
                             conn.atom_name(protocol_atom)
                         );
                         if protocol_atom == conn.atom_delete {
-                            self.events.dispatch(WindowEvent::CloseRequested);
+                            self.events.request_close();
                         }
                     } else {
                         log::warn!("Received ClientMessage {type_atom_name} with wrong format");
@@ -826,6 +1096,8 @@ impl XWindowInner {
                     self.check_dpi_and_synthesize_resize();
                     let appearance = conn.get_appearance();
                     self.appearance_changed(appearance);
+                    // It also reflects changes in the tiled state
+                    self.sure_about_geometry = false;
                 }
 
                 if msg.atom() == conn.atom_net_wm_state {
@@ -834,16 +1106,61 @@ impl XWindowInner {
                     // events consistently/at all/in the same order.
                     self.sure_about_geometry = false;
                     self.verify_focus = true;
+                    // We don't paint while minimized, so we can't wait for
+                    // the geometry verification that happens when we do
+                    // to find out that we've been restored
+                    self.minimized = self
+                        .get_window_state()
+                        .map(|state| state.contains(WindowState::HIDDEN))
+                        .unwrap_or(false);
+                    self.update_visibility();
                 }
             }
+            Event::X(xcb::x::Event::ReparentNotify(e)) => {
+                self.reparented = e.parent() != conn.root;
+            }
+            Event::X(xcb::x::Event::MapNotify(_)) => {
+                self.mapped = true;
+                self.update_visibility();
+            }
+            Event::X(xcb::x::Event::UnmapNotify(_)) => {
+                self.mapped = false;
+                self.update_visibility();
+            }
+            Event::X(xcb::x::Event::VisibilityNotify(e)) => {
+                self.fully_obscured = e.state() == xcb::x::Visibility::FullyObscured;
+                self.update_visibility();
+            }
             Event::X(xcb::x::Event::FocusIn(e)) => {
                 if !matches!(e.detail(), xcb::x::NotifyDetail::Pointer) {
-                    self.focus_changed(true);
+                    self.focus_changed(true, focus_reason(e.mode()));
+                    self.update_keyboard_grab();
+                    // Modifier state changes are only routed to the focused
+                    // window, so we won't have heard about any that were
+                    // pressed before we gained the focus (eg: shift-click to
+                    // focus).  The xkb state is tracked for the device
+                    // regardless of focus, so take the current state from it.
+                    self.events.dispatch(WindowEvent::AdviseModifiersLedStatus(
+                        conn.keyboard.get_key_modifiers(),
+                        conn.keyboard.get_led_status(),
+                    ));
                 }
             }
             Event::X(xcb::x::Event::FocusOut(e)) => {
                 if !matches!(e.detail(), xcb::x::NotifyDetail::Pointer) {
-                    self.focus_changed(false);
+                    self.unlock_pointer();
+                    self.focus_changed(false, focus_reason(e.mode()));
+                    self.update_keyboard_grab();
+                }
+            }
+            Event::X(xcb::x::Event::EnterNotify(e)) => {
+                // Crossings caused by grabs starting or ending aren't
+                // real transitions of the pointer
+                if e.mode() == xcb::x::NotifyMode::Normal {
+                    self.events.dispatch(WindowEvent::MouseEnter(Point::new(
+                        e.event_x().into(),
+                        e.event_y().into(),
+                    )));
                 }
             }
             Event::X(xcb::x::Event::LeaveNotify(_)) => {
@@ -857,6 +1174,22 @@ impl XWindowInner {
         Ok(())
     }
 
+    /// Report a change in visibility and, upon becoming visible,
+    /// paint if we were invalidated while we couldn't be seen
+    fn update_visibility(&mut self) {
+        let visible = self.mapped && !self.minimized && !self.fully_obscured;
+        if visible == self.visible {
+            return;
+        }
+        self.visible = visible;
+        log::trace!("visibility_changed {visible}");
+        self.events
+            .dispatch(WindowEvent::VisibilityChanged(visible));
+        if visible && self.invalidated && !self.paint_throttled {
+            self.invalidate();
+        }
+    }
+
     pub(crate) fn appearance_changed(&mut self, appearance: Appearance) {
         if appearance != self.appearance {
             self.appearance = appearance;
@@ -865,14 +1198,18 @@ impl XWindowInner {
         }
     }
 
-    fn focus_changed(&mut self, focused: bool) {
+    fn focus_changed(&mut self, focused: bool, mut reason: FocusReason) {
         log::trace!("focus_changed {focused}, flagging geometry as unsure");
         self.sure_about_geometry = false;
+        if focused && self.focus_requests.focused() && reason == FocusReason::Unknown {
+            reason = FocusReason::Activation;
+        }
         if self.has_focus != Some(focused) {
             self.has_focus.replace(focused);
             self.update_ime_position();
-            log::trace!("Calling focus_change({focused})");
-            self.events.dispatch(WindowEvent::FocusChanged(focused));
+            log::trace!("Calling focus_change({focused}, {reason:?})");
+            self.events
+                .dispatch(WindowEvent::FocusChanged(focused, reason));
         }
     }
 
@@ -888,6 +1225,9 @@ impl XWindowInner {
             modifiers: Modifiers::NONE,
             repeat_count: 1,
             key_is_down: true,
+            phys: None,
+            time: 0,
+            serial: None,
             raw: None,
         }
         .normalize_shift()
@@ -1051,6 +1391,48 @@ impl XWindowInner {
         Ok(())
     }
 
+    /// Resolves a list_clipboard_formats request with the TARGETS
+    /// reported by the selection owner
+    fn targets_notify(
+        &mut self,
+        clipboard: Clipboard,
+        selection: &xcb::x::SelectionNotifyEvent,
+    ) -> anyhow::Result<()> {
+        let conn = self.conn();
+        let Some(mut promise) = self.copy_and_paste.targets_request_mut(clipboard).take() else {
+            return Ok(());
+        };
+
+        if selection.property() == xcb::x::ATOM_NONE {
+            // There is no owner, or the owner doesn't support TARGETS
+            promise.ok(vec![]);
+            return Ok(());
+        }
+
+        match conn.send_and_wait_request(&xcb::x::GetProperty {
+            delete: true,
+            window: selection.requestor(),
+            property: selection.property(),
+            r#type: xcb::x::ATOM_ATOM,
+            long_offset: 0,
+            long_length: u32::max_value(),
+        }) {
+            Ok(prop) => {
+                let targets = prop
+                    .value::<Atom>()
+                    .iter()
+                    .map(|&atom| conn.atom_name(atom))
+                    .collect();
+                promise.ok(targets);
+            }
+            Err(err) => {
+                log::error!("clipboard: err while getting TARGETS property: {err:#}");
+                promise.err(err);
+            }
+        }
+        Ok(())
+    }
+
     fn selection_notify(&mut self, selection: &xcb::x::SelectionNotifyEvent) -> anyhow::Result<()> {
         let conn = self.conn();
         let window_id = self.window_id;
@@ -1063,6 +1445,10 @@ impl XWindowInner {
         );
 
         if let Some(clipboard) = self.selection_atom_to_clipboard(selection.selection()) {
+            if selection.target() == conn.atom_targets {
+                return self.targets_notify(clipboard, selection);
+            }
+
             if selection.property() == xcb::x::ATOM_NONE {
                 if selection.target() == conn.atom_utf8_string {
                     log::trace!(
@@ -1211,6 +1597,35 @@ impl XWindowInner {
                 window_state |= WindowState::MAXIMIZED;
             } else if s == conn.atom_state_hidden.resource_id() {
                 window_state |= WindowState::HIDDEN;
+            } else if s == conn.atom_state_above.resource_id() {
+                window_state |= WindowState::ALWAYS_ON_TOP;
+            } else if s == conn.atom_state_below.resource_id() {
+                window_state |= WindowState::ALWAYS_ON_BOTTOM;
+            }
+        }
+
+        // _NET_WM_STATE has no notion of tiling, but mutter and other
+        // gtk-aware window managers publish the tiled edges via
+        // _GTK_EDGE_CONSTRAINTS; even bits indicate the tiled state
+        // for the top, right, bottom and left edges respectively.
+        let reply = conn.send_and_wait_request(&xcb::x::GetProperty {
+            delete: false,
+            window: self.window_id,
+            property: conn.atom_gtk_edge_constraints,
+            r#type: xcb::x::ATOM_CARDINAL,
+            long_offset: 0,
+            long_length: 1,
+        })?;
+        if let Some(&constraints) = reply.value::<u32>().first() {
+            for (bit, flag) in [
+                (1 << 0, WindowState::TILED_TOP),
+                (1 << 2, WindowState::TILED_RIGHT),
+                (1 << 4, WindowState::TILED_BOTTOM),
+                (1 << 6, WindowState::TILED_LEFT),
+            ] {
+                if constraints & bit != 0 {
+                    window_state |= flag;
+                }
             }
         }
 
@@ -1245,8 +1660,79 @@ impl XWindowInner {
             ),
         })?;
         conn.flush()?;
-        self.adjust_decorations(self.config.window_decorations)?;
+        self.adjust_decorations(self.window_decorations())?;
+
+        Ok(())
+    }
+
+    fn set_window_level(&mut self, level: WindowLevel) -> anyhow::Result<()> {
+        self.window_level = level;
+        self.set_wm_state(
+            NetWmStateAction::with_bool(self.window_level == WindowLevel::AlwaysOnTop),
+            self.conn().atom_state_above,
+            None,
+        )?;
+        self.set_wm_state(
+            NetWmStateAction::with_bool(self.window_level == WindowLevel::AlwaysOnBottom),
+            self.conn().atom_state_below,
+            None,
+        )
+    }
+
+    fn set_always_on_top(&mut self, enable: bool) -> anyhow::Result<()> {
+        if enable {
+            self.set_window_level(WindowLevel::AlwaysOnTop)
+        } else if self.window_level == WindowLevel::AlwaysOnTop {
+            self.set_window_level(WindowLevel::Normal)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn set_skip_taskbar(&mut self, enable: bool) -> anyhow::Result<()> {
+        self.skip_taskbar = enable;
+        self.set_wm_state(
+            NetWmStateAction::with_bool(enable),
+            self.conn().atom_state_skip_taskbar,
+            None,
+        )
+    }
 
+    /// The window manager only honors client messages for mapped windows,
+    /// and it removes _NET_WM_STATE when a window is withdrawn, so
+    /// before mapping the window we put back the hints that we asked
+    /// for directly in the property
+    fn restore_wm_state_hints(&mut self) -> anyhow::Result<()> {
+        let conn = self.conn();
+        let reply = conn.send_and_wait_request(&xcb::x::GetProperty {
+            delete: false,
+            window: self.window_id,
+            property: conn.atom_net_wm_state,
+            r#type: xcb::x::ATOM_ATOM,
+            long_offset: 0,
+            long_length: 1024,
+        })?;
+        let state = wm_state_with_hints(
+            reply.value::<Atom>(),
+            &[
+                (
+                    conn.atom_state_above,
+                    self.window_level == WindowLevel::AlwaysOnTop,
+                ),
+                (
+                    conn.atom_state_below,
+                    self.window_level == WindowLevel::AlwaysOnBottom,
+                ),
+                (conn.atom_state_skip_taskbar, self.skip_taskbar),
+            ],
+        );
+        conn.send_request_no_reply(&xcb::x::ChangeProperty {
+            mode: PropMode::Replace,
+            window: self.window_id,
+            property: conn.atom_net_wm_state,
+            r#type: xcb::x::ATOM_ATOM,
+            data: &state,
+        })?;
         Ok(())
     }
 
@@ -1266,6 +1752,10 @@ impl XWindowInner {
         )
     }
 
+    fn window_decorations(&self) -> WindowDecorations {
+        self.decorations.unwrap_or(self.config.window_decorations)
+    }
+
     #[allow(clippy::identity_op)]
     fn adjust_decorations(&mut self, decorations: WindowDecorations) -> anyhow::Result<()> {
         // Set the motif hints to disable decorations.
@@ -1331,6 +1821,14 @@ impl XWindowInner {
     }
 }
 
+/// How a newly created window relates to another window
+#[derive(Debug, Clone, Copy)]
+enum XWindowKind {
+    Toplevel,
+    Dialog { parent: xcb::x::Window, modal: bool },
+    Popup { parent: xcb::x::Window },
+}
+
 /// A Window!
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct XWindow(xcb::x::Window);
@@ -1360,6 +1858,7 @@ impl XWindow {
         geometry: RequestedWindowGeometry,
         config: Option<&ConfigHandle>,
         _font_config: Rc<FontConfiguration>,
+        options: WindowOptions<'_>,
         event_handler: F,
     ) -> anyhow::Result<Window>
     where
@@ -1377,12 +1876,136 @@ impl XWindow {
             })?
             .x11();
 
+        let kind = match options.parent {
+            Some(Window::X11(parent)) => XWindowKind::Dialog {
+                parent: parent.0,
+                modal: options.modal,
+            },
+            Some(parent) => bail!("{parent:?} is not an X11 window"),
+            None => XWindowKind::Toplevel,
+        };
+
+        let geometry = conn.resolve_geometry(geometry);
+        Self::create_window(
+            class_name,
+            name,
+            geometry,
+            &config,
+            kind,
+            options,
+            event_handler,
+        )
+        .await
+    }
+
+    /// Create a popup, such as a menu, that is placed adjacent to
+    /// `anchor`, a rectangle in the parent's pixel coordinates.
+    /// This is an override-redirect window, so the window manager
+    /// neither decorates nor focuses it, and it is up to the
+    /// application to close it.
+    pub async fn new_popup<F>(
+        parent: &XWindow,
+        anchor: Rect,
+        size: (usize, usize),
+        event_handler: F,
+    ) -> anyhow::Result<Window>
+    where
+        F: 'static + FnMut(WindowEvent, &Window),
+    {
+        let conn = Connection::get()
+            .ok_or_else(|| {
+                anyhow!(
+                "new_popup must be called on the gui thread after Connection::init has succeeded",
+            )
+            })?
+            .x11();
+
+        let (config, child_id, class_name, instance_name) = {
+            let parent = conn
+                .window_by_id(parent.0)
+                .ok_or_else(|| anyhow!("parent window is gone"))?;
+            let parent = parent.lock().unwrap();
+            (
+                parent.config.clone(),
+                parent.child_id,
+                parent.class_name.clone(),
+                parent.instance_name.clone(),
+            )
+        };
+
+        let origin = conn
+            .send_and_wait_request(&xcb::x::TranslateCoordinates {
+                src_window: child_id,
+                dst_window: conn.root,
+                src_x: 0,
+                src_y: 0,
+            })
+            .context("querying parent window coordinates")?;
+        let anchor: ScreenRect = euclid::rect(
+            anchor.min_x() + isize::from(origin.dst_x()),
+            anchor.min_y() + isize::from(origin.dst_y()),
+            anchor.width(),
+            anchor.height(),
+        );
+
+        let bounds = conn
+            .get_cached_screens()
+            .ok()
+            .and_then(|screens| {
+                screens
+                    .by_name
+                    .values()
+                    .find(|screen| screen.rect.contains(anchor.origin))
+                    .map(|screen| screen.rect)
+            })
+            .unwrap_or_else(|| euclid::rect(0, 0, 65535, 65535));
+        let position = place_popup(anchor, size, bounds);
+
+        let geometry = ResolvedGeometry {
+            x: Some(position.x.try_into()?),
+            y: Some(position.y.try_into()?),
+            width: size.0,
+            height: size.1,
+        };
+        Self::create_window(
+            &class_name,
+            "",
+            geometry,
+            &config,
+            XWindowKind::Popup { parent: parent.0 },
+            WindowOptions {
+                instance_name: instance_name.as_deref(),
+                ..Default::default()
+            },
+            event_handler,
+        )
+        .await
+    }
+
+    async fn create_window<F>(
+        class_name: &str,
+        name: &str,
+        geometry: ResolvedGeometry,
+        config: &ConfigHandle,
+        kind: XWindowKind,
+        options: WindowOptions<'_>,
+        event_handler: F,
+    ) -> anyhow::Result<Window>
+    where
+        F: 'static + FnMut(WindowEvent, &Window),
+    {
+        let conn = Connection::get().unwrap().x11();
         let ResolvedGeometry {
             x,
             y,
             width,
             height,
-        } = conn.resolve_geometry(geometry);
+        } = geometry;
+        let parent = match kind {
+            XWindowKind::Toplevel => None,
+            XWindowKind::Dialog { parent, .. } | XWindowKind::Popup { parent } => Some(parent),
+        };
+        let popup = matches!(kind, XWindowKind::Popup { .. });
 
         let mut events = WindowEventSender::new(event_handler);
 
@@ -1407,6 +2030,35 @@ impl XWindow {
             })
             .context("create_colormap_checked")?;
 
+            // We have to specify both a border pixel color and a colormap
+            // when specifying a depth that doesn't match the root window in
+            // order to avoid a BadMatch
+            let mut value_list = vec![
+                xcb::x::Cw::BackPixel(0), // transparent background
+                xcb::x::Cw::BorderPixel(screen.black_pixel()),
+            ];
+            if popup {
+                // Keep the window manager away from popups
+                value_list.push(xcb::x::Cw::OverrideRedirect(true));
+            }
+            value_list.extend([
+                xcb::x::Cw::EventMask(
+                    xcb::x::EventMask::FOCUS_CHANGE
+                        | xcb::x::EventMask::KEY_PRESS
+                        | xcb::x::EventMask::BUTTON_PRESS
+                        | xcb::x::EventMask::BUTTON_RELEASE
+                        | xcb::x::EventMask::POINTER_MOTION
+                        | xcb::x::EventMask::ENTER_WINDOW
+                        | xcb::x::EventMask::LEAVE_WINDOW
+                        | xcb::x::EventMask::BUTTON_MOTION
+                        | xcb::x::EventMask::KEY_RELEASE
+                        | xcb::x::EventMask::PROPERTY_CHANGE
+                        | xcb::x::EventMask::STRUCTURE_NOTIFY
+                        | xcb::x::EventMask::VISIBILITY_CHANGE,
+                ),
+                xcb::x::Cw::Colormap(color_map_id),
+            ]);
+
             conn.send_request_no_reply(&xcb::x::CreateWindow {
                 depth: conn.depth,
                 wid: window_id,
@@ -1418,26 +2070,7 @@ impl XWindow {
                 border_width: 0,
                 class: xcb::x::WindowClass::InputOutput,
                 visual: conn.visual.visual_id(),
-                value_list: &[
-                    // We have to specify both a border pixel color and a colormap
-                    // when specifying a depth that doesn't match the root window in
-                    // order to avoid a BadMatch
-                    xcb::x::Cw::BackPixel(0), // transparent background
-                    xcb::x::Cw::BorderPixel(screen.black_pixel()),
-                    xcb::x::Cw::EventMask(
-                        xcb::x::EventMask::FOCUS_CHANGE
-                            | xcb::x::EventMask::KEY_PRESS
-                            | xcb::x::EventMask::BUTTON_PRESS
-                            | xcb::x::EventMask::BUTTON_RELEASE
-                            | xcb::x::EventMask::POINTER_MOTION
-                            | xcb::x::EventMask::LEAVE_WINDOW
-                            | xcb::x::EventMask::BUTTON_MOTION
-                            | xcb::x::EventMask::KEY_RELEASE
-                            | xcb::x::EventMask::PROPERTY_CHANGE
-                            | xcb::x::EventMask::STRUCTURE_NOTIFY,
-                    ),
-                    xcb::x::Cw::Colormap(color_map_id),
-                ],
+                value_list: &value_list,
             })
             .context("xcb::create_window_checked")?;
 
@@ -1490,32 +2123,54 @@ impl XWindow {
                 verify_focus: true,
                 last_cursor_position: Rect::default(),
                 paint_throttled: false,
+                mapped: false,
+                minimized: false,
+                fully_obscured: false,
+                visible: false,
                 last_wm_state: WindowState::default(),
                 invalidated: false,
                 pending: vec![],
                 sure_about_geometry: false,
                 current_mouse_event: None,
+                held_button: None,
+                pointer_grab: None,
+                click_streak: ClickStreak::default(),
+                raw_key_mode: RawKeyMode::default(),
+                animation: Animation::default(),
+                animation_timer: false,
+                class_name: class_name.to_string(),
+                instance_name: None,
+                idle_inhibit: false,
+                idle_inhibitor: None,
+                shortcut_inhibit: false,
+                keyboard_grabbed: false,
+                shortcut_inhibit_requests: vec![],
+                hidden: false,
+                focus_requests: FocusRequests::default(),
+                resize_increments: None,
+                size_constraints: SizeConstraints::default(),
+                full_screen_restore: FullScreenRestore::default(),
+                current_screen: None,
+                position: None,
+                reparented: false,
+                parent,
+                decorations: options.decorations,
+                window_level: WindowLevel::Normal,
+                skip_taskbar: matches!(kind, XWindowKind::Dialog { .. }),
                 window_drag_position: None,
                 dragging: false,
                 outstanding_configure_requests: 0,
+                unreported_configure_requests: 0,
                 pending_finished_resizes: 0,
+                inner_size_requests: VecDeque::new(),
+                requested_inner_size: None,
             }))
         };
 
-        // WM_CLASS is encoded as the class and instance name,
-        // null terminated
-        let mut class_string = class_name.as_bytes().to_vec();
-        class_string.push(0);
-        class_string.extend_from_slice(class_name.as_bytes());
-        class_string.push(0);
-
-        conn.send_request_no_reply(&xcb::x::ChangeProperty {
-            mode: PropMode::Replace,
-            window: window_id,
-            property: xcb::x::ATOM_WM_CLASS,
-            r#type: xcb::x::ATOM_STRING,
-            data: &class_string,
-        })?;
+        window
+            .lock()
+            .unwrap()
+            .set_window_class(class_name, options.instance_name)?;
 
         conn.send_request_no_reply(&xcb::x::ChangeProperty {
             mode: PropMode::Replace,
@@ -1541,17 +2196,78 @@ impl XWindow {
             data: &[5u32],
         })?;
 
-        window
-            .lock()
-            .unwrap()
-            .adjust_decorations(config.window_decorations)?;
+        // The initial state, and the properties of dialogs, must be set
+        // before the window is mapped for the window manager to take
+        // them into account
+        let mut state = vec![];
+        match options.initial_state {
+            InitialWindowState::Maximized => {
+                state.push(conn.atom_state_maximized_vert);
+                state.push(conn.atom_state_maximized_horz);
+            }
+            InitialWindowState::FullScreen => state.push(conn.atom_state_fullscreen),
+            InitialWindowState::Normal | InitialWindowState::Hidden => {}
+        }
+
+        if let XWindowKind::Dialog { parent, modal } = kind {
+            conn.send_request_no_reply(&xcb::x::ChangeProperty {
+                mode: PropMode::Replace,
+                window: window_id,
+                property: xcb::x::ATOM_WM_TRANSIENT_FOR,
+                r#type: xcb::x::ATOM_WINDOW,
+                data: &[parent.resource_id()],
+            })?;
+            conn.send_request_no_reply(&xcb::x::ChangeProperty {
+                mode: PropMode::Replace,
+                window: window_id,
+                property: conn.atom_net_wm_window_type,
+                r#type: xcb::x::ATOM_ATOM,
+                data: &[conn.atom_net_wm_window_type_dialog],
+            })?;
+            state.push(conn.atom_state_skip_taskbar);
+            if modal {
+                state.push(conn.atom_state_modal);
+            }
+        }
+
+        if !state.is_empty() {
+            conn.send_request_no_reply(&xcb::x::ChangeProperty {
+                mode: PropMode::Replace,
+                window: window_id,
+                property: conn.atom_net_wm_state,
+                r#type: xcb::x::ATOM_ATOM,
+                data: &state,
+            })?;
+        }
+
+        {
+            let mut inner = window.lock().unwrap();
+            let decorations = inner.window_decorations();
+            inner.adjust_decorations(decorations)?;
+            if options.fixed_size {
+                inner.size_constraints.set_min(width, height);
+                inner.size_constraints.set_max(Some((width, height)));
+                inner.update_normal_hints()?;
+            }
+            if let Some(opacity) = options.opacity {
+                inner.set_window_opacity(opacity);
+            }
+        }
 
         let window_handle = Window::X11(XWindow::from_id(window_id));
 
         conn.windows.borrow_mut().insert(window_id, window);
+        conn.last_window.window_added();
         conn.child_to_parent_id
             .borrow_mut()
             .insert(child_id, window_id);
+        if let XWindowKind::Dialog { parent, .. } | XWindowKind::Popup { parent } = kind {
+            conn.children_by_parent_id
+                .borrow_mut()
+                .entry(parent)
+                .or_default()
+                .push(window_id);
+        }
 
         window_handle.set_title(name);
         // Before we map the window, flush to ensure that all of the other properties
@@ -1559,12 +2275,14 @@ impl XWindow {
         // This is a speculative fix for this race condition issue:
         // <https://github.com/wez/wezterm/issues/2155>
         conn.flush().context("flushing before mapping window")?;
-        window_handle.show();
+        if options.initial_state != InitialWindowState::Hidden {
+            window_handle.show();
 
-        // Some window managers will ignore the x,y that we set during window
-        // creation, so we ask them again once the window is mapped
-        if let (Some(x), Some(y)) = (x, y) {
-            window_handle.set_window_position(ScreenPoint::new(x.try_into()?, y.try_into()?));
+            // Some window managers will ignore the x,y that we set during window
+            // creation, so we ask them again once the window is mapped
+            if let (Some(x), Some(y)) = (x, y) {
+                window_handle.set_window_position(ScreenPoint::new(x.try_into()?, y.try_into()?));
+            }
         }
 
         if conn
@@ -1586,6 +2304,14 @@ impl XWindow {
 
 impl XWindowInner {
     fn close(&mut self) {
+        self.unlock_pointer();
+        self.idle_inhibit = false;
+        self.update_idle_inhibitor();
+        self.shortcut_inhibit = false;
+        self.update_keyboard_grab();
+        for mut pending in self.shortcut_inhibit_requests.drain(..) {
+            pending.err(anyhow!("window is closed"));
+        }
         let conn = self.conn();
         conn.flush()
             .context("flush pending requests prior to issuing DestroyWindow")
@@ -1600,6 +2326,22 @@ impl XWindowInner {
             .borrow_mut()
             .remove(&self.child_id);
 
+        if let Some(parent) = self.parent {
+            if let Some(siblings) = conn.children_by_parent_id.borrow_mut().get_mut(&parent) {
+                siblings.retain(|&id| id != self.window_id);
+            }
+        }
+
+        // Children are closed along with their parent
+        let children = conn
+            .children_by_parent_id
+            .borrow_mut()
+            .remove(&self.window_id)
+            .unwrap_or_default();
+        for child in children {
+            XWindow::from_id(child).force_close();
+        }
+
         // Unmap the window first: calling DestroyWindow here may race
         // with some requests made either by EGL or the IME, but I haven't
         // been able to pin down the source.
@@ -1631,14 +2373,173 @@ impl XWindowInner {
         log::trace!("clear out self.window_id");
         self.window_id = xcb::x::Window::none();
     }
-    fn hide(&mut self) {}
+    /// Withdraw the window, as XWithdrawWindow does: unmap it and
+    /// send a synthetic UnmapNotify so that the window manager
+    /// forgets about it, rather than considering it to be iconified.
+    fn hide(&mut self) {
+        self.hidden = true;
+        self.update_idle_inhibitor();
+        let conn = self.conn();
+        conn.send_request_no_reply_log(&xcb::x::UnmapWindow {
+            window: self.window_id,
+        });
+        conn.send_request_no_reply_log(&xcb::x::SendEvent {
+            propagate: false,
+            destination: xcb::x::SendEventDest::Window(conn.root),
+            event_mask: xcb::x::EventMask::SUBSTRUCTURE_REDIRECT
+                | xcb::x::EventMask::SUBSTRUCTURE_NOTIFY,
+            event: &xcb::x::UnmapNotifyEvent::new(conn.root, self.window_id, false),
+        });
+    }
+
     fn show(&mut self) {
+        self.hidden = false;
+        self.update_idle_inhibitor();
+        if !self.mapped {
+            if let Err(err) = self.restore_wm_state_hints() {
+                log::error!("Failed to restore _NET_WM_STATE: {err:#}");
+            }
+        }
         self.conn().send_request_no_reply_log(&xcb::x::MapWindow {
             window: self.window_id,
         });
     }
 
-    fn focus(&mut self) {
+    fn set_idle_inhibited(&mut self, inhibit: bool) {
+        if self.idle_inhibit == inhibit {
+            return;
+        }
+        self.idle_inhibit = inhibit;
+        self.update_idle_inhibitor();
+    }
+
+    fn set_shortcut_inhibition(&mut self, inhibit: bool, mut outcome: Promise<()>) {
+        if !inhibit {
+            self.shortcut_inhibit = false;
+            self.update_keyboard_grab();
+            for mut pending in self.shortcut_inhibit_requests.drain(..) {
+                pending.err(anyhow!("shortcut inhibition was cancelled"));
+            }
+            outcome.ok(());
+            return;
+        }
+        self.shortcut_inhibit = true;
+        // Resolved once we have the focus and know whether the
+        // grab succeeded
+        self.shortcut_inhibit_requests.push(outcome);
+        self.update_keyboard_grab();
+    }
+
+    /// There is no way to ask the window manager to pass its shortcuts
+    /// through to us, so we grab the keyboard instead, while shortcut
+    /// inhibition has been requested and we have the focus
+    fn update_keyboard_grab(&mut self) {
+        let wanted = self.shortcut_inhibit && self.has_focus == Some(true);
+        if wanted != self.keyboard_grabbed {
+            if wanted {
+                if let Err(err) = self.grab_keyboard() {
+                    log::warn!("unable to inhibit shortcuts: {err:#}");
+                    if !self.shortcut_inhibit_requests.is_empty() {
+                        // Let the caller know, rather than trying
+                        // again the next time that we gain the focus
+                        self.shortcut_inhibit = false;
+                    }
+                    for mut pending in self.shortcut_inhibit_requests.drain(..) {
+                        pending.err(anyhow!("{err:#}"));
+                    }
+                    return;
+                }
+            } else {
+                self.conn()
+                    .send_request_no_reply_log(&xcb::x::UngrabKeyboard {
+                        time: xcb::x::CURRENT_TIME,
+                    });
+            }
+            self.keyboard_grabbed = wanted;
+        }
+        if self.keyboard_grabbed {
+            for mut pending in self.shortcut_inhibit_requests.drain(..) {
+                pending.ok(());
+            }
+        }
+    }
+
+    fn grab_keyboard(&self) -> anyhow::Result<()> {
+        let conn = self.conn();
+        let reply = conn
+            .send_and_wait_request(&xcb::x::GrabKeyboard {
+                owner_events: true,
+                grab_window: self.window_id,
+                time: xcb::x::CURRENT_TIME,
+                pointer_mode: xcb::x::GrabMode::Async,
+                keyboard_mode: xcb::x::GrabMode::Async,
+            })
+            .context("GrabKeyboard")?;
+        ensure!(
+            reply.status() == xcb::x::GrabStatus::Success,
+            "GrabKeyboard failed: {:?}",
+            reply.status()
+        );
+        Ok(())
+    }
+
+    /// Acquire or release the inhibitor so that we only hold one while
+    /// it has been requested and the window is neither hidden nor
+    /// minimized
+    fn update_idle_inhibitor(&mut self) {
+        let wanted =
+            self.idle_inhibit && !self.hidden && !self.last_wm_state.contains(WindowState::HIDDEN);
+        if wanted == self.idle_inhibitor.is_some() {
+            return;
+        }
+        if wanted {
+            self.idle_inhibitor.replace(ScreenSaverInhibitor::new(
+                &self.class_name,
+                "Requested by the application",
+            ));
+        } else {
+            self.idle_inhibitor.take();
+        }
+    }
+
+    /// Iconify the window, as XIconifyWindow does
+    fn minimize(&mut self) {
+        const ICONIC_STATE: u32 = 3;
+        let conn = self.conn();
+        conn.send_request_no_reply_log(&xcb::x::SendEvent {
+            propagate: false,
+            destination: xcb::x::SendEventDest::Window(conn.root),
+            event_mask: xcb::x::EventMask::SUBSTRUCTURE_REDIRECT
+                | xcb::x::EventMask::SUBSTRUCTURE_NOTIFY,
+            event: &xcb::x::ClientMessageEvent::new(
+                self.window_id,
+                conn.atom_wm_change_state,
+                xcb::x::ClientMessageData::Data32([ICONIC_STATE, 0, 0, 0, 0]),
+            ),
+        });
+    }
+
+    fn focus(&mut self, mut outcome: Promise<FocusOutcome>) {
+        if self.has_focus == Some(true) {
+            outcome.ok(FocusOutcome::Focused);
+            return;
+        }
+
+        // The window manager may refuse on the grounds of focus stealing
+        // prevention; we only find out by not receiving the focus
+        let id = self.focus_requests.push(outcome);
+        let window_id = self.window_id;
+        promise::spawn::spawn(async move {
+            async_io::Timer::after(FOCUS_TIMEOUT).await;
+            XConnection::with_window_inner(window_id, move |inner| {
+                if inner.focus_requests.timed_out(id) {
+                    inner.request_attention();
+                }
+                Ok(())
+            });
+        })
+        .detach();
+
         let conn = self.conn();
         conn.send_request_no_reply_log(&xcb::x::SendEvent {
             propagate: true,
@@ -1666,11 +2567,53 @@ impl XWindowInner {
         }
     }
 
+    fn request_attention(&mut self) {
+        if let Err(err) = self.set_wm_state(
+            NetWmStateAction::Add,
+            self.conn().atom_state_demands_attention,
+            None,
+        ) {
+            log::error!("Failed to request attention: {err:#}");
+        }
+    }
+
     fn invalidate(&mut self) {
         self.queue_pending(WindowEvent::NeedRepaint);
         self.dispatch_pending_events().ok();
     }
 
+    fn set_animation(&mut self, hint: Option<AnimationHint>) {
+        self.animation.set(hint);
+        self.schedule_animation_frame();
+    }
+
+    /// X11 doesn't tell us about vblank, so while we're animating we
+    /// repaint whenever the max_fps paint throttle allows it, or less
+    /// often if the animation asked for a lower rate
+    fn schedule_animation_frame(&mut self) {
+        if self.animation_timer {
+            return;
+        }
+        let Some(delay) = self.animation.delay_until_next_frame(Instant::now()) else {
+            return;
+        };
+        if delay.is_zero() {
+            self.invalidate();
+            return;
+        }
+        self.animation_timer = true;
+        let window_id = self.window_id;
+        promise::spawn::spawn(async move {
+            async_io::Timer::after(delay).await;
+            XConnection::with_window_inner(window_id, |inner| {
+                inner.animation_timer = false;
+                inner.schedule_animation_frame();
+                Ok(())
+            });
+        })
+        .detach();
+    }
+
     fn maximize(&mut self) {
         if let Err(err) = self.set_maximized_hint(true) {
             log::error!("Failed to maximize: {err:#}");
@@ -1681,6 +2624,8 @@ impl XWindowInner {
         if let Err(err) = self.set_maximized_hint(false) {
             log::error!("Failed to restore: {err:#}");
         }
+        // Mapping an iconified window de-iconifies it
+        self.show();
     }
 
     fn toggle_fullscreen(&mut self) {
@@ -1698,10 +2643,10 @@ impl XWindowInner {
         let dpi_changed =
             self.config.dpi != config.dpi || self.config.dpi_by_screen != config.dpi_by_screen;
         self.config = config.clone();
-        let _ = self.adjust_decorations(config.window_decorations);
+        let _ = self.adjust_decorations(self.window_decorations());
 
         if dpi_changed {
-            let _ = self.configure_notify("config reload", self.width, self.height);
+            let _ = self.configure_notify("config reload", self.width, self.height, self.position);
         }
     }
 
@@ -1766,6 +2711,264 @@ impl XWindowInner {
         Ok(())
     }
 
+    /// Start an interactive move or resize in response to the
+    /// button press that is currently held
+    fn start_interactive_move_resize(&mut self, direction: u32) -> anyhow::Result<()> {
+        let button = self
+            .held_button
+            .ok_or_else(|| anyhow!("no mouse button is held"))?;
+        let conn = self.conn();
+        ensure!(
+            conn.supported
+                .borrow()
+                .contains(&conn.atom_net_wm_moveresize),
+            "window manager doesn't support _NET_WM_MOVERESIZE"
+        );
+
+        let pos = self
+            .current_mouse_event
+            .as_ref()
+            .and_then(|event| event.screen_coords)
+            .unwrap_or_default();
+        let button = match button {
+            MousePress::Left => 1,
+            MousePress::Middle => 2,
+            MousePress::Right => 3,
+            MousePress::Back => 8,
+            MousePress::Forward => 9,
+            MousePress::Other(b) => b.into(),
+        };
+
+        self.net_wm_moveresize(pos.x as u32, pos.y as u32, direction, button);
+        Ok(())
+    }
+
+    /// Ask the window manager to show its window menu, using the
+    /// GTK extension that is supported by mutter, kwin and others
+    fn show_window_menu(
+        &mut self,
+        coords: Point,
+        serial: Option<InputSerial>,
+    ) -> anyhow::Result<()> {
+        let conn = self.conn();
+        ensure!(
+            conn.supported
+                .borrow()
+                .contains(&conn.atom_gtk_show_window_menu),
+            "window manager doesn't support _GTK_SHOW_WINDOW_MENU"
+        );
+
+        let root = conn.send_and_wait_request(&xcb::x::TranslateCoordinates {
+            src_window: self.window_id,
+            dst_window: conn.root,
+            src_x: coords.x.try_into()?,
+            src_y: coords.y.try_into()?,
+        })?;
+
+        // Like _NET_WM_MOVERESIZE, this only works if we release
+        // the implicit grab from the button press
+        conn.send_request_no_reply_log(&xcb::x::UngrabPointer {
+            time: serial
+                .map(InputSerial::value)
+                .unwrap_or(self.copy_and_paste.time),
+        });
+        conn.send_request_no_reply_log(&xcb::x::SendEvent {
+            propagate: false,
+            destination: xcb::x::SendEventDest::Window(conn.root),
+            event_mask: xcb::x::EventMask::SUBSTRUCTURE_REDIRECT
+                | xcb::x::EventMask::SUBSTRUCTURE_NOTIFY,
+            event: &xcb::x::ClientMessageEvent::new(
+                self.window_id,
+                conn.atom_gtk_show_window_menu,
+                xcb::x::ClientMessageData::Data32([
+                    0, // device id
+                    root.dst_x() as u32,
+                    root.dst_y() as u32,
+                    0,
+                    0,
+                ]),
+            ),
+        });
+        conn.flush().context("flush show_window_menu")?;
+        Ok(())
+    }
+
+    fn grab_pointer(&mut self, confine_to: xcb::x::Window) -> anyhow::Result<()> {
+        ensure!(
+            self.has_focus == Some(true),
+            "the pointer can only be grabbed by the focused window"
+        );
+        let conn = self.conn();
+        let reply = conn
+            .send_and_wait_request(&xcb::x::GrabPointer {
+                owner_events: false,
+                grab_window: self.window_id,
+                event_mask: xcb::x::EventMask::BUTTON_PRESS
+                    | xcb::x::EventMask::BUTTON_RELEASE
+                    | xcb::x::EventMask::POINTER_MOTION,
+                pointer_mode: xcb::x::GrabMode::Async,
+                keyboard_mode: xcb::x::GrabMode::Async,
+                confine_to,
+                cursor: xcb::x::Cursor::none(),
+                time: xcb::x::CURRENT_TIME,
+            })
+            .context("GrabPointer")?;
+        ensure!(
+            reply.status() == xcb::x::GrabStatus::Success,
+            "GrabPointer failed: {:?}",
+            reply.status()
+        );
+        Ok(())
+    }
+
+    fn warp_pointer(&self, position: Point) {
+        self.conn().send_request_no_reply_log(&xcb::x::WarpPointer {
+            src_window: xcb::x::Window::none(),
+            dst_window: self.window_id,
+            src_x: 0,
+            src_y: 0,
+            src_width: 0,
+            src_height: 0,
+            dst_x: position.x as i16,
+            dst_y: position.y as i16,
+        });
+    }
+
+    fn lock_pointer(&mut self, position_hint: Option<Point>) -> anyhow::Result<()> {
+        if let Some(PointerGrab {
+            kind: PointerGrabKind::Locked { .. },
+            ..
+        }) = &self.pointer_grab
+        {
+            return Ok(());
+        }
+        self.unlock_pointer();
+        self.grab_pointer(self.window_id)?;
+
+        let position = self
+            .current_mouse_event
+            .as_ref()
+            .map(|event| event.coords)
+            .unwrap_or_else(|| Point::new(self.width as isize / 2, self.height as isize / 2));
+        self.pointer_grab.replace(PointerGrab {
+            kind: PointerGrabKind::Locked {
+                position,
+                position_hint,
+            },
+            last_position: position,
+        });
+        Ok(())
+    }
+
+    fn confine_pointer(&mut self, rect: Rect) -> anyhow::Result<()> {
+        if let Some(PointerGrab {
+            kind: PointerGrabKind::Confined { rect: current, .. },
+            ..
+        }) = &self.pointer_grab
+        {
+            if *current == rect {
+                return Ok(());
+            }
+        }
+        self.unlock_pointer();
+
+        // The core protocol can only confine the pointer to a window,
+        // so make an invisible one that covers the requested region
+        let conn = self.conn();
+        let window = conn.conn().generate_id();
+        conn.send_request_no_reply(&xcb::x::CreateWindow {
+            depth: xcb::x::COPY_FROM_PARENT as u8,
+            wid: window,
+            parent: self.window_id,
+            x: rect.origin.x.try_into()?,
+            y: rect.origin.y.try_into()?,
+            width: rect.size.width.try_into()?,
+            height: rect.size.height.try_into()?,
+            border_width: 0,
+            class: xcb::x::WindowClass::InputOnly,
+            visual: xcb::x::COPY_FROM_PARENT,
+            value_list: &[],
+        })
+        .context("create confine window")?;
+        conn.send_request_no_reply_log(&xcb::x::MapWindow { window });
+
+        if let Err(err) = self.grab_pointer(window) {
+            conn.send_request_no_reply_log(&xcb::x::DestroyWindow { window });
+            return Err(err);
+        }
+
+        let last_position = self
+            .current_mouse_event
+            .as_ref()
+            .map(|event| event.coords)
+            .unwrap_or_default();
+        self.pointer_grab.replace(PointerGrab {
+            kind: PointerGrabKind::Confined { window, rect },
+            last_position,
+        });
+        Ok(())
+    }
+
+    fn unlock_pointer(&mut self) {
+        let grab = match self.pointer_grab.take() {
+            Some(grab) => grab,
+            None => return,
+        };
+        let conn = self.conn();
+        conn.send_request_no_reply_log(&xcb::x::UngrabPointer {
+            time: xcb::x::CURRENT_TIME,
+        });
+        match grab.kind {
+            PointerGrabKind::Locked {
+                position_hint: Some(position),
+                ..
+            } => self.warp_pointer(position),
+            PointerGrabKind::Locked { .. } => {}
+            PointerGrabKind::Confined { window, .. } => {
+                conn.send_request_no_reply_log(&xcb::x::DestroyWindow { window });
+            }
+        }
+        conn.flush().context("flush unlock_pointer").ok();
+    }
+
+    /// Report relative motion while the pointer is grabbed by
+    /// lock_pointer or confine_pointer.  Returns true if the event
+    /// should not be reported as regular motion because the pointer
+    /// is locked in place.
+    /// The deltas are derived from the core pointer position, so,
+    /// unlike XI2 raw motion, they include pointer acceleration.
+    fn grabbed_pointer_motion(&mut self, event: &MouseEvent) -> bool {
+        let (dx, dy, locked_at) = match self.pointer_grab.as_mut() {
+            Some(grab) => {
+                let dx = (event.coords.x - grab.last_position.x) as f64;
+                let dy = (event.coords.y - grab.last_position.y) as f64;
+                let locked_at = match grab.kind {
+                    PointerGrabKind::Locked { position, .. } => Some(position),
+                    PointerGrabKind::Confined { .. } => None,
+                };
+                grab.last_position = locked_at.unwrap_or(event.coords);
+                (dx, dy, locked_at)
+            }
+            None => return false,
+        };
+
+        // This is also how we swallow the motion that results
+        // from warping the pointer back into place
+        if dx == 0. && dy == 0. {
+            return locked_at.is_some();
+        }
+
+        if let Some(position) = locked_at {
+            self.warp_pointer(position);
+        }
+        self.events.dispatch(WindowEvent::MouseEvent(MouseEvent {
+            kind: MouseEventKind::RelativeMove { dx, dy },
+            coords: locked_at.unwrap_or(event.coords),
+            ..event.clone()
+        }));
+        locked_at.is_some()
+    }
+
     fn set_window_position(&mut self, coords: ScreenPoint) {
         if self.dragging {
             return;
@@ -1778,6 +2981,23 @@ impl XWindowInner {
         // under the crostini environment on a chromebook :-(
         let conn = self.conn();
 
+        if !conn
+            .supported
+            .borrow()
+            .contains(&conn.atom_net_move_resize_window)
+        {
+            // There is no window manager to ask, or it doesn't know
+            // how; move the window ourselves
+            conn.send_request_no_reply_log(&xcb::x::ConfigureWindow {
+                window: self.window_id,
+                value_list: &[
+                    xcb::x::ConfigWindow::X(coords.x as i32),
+                    xcb::x::ConfigWindow::Y(coords.y as i32),
+                ],
+            });
+            return;
+        }
+
         conn.send_request_no_reply_log(&xcb::x::SendEvent {
             propagate: true,
             destination: xcb::x::SendEventDest::Window(conn.root),
@@ -1801,6 +3021,32 @@ impl XWindowInner {
         });
     }
 
+    /// Set WM_CLASS, which window managers use to group windows
+    /// and to match them against their rules
+    fn set_window_class(
+        &mut self,
+        class_name: &str,
+        instance_name: Option<&str>,
+    ) -> anyhow::Result<()> {
+        // WM_CLASS is encoded as the instance and class name,
+        // null terminated
+        let mut class_string = instance_name.unwrap_or(class_name).as_bytes().to_vec();
+        class_string.push(0);
+        class_string.extend_from_slice(class_name.as_bytes());
+        class_string.push(0);
+
+        self.conn().send_request_no_reply(&xcb::x::ChangeProperty {
+            mode: PropMode::Replace,
+            window: self.window_id,
+            property: xcb::x::ATOM_WM_CLASS,
+            r#type: xcb::x::ATOM_STRING,
+            data: &class_string,
+        })?;
+        self.class_name = class_name.to_string();
+        self.instance_name = instance_name.map(str::to_string);
+        Ok(())
+    }
+
     /// Change the title for the window manager
     fn set_title(&mut self, title: &str) {
         if title == self.title {
@@ -1875,30 +3121,192 @@ impl XWindowInner {
             });
     }
 
+    fn set_window_opacity(&mut self, opacity: f32) {
+        let conn = self.conn();
+        match opacity_to_cardinal(opacity) {
+            Some(value) => {
+                conn.send_request_no_reply_log(&xcb::x::ChangeProperty {
+                    mode: PropMode::Replace,
+                    window: self.window_id,
+                    property: conn.atom_net_wm_window_opacity,
+                    r#type: xcb::x::ATOM_CARDINAL,
+                    data: &[value],
+                });
+            }
+            None => {
+                // Fully opaque is expressed by the absence of the property
+                conn.send_request_no_reply_log(&xcb::x::DeleteProperty {
+                    window: self.window_id,
+                    property: conn.atom_net_wm_window_opacity,
+                });
+            }
+        }
+    }
+
+    fn set_opaque_region(&mut self, region: Option<Rect>) {
+        let conn = self.conn();
+        match region {
+            Some(rect) => {
+                let data = [
+                    rect.origin.x.max(0) as u32,
+                    rect.origin.y.max(0) as u32,
+                    rect.size.width.max(0) as u32,
+                    rect.size.height.max(0) as u32,
+                ];
+                conn.send_request_no_reply_log(&xcb::x::ChangeProperty {
+                    mode: PropMode::Replace,
+                    window: self.window_id,
+                    property: conn.atom_net_wm_opaque_region,
+                    r#type: xcb::x::ATOM_CARDINAL,
+                    data: &data,
+                });
+            }
+            None => {
+                conn.send_request_no_reply_log(&xcb::x::DeleteProperty {
+                    window: self.window_id,
+                    property: conn.atom_net_wm_opaque_region,
+                });
+            }
+        }
+    }
+
+    fn set_inner_size(
+        &mut self,
+        width: usize,
+        height: usize,
+        constrained: ConstrainedResize,
+        mut prom: Promise<Dimensions>,
+    ) {
+        if constrained == ConstrainedResize::Reject && self.full_screen_restore.constrained() {
+            self.events.dispatch(WindowEvent::SetInnerSizeCompleted);
+            prom.err(
+                ResizeRejected {
+                    state: self.last_wm_state,
+                }
+                .into(),
+            );
+            return;
+        }
+        self.inner_size_requests.push_back(prom);
+        if self.full_screen_restore.defer_resize(width, height) {
+            // Applied when the window is no longer full screen or maximized
+            self.pending_finished_resizes += 1;
+            return;
+        }
+        self.request_inner_size(width, height);
+    }
+
+    /// Resize to the specified inner size. Unlike set_inner_size, the
+    /// completion isn't reported, so this is suitable for resizes that we
+    /// initiate ourselves rather than at the request of the application.
+    fn resize_window(&mut self, width: usize, height: usize) {
+        self.unreported_configure_requests += 1;
+        self.request_inner_size(width, height);
+    }
+
+    fn request_inner_size(&mut self, width: usize, height: usize) {
+        self.conn()
+            .send_request_no_reply_log(&xcb::x::ConfigureWindow {
+                window: self.window_id,
+                value_list: &[
+                    xcb::x::ConfigWindow::Width(width as u32),
+                    xcb::x::ConfigWindow::Height(height as u32),
+                ],
+            });
+        self.resize_child(width as u32, height as u32);
+        self.outstanding_configure_requests += 1;
+        self.requested_inner_size.replace((width, height));
+    }
+
+    /// Window managers don't reliably put back the size that the
+    /// window had before it went full screen, so do it ourselves
+    fn restore_pre_full_screen_size(&mut self) {
+        if let Some((width, height)) = self.full_screen_restore.configured(
+            self.last_wm_state,
+            self.width as usize,
+            self.height as usize,
+        ) {
+            log::debug!("restoring pre-fullscreen size {width}x{height}");
+            self.resize_window(width, height);
+        }
+    }
+
     fn set_resize_increments(&mut self, incr: ResizeIncrement) -> anyhow::Result<()> {
+        if self.resize_increments == Some(incr) {
+            return Ok(());
+        }
+        self.resize_increments.replace(incr);
+        self.update_normal_hints()?;
+
+        // The window manager applies the increments to subsequent
+        // resizes; snap the current size to them ourselves
+        if self.last_wm_state.can_resize() && !self.last_wm_state.intersects(WindowState::TILED) {
+            let (width, height) = self
+                .requested_inner_size
+                .unwrap_or((self.width as usize, self.height as usize));
+            let snapped = snap_to_increments(&incr, width, height);
+            if snapped != (width, height) {
+                self.resize_window(snapped.0, snapped.1);
+            }
+        }
+        Ok(())
+    }
+
+    fn set_min_inner_size(&mut self, width: usize, height: usize) -> anyhow::Result<()> {
+        self.size_constraints.set_min(width, height);
+        self.update_normal_hints()
+    }
+
+    fn set_max_inner_size(&mut self, size: Option<(usize, usize)>) -> anyhow::Result<()> {
+        self.size_constraints.set_max(size);
+        self.update_normal_hints()
+    }
+
+    /// X11 sizes are in pixels already, so unlike Wayland, these don't
+    /// need to be recomputed when the dpi changes
+    fn update_normal_hints(&mut self) -> anyhow::Result<()> {
         use xcb_util::*;
-        let hints = xcb_size_hints_t {
-            flags: XCB_ICCCM_SIZE_HINT_P_MIN_SIZE
-                | XCB_ICCCM_SIZE_HINT_P_RESIZE_INC
-                | XCB_ICCCM_SIZE_HINT_BASE_SIZE,
+        let mut hints = xcb_size_hints_t {
+            flags: 0,
             x: 0,
             y: 0,
             width: 0,
             height: 0,
-            min_width: (incr.base_width + incr.x).into(),
-            min_height: (incr.base_height + incr.y).into(),
+            min_width: 0,
+            min_height: 0,
             max_width: 0,
             max_height: 0,
-            width_inc: incr.x.into(),
-            height_inc: incr.y.into(),
+            width_inc: 0,
+            height_inc: 0,
             min_aspect_num: 0,
             min_aspect_den: 0,
             max_aspect_num: 0,
             max_aspect_den: 0,
-            base_width: incr.base_width.into(),
-            base_height: incr.base_height.into(),
+            base_width: 0,
+            base_height: 0,
             win_gravity: 0,
         };
+        if let Some(incr) = self.resize_increments {
+            hints.flags |= XCB_ICCCM_SIZE_HINT_P_MIN_SIZE
+                | XCB_ICCCM_SIZE_HINT_P_RESIZE_INC
+                | XCB_ICCCM_SIZE_HINT_BASE_SIZE;
+            hints.min_width = (incr.base_width + incr.x).into();
+            hints.min_height = (incr.base_height + incr.y).into();
+            hints.width_inc = incr.x.into();
+            hints.height_inc = incr.y.into();
+            hints.base_width = incr.base_width.into();
+            hints.base_height = incr.base_height.into();
+        }
+        if let Some((width, height)) = self.size_constraints.min {
+            hints.flags |= XCB_ICCCM_SIZE_HINT_P_MIN_SIZE;
+            hints.min_width = hints.min_width.max(width.try_into()?);
+            hints.min_height = hints.min_height.max(height.try_into()?);
+        }
+        if let Some((width, height)) = self.size_constraints.max {
+            hints.flags |= XCB_ICCCM_SIZE_HINT_P_MAX_SIZE;
+            hints.max_width = width.try_into()?;
+            hints.max_height = height.try_into()?;
+        }
 
         let data = unsafe {
             std::slice::from_raw_parts(
@@ -1938,7 +3346,13 @@ impl HasWindowHandle for XWindow {
             .window_by_id(self.0)
             .expect("window handle invalid!?");
 
-        let inner = handle.lock().unwrap();
+        // This is called synchronously, possibly from an event handler
+        // while the window is busy; report that rather than deadlocking
+        let inner = match handle.try_lock() {
+            Ok(inner) => inner,
+            Err(TryLockError::WouldBlock) => return Err(HandleError::Unavailable),
+            Err(err @ TryLockError::Poisoned(_)) => panic!("{err}"),
+        };
         let handle = inner.window_handle()?;
         unsafe { Ok(WindowHandle::borrow_raw(handle.as_raw())) }
     }
@@ -1972,6 +3386,13 @@ impl WindowOps for XWindow {
     }
 
     fn close(&self) {
+        XConnection::with_window_inner(self.0, |inner| {
+            inner.events.request_close();
+            Ok(())
+        });
+    }
+
+    fn force_close(&self) {
         XConnection::with_window_inner(self.0, |inner| {
             inner.close();
             Ok(())
@@ -1985,6 +3406,13 @@ impl WindowOps for XWindow {
         });
     }
 
+    fn minimize(&self) {
+        XConnection::with_window_inner(self.0, |inner| {
+            inner.minimize();
+            Ok(())
+        });
+    }
+
     fn toggle_fullscreen(&self) {
         XConnection::with_window_inner(self.0, |inner| {
             inner.toggle_fullscreen();
@@ -2014,9 +3442,21 @@ impl WindowOps for XWindow {
         });
     }
 
-    fn focus(&self) {
+    // The serial is not used: _NET_ACTIVE_WINDOW is sent with
+    // CURRENT_TIME, see XWindowInner::focus
+    fn focus(&self, _serial: Option<InputSerial>) -> Future<FocusOutcome> {
+        let mut outcome = Promise::new();
+        let future = outcome.get_future().unwrap();
+        XConnection::with_window_inner(self.0, move |inner| {
+            inner.focus(outcome);
+            Ok(())
+        });
+        future
+    }
+
+    fn request_attention(&self) {
         XConnection::with_window_inner(self.0, |inner| {
-            inner.focus();
+            inner.request_attention();
             Ok(())
         });
     }
@@ -2035,6 +3475,12 @@ impl WindowOps for XWindow {
         });
     }
 
+    fn set_custom_cursor(&self, image: Image, hotspot: Point) -> Future<()> {
+        XConnection::with_window_inner(self.0, move |inner| {
+            inner.set_custom_cursor(&image, hotspot)
+        })
+    }
+
     fn invalidate(&self) {
         XConnection::with_window_inner(self.0, |inner| {
             inner.invalidate();
@@ -2042,6 +3488,13 @@ impl WindowOps for XWindow {
         });
     }
 
+    fn set_animation(&self, hint: Option<AnimationHint>) {
+        XConnection::with_window_inner(self.0, move |inner| {
+            inner.set_animation(hint);
+            Ok(())
+        });
+    }
+
     fn set_title(&self, title: &str) {
         let title = title.to_owned();
         XConnection::with_window_inner(self.0, move |inner| {
@@ -2050,19 +3503,114 @@ impl WindowOps for XWindow {
         });
     }
 
-    fn set_inner_size(&self, width: usize, height: usize) {
+    fn set_always_on_top(&self, enable: bool) -> Future<()> {
+        XConnection::with_window_inner(self.0, move |inner| inner.set_always_on_top(enable))
+    }
+
+    fn set_window_level(&self, level: WindowLevel) {
+        XConnection::with_window_inner(self.0, move |inner| inner.set_window_level(level));
+    }
+
+    fn set_skip_taskbar(&self, enable: bool) -> Future<()> {
+        XConnection::with_window_inner(self.0, move |inner| inner.set_skip_taskbar(enable))
+    }
+
+    fn set_window_class(&self, class_name: &str, instance_name: Option<&str>) {
+        let class_name = class_name.to_owned();
+        let instance_name = instance_name.map(str::to_owned);
         XConnection::with_window_inner(self.0, move |inner| {
-            inner
-                .conn()
-                .send_request_no_reply_log(&xcb::x::ConfigureWindow {
-                    window: inner.window_id,
-                    value_list: &[
-                        xcb::x::ConfigWindow::Width(width as u32),
-                        xcb::x::ConfigWindow::Height(height as u32),
-                    ],
-                });
-            inner.resize_child(width as u32, height as u32);
-            inner.outstanding_configure_requests += 1;
+            inner.set_window_class(&class_name, instance_name.as_deref())
+        });
+    }
+
+    fn set_inner_size(
+        &self,
+        width: usize,
+        height: usize,
+        constrained: ConstrainedResize,
+    ) -> Future<Dimensions> {
+        let mut prom = Promise::new();
+        let future = prom.get_future().unwrap();
+        XConnection::with_window_inner(self.0, move |inner| {
+            inner.set_inner_size(width, height, constrained, prom);
+            Ok(())
+        });
+        future
+    }
+
+    fn get_dimensions(&self) -> Future<Dimensions> {
+        XConnection::with_window_inner(self.0, |inner| {
+            let (pixel_width, pixel_height) = inner
+                .requested_inner_size
+                .unwrap_or((inner.width as usize, inner.height as usize));
+            Ok(Dimensions {
+                pixel_width,
+                pixel_height,
+                dpi: inner.dpi as usize,
+            })
+        })
+    }
+
+    fn get_window_state(&self) -> Future<WindowState> {
+        XConnection::with_window_inner(self.0, |inner| {
+            let mut state = inner.get_window_state()?;
+            state.set(WindowState::FOCUSED, inner.has_focus.unwrap_or(false));
+            state.set(
+                WindowState::IDLE_INHIBITED,
+                inner
+                    .idle_inhibitor
+                    .as_ref()
+                    .map(|i| i.is_active())
+                    .unwrap_or(false),
+            );
+            state.set(WindowState::SHORTCUTS_INHIBITED, inner.keyboard_grabbed);
+            Ok(state)
+        })
+    }
+
+    // _NET_WM_MOVERESIZE carries no timestamp, so there is
+    // nothing to do with the serial
+    fn start_interactive_move(&self, _serial: Option<InputSerial>) -> Future<()> {
+        XConnection::with_window_inner(self.0, |inner| {
+            inner.start_interactive_move_resize(_NET_WM_MOVERESIZE_MOVE)
+        })
+    }
+
+    fn start_interactive_resize(
+        &self,
+        edge: WindowResizeEdge,
+        _serial: Option<InputSerial>,
+    ) -> Future<()> {
+        let direction = match edge {
+            WindowResizeEdge::Top => _NET_WM_MOVERESIZE_SIZE_TOP,
+            WindowResizeEdge::Bottom => _NET_WM_MOVERESIZE_SIZE_BOTTOM,
+            WindowResizeEdge::Left => _NET_WM_MOVERESIZE_SIZE_LEFT,
+            WindowResizeEdge::Right => _NET_WM_MOVERESIZE_SIZE_RIGHT,
+            WindowResizeEdge::TopLeft => _NET_WM_MOVERESIZE_SIZE_TOPLEFT,
+            WindowResizeEdge::TopRight => _NET_WM_MOVERESIZE_SIZE_TOPRIGHT,
+            WindowResizeEdge::BottomLeft => _NET_WM_MOVERESIZE_SIZE_BOTTOMLEFT,
+            WindowResizeEdge::BottomRight => _NET_WM_MOVERESIZE_SIZE_BOTTOMRIGHT,
+        };
+        XConnection::with_window_inner(self.0, move |inner| {
+            inner.start_interactive_move_resize(direction)
+        })
+    }
+
+    fn show_window_menu(&self, coords: Point, serial: Option<InputSerial>) -> Future<()> {
+        XConnection::with_window_inner(self.0, move |inner| inner.show_window_menu(coords, serial))
+    }
+
+    fn lock_pointer(&self, position_hint: Option<Point>) -> Future<()> {
+        XConnection::with_window_inner(self.0, move |inner| inner.lock_pointer(position_hint))
+    }
+
+    fn confine_pointer(&self, region: Rect) -> Future<()> {
+        XConnection::with_window_inner(self.0, move |inner| inner.confine_pointer(region))
+    }
+
+    fn unlock_pointer(&self) {
+        XConnection::with_window_inner(self.0, |inner| {
+            inner.unlock_pointer();
             Ok(())
         });
     }
@@ -2088,6 +3636,23 @@ impl WindowOps for XWindow {
         });
     }
 
+    fn get_window_position(&self) -> Future<Option<ScreenPoint>> {
+        XConnection::with_window_inner(self.0, |inner| Ok(Some(inner.window_origin()?)))
+    }
+
+    fn get_current_screen(&self) -> Future<ScreenInfo> {
+        XConnection::with_window_inner(self.0, |inner| {
+            if let Some(screen) = inner.current_screen.clone() {
+                return Ok(screen);
+            }
+            let origin = inner.window_origin()?;
+            match inner.screen_for_window(origin, inner.width, inner.height)? {
+                Some(screen) => Ok(screen),
+                None => Ok(Connection::get().unwrap().screens()?.active),
+            }
+        })
+    }
+
     fn set_text_cursor_position(&self, cursor: Rect) {
         XConnection::with_window_inner(self.0, move |inner| {
             inner.set_text_cursor_position(cursor);
@@ -2102,6 +3667,37 @@ impl WindowOps for XWindow {
         });
     }
 
+    fn set_window_opacity(&self, opacity: f32) {
+        XConnection::with_window_inner(self.0, move |inner| {
+            inner.set_window_opacity(opacity);
+            Ok(())
+        });
+    }
+
+    fn set_idle_inhibited(&self, inhibit: bool) {
+        XConnection::with_window_inner(self.0, move |inner| {
+            inner.set_idle_inhibited(inhibit);
+            Ok(())
+        });
+    }
+
+    fn set_shortcut_inhibition(&self, inhibit: bool) -> Future<()> {
+        let mut outcome = Promise::new();
+        let future = outcome.get_future().unwrap();
+        XConnection::with_window_inner(self.0, move |inner| {
+            inner.set_shortcut_inhibition(inhibit, outcome);
+            Ok(())
+        });
+        future
+    }
+
+    fn set_opaque_region(&self, region: Option<Rect>) {
+        XConnection::with_window_inner(self.0, move |inner| {
+            inner.set_opaque_region(region);
+            Ok(())
+        });
+    }
+
     fn set_resize_increments(&self, incr: ResizeIncrement) {
         XConnection::with_window_inner(self.0, move |inner| {
             if let Err(err) = inner.set_resize_increments(incr) {
@@ -2111,6 +3707,31 @@ impl WindowOps for XWindow {
         });
     }
 
+    fn set_min_inner_size(&self, width: usize, height: usize) {
+        XConnection::with_window_inner(self.0, move |inner| {
+            if let Err(err) = inner.set_min_inner_size(width, height) {
+                log::error!("set_min_inner_size failed: {:#}", err);
+            }
+            Ok(())
+        });
+    }
+
+    fn set_max_inner_size(&self, size: Option<(usize, usize)>) {
+        XConnection::with_window_inner(self.0, move |inner| {
+            if let Err(err) = inner.set_max_inner_size(size) {
+                log::error!("set_max_inner_size failed: {:#}", err);
+            }
+            Ok(())
+        });
+    }
+
+    fn set_raw_key_mode(&self, enable: bool) {
+        XConnection::with_window_inner(self.0, move |inner| {
+            inner.raw_key_mode.set_enabled(enable);
+            Ok(())
+        });
+    }
+
     /// Initiate textual transfer from the clipboard
     fn get_clipboard(&self, clipboard: Clipboard) -> Future<String> {
         let window_id = self.0;
@@ -2152,6 +3773,38 @@ impl WindowOps for XWindow {
         future
     }
 
+    fn list_clipboard_formats(&self, clipboard: Clipboard) -> Future<Vec<String>> {
+        let window_id = self.0;
+        let mut promise = Promise::new();
+        let future = promise.get_future().unwrap();
+        let mut promise = Some(promise);
+
+        XConnection::with_window_inner(window_id, move |inner| {
+            let promise = promise.take().unwrap();
+            inner
+                .copy_and_paste
+                .targets_request_mut(clipboard)
+                .replace(promise);
+            let conn = inner.conn();
+            // Ask the owner to tell us which targets it supports.
+            // The reply is stored in the property of the same name,
+            // so that it doesn't clash with a concurrent get_clipboard.
+            conn.send_request_no_reply_log(&xcb::x::ConvertSelection {
+                requestor: inner.window_id,
+                selection: match clipboard {
+                    Clipboard::Clipboard => conn.atom_clipboard,
+                    Clipboard::PrimarySelection => xcb::x::ATOM_PRIMARY,
+                },
+                target: conn.atom_targets,
+                property: conn.atom_targets,
+                time: inner.copy_and_paste.time,
+            });
+            Ok(())
+        });
+
+        future
+    }
+
     /// Set some text in the clipboard
     fn set_clipboard(&self, clipboard: Clipboard, text: String) {
         let window_id = self.0;
@@ -2231,6 +3884,82 @@ fn decode_dropped_url_string(raw: &[u8]) -> String {
     }
 }
 
+/// Returns the position for a popup of the given size, in the same
+/// coordinates as `anchor`. The popup goes below the anchor, or above
+/// it if there is no room below, and is slid left to keep it within
+/// `bounds` where possible.
+fn place_popup(anchor: ScreenRect, size: (usize, usize), bounds: ScreenRect) -> ScreenPoint {
+    let (width, height) = (size.0 as isize, size.1 as isize);
+
+    let mut y = anchor.max_y();
+    if y + height > bounds.max_y() && anchor.min_y() - height >= bounds.min_y() {
+        y = anchor.min_y() - height;
+    }
+
+    let x = anchor
+        .min_x()
+        .min(bounds.max_x() - width)
+        .max(bounds.min_x());
+
+    ScreenPoint::new(x, y)
+}
+
+/// Returns the window relative and screen positions of the pointer
+/// from the event and root coordinates of a pointer event.
+/// The root window spans all of the monitors, so its coordinates are
+/// the screen coordinates, wherever the window is and whether or not
+/// the window manager has reparented it.
+fn pointer_position(event: (i16, i16), root: (i16, i16)) -> (Point, ScreenPoint) {
+    (
+        Point::new(event.0.into(), event.1.into()),
+        ScreenPoint::new(root.0.into(), root.1.into()),
+    )
+}
+
+/// Focus changes that result from a keyboard grab starting or ending
+/// are reported with a distinct mode
+fn focus_reason(mode: xcb::x::NotifyMode) -> FocusReason {
+    match mode {
+        xcb::x::NotifyMode::Grab
+        | xcb::x::NotifyMode::Ungrab
+        | xcb::x::NotifyMode::WhileGrabbed => FocusReason::Grab,
+        _ => FocusReason::Unknown,
+    }
+}
+
+/// _NET_WM_WINDOW_OPACITY is a 32-bit fraction of fully opaque.
+/// Returns None for fully opaque, for which the property is removed.
+fn opacity_to_cardinal(opacity: f32) -> Option<u32> {
+    let opacity = if opacity.is_nan() {
+        1.0
+    } else {
+        opacity.clamp(0.0, 1.0)
+    };
+    if opacity >= 1.0 {
+        None
+    } else {
+        Some((f64::from(opacity) * f64::from(u32::MAX)).round() as u32)
+    }
+}
+
+/// Returns the `current` _NET_WM_STATE with each of the `hints`
+/// present or absent according to whether it was requested.
+/// The other atoms, which belong to the window manager, are kept.
+fn wm_state_with_hints<A: Copy + PartialEq>(current: &[A], hints: &[(A, bool)]) -> Vec<A> {
+    let mut state: Vec<A> = current
+        .iter()
+        .copied()
+        .filter(|s| !hints.iter().any(|(hint, _)| hint == s))
+        .collect();
+    state.extend(
+        hints
+            .iter()
+            .filter(|(_, enable)| *enable)
+            .map(|(hint, _)| *hint),
+    );
+    state
+}
+
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 #[repr(u32)]
 enum NetWmStateAction {
@@ -2249,3 +3978,149 @@ impl NetWmStateAction {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn grab_focus_reason() {
+        assert_eq!(
+            focus_reason(xcb::x::NotifyMode::Normal),
+            FocusReason::Unknown
+        );
+        assert_eq!(focus_reason(xcb::x::NotifyMode::Grab), FocusReason::Grab);
+        assert_eq!(focus_reason(xcb::x::NotifyMode::Ungrab), FocusReason::Grab);
+        assert_eq!(
+            focus_reason(xcb::x::NotifyMode::WhileGrabbed),
+            FocusReason::Grab
+        );
+    }
+
+    #[test]
+    fn pointer_screen_coords() {
+        // A window whose client area is at 100,50 on the screen
+        let (coords, screen) = pointer_position((10, 20), (110, 70));
+        assert_eq!(coords, Point::new(10, 20));
+        assert_eq!(screen, ScreenPoint::new(110, 70));
+
+        // After the window moves to 300,200, the same spot on the screen
+        // is outside of the window; we still see it while a button is
+        // held, and the screen position is unaffected by the move
+        let (coords, screen) = pointer_position((-190, -130), (110, 70));
+        assert_eq!(coords, Point::new(-190, -130));
+        assert_eq!(screen, ScreenPoint::new(110, 70));
+    }
+
+    #[test]
+    fn window_opacity() {
+        assert_eq!(opacity_to_cardinal(1.0), None);
+        assert_eq!(opacity_to_cardinal(2.0), None);
+        assert_eq!(opacity_to_cardinal(f32::NAN), None);
+        assert_eq!(opacity_to_cardinal(0.0), Some(0));
+        assert_eq!(opacity_to_cardinal(-1.0), Some(0));
+        assert_eq!(opacity_to_cardinal(0.5), Some(0x8000_0000));
+    }
+
+    #[test]
+    fn wm_state_hints() {
+        const MAXIMIZED: u32 = 1;
+        const ABOVE: u32 = 2;
+        const BELOW: u32 = 3;
+        const SKIP_TASKBAR: u32 = 4;
+
+        // Nothing left over from before the window was withdrawn
+        assert_eq!(
+            wm_state_with_hints(&[], &[(ABOVE, true), (BELOW, false), (SKIP_TASKBAR, true)]),
+            vec![ABOVE, SKIP_TASKBAR]
+        );
+        // The window manager's state is kept, and ours isn't duplicated
+        assert_eq!(
+            wm_state_with_hints(
+                &[ABOVE, MAXIMIZED, ABOVE],
+                &[(ABOVE, true), (BELOW, false), (SKIP_TASKBAR, false)]
+            ),
+            vec![MAXIMIZED, ABOVE]
+        );
+        // A hint that is no longer wanted is removed
+        assert_eq!(
+            wm_state_with_hints(
+                &[ABOVE, MAXIMIZED, SKIP_TASKBAR],
+                &[(ABOVE, false), (BELOW, true), (SKIP_TASKBAR, false)]
+            ),
+            vec![MAXIMIZED, BELOW]
+        );
+    }
+
+    /// Needs an X server without a window manager, such as Xvfb:
+    /// `xvfb-run cargo test -p window -- --ignored window_position_round_trip`
+    #[test]
+    #[ignore]
+    fn window_position_round_trip() {
+        let _guard = crate::spawn::TEST_MESSAGE_LOOP
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+        std::env::set_var("WEZTERM_WINDOW_BACKEND", "x11");
+        let conn = Connection::init().unwrap();
+
+        let result = Rc::new(std::cell::RefCell::new(None));
+        {
+            let result = Rc::clone(&result);
+            promise::spawn::spawn(async move {
+                let test = async {
+                    let font_config =
+                        Rc::new(FontConfiguration::new(None, crate::DEFAULT_DPI as usize)?);
+                    let win = Window::new_window(
+                        "class",
+                        "position",
+                        RequestedWindowGeometry::default(),
+                        None,
+                        font_config,
+                        |_, _| {},
+                    )
+                    .await?;
+                    win.show();
+
+                    for target in [ScreenPoint::new(120, 80), ScreenPoint::new(10, 300)] {
+                        win.set_window_position(target);
+                        assert_eq!(win.get_window_position().await?, Some(target));
+                    }
+
+                    win.close();
+                    Ok::<(), anyhow::Error>(())
+                };
+                result.borrow_mut().replace(test.await);
+                Connection::get().unwrap().terminate_message_loop();
+            })
+            .detach();
+        }
+        conn.run_message_loop().unwrap();
+        result.borrow_mut().take().unwrap().unwrap();
+    }
+
+    #[test]
+    fn popup_placement() {
+        let bounds = euclid::rect(0, 0, 1000, 800);
+        let anchor = |x, y| euclid::rect(x, y, 10, 20);
+
+        assert_eq!(
+            place_popup(anchor(100, 100), (200, 300), bounds),
+            ScreenPoint::new(100, 120)
+        );
+        // Flipped above the anchor
+        assert_eq!(
+            place_popup(anchor(100, 700), (200, 300), bounds),
+            ScreenPoint::new(100, 400)
+        );
+        // Slid left to stay on screen
+        assert_eq!(
+            place_popup(anchor(900, 100), (200, 300), bounds),
+            ScreenPoint::new(800, 120)
+        );
+        // No room above either, so it stays below
+        assert_eq!(
+            place_popup(anchor(100, 100), (200, 750), bounds),
+            ScreenPoint::new(100, 120)
+        );
+    }
+}
@@ -1,24 +1,33 @@
 #![cfg(all(unix, not(target_os = "macos")))]
 
 use crate::connection::ConnectionOps;
+use crate::os::fd_watch::{FdInterest, FdReadiness, FdWatch};
+use crate::os::headless::connection::HeadlessConnection;
+use crate::os::headless::window::HeadlessWindow;
 #[cfg(feature = "wayland")]
-use crate::os::wayland::connection::WaylandConnection;
+use crate::os::wayland::connection::{WaylandConnection, WaylandInitError};
 #[cfg(feature = "wayland")]
 use crate::os::wayland::window::WaylandWindow;
 use crate::os::x11::connection::XConnection;
 use crate::os::x11::window::XWindow;
-use crate::screen::Screens;
+use crate::screen::{ScreenInfo, Screens};
 use crate::{
-    Appearance, Clipboard, MouseCursor, Rect, RequestedWindowGeometry, ResizeIncrement,
-    ScreenPoint, WindowEvent, WindowOps,
+    AnimationHint, Appearance, Clipboard, ConstrainedResize, Dimensions, FocusOutcome, FrameTiming,
+    Image, InputSerial, KeyboardLayout, MouseCursor, Point, Rect, RequestedWindowGeometry,
+    ResizeIncrement, ScreenPoint, UiSettings, WindowEvent, WindowOps, WindowOptions,
+    WindowResizeEdge, WindowState, WmCapabilities,
 };
+#[cfg(feature = "wayland")]
+use anyhow::Context;
 use async_trait::async_trait;
+use config::window::WindowLevel;
 use config::ConfigHandle;
 use promise::*;
 use raw_window_handle::{
     DisplayHandle, HandleError, HasDisplayHandle, HasWindowHandle, WindowHandle,
 };
 use std::any::Any;
+use std::os::unix::io::RawFd;
 use std::rc::Rc;
 use wezterm_font::FontConfiguration;
 
@@ -26,32 +35,115 @@ pub enum Connection {
     X11(Rc<XConnection>),
     #[cfg(feature = "wayland")]
     Wayland(Rc<WaylandConnection>),
+    Headless(Rc<HeadlessConnection>),
 }
 
+/// A handle to a window; clones refer to the same window.
+///
+/// This implements `HasWindowHandle` and `HasDisplayHandle`, so that
+/// an application can render with something other than `enable_opengl`,
+/// such as a wgpu surface of its own; see `examples/wgpu.rs`.
+/// The handles must be obtained on the gui thread, and the window handle
+/// is `HandleError::Unavailable` while the window is dispatching an
+/// event to its handler. The underlying surface lives until the window
+/// is closed, so anything created from the handles must be dropped
+/// before that. The headless backend has no handles to offer.
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Ord, PartialOrd)]
 pub enum Window {
     X11(XWindow),
     #[cfg(feature = "wayland")]
     Wayland(WaylandWindow),
+    Headless(HeadlessWindow),
+}
+
+/// Which windowing system backend to use
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BackendSelection {
+    /// Try wayland if it is enabled in the config, falling back to X11
+    Automatic,
+    /// Use X11 only
+    X11,
+    /// Use Wayland only; failure to connect is an error
+    Wayland,
+    /// Don't connect to any display server; see crate::os::headless
+    Headless,
+}
+
+impl BackendSelection {
+    /// The WEZTERM_WINDOW_BACKEND environment variable can be used to
+    /// override the config when debugging backend specific issues
+    fn from_env() -> anyhow::Result<Self> {
+        match std::env::var("WEZTERM_WINDOW_BACKEND") {
+            Err(_) => Ok(Self::Automatic),
+            Ok(value) => match value.to_ascii_lowercase().as_str() {
+                "" | "auto" => Ok(Self::Automatic),
+                "x11" => Ok(Self::X11),
+                "wayland" => Ok(Self::Wayland),
+                "headless" => Ok(Self::Headless),
+                _ => anyhow::bail!(
+                    "WEZTERM_WINDOW_BACKEND={value} is invalid; \
+                     expected one of x11, wayland, headless or auto"
+                ),
+            },
+        }
+    }
 }
 
 impl Connection {
     pub(crate) fn create_new() -> anyhow::Result<Connection> {
+        let selection = BackendSelection::from_env()?;
+
+        if selection == BackendSelection::Headless {
+            log::debug!("Using headless connection!");
+            return Ok(Connection::Headless(Rc::new(
+                HeadlessConnection::create_new()?,
+            )));
+        }
+
         #[cfg(feature = "wayland")]
-        if config::configuration().enable_wayland {
-            match WaylandConnection::create_new() {
-                Ok(w) => {
-                    log::debug!("Using wayland connection!");
-                    return Ok(Connection::Wayland(Rc::new(w)));
-                }
-                Err(e) => {
-                    log::debug!("Failed to init wayland: {}", e);
+        match selection {
+            BackendSelection::Wayland => {
+                let w = WaylandConnection::create_new()
+                    .context("WEZTERM_WINDOW_BACKEND=wayland but wayland init failed")?;
+                log::debug!("Using wayland connection!");
+                return Ok(Connection::Wayland(Rc::new(w)));
+            }
+            BackendSelection::Automatic if config::configuration().enable_wayland => {
+                match WaylandConnection::create_new() {
+                    Ok(w) => {
+                        log::debug!("Using wayland connection!");
+                        return Ok(Connection::Wayland(Rc::new(w)));
+                    }
+                    Err(WaylandInitError::NoWaylandSocket) => {
+                        log::trace!("No wayland compositor; using X11");
+                    }
+                    Err(e) => {
+                        log::warn!("Failed to init wayland, falling back to X11: {:#}", e);
+                    }
                 }
             }
+            BackendSelection::Automatic | BackendSelection::X11 | BackendSelection::Headless => {}
         }
+
+        #[cfg(not(feature = "wayland"))]
+        if selection == BackendSelection::Wayland {
+            anyhow::bail!("WEZTERM_WINDOW_BACKEND=wayland but wayland support was not compiled in");
+        }
+
         Ok(Connection::X11(XConnection::create_new()?))
     }
 
+    /// Returns the name of the windowing system backend that is
+    /// actually in use
+    pub fn backend_name(&self) -> &'static str {
+        match self {
+            Self::X11(_) => "X11",
+            #[cfg(feature = "wayland")]
+            Self::Wayland(_) => "Wayland",
+            Self::Headless(_) => "Headless",
+        }
+    }
+
     pub async fn new_window<F>(
         &self,
         class_name: &str,
@@ -59,6 +151,7 @@ impl Connection {
         geometry: RequestedWindowGeometry,
         config: Option<&ConfigHandle>,
         font_config: Rc<FontConfiguration>,
+        options: WindowOptions<'_>,
         event_handler: F,
     ) -> anyhow::Result<Window>
     where
@@ -72,6 +165,7 @@ impl Connection {
                     geometry,
                     config,
                     font_config,
+                    options,
                     event_handler,
                 )
                 .await
@@ -84,17 +178,50 @@ impl Connection {
                     geometry,
                     config,
                     font_config,
+                    options,
                     event_handler,
                 )
                 .await
             }
+            Self::Headless(_) => {
+                HeadlessWindow::new_window(
+                    class_name,
+                    name,
+                    geometry,
+                    config,
+                    font_config,
+                    options,
+                    event_handler,
+                )
+                .await
+            }
+        }
+    }
+
+    /// Have the event loop call `callback` on the main thread when
+    /// `fd` becomes ready for the specified kind of io, for as long
+    /// as the returned FdWatch is kept alive.
+    /// Readiness is edge triggered, so the callback should read or
+    /// write until the operation would block.
+    pub fn watch_fd(
+        &self,
+        fd: RawFd,
+        interest: FdInterest,
+        callback: impl FnMut(FdReadiness) + 'static,
+    ) -> anyhow::Result<FdWatch> {
+        match self {
+            Self::X11(x) => x.fd_watchers.watch(fd, interest, callback),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.fd_watchers.watch(fd, interest, callback),
+            Self::Headless(_) => {
+                anyhow::bail!("watch_fd is not supported by the headless backend")
+            }
         }
     }
 
     pub(crate) fn x11(&self) -> Rc<XConnection> {
         match self {
             Self::X11(x) => Rc::clone(x),
-            #[cfg(feature = "wayland")]
             _ => panic!("attempted to get x11 reference on non-x11 connection"),
         }
     }
@@ -107,12 +234,31 @@ impl Connection {
         }
     }
 
+    pub(crate) fn headless(&self) -> Rc<HeadlessConnection> {
+        match self {
+            Self::Headless(h) => Rc::clone(h),
+            _ => panic!("attempted to get headless reference on non-headless connection"),
+        }
+    }
+
     pub(crate) fn advise_of_appearance_change(&self, appearance: Appearance) {
         log::trace!("Appearance changed to {appearance:?}");
         match self {
             Self::X11(x) => x.advise_of_appearance_change(appearance),
             #[cfg(feature = "wayland")]
             Self::Wayland(w) => w.advise_of_appearance_change(appearance),
+            Self::Headless(h) => h.advise_of_appearance_change(appearance),
+        }
+    }
+
+    pub(crate) fn advise_of_ui_settings_change(&self) {
+        let settings = self.get_ui_settings();
+        log::trace!("UI settings changed to {settings:?}");
+        match self {
+            Self::X11(x) => x.advise_of_ui_settings_change(settings),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.advise_of_ui_settings_change(settings),
+            Self::Headless(h) => h.advise_of_ui_settings_change(settings),
         }
     }
 }
@@ -123,6 +269,7 @@ impl ConnectionOps for Connection {
             Self::X11(x) => x.name(),
             #[cfg(feature = "wayland")]
             Self::Wayland(w) => w.name(),
+            Self::Headless(h) => h.name(),
         }
     }
 
@@ -131,6 +278,16 @@ impl ConnectionOps for Connection {
             Self::X11(x) => x.terminate_message_loop(),
             #[cfg(feature = "wayland")]
             Self::Wayland(w) => w.terminate_message_loop(),
+            Self::Headless(h) => h.terminate_message_loop(),
+        }
+    }
+
+    fn set_terminate_when_last_window_closed(&self, terminate: bool) {
+        match self {
+            Self::X11(x) => x.set_terminate_when_last_window_closed(terminate),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.set_terminate_when_last_window_closed(terminate),
+            Self::Headless(h) => h.set_terminate_when_last_window_closed(terminate),
         }
     }
 
@@ -139,15 +296,20 @@ impl ConnectionOps for Connection {
             Self::X11(x) => x.default_dpi(),
             #[cfg(feature = "wayland")]
             Self::Wayland(w) => w.default_dpi(),
+            Self::Headless(h) => h.default_dpi(),
         }
     }
 
     fn run_message_loop(&self) -> anyhow::Result<()> {
-        crate::os::xdg_desktop_portal::subscribe();
+        // Don't consult the desktop environment for a headless session
+        if !matches!(self, Self::Headless(_)) {
+            crate::os::xdg_desktop_portal::subscribe();
+        }
         match self {
             Self::X11(x) => x.run_message_loop(),
             #[cfg(feature = "wayland")]
             Self::Wayland(w) => w.run_message_loop(),
+            Self::Headless(h) => h.run_message_loop(),
         }
     }
 
@@ -156,6 +318,25 @@ impl ConnectionOps for Connection {
             Self::X11(x) => x.get_appearance(),
             #[cfg(feature = "wayland")]
             Self::Wayland(w) => w.get_appearance(),
+            Self::Headless(h) => h.get_appearance(),
+        }
+    }
+
+    fn get_ui_settings(&self) -> UiSettings {
+        match self {
+            Self::X11(x) => x.get_ui_settings(),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.get_ui_settings(),
+            Self::Headless(h) => h.get_ui_settings(),
+        }
+    }
+
+    fn keyboard_layout(&self) -> Option<KeyboardLayout> {
+        match self {
+            Self::X11(x) => x.keyboard_layout(),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.keyboard_layout(),
+            Self::Headless(h) => h.keyboard_layout(),
         }
     }
 
@@ -164,6 +345,7 @@ impl ConnectionOps for Connection {
             Self::X11(x) => x.beep(),
             #[cfg(feature = "wayland")]
             Self::Wayland(w) => w.beep(),
+            Self::Headless(h) => h.beep(),
         }
     }
 
@@ -172,6 +354,7 @@ impl ConnectionOps for Connection {
             Self::X11(x) => x.screens(),
             #[cfg(feature = "wayland")]
             Self::Wayland(w) => w.screens(),
+            Self::Headless(h) => h.screens(),
         }
     }
 }
@@ -185,6 +368,30 @@ impl Window {
         font_config: Rc<FontConfiguration>,
         event_handler: F,
     ) -> anyhow::Result<Window>
+    where
+        F: 'static + FnMut(WindowEvent, &Window),
+    {
+        Self::new_window_with_options(
+            class_name,
+            name,
+            geometry,
+            config,
+            font_config,
+            WindowOptions::default(),
+            event_handler,
+        )
+        .await
+    }
+
+    pub async fn new_window_with_options<F>(
+        class_name: &str,
+        name: &str,
+        geometry: RequestedWindowGeometry,
+        config: Option<&ConfigHandle>,
+        font_config: Rc<FontConfiguration>,
+        options: WindowOptions<'_>,
+        event_handler: F,
+    ) -> anyhow::Result<Window>
     where
         F: 'static + FnMut(WindowEvent, &Window),
     {
@@ -196,10 +403,37 @@ impl Window {
                 geometry,
                 config,
                 font_config,
+                options,
                 event_handler,
             )
             .await
     }
+
+    /// Create a popup, such as a menu, that is placed adjacent to
+    /// `anchor`, a rectangle in the pixel coordinates of `parent`.
+    /// The popup is closed along with its parent. Wayland compositors
+    /// also close it when the user clicks elsewhere, which is otherwise
+    /// left to the application.
+    pub async fn new_popup<F>(
+        parent: &Window,
+        anchor: Rect,
+        size: (usize, usize),
+        event_handler: F,
+    ) -> anyhow::Result<Window>
+    where
+        F: 'static + FnMut(WindowEvent, &Window),
+    {
+        match parent {
+            Self::X11(parent) => XWindow::new_popup(parent, anchor, size, event_handler).await,
+            #[cfg(feature = "wayland")]
+            Self::Wayland(parent) => {
+                WaylandWindow::new_popup(parent, anchor, size, event_handler).await
+            }
+            Self::Headless(parent) => {
+                HeadlessWindow::new_popup(parent, anchor, size, event_handler).await
+            }
+        }
+    }
 }
 
 impl HasDisplayHandle for Window {
@@ -208,6 +442,7 @@ impl HasDisplayHandle for Window {
             Self::X11(x) => x.display_handle(),
             #[cfg(feature = "wayland")]
             Self::Wayland(w) => w.display_handle(),
+            Self::Headless(h) => h.display_handle(),
         }
     }
 }
@@ -218,6 +453,7 @@ impl HasWindowHandle for Window {
             Self::X11(x) => x.window_handle(),
             #[cfg(feature = "wayland")]
             Self::Wayland(w) => w.window_handle(),
+            Self::Headless(h) => h.window_handle(),
         }
     }
 }
@@ -229,6 +465,7 @@ impl WindowOps for Window {
             Self::X11(x) => x.enable_opengl().await,
             #[cfg(feature = "wayland")]
             Self::Wayland(w) => w.enable_opengl().await,
+            Self::Headless(h) => h.enable_opengl().await,
         }
     }
 
@@ -237,6 +474,7 @@ impl WindowOps for Window {
             Self::X11(x) => x.finish_frame(frame),
             #[cfg(feature = "wayland")]
             Self::Wayland(w) => w.finish_frame(frame),
+            Self::Headless(h) => h.finish_frame(frame),
         }
     }
 
@@ -245,6 +483,16 @@ impl WindowOps for Window {
             Self::X11(x) => x.close(),
             #[cfg(feature = "wayland")]
             Self::Wayland(w) => w.close(),
+            Self::Headless(h) => h.close(),
+        }
+    }
+
+    fn force_close(&self) {
+        match self {
+            Self::X11(x) => x.force_close(),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.force_close(),
+            Self::Headless(h) => h.force_close(),
         }
     }
     fn notify<T: Any + Send + Sync>(&self, t: T)
@@ -255,6 +503,7 @@ impl WindowOps for Window {
             Self::X11(x) => x.notify(t),
             #[cfg(feature = "wayland")]
             Self::Wayland(w) => w.notify(t),
+            Self::Headless(h) => h.notify(t),
         }
     }
 
@@ -263,14 +512,34 @@ impl WindowOps for Window {
             Self::X11(x) => x.hide(),
             #[cfg(feature = "wayland")]
             Self::Wayland(w) => w.hide(),
+            Self::Headless(h) => h.hide(),
+        }
+    }
+
+    fn minimize(&self) {
+        match self {
+            Self::X11(x) => x.minimize(),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.minimize(),
+            Self::Headless(h) => h.minimize(),
         }
     }
 
-    fn focus(&self) {
+    fn focus(&self, serial: Option<InputSerial>) -> Future<FocusOutcome> {
         match self {
-            Self::X11(x) => x.focus(),
+            Self::X11(x) => x.focus(serial),
             #[cfg(feature = "wayland")]
-            Self::Wayland(w) => w.focus(),
+            Self::Wayland(w) => w.focus(serial),
+            Self::Headless(h) => h.focus(serial),
+        }
+    }
+
+    fn request_attention(&self) {
+        match self {
+            Self::X11(x) => x.request_attention(),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.request_attention(),
+            Self::Headless(h) => h.request_attention(),
         }
     }
 
@@ -279,6 +548,7 @@ impl WindowOps for Window {
             Self::X11(x) => x.toggle_fullscreen(),
             #[cfg(feature = "wayland")]
             Self::Wayland(w) => w.toggle_fullscreen(),
+            Self::Headless(h) => h.toggle_fullscreen(),
         }
     }
 
@@ -287,6 +557,7 @@ impl WindowOps for Window {
             Self::X11(x) => x.config_did_change(config),
             #[cfg(feature = "wayland")]
             Self::Wayland(w) => w.config_did_change(config),
+            Self::Headless(h) => h.config_did_change(config),
         }
     }
 
@@ -295,6 +566,7 @@ impl WindowOps for Window {
             Self::X11(x) => x.show(),
             #[cfg(feature = "wayland")]
             Self::Wayland(w) => w.show(),
+            Self::Headless(h) => h.show(),
         }
     }
 
@@ -303,6 +575,25 @@ impl WindowOps for Window {
             Self::X11(x) => x.set_cursor(cursor),
             #[cfg(feature = "wayland")]
             Self::Wayland(w) => w.set_cursor(cursor),
+            Self::Headless(h) => h.set_cursor(cursor),
+        }
+    }
+
+    fn set_custom_cursor(&self, image: Image, hotspot: Point) -> Future<()> {
+        match self {
+            Self::X11(x) => x.set_custom_cursor(image, hotspot),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.set_custom_cursor(image, hotspot),
+            Self::Headless(h) => h.set_custom_cursor(image, hotspot),
+        }
+    }
+
+    fn present_software(&self, image: Image) -> Future<()> {
+        match self {
+            Self::X11(x) => x.present_software(image),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.present_software(image),
+            Self::Headless(h) => h.present_software(image),
         }
     }
 
@@ -311,6 +602,61 @@ impl WindowOps for Window {
             Self::X11(x) => x.invalidate(),
             #[cfg(feature = "wayland")]
             Self::Wayland(w) => w.invalidate(),
+            Self::Headless(h) => h.invalidate(),
+        }
+    }
+
+    fn set_animation(&self, hint: Option<AnimationHint>) {
+        match self {
+            Self::X11(x) => x.set_animation(hint),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.set_animation(hint),
+            Self::Headless(h) => h.set_animation(hint),
+        }
+    }
+
+    fn set_window_opacity(&self, opacity: f32) {
+        match self {
+            Self::X11(x) => x.set_window_opacity(opacity),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.set_window_opacity(opacity),
+            Self::Headless(h) => h.set_window_opacity(opacity),
+        }
+    }
+
+    fn set_idle_inhibited(&self, inhibit: bool) {
+        match self {
+            Self::X11(x) => x.set_idle_inhibited(inhibit),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.set_idle_inhibited(inhibit),
+            Self::Headless(h) => h.set_idle_inhibited(inhibit),
+        }
+    }
+
+    fn set_shortcut_inhibition(&self, inhibit: bool) -> Future<()> {
+        match self {
+            Self::X11(x) => x.set_shortcut_inhibition(inhibit),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.set_shortcut_inhibition(inhibit),
+            Self::Headless(h) => h.set_shortcut_inhibition(inhibit),
+        }
+    }
+
+    fn set_opaque_region(&self, region: Option<Rect>) {
+        match self {
+            Self::X11(x) => x.set_opaque_region(region),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.set_opaque_region(region),
+            Self::Headless(h) => h.set_opaque_region(region),
+        }
+    }
+
+    fn invalidate_rect(&self, rect: Rect) {
+        match self {
+            Self::X11(x) => x.invalidate_rect(rect),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.invalidate_rect(rect),
+            Self::Headless(h) => h.invalidate_rect(rect),
         }
     }
 
@@ -319,6 +665,34 @@ impl WindowOps for Window {
             Self::X11(x11) => x11.set_resize_increments(incr),
             #[cfg(feature = "wayland")]
             Self::Wayland(w) => w.set_resize_increments(incr),
+            Self::Headless(h) => h.set_resize_increments(incr),
+        }
+    }
+
+    fn set_min_inner_size(&self, width: usize, height: usize) {
+        match self {
+            Self::X11(x) => x.set_min_inner_size(width, height),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.set_min_inner_size(width, height),
+            Self::Headless(h) => h.set_min_inner_size(width, height),
+        }
+    }
+
+    fn set_max_inner_size(&self, size: Option<(usize, usize)>) {
+        match self {
+            Self::X11(x) => x.set_max_inner_size(size),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.set_max_inner_size(size),
+            Self::Headless(h) => h.set_max_inner_size(size),
+        }
+    }
+
+    fn set_raw_key_mode(&self, enable: bool) {
+        match self {
+            Self::X11(x) => x.set_raw_key_mode(enable),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.set_raw_key_mode(enable),
+            Self::Headless(h) => h.set_raw_key_mode(enable),
         }
     }
 
@@ -327,6 +701,43 @@ impl WindowOps for Window {
             Self::X11(x) => x.set_title(title),
             #[cfg(feature = "wayland")]
             Self::Wayland(w) => w.set_title(title),
+            Self::Headless(h) => h.set_title(title),
+        }
+    }
+
+    fn set_always_on_top(&self, enable: bool) -> Future<()> {
+        match self {
+            Self::X11(x) => x.set_always_on_top(enable),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.set_always_on_top(enable),
+            Self::Headless(h) => h.set_always_on_top(enable),
+        }
+    }
+
+    fn set_window_level(&self, level: WindowLevel) {
+        match self {
+            Self::X11(x) => x.set_window_level(level),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.set_window_level(level),
+            Self::Headless(h) => h.set_window_level(level),
+        }
+    }
+
+    fn set_skip_taskbar(&self, enable: bool) -> Future<()> {
+        match self {
+            Self::X11(x) => x.set_skip_taskbar(enable),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.set_skip_taskbar(enable),
+            Self::Headless(h) => h.set_skip_taskbar(enable),
+        }
+    }
+
+    fn set_window_class(&self, class_name: &str, instance_name: Option<&str>) {
+        match self {
+            Self::X11(x) => x.set_window_class(class_name, instance_name),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.set_window_class(class_name, instance_name),
+            Self::Headless(h) => h.set_window_class(class_name, instance_name),
         }
     }
 
@@ -335,6 +746,7 @@ impl WindowOps for Window {
             Self::X11(x) => x.set_icon(image),
             #[cfg(feature = "wayland")]
             Self::Wayland(w) => w.set_icon(image),
+            Self::Headless(h) => h.set_icon(image),
         }
     }
 
@@ -343,6 +755,7 @@ impl WindowOps for Window {
             Self::X11(x) => x.maximize(),
             #[cfg(feature = "wayland")]
             Self::Wayland(w) => w.maximize(),
+            Self::Headless(h) => h.maximize(),
         }
     }
 
@@ -351,14 +764,21 @@ impl WindowOps for Window {
             Self::X11(x) => x.restore(),
             #[cfg(feature = "wayland")]
             Self::Wayland(w) => w.restore(),
+            Self::Headless(h) => h.restore(),
         }
     }
 
-    fn set_inner_size(&self, width: usize, height: usize) {
+    fn set_inner_size(
+        &self,
+        width: usize,
+        height: usize,
+        constrained: ConstrainedResize,
+    ) -> Future<Dimensions> {
         match self {
-            Self::X11(x) => x.set_inner_size(width, height),
+            Self::X11(x) => x.set_inner_size(width, height, constrained),
             #[cfg(feature = "wayland")]
-            Self::Wayland(w) => w.set_inner_size(width, height),
+            Self::Wayland(w) => w.set_inner_size(width, height, constrained),
+            Self::Headless(h) => h.set_inner_size(width, height, constrained),
         }
     }
 
@@ -367,6 +787,74 @@ impl WindowOps for Window {
             Self::X11(x) => x.request_drag_move(),
             #[cfg(feature = "wayland")]
             Self::Wayland(w) => w.request_drag_move(),
+            Self::Headless(h) => h.request_drag_move(),
+        }
+    }
+
+    fn start_interactive_move(&self, serial: Option<InputSerial>) -> Future<()> {
+        match self {
+            Self::X11(x) => x.start_interactive_move(serial),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.start_interactive_move(serial),
+            Self::Headless(h) => h.start_interactive_move(serial),
+        }
+    }
+
+    fn start_interactive_resize(
+        &self,
+        edge: WindowResizeEdge,
+        serial: Option<InputSerial>,
+    ) -> Future<()> {
+        match self {
+            Self::X11(x) => x.start_interactive_resize(edge, serial),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.start_interactive_resize(edge, serial),
+            Self::Headless(h) => h.start_interactive_resize(edge, serial),
+        }
+    }
+
+    fn lock_pointer(&self, position_hint: Option<Point>) -> Future<()> {
+        match self {
+            Self::X11(x) => x.lock_pointer(position_hint),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.lock_pointer(position_hint),
+            Self::Headless(h) => h.lock_pointer(position_hint),
+        }
+    }
+
+    fn confine_pointer(&self, region: Rect) -> Future<()> {
+        match self {
+            Self::X11(x) => x.confine_pointer(region),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.confine_pointer(region),
+            Self::Headless(h) => h.confine_pointer(region),
+        }
+    }
+
+    fn unlock_pointer(&self) {
+        match self {
+            Self::X11(x) => x.unlock_pointer(),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.unlock_pointer(),
+            Self::Headless(h) => h.unlock_pointer(),
+        }
+    }
+
+    fn show_window_menu(&self, coords: Point, serial: Option<InputSerial>) -> Future<()> {
+        match self {
+            Self::X11(x) => x.show_window_menu(coords, serial),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.show_window_menu(coords, serial),
+            Self::Headless(h) => h.show_window_menu(coords, serial),
+        }
+    }
+
+    fn frame_timings(&self) -> Future<Vec<FrameTiming>> {
+        match self {
+            Self::X11(x) => x.frame_timings(),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.frame_timings(),
+            Self::Headless(h) => h.frame_timings(),
         }
     }
 
@@ -375,6 +863,7 @@ impl WindowOps for Window {
             Self::X11(x) => x.set_window_drag_position(coords),
             #[cfg(feature = "wayland")]
             Self::Wayland(w) => w.set_window_drag_position(coords),
+            Self::Headless(h) => h.set_window_drag_position(coords),
         }
     }
 
@@ -383,6 +872,25 @@ impl WindowOps for Window {
             Self::X11(x) => x.set_window_position(coords),
             #[cfg(feature = "wayland")]
             Self::Wayland(w) => w.set_window_position(coords),
+            Self::Headless(h) => h.set_window_position(coords),
+        }
+    }
+
+    fn get_window_position(&self) -> Future<Option<ScreenPoint>> {
+        match self {
+            Self::X11(x) => x.get_window_position(),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.get_window_position(),
+            Self::Headless(h) => h.get_window_position(),
+        }
+    }
+
+    fn get_current_screen(&self) -> Future<ScreenInfo> {
+        match self {
+            Self::X11(x) => x.get_current_screen(),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.get_current_screen(),
+            Self::Headless(h) => h.get_current_screen(),
         }
     }
 
@@ -391,6 +899,7 @@ impl WindowOps for Window {
             Self::X11(x) => x.set_text_cursor_position(cursor),
             #[cfg(feature = "wayland")]
             Self::Wayland(w) => w.set_text_cursor_position(cursor),
+            Self::Headless(h) => h.set_text_cursor_position(cursor),
         }
     }
 
@@ -399,6 +908,7 @@ impl WindowOps for Window {
             Self::X11(x) => x.get_clipboard(clipboard),
             #[cfg(feature = "wayland")]
             Self::Wayland(w) => w.get_clipboard(clipboard),
+            Self::Headless(h) => h.get_clipboard(clipboard),
         }
     }
     fn set_clipboard(&self, clipboard: Clipboard, text: String) {
@@ -406,6 +916,58 @@ impl WindowOps for Window {
             Self::X11(x) => x.set_clipboard(clipboard, text),
             #[cfg(feature = "wayland")]
             Self::Wayland(w) => w.set_clipboard(clipboard, text),
+            Self::Headless(h) => h.set_clipboard(clipboard, text),
+        }
+    }
+    fn get_clipboard_data(&self, clipboard: Clipboard, mime: &str) -> Future<Vec<u8>> {
+        match self {
+            Self::X11(x) => x.get_clipboard_data(clipboard, mime),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.get_clipboard_data(clipboard, mime),
+            Self::Headless(h) => h.get_clipboard_data(clipboard, mime),
+        }
+    }
+    fn set_clipboard_data(&self, clipboard: Clipboard, mime: &str, data: Vec<u8>) {
+        match self {
+            Self::X11(x) => x.set_clipboard_data(clipboard, mime, data),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.set_clipboard_data(clipboard, mime, data),
+            Self::Headless(h) => h.set_clipboard_data(clipboard, mime, data),
+        }
+    }
+    fn list_clipboard_formats(&self, clipboard: Clipboard) -> Future<Vec<String>> {
+        match self {
+            Self::X11(x) => x.list_clipboard_formats(clipboard),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.list_clipboard_formats(clipboard),
+            Self::Headless(h) => h.list_clipboard_formats(clipboard),
+        }
+    }
+
+    fn get_dimensions(&self) -> Future<Dimensions> {
+        match self {
+            Self::X11(x) => x.get_dimensions(),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.get_dimensions(),
+            Self::Headless(h) => h.get_dimensions(),
+        }
+    }
+
+    fn get_window_state(&self) -> Future<WindowState> {
+        match self {
+            Self::X11(x) => x.get_window_state(),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.get_window_state(),
+            Self::Headless(h) => h.get_window_state(),
+        }
+    }
+
+    fn get_wm_capabilities(&self) -> Future<WmCapabilities> {
+        match self {
+            Self::X11(x) => x.get_wm_capabilities(),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.get_wm_capabilities(),
+            Self::Headless(h) => h.get_wm_capabilities(),
         }
     }
 }
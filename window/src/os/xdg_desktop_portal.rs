@@ -2,13 +2,13 @@
 
 //! <https://github.com/flatpak/xdg-desktop-portal/blob/main/data/org.freedesktop.portal.Settings.xml>
 
-use crate::{Appearance, Connection, ConnectionOps};
+use crate::{Appearance, Connection, ConnectionOps, UiSettings};
 use anyhow::Context;
 use futures_lite::future::FutureExt;
 use futures_util::stream::StreamExt;
 use std::collections::HashMap;
 use std::sync::Mutex;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use zbus::proxy;
 use zvariant::OwnedValue;
 
@@ -51,6 +51,8 @@ impl CachedAppearance {
 
 struct State {
     appearance: CachedAppearance,
+    /// None until first queried; kept up to date by the subscription
+    ui_settings: Option<UiSettings>,
     subscribe_running: bool,
     last_update: Instant,
 }
@@ -59,6 +61,7 @@ lazy_static::lazy_static! {
   static ref STATE: Mutex<State> = Mutex::new(
           State {
               appearance: CachedAppearance::Unknown,
+              ui_settings: None,
               subscribe_running: false,
               last_update: Instant::now(),
           }
@@ -87,6 +90,83 @@ pub async fn read_setting(namespace: &str, key: &str) -> anyhow::Result<OwnedVal
         .with_context(|| format!("Reading xdg-portal {namespace} {key}"))
 }
 
+/// Like read_setting, but reads every key in each of `namespaces`
+async fn read_all(
+    namespaces: &[&str],
+) -> anyhow::Result<HashMap<String, HashMap<String, OwnedValue>>> {
+    let connection = zbus::ConnectionBuilder::session()?.build().await?;
+    let proxy = PortalSettingsProxy::new(&connection)
+        .await
+        .context("make proxy")?;
+
+    proxy
+        .ReadAll(namespaces)
+        .or(async {
+            async_io::Timer::after(std::time::Duration::from_secs(1)).await;
+            Err(std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                "Timed out reading from xdg-portal; this indicates a problem \
+                 with your graphical environment. Consider running \
+                 'systemctl restart --user xdg-desktop-portal.service'",
+            )
+            .into())
+        })
+        .await
+        .with_context(|| format!("Reading xdg-portal {namespaces:?}"))
+}
+
+/// The portal exposes the gsettings of GNOME based desktops
+const MOUSE_NAMESPACE: &str = "org.gnome.desktop.peripherals.mouse";
+const INTERFACE_NAMESPACE: &str = "org.gnome.desktop.interface";
+
+/// Updates `settings` if `namespace` and `key` name one of the settings
+/// that we care about. Returns true if it did so.
+fn apply_ui_setting(
+    settings: &mut UiSettings,
+    namespace: &str,
+    key: &str,
+    value: &OwnedValue,
+) -> bool {
+    let millis = || {
+        value
+            .downcast_ref::<i32>()
+            .ok()
+            .and_then(|ms| u64::try_from(ms).ok())
+            .map(Duration::from_millis)
+    };
+    let flag = || value.downcast_ref::<bool>().ok();
+
+    match (namespace, key) {
+        (MOUSE_NAMESPACE, "double-click") => settings.double_click_interval = millis(),
+        (MOUSE_NAMESPACE, "natural-scroll") => settings.natural_scrolling = flag(),
+        (INTERFACE_NAMESPACE, "cursor-blink") => settings.cursor_blink = flag(),
+        (INTERFACE_NAMESPACE, "cursor-blink-time") => settings.cursor_blink_interval = millis(),
+        _ => return false,
+    }
+    true
+}
+
+/// Returns the interaction settings that the portal knows about.
+/// The result is cached, including the absence of the portal.
+pub async fn get_ui_settings() -> anyhow::Result<UiSettings> {
+    let mut state = STATE.lock().unwrap();
+    if let Some(settings) = state.ui_settings {
+        return Ok(settings);
+    }
+
+    let mut settings = UiSettings::default();
+    let result = read_all(&[MOUSE_NAMESPACE, INTERFACE_NAMESPACE]).await;
+    if let Ok(namespaces) = &result {
+        for (namespace, values) in namespaces {
+            for (key, value) in values {
+                apply_ui_setting(&mut settings, namespace, key, value);
+            }
+        }
+    }
+    state.ui_settings.replace(settings);
+    result.map(|_| settings).context("get_ui_settings.read_all")
+}
+
 fn value_to_appearance(value: OwnedValue) -> anyhow::Result<Appearance> {
     Ok(match value.downcast_ref::<u32>() {
         Ok(1) => Appearance::Dark,
@@ -166,6 +246,19 @@ pub async fn run_signal_loop(stream: &mut SettingChangedStream<'_>) -> Result<()
                     Connection::get().ok_or_else(|| anyhow::anyhow!("connection is dead"))?;
                 conn.advise_of_appearance_change(appearance);
             }
+        } else {
+            let mut state = STATE.lock().unwrap();
+            let changed = match state.ui_settings.as_mut() {
+                Some(settings) => apply_ui_setting(settings, args.namespace, args.key, &args.value),
+                // Nothing has asked yet, so nobody needs to be told
+                None => false,
+            };
+            drop(state);
+            if changed {
+                let conn =
+                    Connection::get().ok_or_else(|| anyhow::anyhow!("connection is dead"))?;
+                conn.advise_of_ui_settings_change();
+            }
         }
     }
     Result::<(), anyhow::Error>::Ok(())
@@ -78,6 +78,7 @@ pub fn keysym_to_keycode(keysym: u32) -> Option<KeyCode> {
         KEY_Super_R => KeyCode::Super,
         KEY_Menu => KeyCode::Applications,
         KEY_Help => KeyCode::Help,
+        KEY_Multi_key => KeyCode::Compose,
 
         KEY_VoidSymbol => KeyCode::VoidSymbol,
 
@@ -122,6 +123,54 @@ pub fn keysym_to_keycode(keysym: u32) -> Option<KeyCode> {
         KEY_XF86AudioLowerVolume => KeyCode::VolumeDown,
         KEY_XF86AudioMute => KeyCode::VolumeMute,
         KEY_XF86AudioRaiseVolume => KeyCode::VolumeUp,
+        KEY_XF86AudioPlay => KeyCode::MediaPlayPause,
+        KEY_XF86AudioPause => KeyCode::MediaPlayPause,
+        KEY_XF86AudioStop => KeyCode::MediaStop,
+        KEY_XF86AudioNext => KeyCode::MediaNextTrack,
+        KEY_XF86AudioPrev => KeyCode::MediaPrevTrack,
+
+        KEY_XF86MonBrightnessUp => KeyCode::BrightnessUp,
+        KEY_XF86MonBrightnessDown => KeyCode::BrightnessDown,
+
+        KEY_XF86Search => KeyCode::BrowserSearch,
+        KEY_XF86Sleep => KeyCode::Sleep,
         _ => return None,
     })
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use xkbcommon::xkb::keysyms::*;
+
+    #[test]
+    fn keysym_mapping() {
+        for (keysym, expected) in [
+            (KEY_a, Some(KeyCode::Char('a'))),
+            (KEY_Escape, Some(KeyCode::Char('\u{1b}'))),
+            (KEY_Print, Some(KeyCode::Print)),
+            (KEY_Menu, Some(KeyCode::Applications)),
+            (KEY_Multi_key, Some(KeyCode::Compose)),
+            (KEY_F1, Some(KeyCode::Function(1))),
+            (KEY_F12, Some(KeyCode::Function(12))),
+            (KEY_F13, Some(KeyCode::Function(13))),
+            (KEY_F24, Some(KeyCode::Function(24))),
+            (KEY_XF86AudioRaiseVolume, Some(KeyCode::VolumeUp)),
+            (KEY_XF86AudioLowerVolume, Some(KeyCode::VolumeDown)),
+            (KEY_XF86AudioMute, Some(KeyCode::VolumeMute)),
+            (KEY_XF86AudioPlay, Some(KeyCode::MediaPlayPause)),
+            (KEY_XF86AudioPause, Some(KeyCode::MediaPlayPause)),
+            (KEY_XF86AudioStop, Some(KeyCode::MediaStop)),
+            (KEY_XF86AudioNext, Some(KeyCode::MediaNextTrack)),
+            (KEY_XF86AudioPrev, Some(KeyCode::MediaPrevTrack)),
+            (KEY_XF86MonBrightnessUp, Some(KeyCode::BrightnessUp)),
+            (KEY_XF86MonBrightnessDown, Some(KeyCode::BrightnessDown)),
+            (KEY_XF86Search, Some(KeyCode::BrowserSearch)),
+            (KEY_XF86Sleep, Some(KeyCode::Sleep)),
+            (KEY_XF86Calculator, None),
+            (KEY_NoSymbol, None),
+        ] {
+            assert_eq!(keysym_to_keycode(keysym), expected, "keysym 0x{keysym:x}");
+        }
+    }
+}
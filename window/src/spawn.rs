@@ -1,5 +1,8 @@
+#[cfg(target_os = "macos")]
+use crate::connection::ConnectionOps;
 #[cfg(windows)]
 use crate::os::windows::event::EventHandle;
+use crate::{EventLoopStats, SpawnBudget};
 #[cfg(target_os = "macos")]
 use core_foundation::runloop::*;
 use promise::spawn::{Runnable, SpawnFunc};
@@ -16,6 +19,12 @@ lazy_static::lazy_static! {
     pub(crate) static ref SPAWN_QUEUE: Arc<SpawnQueue> = Arc::new(SpawnQueue::new().expect("failed to create SpawnQueue"));
 }
 
+/// The connection is per thread, but the spawn queue and the request
+/// to terminate the message loop are shared, so tests that run the
+/// message loop must not run at the same time
+#[cfg(test)]
+pub(crate) static TEST_MESSAGE_LOOP: Mutex<()> = Mutex::new(());
+
 struct InstrumentedSpawnFunc {
     func: SpawnFunc,
     at: Instant,
@@ -24,6 +33,8 @@ struct InstrumentedSpawnFunc {
 pub(crate) struct SpawnQueue {
     spawned_funcs: Mutex<VecDeque<InstrumentedSpawnFunc>>,
     spawned_funcs_low_pri: Mutex<VecDeque<InstrumentedSpawnFunc>>,
+    budget: Mutex<SpawnBudget>,
+    stats: Mutex<EventLoopStats>,
 
     #[cfg(windows)]
     pub event_handle: EventHandle,
@@ -60,9 +71,37 @@ impl SpawnQueue {
     }
 
     pub fn run(&self) -> bool {
+        crate::connection::service_main_thread_channels();
         self.run_impl()
     }
 
+    pub fn set_budget(&self, budget: SpawnBudget) {
+        *self.budget.lock().unwrap() = budget;
+    }
+
+    pub fn stats(&self) -> EventLoopStats {
+        let mut stats = *self.stats.lock().unwrap();
+        stats.queue_depth = self.spawned_funcs.lock().unwrap().len()
+            + self.spawned_funcs_low_pri.lock().unwrap().len();
+        stats
+    }
+
+    fn run_func(&self, func: SpawnFunc) {
+        let start = Instant::now();
+        func();
+        let elapsed = start.elapsed();
+        let mut stats = self.stats.lock().unwrap();
+        stats.tasks_run += 1;
+        stats.max_task_duration = stats.max_task_duration.max(elapsed);
+    }
+
+    /// Wake up the event loop on the main thread, without queueing
+    /// anything for it to run.
+    /// This can be called from any thread.
+    pub fn wake(&self) {
+        self.wake_impl()
+    }
+
     // This needs to be a separate function from the loop in `run`
     // in order for the lock to be released before we call the
     // returned function
@@ -106,19 +145,25 @@ impl SpawnQueue {
         Ok(Self {
             spawned_funcs,
             spawned_funcs_low_pri,
+            budget: Mutex::new(SpawnBudget::default()),
+            stats: Mutex::new(EventLoopStats::default()),
             event_handle,
         })
     }
 
     fn spawn_impl(&self, f: SpawnFunc, high_pri: bool) {
         self.queue_func(f, high_pri);
+        self.wake_impl();
+    }
+
+    fn wake_impl(&self) {
         self.event_handle.set_event();
     }
 
     fn run_impl(&self) -> bool {
         self.event_handle.reset_event();
         while let Some(func) = self.pop_func() {
-            func();
+            self.run_func(func);
         }
         self.has_any_queued()
     }
@@ -143,15 +188,21 @@ impl SpawnQueue {
         Ok(Self {
             spawned_funcs: Mutex::new(VecDeque::new()),
             spawned_funcs_low_pri: Mutex::new(VecDeque::new()),
+            budget: Mutex::new(SpawnBudget::default()),
+            stats: Mutex::new(EventLoopStats::default()),
             write: Mutex::new(pipe.write),
             read: Mutex::new(pipe.read),
         })
     }
 
     fn spawn_impl(&self, f: SpawnFunc, high_pri: bool) {
+        self.queue_func(f, high_pri);
+        self.wake_impl();
+    }
+
+    fn wake_impl(&self) {
         use std::io::Write;
 
-        self.queue_func(f, high_pri);
         while let Err(err) = self.write.lock().unwrap().write(b"x") {
             if err.kind() == std::io::ErrorKind::Interrupted {
                 continue;
@@ -162,11 +213,19 @@ impl SpawnQueue {
     }
 
     fn run_impl(&self) -> bool {
-        // On linux we only ever process one at at time, so that
-        // we can return to the main loop and process messages
-        // from the X server
-        if let Some(func) = self.pop_func() {
-            func();
+        // Run a limited amount of work at a time, so that we return
+        // to the main loop and process messages from the display
+        // server even if tasks keep spawning more tasks
+        let budget = *self.budget.lock().unwrap();
+        let start = Instant::now();
+        for _ in 0..budget.max_tasks.max(1) {
+            match self.pop_func() {
+                Some(func) => self.run_func(func),
+                None => break,
+            }
+            if start.elapsed() >= budget.max_duration {
+                break;
+            }
         }
 
         // try to drain the pipe.
@@ -211,6 +270,8 @@ impl SpawnQueue {
         Ok(Self {
             spawned_funcs,
             spawned_funcs_low_pri,
+            budget: Mutex::new(SpawnBudget::default()),
+            stats: Mutex::new(EventLoopStats::default()),
         })
     }
 
@@ -222,6 +283,11 @@ impl SpawnQueue {
         if SPAWN_QUEUE.run() {
             Self::queue_wakeup();
         }
+        if crate::connection::take_terminate_request() {
+            if let Some(conn) = crate::Connection::get() {
+                conn.terminate_message_loop();
+            }
+        }
     }
 
     fn queue_wakeup() {
@@ -235,10 +301,87 @@ impl SpawnQueue {
         Self::queue_wakeup();
     }
 
+    fn wake_impl(&self) {
+        Self::queue_wakeup();
+    }
+
     fn run_impl(&self) -> bool {
         if let Some(func) = self.pop_func() {
-            func();
+            self.run_func(func);
         }
         self.has_any_queued()
     }
 }
+
+#[cfg(all(test, unix, not(target_os = "macos")))]
+mod test {
+    use super::*;
+    use filedescriptor::{poll, pollfd, POLLIN};
+    use std::time::Duration;
+
+    #[test]
+    fn wake_from_another_thread_is_prompt() {
+        let queue = Arc::new(SpawnQueue::new().unwrap());
+        let mut pfd = [pollfd {
+            fd: queue.raw_fd(),
+            events: POLLIN,
+            revents: 0,
+        }];
+        // Nothing is pending, so the poll times out
+        assert_eq!(poll(&mut pfd, Some(Duration::from_millis(1))).unwrap(), 0);
+
+        let waker = Arc::clone(&queue);
+        let start = Instant::now();
+        let thread = std::thread::spawn(move || waker.wake());
+        assert_eq!(poll(&mut pfd, Some(Duration::from_secs(5))).unwrap(), 1);
+        let elapsed = start.elapsed();
+        thread.join().unwrap();
+
+        // Allow for a loaded CI machine; the fallback timeout of an
+        // idle event loop is measured in seconds
+        assert!(elapsed < Duration::from_millis(100), "took {elapsed:?}");
+
+        // Running the queue drains the wakeup
+        assert!(!queue.run());
+        pfd[0].revents = 0;
+        assert_eq!(poll(&mut pfd, Some(Duration::from_millis(1))).unwrap(), 0);
+    }
+
+    #[test]
+    fn run_respects_budget() {
+        let queue = SpawnQueue::new().unwrap();
+        queue.set_budget(SpawnBudget {
+            max_tasks: 4,
+            max_duration: Duration::from_secs(60),
+        });
+        for _ in 0..10 {
+            queue.spawn_impl(Box::new(|| {}), true);
+        }
+
+        assert!(queue.run());
+        let stats = queue.stats();
+        assert_eq!(stats.tasks_run, 4);
+        assert_eq!(stats.queue_depth, 6);
+
+        assert!(queue.run());
+        assert!(!queue.run());
+        let stats = queue.stats();
+        assert_eq!(stats.tasks_run, 10);
+        assert_eq!(stats.queue_depth, 0);
+
+        // A slow task uses up the time budget by itself
+        queue.set_budget(SpawnBudget {
+            max_tasks: 4,
+            max_duration: Duration::from_millis(1),
+        });
+        queue.spawn_impl(
+            Box::new(|| std::thread::sleep(Duration::from_millis(5))),
+            true,
+        );
+        queue.spawn_impl(Box::new(|| {}), true);
+        assert!(queue.run());
+        let stats = queue.stats();
+        assert_eq!(stats.tasks_run, 11);
+        assert!(stats.max_task_duration >= Duration::from_millis(5));
+    }
+}